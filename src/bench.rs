@@ -0,0 +1,163 @@
+use crate::util::time::format_duration_ms;
+use chrono::{DateTime, Datelike, Duration, Utc};
+use serde::Serialize;
+use std::fmt::Write as _;
+
+// runs compared against the ones before them to spot a regression
+const RECENT_RUNS: usize = 5;
+// a history spanning less than this is bucketed by day, otherwise by week
+const DAILY_SPAN_DAYS: i64 = 14;
+const BAR_WIDTH: usize = 30;
+
+/// duration statistics of the recorded runs of one command
+#[derive(Debug, Serialize)]
+pub struct Bench {
+    pub pattern: String,
+    pub runs: usize,
+    pub min_ms: u64,
+    pub median_ms: u64,
+    pub p95_ms: u64,
+    pub max_ms: u64,
+    pub first: DateTime<Utc>,
+    pub last: DateTime<Utc>,
+    pub bucket: &'static str, // "day" or "week"
+    pub trend: Vec<Bucket>,
+    /// median of the latest runs and how far it is from the median of the runs before them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recent: Option<Recent>,
+}
+
+/// runs within one day or week
+#[derive(Debug, Serialize)]
+pub struct Bucket {
+    pub label: String,
+    pub runs: usize,
+    pub median_ms: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Recent {
+    pub runs: usize,
+    pub median_ms: u64,
+    pub change_percent: f64,
+}
+
+impl Bench {
+    /// statistics of `runs` (start time and duration in ms); none when there are no runs
+    pub fn build(pattern: &str, mut runs: Vec<(DateTime<Utc>, u64)>) -> Option<Self> {
+        if runs.is_empty() {
+            return None;
+        }
+        runs.sort_by_key(|(at, _)| *at);
+        let mut durations: Vec<u64> = runs.iter().map(|(_, ms)| *ms).collect();
+        durations.sort_unstable();
+        let first = runs[0].0;
+        let last = runs[runs.len() - 1].0;
+
+        let daily = last - first < Duration::days(DAILY_SPAN_DAYS);
+        let mut trend: Vec<(String, Vec<u64>)> = Vec::new();
+        for (at, ms) in &runs {
+            let label = if daily {
+                at.format("%Y-%m-%d").to_string()
+            } else {
+                let week = at.iso_week();
+                format!("{}-W{:02}", week.year(), week.week())
+            };
+            match trend.last_mut() {
+                Some((l, values)) if *l == label => values.push(*ms),
+                _ => trend.push((label, vec![*ms])),
+            }
+        }
+        let trend = trend
+            .into_iter()
+            .map(|(label, mut values)| {
+                values.sort_unstable();
+                Bucket { label, runs: values.len(), median_ms: percentile(&values, 50.0) }
+            })
+            .collect();
+
+        let recent = (runs.len() > RECENT_RUNS).then(|| {
+            let split = runs.len() - RECENT_RUNS;
+            let sorted = |part: &[(DateTime<Utc>, u64)]| {
+                let mut values: Vec<u64> = part.iter().map(|(_, ms)| *ms).collect();
+                values.sort_unstable();
+                values
+            };
+            let before = percentile(&sorted(&runs[..split]), 50.0);
+            let latest = percentile(&sorted(&runs[split..]), 50.0);
+            Recent {
+                runs: RECENT_RUNS,
+                median_ms: latest,
+                change_percent: if before == 0 { 0.0 } else { (latest as f64 - before as f64) / before as f64 * 100.0 },
+            }
+        });
+
+        Some(Bench {
+            pattern: pattern.to_string(),
+            runs: runs.len(),
+            min_ms: durations[0],
+            median_ms: percentile(&durations, 50.0),
+            p95_ms: percentile(&durations, 95.0),
+            max_ms: durations[durations.len() - 1],
+            first,
+            last,
+            bucket: if daily { "day" } else { "week" },
+            trend,
+            recent,
+        })
+    }
+
+    /// plain text summary with a bar per day or week
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{}: {} runs from {} to {}",
+            self.pattern,
+            self.runs,
+            self.first.format("%Y-%m-%d"),
+            self.last.format("%Y-%m-%d")
+        );
+        let _ = writeln!(
+            out,
+            "  min {}  median {}  p95 {}  max {}",
+            format_duration_ms(self.min_ms),
+            format_duration_ms(self.median_ms),
+            format_duration_ms(self.p95_ms),
+            format_duration_ms(self.max_ms)
+        );
+        if let Some(recent) = &self.recent {
+            let _ = writeln!(
+                out,
+                "  last {} runs: median {} ({:+.0}% against the runs before)",
+                recent.runs,
+                format_duration_ms(recent.median_ms),
+                recent.change_percent
+            );
+        }
+        let _ = writeln!(out, "\nmedian per {}", self.bucket);
+        let widest = self.trend.iter().map(|b| b.median_ms).max().unwrap_or(0).max(1);
+        for bucket in &self.trend {
+            let bar = (bucket.median_ms as f64 / widest as f64 * BAR_WIDTH as f64).round().max(1.0) as usize;
+            let _ = writeln!(
+                out,
+                "  {:<10}  {:>4} {:<4}  {:>8}  {}",
+                bucket.label,
+                bucket.runs,
+                if bucket.runs == 1 { "run" } else { "runs" },
+                format_duration_ms(bucket.median_ms),
+                "\u{2588}".repeat(bar)
+            );
+        }
+        out
+    }
+}
+
+// nearest-rank percentile of sorted values
+fn percentile(sorted: &[u64], p: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
@@ -0,0 +1,311 @@
+use crate::config::InputRecording;
+use serde_json::{json, Value};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Instant;
+
+/// asciicast v2 recording of everything shown during a session, next to commands.json
+pub const CAST_FILE: &str = "session.cast";
+/// what was typed during a session, as asciicast input events; only written when [recording] input is on
+pub const INPUT_FILE: &str = "input.cast";
+// a pause between keys longer than this is thinking, not typing
+const TYPING_GAP: f64 = 2.0;
+
+/// appends output and resizes to a session's cast as they happen
+pub struct CastWriter {
+    file: File,
+    start: Instant,
+    // time of the last event already in the file, when a resumed session appends to it
+    offset: f64,
+    // an incomplete utf-8 sequence held back for the next chunk
+    pending: Vec<u8>,
+}
+
+impl CastWriter {
+    /// start the cast in `log_dir`, or continue it when the session is resumed
+    pub fn open(log_dir: &Path, cols: u16, rows: u16, shell: &str) -> io::Result<Self> {
+        let header = json!({
+            "version": 2,
+            "width": cols,
+            "height": rows,
+            "timestamp": chrono::Utc::now().timestamp(),
+            "env": { "SHELL": shell, "TERM": std::env::var("TERM").unwrap_or_default() },
+        });
+        let (file, offset) = open_appending(&log_dir.join(CAST_FILE), &header)?;
+        let mut writer = Self {
+            file,
+            start: Instant::now(),
+            offset,
+            pending: Vec::new(),
+        };
+        // a resumed session may be in a terminal of another size, which replay has to know
+        if offset > 0.0 {
+            writer.resize(cols, rows);
+        }
+        Ok(writer)
+    }
+
+    pub fn output(&mut self, data: &[u8]) {
+        self.pending.extend_from_slice(data);
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(_) => self.pending.len(),
+            // hold back a sequence cut off at the end, give up on anything else
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => self.pending.len(),
+        };
+        if valid == 0 {
+            return;
+        }
+        let text = String::from_utf8_lossy(&self.pending[..valid]).to_string();
+        self.pending.drain(..valid);
+        self.event("o", text);
+    }
+
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        self.event("r", format!("{}x{}", cols, rows));
+    }
+
+    /// mark where a logged command ended, labelled with its command line; the nth marker belongs
+    /// to the nth entry in commands.json
+    pub fn marker(&mut self, label: &str) {
+        self.event("m", label.to_string());
+    }
+
+    fn event(&mut self, kind: &str, data: String) {
+        write_event(&mut self.file, self.offset + self.start.elapsed().as_secs_f64(), kind, data);
+    }
+}
+
+/// appends keystrokes to a session's input stream, as text or as timing only
+pub struct InputRecorder {
+    file: File,
+    start: Instant,
+    offset: f64,
+    mode: InputRecording,
+}
+
+impl InputRecorder {
+    /// start the input stream in `log_dir`, or continue it; none when input recording is off
+    pub fn open(log_dir: &Path, mode: InputRecording, cols: u16, rows: u16) -> io::Result<Option<Self>> {
+        let name = match mode {
+            InputRecording::Off => return Ok(None),
+            InputRecording::Timing => "timing",
+            InputRecording::Full => "full",
+        };
+        let header = json!({
+            "version": 2,
+            "width": cols,
+            "height": rows,
+            "timestamp": chrono::Utc::now().timestamp(),
+            "input": name,
+        });
+        let (file, offset) = open_appending(&log_dir.join(INPUT_FILE), &header)?;
+        Ok(Some(Self {
+            file,
+            start: Instant::now(),
+            offset,
+            mode,
+        }))
+    }
+
+    /// typed bytes; `hidden` when the terminal doesn't echo them, so they are never kept as text
+    pub fn keys(&mut self, bytes: &[u8], hidden: bool) {
+        let data = if self.mode == InputRecording::Full && !hidden {
+            String::from_utf8_lossy(bytes).to_string()
+        } else {
+            key_classes(&String::from_utf8_lossy(bytes))
+        };
+        if !data.is_empty() {
+            self.event("i", data);
+        }
+    }
+
+    /// a paste as one event: its text, or only its size when recording timing or when `hidden`,
+    /// pasted while the terminal doesn't echo
+    pub fn paste(&mut self, text: &[u8], hidden: bool) {
+        let data = match self.mode {
+            InputRecording::Full if !hidden => String::from_utf8_lossy(text).to_string(),
+            _ => text.len().to_string(),
+        };
+        self.event("p", data);
+    }
+
+    fn event(&mut self, kind: &str, data: String) {
+        write_event(&mut self.file, self.offset + self.start.elapsed().as_secs_f64(), kind, data);
+    }
+}
+
+/// typing speed over the input streams of one or more sessions
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TypingStats {
+    pub keys: u64,        // characters typed
+    pub corrections: u64, // backspaces
+    pub typing_secs: f64, // time between keys, leaving out pauses
+}
+
+impl TypingStats {
+    /// stats of the input stream in `log_dir`, if the session recorded one
+    pub fn load(log_dir: &Path) -> Option<Self> {
+        let text = fs::read_to_string(log_dir.join(INPUT_FILE)).ok()?;
+        let mut stats = Self::default();
+        let mut last: Option<f64> = None;
+        for line in text.lines().skip(1) {
+            let Ok(Value::Array(event)) = serde_json::from_str::<Value>(line) else { continue };
+            let (Some(time), Some("i"), Some(data)) = (
+                event.first().and_then(Value::as_f64),
+                event.get(1).and_then(Value::as_str),
+                event.get(2).and_then(Value::as_str),
+            ) else {
+                continue;
+            };
+            for class in key_classes(data).chars() {
+                match class {
+                    'x' => stats.keys += 1,
+                    '\x7f' | '\x08' => stats.corrections += 1,
+                    _ => {}
+                }
+            }
+            if let Some(gap) = last.map(|l| time - l).filter(|g| (0.0..TYPING_GAP).contains(g)) {
+                stats.typing_secs += gap;
+            }
+            last = Some(time);
+        }
+        Some(stats)
+    }
+
+    pub fn add(&mut self, other: &TypingStats) {
+        self.keys += other.keys;
+        self.corrections += other.corrections;
+        self.typing_secs += other.typing_secs;
+    }
+
+    /// words per minute, counting five characters as a word
+    pub fn wpm(&self) -> Option<f64> {
+        (self.typing_secs > 0.0).then(|| self.keys as f64 / 5.0 / (self.typing_secs / 60.0))
+    }
+
+    /// share of keys that were backspaces
+    pub fn correction_rate(&self) -> f64 {
+        let total = self.keys + self.corrections;
+        if total == 0 { 0.0 } else { self.corrections as f64 / total as f64 }
+    }
+}
+
+// each key reduced to its kind: `x` for a character, escape for a whole escape sequence
+// (arrows, function keys), `^` for other control keys; enter, tab and backspace stay as they are
+fn key_classes(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' | '\n' | '\t' | '\x7f' | '\x08' => out.push(c),
+            '\x1b' => {
+                if let Some('[' | 'O') = chars.peek() {
+                    chars.next();
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                out.push('\x1b');
+            }
+            c if c.is_control() => out.push('^'),
+            _ => out.push('x'),
+        }
+    }
+    out
+}
+
+/// a cast read back for export
+#[derive(Debug, Clone)]
+pub struct Cast {
+    pub width: u16,
+    pub height: u16,
+    // unix time the recording started, when the header has it
+    pub timestamp: Option<i64>,
+    pub events: Vec<CastEvent>,
+    // where logged commands ended, with their command lines, in order
+    pub markers: Vec<(f64, String)>,
+}
+
+#[derive(Debug, Clone)]
+pub enum CastEvent {
+    Output { time: f64, data: String },
+    Resize { time: f64, cols: u16, rows: u16 },
+}
+
+impl CastEvent {
+    pub fn time(&self) -> f64 {
+        match self {
+            CastEvent::Output { time, .. } | CastEvent::Resize { time, .. } => *time,
+        }
+    }
+}
+
+impl Cast {
+    /// read the cast of the session in `log_dir`
+    pub fn load(log_dir: &Path) -> Result<Self, String> {
+        let path = log_dir.join(CAST_FILE);
+        let text = fs::read_to_string(&path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        let mut lines = text.lines();
+        let header: Value = lines
+            .next()
+            .and_then(|l| serde_json::from_str(l).ok())
+            .ok_or_else(|| format!("{} has no asciicast header", path.display()))?;
+        let mut cast = Cast {
+            // a recorder without a terminal notes 0x0
+            width: header["width"].as_u64().filter(|&w| w > 0).unwrap_or(80) as u16,
+            height: header["height"].as_u64().filter(|&h| h > 0).unwrap_or(24) as u16,
+            timestamp: header["timestamp"].as_i64(),
+            events: Vec::new(),
+            markers: Vec::new(),
+        };
+        for line in lines {
+            let Ok(Value::Array(event)) = serde_json::from_str::<Value>(line) else { continue };
+            let (Some(time), Some(kind), Some(data)) = (
+                event.first().and_then(Value::as_f64),
+                event.get(1).and_then(Value::as_str),
+                event.get(2).and_then(Value::as_str),
+            ) else {
+                continue;
+            };
+            match kind {
+                "o" => cast.events.push(CastEvent::Output { time, data: data.to_string() }),
+                "m" => cast.markers.push((time, data.to_string())),
+                "r" => {
+                    let Some((cols, rows)) = data.split_once('x') else { continue };
+                    if let (Ok(cols), Ok(rows)) = (cols.parse(), rows.parse()) {
+                        cast.events.push(CastEvent::Resize { time, cols, rows });
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(cast)
+    }
+}
+
+// append to the cast at `path`, writing `header` when it is new; returns the time of its last event
+fn open_appending(path: &Path, header: &Value) -> io::Result<(File, f64)> {
+    let existing = fs::read_to_string(path).unwrap_or_default();
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let offset = if existing.trim().is_empty() {
+        writeln!(file, "{}", header)?;
+        0.0
+    } else {
+        existing.lines().rev().find_map(event_time).unwrap_or(0.0)
+    };
+    Ok((file, offset))
+}
+
+fn write_event(file: &mut File, time: f64, kind: &str, data: String) {
+    // rounded like asciinema does, which keeps the file small
+    let time = (time * 1e6).round() / 1e6;
+    let _ = writeln!(file, "{}", json!([time, kind, data]));
+}
+
+fn event_time(line: &str) -> Option<f64> {
+    serde_json::from_str::<Value>(line).ok()?.get(0)?.as_f64()
+}
@@ -0,0 +1,251 @@
+use crate::io::strip_ansi;
+use crate::util::time::format_duration_ms;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::fmt::Write as _;
+
+// github shows at most 50 annotations per job, so more would just be dropped
+const MAX_FINDINGS: usize = 50;
+// output lines quoted in the job summary
+const SUMMARY_TAIL_LINES: usize = 30;
+
+/// ci system recli is running under, from the environment it sets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    GitHub,
+    GitLab,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// a diagnostic picked out of command output
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub severity: Severity,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub col: Option<u32>,
+    pub message: String,
+}
+
+pub fn detect() -> Option<Provider> {
+    if std::env::var("GITHUB_ACTIONS").is_ok_and(|v| v == "true") {
+        Some(Provider::GitHub)
+    } else if std::env::var("GITLAB_CI").is_ok_and(|v| v == "true") {
+        Some(Provider::GitLab)
+    } else {
+        None
+    }
+}
+
+/// find compiler-style diagnostics: `path:line[:col]: error: msg`, rustc's
+/// `error[E0308]: msg` followed by `--> path:line:col`, and bare `error: msg` lines
+pub fn scan(output: &str) -> Vec<Finding> {
+    let lines: Vec<String> = output.lines().map(|l| strip_ansi(l.as_bytes())).collect();
+    let mut findings = Vec::new();
+
+    for (i, line) in lines.iter().enumerate() {
+        if findings.len() >= MAX_FINDINGS {
+            break;
+        }
+        let trimmed = line.trim_start();
+
+        if let Some(f) = located(trimmed) {
+            findings.push(f);
+            continue;
+        }
+
+        let Some((severity, message)) = headline(trimmed) else { continue };
+        // rustc-style: the location follows on a `-->` line
+        let mut finding = Finding { severity, file: None, line: None, col: None, message };
+        if let Some(arrow) = lines.iter().skip(i + 1).take(3).find_map(|l| l.trim_start().strip_prefix("--> ")) {
+            let (file, line, col) = split_location(arrow.trim());
+            finding.file = Some(file.to_string());
+            finding.line = line;
+            finding.col = col;
+        }
+        findings.push(finding);
+    }
+    findings
+}
+
+// runtime failures that don't follow the compiler `error:` shape
+const ERROR_PHRASES: &[&str] = &["panicked at", "Traceback (most recent call last)", ": command not found", "fatal: "];
+
+/// whether a single output line reads as an error or warning diagnostic
+pub fn line_severity(line: &str) -> Option<Severity> {
+    let line = strip_ansi(line.as_bytes());
+    let trimmed = line.trim();
+    if let Some(f) = located(trimmed) {
+        return Some(f.severity);
+    }
+    if let Some((severity, _)) = headline(trimmed) {
+        return Some(severity);
+    }
+    ERROR_PHRASES
+        .iter()
+        .any(|p| trimmed.contains(p))
+        .then_some(Severity::Error)
+}
+
+/// a github workflow command that turns a finding into an annotation
+pub fn github_annotation(f: &Finding) -> String {
+    let kind = match f.severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    };
+    let mut props = Vec::new();
+    if let Some(file) = &f.file {
+        props.push(format!("file={}", escape_property(&relative_to_workspace(file))));
+    }
+    if let Some(line) = f.line {
+        props.push(format!("line={}", line));
+    }
+    if let Some(col) = f.col {
+        props.push(format!("col={}", col));
+    }
+    let props = if props.is_empty() { String::new() } else { format!(" {}", props.join(",")) };
+    format!("::{}{}::{}", kind, props, escape_data(&f.message))
+}
+
+/// gitlab code quality report entries, shown on merge requests
+pub fn gitlab_code_quality(findings: &[Finding]) -> serde_json::Value {
+    // gitlab needs a path for every issue, so unlocated findings stay in the summary only
+    let issues: Vec<serde_json::Value> = findings
+        .iter()
+        .filter_map(|f| Some((f, relative_to_workspace(f.file.as_deref()?))))
+        .map(|(f, path)| {
+            let line = f.line.unwrap_or(1);
+            let fingerprint = hex::encode(Sha256::digest(format!("{}:{}:{}", path, line, f.message).as_bytes()));
+            json!({
+                "description": f.message,
+                "check_name": "recli",
+                "fingerprint": fingerprint,
+                "severity": if f.severity == Severity::Error { "major" } else { "minor" },
+                "location": { "path": path, "lines": { "begin": line } },
+            })
+        })
+        .collect();
+    serde_json::Value::Array(issues)
+}
+
+/// markdown job summary for one recorded command
+pub fn job_summary(cmd: &str, exit_code: i32, duration_ms: u64, findings: &[Finding], output: &str) -> String {
+    let mut md = String::new();
+    let status = if exit_code == 0 { "passed" } else { "failed" };
+    let _ = writeln!(md, "### recli: `{}` {}\n", cmd.replace('`', "'"), status);
+    let _ = writeln!(md, "| exit code | duration | errors | warnings |");
+    let _ = writeln!(md, "|---|---|---|---|");
+    let errors = findings.iter().filter(|f| f.severity == Severity::Error).count();
+    let _ = writeln!(
+        md,
+        "| {} | {} | {} | {} |\n",
+        exit_code,
+        format_duration_ms(duration_ms),
+        errors,
+        findings.len() - errors
+    );
+
+    if !findings.is_empty() {
+        for f in findings {
+            let location = match (&f.file, f.line) {
+                (Some(file), Some(line)) => format!("`{}:{}` ", relative_to_workspace(file), line),
+                (Some(file), None) => format!("`{}` ", relative_to_workspace(file)),
+                _ => String::new(),
+            };
+            let _ = writeln!(md, "- {}{}", location, f.message);
+        }
+        md.push('\n');
+    }
+
+    let lines: Vec<String> = output.lines().map(|l| strip_ansi(l.as_bytes())).collect();
+    if !lines.is_empty() {
+        let tail = &lines[lines.len().saturating_sub(SUMMARY_TAIL_LINES)..];
+        let _ = writeln!(md, "<details><summary>last {} lines of output</summary>\n", tail.len());
+        let _ = writeln!(md, "```\n{}\n```\n</details>", tail.join("\n"));
+    }
+    md
+}
+
+// `path:line[:col]: error|warning: message`
+fn located(line: &str) -> Option<Finding> {
+    for (marker, severity) in [(": error: ", Severity::Error), (": warning: ", Severity::Warning)] {
+        let Some(idx) = line.find(marker) else { continue };
+        let (file, line_no, col) = split_location(&line[..idx]);
+        line_no?;
+        if file.is_empty() || file.contains(' ') {
+            return None;
+        }
+        return Some(Finding {
+            severity,
+            file: Some(file.to_string()),
+            line: line_no,
+            col,
+            message: line[idx + marker.len()..].trim().to_string(),
+        });
+    }
+    None
+}
+
+// `error: msg`, `error[E0308]: msg`, `warning: msg`, `ERROR: msg`
+fn headline(line: &str) -> Option<(Severity, String)> {
+    let lower = line.get(..8).unwrap_or(line).to_ascii_lowercase();
+    let (severity, rest) = if lower.starts_with("error") {
+        (Severity::Error, &line[5..])
+    } else if lower.starts_with("warning") {
+        (Severity::Warning, &line[7..])
+    } else {
+        return None;
+    };
+    // skip an optional [code]
+    let rest = match rest.strip_prefix('[') {
+        Some(r) => &r[r.find(']')? + 1..],
+        None => rest,
+    };
+    let message = rest.strip_prefix(':')?.trim();
+    // summary lines like "warning: 3 warnings emitted" add nothing
+    if message.is_empty() || message.ends_with("warnings emitted") || message.ends_with("warning emitted") {
+        return None;
+    }
+    Some((severity, message.to_string()))
+}
+
+// "src/main.rs:10:5" -> ("src/main.rs", Some(10), Some(5))
+fn split_location(loc: &str) -> (&str, Option<u32>, Option<u32>) {
+    let mut parts = loc.rsplitn(3, ':');
+    let last = parts.next().unwrap_or_default();
+    let mid = parts.next();
+    let first = parts.next();
+    match (first, mid.and_then(|m| m.parse::<u32>().ok()), last.parse::<u32>().ok()) {
+        (Some(file), Some(line), Some(col)) => (file, Some(line), Some(col)),
+        _ => match (mid, last.parse::<u32>().ok()) {
+            (Some(_), Some(line)) => (loc.rsplit_once(':').map(|(f, _)| f).unwrap_or(loc), Some(line), None),
+            _ => (loc, None, None),
+        },
+    }
+}
+
+// annotations only link to files given relative to the checkout
+fn relative_to_workspace(file: &str) -> String {
+    let root = std::env::var("GITHUB_WORKSPACE").or_else(|_| std::env::var("CI_PROJECT_DIR"));
+    match root {
+        Ok(root) if !root.is_empty() => file
+            .strip_prefix(&root)
+            .map(|p| p.trim_start_matches('/').to_string())
+            .unwrap_or_else(|| file.to_string()),
+        _ => file.to_string(),
+    }
+}
+
+fn escape_data(s: &str) -> String {
+    s.replace('%', "%25").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+fn escape_property(s: &str) -> String {
+    escape_data(s).replace(':', "%3A").replace(',', "%2C")
+}
+
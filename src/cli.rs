@@ -0,0 +1,829 @@
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use std::path::PathBuf;
+
+/// CLI configuration for Recli
+#[derive(Parser, Debug, Clone)]
+#[command(name = "recli")]
+#[command(author, version, about, long_about = None)]
+pub struct Cli {
+    /// enable verbose output
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+
+    /// path to config file (defaults to ~/.recli/recli.toml)
+    #[arg(short, long, global = true)]
+    pub config: Option<String>,
+
+    /// record sessions into this directory instead of ~/.recli/logs
+    #[arg(long, global = true, value_name = "PATH")]
+    pub log_dir: Option<String>,
+
+    /// stream each recorded command as a LogEventV1 json line to DEST
+    /// (`=-` or bare for stdout, `=fd:N` for an open descriptor, otherwise a file to append to)
+    #[arg(long, global = true, value_name = "DEST", num_args = 0..=1, require_equals = true, default_missing_value = "-")]
+    pub json_events: Option<String>,
+
+    /// check that the cosmos container is reachable before uploading a session (read-only)
+    #[arg(long, global = true)]
+    pub verify_upload: bool,
+
+    /// shell to use (defaults to user's default shell)
+    #[arg(short, long, global = true)]
+    pub shell: Option<String>,
+
+    /// subcommand; anything unrecognised is run as a single recorded command
+    #[command(subcommand)]
+    pub command: Option<RecliCommands>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum RecliCommands {
+    /// start capturing a terminal session in a pty
+    Start {
+        /// append to an earlier session instead of starting a new one
+        #[arg(long, value_name = "SESSION_ID")]
+        resume: Option<String>,
+
+        /// run output through the wasm filters of this [filters] profile
+        #[arg(long, value_name = "PROFILE")]
+        filter_profile: Option<String>,
+
+        /// alert and tag the command when its output matches this regex (repeatable, adds to [watch])
+        #[arg(long, value_name = "REGEX")]
+        watch: Vec<String>,
+
+        /// record through bash or zsh hooks without a pseudo-terminal; command output isn't kept
+        #[arg(long)]
+        no_pty: bool,
+
+        /// label the session, e.g. private to keep it from the uploaders [sinks] skips it for (repeatable)
+        #[arg(long, value_name = "TAG")]
+        tag: Vec<String>,
+
+        /// record even inside a shell another recli session already records, which then records
+        /// the output of this one as well
+        #[arg(long)]
+        force_nested: bool,
+    },
+
+    /// end current capturing session and save logs
+    #[command(alias = "stop")] // keep stop as an alias for backward compatibility
+    End,
+
+    /// show status of the active session
+    Status,
+
+    /// print where sessions are recorded, or the directory of one session
+    Path {
+        /// session id; without one, the directory sessions are recorded under
+        #[arg(value_name = "SESSION_ID")]
+        session: Option<String>,
+
+        /// the session most recently started or finished, which ~/.recli/logs/latest links to
+        #[arg(long, conflicts_with = "session")]
+        latest: bool,
+    },
+
+    /// watch the active session live: running command, failures, output rate and uploads
+    Dashboard,
+
+    /// print the full output of the last command in the active session
+    Last {
+        /// show the nth-last command instead (1 is the last)
+        #[arg(short = 'n', default_value = "1")]
+        nth: usize,
+
+        /// print only what the command wrote to stderr (needs split_stderr)
+        #[arg(long)]
+        stderr: bool,
+
+        /// prefix each line with when it was printed and point out long pauses
+        #[arg(short = 't', long, conflicts_with = "stderr")]
+        timestamps: bool,
+    },
+
+    /// open a command's full output in $PAGER, or $EDITOR with --edit, and print its session's
+    /// directory
+    Open {
+        /// the command's id, as in commands.json, `recli query` or `--json` listings
+        #[arg(value_name = "COMMAND_ID", required_unless_present = "last")]
+        id: Option<String>,
+
+        /// the last command of the active session, or else of the most recent one
+        #[arg(long, conflicts_with = "id")]
+        last: bool,
+
+        /// open it in $EDITOR rather than $PAGER
+        #[arg(long)]
+        edit: bool,
+    },
+
+    /// copy the last command line of the active session to the clipboard
+    Copy {
+        /// copy the command's output instead
+        #[arg(long)]
+        output: bool,
+
+        /// use the nth-last command instead (1 is the last)
+        #[arg(short = 'n', default_value = "1")]
+        nth: usize,
+
+        /// strip colors and other escape sequences from the output
+        #[arg(long)]
+        plain: bool,
+    },
+
+    /// note why the last command went as it did, e.g. "expected, retried with sudo"; in the active
+    /// session, or else the most recent one
+    AnnotateLast {
+        /// the note, asked for when left out; an empty one removes it
+        text: Option<String>,
+
+        /// note the nth-last command instead (1 is the last)
+        #[arg(short = 'n', default_value = "1")]
+        nth: usize,
+
+        /// count failed commands only, so -n 1 is the last one that failed
+        #[arg(long)]
+        failed: bool,
+    },
+
+    /// add a recorded command to the snippets library (~/.recli/snippets.json), to run again later
+    Star {
+        /// star the newest recorded command containing this text instead of the last one
+        #[arg(long, value_name = "TEXT")]
+        search: Option<String>,
+
+        /// star the nth-last command instead (1 is the last)
+        #[arg(short = 'n', default_value = "1", conflicts_with = "search")]
+        nth: usize,
+
+        /// name to run it by (defaults to its first words, e.g. cargo-build)
+        #[arg(long)]
+        name: Option<String>,
+
+        /// what it is for
+        #[arg(short, long)]
+        description: Option<String>,
+
+        /// turn TEXT in the command into a placeholder called NAME, filled in when it runs (repeatable)
+        #[arg(short, long, value_name = "TEXT=NAME")]
+        placeholder: Vec<String>,
+    },
+
+    /// list, run and remove starred commands
+    Snippets {
+        #[command(subcommand)]
+        command: SnippetsCommands,
+    },
+
+    /// check that the cosmos db settings are complete, then connectivity and container setup
+    #[command(alias = "cosmos_doctor")]
+    Doctor,
+
+    /// show recent commands from every machine in the local index
+    History {
+        /// number of commands to show
+        #[arg(short = 'n', long, default_value = "20")]
+        count: usize,
+
+        /// only commands run in the git repository of the current directory (or in this directory, outside one)
+        #[arg(long)]
+        here: bool,
+
+        /// print one json object per command
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// list recorded sessions from the local index, newest first
+    Sessions {
+        /// only sessions that ran commands in this directory's git repository (e.g. `--project .`)
+        #[arg(long)]
+        project: Option<PathBuf>,
+
+        /// number of sessions to show
+        #[arg(short = 'n', long, default_value = "20")]
+        limit: usize,
+
+        /// print one json object per session
+        #[arg(long)]
+        json: bool,
+
+        /// show times in the local time zone rather than utc
+        #[arg(long)]
+        local: bool,
+    },
+
+    /// search the local index for commands containing text
+    /// (ranked, over output too, when built with the fulltext feature)
+    Search {
+        /// text to look for
+        text: String,
+
+        /// maximum number of results, newest first
+        #[arg(short = 'n', long, default_value = "50")]
+        limit: usize,
+
+        /// print one json object per result
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// search the recorded output of commands, for when you remember the error rather than the command
+    GrepOutput {
+        /// regular expression to look for in each line of output
+        pattern: String,
+
+        /// match case-insensitively
+        #[arg(short = 'i', long)]
+        ignore_case: bool,
+
+        /// take the pattern as plain text rather than a regular expression
+        #[arg(short = 'F', long)]
+        fixed_strings: bool,
+
+        /// lines of context to show around each match
+        #[arg(short = 'C', long, default_value = "0")]
+        context: usize,
+
+        /// only this session
+        #[arg(long)]
+        session: Option<String>,
+
+        /// only commands run in the git repository of the current directory (or in this directory, outside one)
+        #[arg(long)]
+        here: bool,
+
+        /// maximum number of commands to show, newest first
+        #[arg(short = 'n', long, default_value = "50")]
+        limit: usize,
+
+        /// print one json object per command
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// run a read-only sql query over the local index (table `commands`)
+    Query {
+        /// sql statement, e.g. "SELECT cmd, count(*) FROM commands WHERE exit_code != 0 GROUP BY cmd"
+        sql: String,
+
+        /// print one json object per row
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// summarize recent sessions: failures, slowest commands and time spent
+    Report {
+        /// start of the period (rfc3339, yyyy-mm-dd, or an age like 12h, 1d)
+        #[arg(long, default_value = "1d")]
+        since: String,
+
+        /// mail the report using the [report] smtp settings instead of printing it
+        #[arg(long)]
+        email: bool,
+    },
+
+    /// merge the commands of every session in a time range into one chronological list
+    Timeline {
+        /// start of the range (rfc3339, yyyy-mm-dd, local "yyyy-mm-dd hh:mm", today, yesterday, or an age like 3h)
+        #[arg(long, default_value = "today")]
+        since: String,
+
+        /// end of the range, same formats as --since (defaults to now)
+        #[arg(long)]
+        until: Option<String>,
+
+        /// list each host's commands on their own instead of interleaved
+        #[arg(long)]
+        by_host: bool,
+
+        /// print one json object per command
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// duration statistics and trend of the recorded runs of a command, e.g. `recli bench "cargo build"`
+    Bench {
+        /// text the command line contains (a regex with --regex)
+        pattern: String,
+
+        /// treat the pattern as a regular expression
+        #[arg(long)]
+        regex: bool,
+
+        /// only runs since then (rfc3339, yyyy-mm-dd, or an age like 30d)
+        #[arg(long)]
+        since: Option<String>,
+
+        /// only runs in the git repository of the current directory
+        #[arg(long)]
+        here: bool,
+
+        /// count runs that failed too
+        #[arg(long)]
+        include_failed: bool,
+
+        /// print the statistics as json
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// list the recorded commands that modified, created or deleted a file (needs track_files)
+    Changed {
+        /// file to look up
+        path: PathBuf,
+
+        /// print one json object per command
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// redact a recorded session after the fact: replace what matches a pattern in its commands,
+    /// output sidecars and casts
+    Scrub {
+        /// session id
+        session: String,
+
+        /// regular expression for what to remove, e.g. 'ghp_[A-Za-z0-9]{36}'
+        #[arg(short = 'p', long)]
+        pattern: String,
+
+        /// what to put in its place; $1, or ${1} when letters follow, refers to a group of the pattern
+        #[arg(long, default_value = "****")]
+        replace: String,
+
+        /// only report what would be replaced
+        #[arg(long)]
+        dry_run: bool,
+
+        /// upload the scrubbed session again, replacing the copy in the cloud
+        #[arg(long)]
+        upload: bool,
+    },
+
+    /// refresh the local index from recorded sessions
+    Sync {
+        /// also merge in commands this user uploaded from other machines
+        #[arg(long)]
+        pull: bool,
+
+        /// list what would be sent to each sink, and send nothing
+        #[arg(long, conflicts_with = "pull")]
+        dry_run: bool,
+
+        /// with --dry-run, print the json documents as they would be sent
+        #[arg(long, requires = "dry_run")]
+        show: bool,
+
+        /// with --dry-run, preview this session for every sink, configured or not
+        #[arg(long, requires = "dry_run")]
+        session: Option<String>,
+    },
+
+    /// check the config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+
+    /// check the upload destinations: cosmos, the team server and the share targets
+    Sinks {
+        #[command(subcommand)]
+        command: SinksCommands,
+    },
+
+    /// work with sessions uploaded to cosmos db
+    Cloud {
+        #[command(subcommand)]
+        command: CloudCommands,
+    },
+
+    /// record a shell or command run inside a docker or podman container
+    #[command(name = "exec")]
+    ContainerExec {
+        /// docker container to run in
+        #[arg(long, value_name = "CONTAINER", required_unless_present = "podman", conflicts_with = "podman")]
+        docker: Option<String>,
+
+        /// podman container to run in
+        #[arg(long, value_name = "CONTAINER")]
+        podman: Option<String>,
+
+        /// user to run as inside the container
+        #[arg(short, long)]
+        user: Option<String>,
+
+        /// record even inside a shell another recli session already records, which then records
+        /// the output of this one as well
+        #[arg(long)]
+        force_nested: bool,
+
+        /// command to run; without one an interactive shell is started (--shell, or sh)
+        #[arg(last = true, value_name = "CMD")]
+        command: Vec<String>,
+    },
+
+    /// record an ssh session to another host, kept locally
+    Ssh {
+        /// where to connect, as ssh takes it (user@host or a host alias)
+        destination: String,
+
+        /// copy recli's shell hook to ~/.recli on the host first and start the shell with it,
+        /// so commands are recorded as exactly as local ones
+        #[arg(long)]
+        install_hook: bool,
+
+        /// record even inside a shell another recli session already records, which then records
+        /// the output of this one as well
+        #[arg(long)]
+        force_nested: bool,
+
+        /// options passed on to ssh, e.g. `-- -p 2222 -i key`
+        #[arg(last = true, value_name = "SSH_OPTIONS")]
+        options: Vec<String>,
+    },
+
+    /// record panes of a running tmux server without restarting what runs in them
+    Tmux {
+        #[command(subcommand)]
+        command: TmuxCommands,
+    },
+
+    /// change how much the active session writes to recli's diagnostics log, without restarting it:
+    /// a level (error, warn, info, debug, trace) or a RUST_LOG-style filter such as recli::pty=trace;
+    /// without one, print the filter in effect
+    LogLevel {
+        filter: Option<String>,
+    },
+
+    /// show recli's own diagnostics log
+    Logs {
+        /// number of trailing lines to show
+        #[arg(short = 'n', long, default_value = "50")]
+        count: usize,
+
+        /// print the log directory instead of its contents
+        #[arg(long)]
+        path: bool,
+    },
+
+    /// remove what recli set up: its shell hooks, the zsh bootstrap it points ZDOTDIR at, pid files
+    /// and control sockets. Recordings and settings stay unless asked for
+    Uninstall {
+        /// also remove the recorded sessions, the search indexes, the upload outbox and recli's own logs
+        #[arg(long)]
+        data: bool,
+
+        /// remove everything under ~/.recli, config and keys included, and the recordings wherever they are
+        #[arg(long, conflicts_with = "data")]
+        all: bool,
+
+        /// list what would be removed without removing it
+        #[arg(long)]
+        dry_run: bool,
+
+        /// don't ask before removing recorded data
+        #[arg(short = 'y', long)]
+        yes: bool,
+    },
+
+    /// print a shell completion script to stdout
+    Completions {
+        /// target shell
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+
+    /// complete a command line from recorded history (used by the shell snippets)
+    #[command(name = "complete-line")]
+    CompleteLine {
+        /// text typed so far
+        #[arg(default_value = "")]
+        prefix: String,
+
+        /// directory whose history ranks higher (defaults to the current directory)
+        #[arg(long)]
+        cwd: Option<String>,
+
+        /// maximum number of suggestions
+        #[arg(short = 'n', long, default_value = "10")]
+        count: usize,
+
+        /// print the key binding snippet for this shell instead
+        #[arg(long, value_name = "SHELL", value_parser = ["bash", "zsh"])]
+        init: Option<String>,
+    },
+
+    /// pick a recorded command with a fuzzy finder, previewing its output; prints the choice
+    Pick {
+        /// text to start the search with
+        #[arg(default_value = "")]
+        query: String,
+
+        /// only commands run in the git repository of the current directory
+        #[arg(long)]
+        here: bool,
+
+        /// print the Ctrl-R binding snippet for this shell instead
+        #[arg(long, value_name = "SHELL", value_parser = ["bash", "zsh"])]
+        init: Option<String>,
+    },
+
+    /// play a session back in the terminal, rendered at the size it was recorded at; space pauses,
+    /// +/- change the speed, n skips to the next command, q quits
+    Replay {
+        /// session id, the latest session when left out
+        session: Option<String>,
+
+        /// playback speed, 2 plays twice as fast
+        #[arg(long, default_value_t = 1.0)]
+        speed: f64,
+
+        /// cut pauses down to this many seconds, 0 keeps them as recorded
+        #[arg(long, value_name = "SECONDS", default_value_t = 2.0)]
+        idle_limit: f64,
+
+        /// write the recorded output as it is instead of rendering it at the recorded size
+        #[arg(long)]
+        raw: bool,
+    },
+
+    /// list a session's commands, numbered, or show the screen as it was at one of them
+    Show {
+        /// session id, the latest session when left out
+        session: Option<String>,
+
+        /// render what the terminal looked like when command N (from 1) finished
+        #[arg(long, value_name = "N")]
+        screen_at: Option<usize>,
+
+        /// show times in the local time zone rather than utc
+        #[arg(long)]
+        local: bool,
+    },
+
+    /// propose likely next commands, from what followed the previous one in recorded history
+    Suggest {
+        /// only suggestions starting with this text
+        #[arg(default_value = "")]
+        prefix: String,
+
+        /// the command that just ran (defaults to the last one of the active session)
+        #[arg(long, value_name = "CMD")]
+        after: Option<String>,
+
+        /// directory to suggest for (defaults to the current directory)
+        #[arg(long)]
+        cwd: Option<String>,
+
+        /// maximum number of suggestions
+        #[arg(short = 'n', long, default_value = "5")]
+        count: usize,
+
+        /// print one json object per suggestion, with its score
+        #[arg(long)]
+        json: bool,
+
+        /// print the key binding snippet for this shell instead
+        #[arg(long, value_name = "SHELL", value_parser = ["bash", "zsh"])]
+        init: Option<String>,
+    },
+
+    /// write a recorded session out in another format
+    Export {
+        /// id of the session (defaults to the most recent one)
+        session: Option<String>,
+
+        /// output format
+        #[arg(long, default_value = "html", value_parser = ["html", "svg", "gif", "webm", "parquet"])]
+        format: String,
+
+        /// include every recorded session (parquet only)
+        #[arg(long, conflicts_with = "session")]
+        all: bool,
+
+        /// file to write to instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+
+    /// upload a session as an html page and asciicast and print a read-only link to it
+    Share {
+        /// id of the session (defaults to the most recent one)
+        session: Option<String>,
+
+        /// where to upload (defaults to target under [share], or the only one set up)
+        #[arg(long, value_parser = ["azure", "s3", "gist"])]
+        target: Option<String>,
+
+        /// how long the link works, e.g. 24h or 3d (s3 only, at most 7d)
+        #[arg(long)]
+        expires: Option<String>,
+    },
+
+    /// list installed plugins (`recli-<name>` executables in ~/.recli/plugins)
+    Plugins,
+
+    /// run a single command and record it, e.g. `recli cargo build`; `recli -- <cmd>` for one that
+    /// starts with `-` or is named like a subcommand. `recli <name>` runs the plugin of that name
+    /// instead when one is installed
+    #[command(external_subcommand)]
+    Exec(Vec<String>),
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum CloudCommands {
+    /// search recorded commands across uploaded sessions
+    Search(CloudSearchArgs),
+
+    /// download an uploaded session into the local logs directory
+    Pull {
+        /// id of the session to fetch
+        session_id: String,
+
+        /// replace an existing local copy
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// print an uploaded session as json, decrypting its command lines and output
+    Decrypt {
+        /// id of the session
+        session_id: String,
+
+        /// file to write to instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+
+    /// delete the `_recli_ping_*` documents older versions wrote next to every session
+    PrunePings {
+        /// list them without deleting anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ConfigCommands {
+    /// report unknown keys, values of the wrong type and conflicting settings, with line numbers
+    Validate {
+        /// print the problems as one json object
+        #[arg(long)]
+        json: bool,
+    },
+    /// have the recording session read the config again; filters, watch, hooks and the log level
+    /// change right away, other sections with the next session
+    Reload,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum SinksCommands {
+    /// write a canary to each configured sink and delete it again, reporting latency and errors
+    Test {
+        /// only this sink: cosmos, team, azure, s3 or gist
+        sink: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum SnippetsCommands {
+    /// list the starred commands
+    List {
+        /// print one json object per snippet
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// run a starred command through $SHELL, filling in its placeholders
+    Run {
+        /// name of the snippet
+        name: String,
+
+        /// placeholder values as NAME=VALUE; on a terminal the others are asked for
+        #[arg(value_name = "NAME=VALUE")]
+        values: Vec<String>,
+
+        /// print the command instead of running it
+        #[arg(long)]
+        print: bool,
+    },
+
+    /// remove a starred command
+    Remove {
+        /// name of the snippet
+        name: String,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum TmuxCommands {
+    /// start recording a pane's output through tmux pipe-pane, one session per pane
+    Attach {
+        /// pane to record (defaults to the current one)
+        #[arg(short, long, value_name = "PANE")]
+        target: Option<String>,
+
+        /// every pane of the tmux session instead of one
+        #[arg(long)]
+        all: bool,
+    },
+
+    /// stop recording a pane and close its session
+    Detach {
+        /// pane to stop recording (defaults to the current one)
+        #[arg(short, long, value_name = "PANE")]
+        target: Option<String>,
+
+        /// every pane of the tmux session instead of one
+        #[arg(long)]
+        all: bool,
+    },
+
+    /// read a pane's output from stdin; run by tmux pipe-pane
+    #[command(hide = true)]
+    Record {
+        #[arg(long)]
+        pane: String,
+        #[arg(long)]
+        shell: String,
+        #[arg(long)]
+        cols: u16,
+        #[arg(long)]
+        rows: u16,
+    },
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CloudSearchArgs {
+    /// only commands containing this text
+    pub contains: Option<String>,
+
+    /// only sessions recorded on this host
+    #[arg(long)]
+    pub host: Option<String>,
+
+    /// only sessions recorded by this user
+    #[arg(long)]
+    pub user: Option<String>,
+
+    /// start of the time range (rfc3339, yyyy-mm-dd, or an age like 2h, 3d)
+    #[arg(long)]
+    pub since: Option<String>,
+
+    /// end of the time range, same formats as --since
+    #[arg(long)]
+    pub until: Option<String>,
+
+    /// only commands that exited with this code
+    #[arg(long, allow_hyphen_values = true)]
+    pub exit_code: Option<i32>,
+
+    /// workspace to search (defaults to the configured one)
+    #[arg(long)]
+    pub workspace: Option<String>,
+
+    /// maximum number of results, newest first
+    #[arg(short = 'n', long, default_value = "50")]
+    pub limit: usize,
+
+    /// print one json object per result
+    #[arg(long)]
+    pub json: bool,
+}
+
+impl Cli {
+    /// parse command line arguments
+    pub fn parse_args() -> Self {
+        // `recli [OPTIONS] -- <cmd>` runs <cmd> as `recli <cmd>` would, for commands that start
+        // with `-` or share a subcommand's name
+        let args: Vec<std::ffi::OsString> = std::env::args_os().collect();
+        if let Some(pos) = args.iter().position(|a| a == "--") {
+            if let Ok(mut cli) = Cli::try_parse_from(&args[..pos]) {
+                if cli.command.is_none() && pos + 1 < args.len() {
+                    let command = args[pos + 1..].iter().map(|a| a.to_string_lossy().to_string()).collect();
+                    cli.command = Some(RecliCommands::Exec(command));
+                    return cli;
+                }
+            }
+        }
+        Cli::parse()
+    }
+
+    /// get the shell to use with fallback logic
+    pub fn get_shell(&self) -> String {
+        self.shell
+            .clone()
+            .unwrap_or_else(|| std::env::var("SHELL").unwrap_or_else(|_| "/bin/bash".to_string()))
+    }
+
+    /// write a completion script for `shell` covering every subcommand and flag
+    pub fn print_completions(shell: Shell) {
+        let mut cmd = Cli::command();
+        let name = cmd.get_name().to_string();
+        clap_complete::generate(shell, &mut cmd, name, &mut std::io::stdout());
+    }
+}
@@ -0,0 +1,846 @@
+use crate::filters::{FilterChain, Verdict, REDACTED_TAG};
+use crate::config::{DetectionStrategy, RecordMode};
+use crate::exit_signal::signal_of_exit_code;
+use crate::io::{strip_ansi, ErrorHighlighter, OscEvent, OscScanner, OutputWatch};
+use crate::policy::{RecordingPolicy, METADATA_ONLY_TAG};
+use crate::session::{LogEvent, SessionManager};
+use crate::util::time::format_duration_ms;
+use std::sync::{Arc, Mutex};
+use regex::Regex;
+use std::time::{Duration, Instant};
+
+// prompt-regex patterns when none are configured: user@host:dir$, plain $ / # / %, starship's ❯
+const DEFAULT_PROMPT_PATTERNS: &[&str] = &[r"^\S+@\S+[^$#%]*[$#%] ", r"^[^\s$#%]*[$#%] ", r"^[❯λ›] "];
+// longest prompt line or typed command line looked at
+const LINE_LIMIT: usize = 4096;
+
+/// how the detector finds where commands start and end
+#[derive(Debug, Clone, Default)]
+pub enum Detection {
+    /// RECLI_ markers written by recli's zsh hook
+    #[default]
+    HookMarkers,
+    /// osc 133 marks: A prompt, B typed input, C output, D;exit finished
+    Osc133,
+    /// lines matching one of the prompt patterns; the command is the rest of the line
+    Prompt(Vec<Regex>),
+}
+
+impl Detection {
+    pub fn new(strategy: DetectionStrategy, patterns: &[String]) -> std::result::Result<Self, String> {
+        Ok(match strategy {
+            DetectionStrategy::HookMarkers => Self::HookMarkers,
+            DetectionStrategy::Osc133 => Self::Osc133,
+            DetectionStrategy::PromptRegex => {
+                let patterns: Vec<&str> = if patterns.is_empty() {
+                    DEFAULT_PROMPT_PATTERNS.to_vec()
+                } else {
+                    patterns.iter().map(String::as_str).collect()
+                };
+                let regexes = patterns
+                    .iter()
+                    .map(|p| Regex::new(p).map_err(|e| format!("invalid prompt pattern {:?}: {}", p, e)))
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                Self::Prompt(regexes)
+            }
+        })
+    }
+
+    /// whether the shell has to load recli's hook
+    pub fn uses_hook(&self) -> bool {
+        matches!(self, Self::HookMarkers)
+    }
+}
+
+// streaming scanner that preserves bytes and strips recli markers without corrupting utf8
+#[derive(Debug)]
+pub struct CommandDetector {
+    // holds tail bytes if a marker starts near the end of a chunk
+    partial_marker: Option<Vec<u8>>,
+    in_command: bool,
+    // suppress logging until first newline after start to avoid echoing the typed line
+    skip_until_eol: bool,
+    // time when skip started to avoid eating real output if no newline arrives
+    skip_started_at: Option<Instant>,
+    pending_exit_code: Option<i32>,
+    pending_pipestatus: Option<Vec<i32>>,
+    pending_pwd: Option<String>,
+    last_pwd: Option<String>,
+    // lines of a multi-line command, announced ahead of its start marker
+    cmd_lines: Vec<String>,
+    session_manager: Arc<Mutex<SessionManager>>,
+    // while paused, markers are still tracked but output is not logged
+    paused: bool,
+    // title and osc 7 cwd reports, passed through untouched
+    osc: OscScanner,
+    // wasm output filters of the session's profile
+    filters: Option<FilterChain>,
+    // strongest classifier verdict already tagged on the running command
+    flagged: Option<Verdict>,
+    // the running command was already tagged as redacted
+    redacted: bool,
+    // the last chunk was kept out of the recording because a redacting filter failed on it
+    filter_failing: bool,
+    // marks error lines on screen only
+    highlighter: Option<ErrorHighlighter>,
+    // print a line with exit code and duration after each command
+    summary: bool,
+    // hint shown after a failed command on how to note why, e.g. "ctrl-x n adds a note"
+    note_prompt: Option<String>,
+    started_at: Option<Instant>,
+    // summary and watch alert lines to draw, at offsets into the current chunk's display bytes
+    summaries: Vec<(usize, String)>,
+    // the last byte shown didn't end a line
+    line_open: bool,
+    last_exit_code: Option<i32>,
+    // [watch] patterns looked for in command output
+    watch: Option<OutputWatch>,
+    policy: RecordingPolicy,
+    // what the policy allows of the running command
+    mode: RecordMode,
+    // this chunk held output the policy keeps out of the recording
+    withheld: bool,
+    detection: Detection,
+    // the line being drawn, for prompt-regex
+    line: Vec<u8>,
+    // a carriage return was seen; the line starts over unless a newline follows
+    line_cr: bool,
+    // the line showed a bare prompt, so the next newline runs whatever was typed after it
+    at_prompt: bool,
+    // echo of the command being typed, between osc 133 B and C
+    typed: Option<Vec<u8>>,
+    // offset into the chunk's output where a command found without the hook ended, and its exit code
+    end_at: Option<(usize, i32)>,
+    // the same for where one started
+    start_at: Option<usize>,
+    // offset into the chunk's output where the hook reported the end of the running command
+    marker_end_at: Option<usize>,
+    // the line of the running command, and of those logged as ended since the cast last asked
+    running: Option<String>,
+    ended: Vec<String>,
+    // the hook said which version it is, or recli already warned that it didn't
+    hook_checked: bool,
+    // namespace the hook of a nested session puts before its markers
+    marker_ns: Option<String>,
+}
+
+impl CommandDetector {
+    pub fn new(session_manager: Arc<Mutex<SessionManager>>) -> Self {
+        Self {
+            partial_marker: None,
+            in_command: false,
+            skip_until_eol: false,
+            skip_started_at: None,
+            pending_exit_code: None,
+            pending_pipestatus: None,
+            pending_pwd: None,
+            last_pwd: None,
+            cmd_lines: Vec::new(),
+            session_manager,
+            paused: false,
+            osc: OscScanner::default(),
+            filters: None,
+            flagged: None,
+            redacted: false,
+            filter_failing: false,
+            highlighter: None,
+            summary: false,
+            note_prompt: None,
+            started_at: None,
+            summaries: Vec::new(),
+            line_open: false,
+            last_exit_code: None,
+            watch: None,
+            policy: RecordingPolicy::default(),
+            mode: RecordMode::Full,
+            withheld: false,
+            detection: Detection::HookMarkers,
+            line: Vec::new(),
+            line_cr: false,
+            at_prompt: false,
+            typed: None,
+            end_at: None,
+            start_at: None,
+            marker_end_at: None,
+            running: None,
+            ended: Vec::new(),
+            hook_checked: false,
+            marker_ns: None,
+        }
+    }
+
+    /// find commands with `detection` instead of the hook markers
+    pub fn with_detection(mut self, detection: Detection) -> Self {
+        self.detection = detection;
+        self
+    }
+
+    /// record commands only as far as `policy` allows
+    pub fn with_policy(mut self, policy: RecordingPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// whether the last chunk held output of a command the policy records without it;
+    /// such chunks must not go into the session cast either
+    pub fn output_withheld(&self) -> bool {
+        self.withheld
+    }
+
+    /// lines of the commands logged as ended since the last call, which the cast marks so that replays
+    /// can find them
+    pub fn take_ended(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.ended)
+    }
+
+    // a command whose output filters redacted is marked without its line, which could hold the secret
+    fn marker_label(&self, cmd: String) -> String {
+        match self.redacted {
+            true => REDACTED_TAG.to_string(),
+            false => cmd,
+        }
+    }
+
+    // the running command is in the log at all
+    fn logged(&self) -> bool {
+        self.in_command && self.mode != RecordMode::Skip
+    }
+
+    /// act only on markers written as `@<namespace> RECLI_...`, as the hook in a nested session's
+    /// shell writes them; others are dropped from the output unused
+    pub fn with_marker_namespace(mut self, namespace: Option<String>) -> Self {
+        self.marker_ns = namespace;
+        self
+    }
+
+    /// what of a paste goes into the log: nothing while the running command's output is kept out by
+    /// the policy, otherwise the text as the output filters leave it
+    pub fn paste_text(&mut self, text: Vec<u8>) -> Option<String> {
+        if self.in_command && self.mode != RecordMode::Full {
+            return None;
+        }
+        let text = match self.filters.as_mut() {
+            Some(filters) => {
+                let filtered = filters.apply_text(text);
+                if filtered.withheld {
+                    return None;
+                }
+                filtered.bytes
+            }
+            None => text,
+        };
+        Some(String::from_utf8_lossy(&text).to_string())
+    }
+
+    /// run displayed and logged output through `filters`
+    pub fn with_filters(mut self, filters: Option<FilterChain>) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    /// mark error lines of command output on screen
+    pub fn with_error_highlighting(mut self, enabled: bool) -> Self {
+        self.highlighter = enabled.then(ErrorHighlighter::default);
+        self
+    }
+
+    /// show "↳ exit 1 · 3.2s · logged" after each command
+    pub fn with_command_summary(mut self, enabled: bool) -> Self {
+        self.summary = enabled;
+        self
+    }
+
+    /// after a failed command that was recorded, show `hint` on how to add a note to it
+    pub fn with_note_prompt(mut self, hint: Option<String>) -> Self {
+        self.note_prompt = hint;
+        self
+    }
+
+    /// alert and tag the command when its output matches a watched pattern
+    pub fn with_watch(mut self, watch: Option<OutputWatch>) -> Self {
+        self.watch = watch;
+        self
+    }
+
+    /// pause or resume logging of command output
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// swap in filters and watched patterns from a config read again mid-session
+    pub fn reconfigure(&mut self, filters: Option<FilterChain>, watch: Option<OutputWatch>) {
+        self.filters = filters;
+        self.watch = watch;
+    }
+
+    /// byte-preserving pass-through with in-band marker stripping
+    pub fn process_output(&mut self, data: &[u8]) -> Vec<u8> {
+        self.withheld = self.in_command && self.mode != RecordMode::Full;
+        for event in self.osc.observe(data) {
+            match event {
+                // shells without the hook still report their cwd this way
+                OscEvent::Cwd(path) => self.last_pwd = Some(path),
+                OscEvent::Title(title) => {
+                    if let Ok(sm) = self.session_manager.lock() {
+                        sm.send_log_event(LogEvent::Title { title });
+                    }
+                }
+            }
+        }
+
+        // stitch any partial marker from last time
+        let mut buf = Vec::with_capacity(
+            self.partial_marker.as_ref().map(|v| v.len()).unwrap_or(0) + data.len(),
+        );
+        if let Some(mut tail) = self.partial_marker.take() {
+            buf.append(&mut tail);
+        }
+        buf.extend_from_slice(data);
+
+        let mut out: Vec<u8> = Vec::with_capacity(buf.len());
+        let mut i = 0;
+    while i < buf.len() {
+            if buf[i] == 0x1e {
+                // found rs: look for newline or cr that ends the marker
+                let mut j = i + 1;
+                while j < buf.len() && buf[j] != b'\n' && buf[j] != b'\r' {
+                    j += 1;
+                }
+                if j >= buf.len() {
+                    // incomplete marker: stash and stop
+                    self.partial_marker = Some(buf[i..].to_vec());
+                    break;
+                }
+
+                // parse marker payload between i+1 .. j as ascii
+                let marker = String::from_utf8_lossy(&buf[i + 1..j]);
+                let was_in_command = self.in_command;
+                self.handle_marker(&marker, out.len());
+                if was_in_command && !self.in_command {
+                    if let Some(lines) = self.after_command_lines() {
+                        self.summaries.push((out.len(), lines));
+                    }
+                }
+
+                // skip marker and its line ending; through a pty that is \r\n, after which
+                // there is no rest of the line left to skip
+                i = j + 1;
+                if buf[j] == b'\n' || (buf[j] == b'\r' && buf.get(i) == Some(&b'\n')) {
+                    i += (buf[j] == b'\r') as usize;
+                    self.skip_until_eol = false;
+                    self.skip_started_at = None;
+                }
+                continue;
+            }
+
+            // osc 133 marks are passed on, terminals and recordings understand them as well
+            if buf[i] == 0x1b && matches!(self.detection, Detection::Osc133) {
+                match osc133_at(&buf[i..]) {
+                    Osc133::Partial => {
+                        self.partial_marker = Some(buf[i..].to_vec());
+                        break;
+                    }
+                    Osc133::Mark(mark, arg, len) => {
+                        self.handle_osc133(mark, arg, out.len());
+                        out.extend_from_slice(&buf[i..i + len]);
+                        if mark == b'C' && self.in_command {
+                            self.start_at = Some(out.len());
+                        }
+                        i += len;
+                        continue;
+                    }
+                    Osc133::None => {}
+                }
+            }
+
+            // if a command just started, drop everything until we hit the first newline
+            if self.in_command && self.skip_until_eol {
+                // stop skipping either at newline or after a small grace window
+                let timed_out = self
+                    .skip_started_at
+                    .map(|t| t.elapsed() >= Duration::from_millis(50))
+                    .unwrap_or(false);
+                if buf[i] == b'\n' || buf[i] == b'\r' || timed_out {
+                    self.skip_until_eol = false;
+                    self.skip_started_at = None;
+                } else {
+                    i += 1;
+                    continue;
+                }
+            }
+
+            // normal byte; keep exact
+            out.push(buf[i]);
+            if let Some(typed) = self.typed.as_mut().filter(|t| t.len() < LINE_LIMIT) {
+                typed.push(buf[i]);
+            }
+            if matches!(self.detection, Detection::Prompt(_)) {
+                self.track_line(buf[i], out.len());
+            }
+            i += 1;
+        }
+
+        // a prompt drawn at the end of the chunk ends the running command
+        if matches!(self.detection, Detection::Prompt(_)) && !self.at_prompt {
+            let prompt_start = out.len().saturating_sub(self.line.len());
+            self.check_prompt(prompt_start);
+        }
+
+        // filters see exactly what is shown and logged, with markers already removed
+        let mut filter_withheld = false;
+        if let Some(filters) = self.filters.as_mut().filter(|_| !out.is_empty()) {
+            let filtered = filters.apply(out);
+            let failing = filters.failing().map(str::to_string);
+            let (verdict, rewritten) = (filtered.verdict, filtered.rewritten);
+            out = filtered.bytes;
+            // fail closed: what a redacting filter couldn't look at is shown but not recorded
+            filter_withheld = filtered.withheld;
+            if filter_withheld {
+                self.withheld = true;
+                if let Some(name) = failing.filter(|_| !self.filter_failing) {
+                    let notice = format!("\x1b[33m[recli] output filter {} failed; output is not recorded until it works again\x1b[0m\r\n", name);
+                    self.summaries.push((out.len(), notice));
+                }
+            }
+            self.filter_failing = filter_withheld;
+            if (rewritten || filter_withheld) && self.logged() && !self.redacted {
+                self.redacted = true;
+                if let Ok(sm) = self.session_manager.lock() {
+                    sm.send_log_event(LogEvent::Annotate { tag: REDACTED_TAG.to_string() });
+                }
+            }
+            if let Some(verdict) = verdict.filter(|v| self.logged() && Some(*v) > self.flagged) {
+                self.flagged = Some(verdict);
+                if let Ok(sm) = self.session_manager.lock() {
+                    sm.send_log_event(LogEvent::Annotate { tag: verdict.tag().to_string() });
+                }
+            }
+        }
+
+        // output that isn't logged isn't watched either
+        let output_logged = self.in_command && !self.paused && self.mode == RecordMode::Full && !filter_withheld;
+        if output_logged {
+            if let Some(watch) = self.watch.as_mut() {
+                for (pattern, line) in watch.scan(&out) {
+                    let bell = if watch.bell { "\x07" } else { "" };
+                    let alert = format!("{}\x1b[1;33m\u{2691} watch: /{}/ matched\x1b[0m\r\n", bell, pattern);
+                    self.summaries.push((out.len(), alert));
+                    if let Ok(sm) = self.session_manager.lock() {
+                        sm.send_log_event(LogEvent::Watch { tag: watch.tag.clone(), pattern, line });
+                    }
+                }
+            }
+        }
+
+        // a command keeps the output in front of the marker, mark or prompt that ended it
+        // and the output after the one that started it
+        self.marker_end_at = None;
+        let end_at = self.end_at.take().map(|(pos, exit_code)| (pos.min(out.len()), exit_code));
+        let logged_end = end_at.map_or(out.len(), |(pos, _)| pos);
+        let logged_start = self.start_at.take().unwrap_or(0).min(logged_end);
+
+        // stream-log display bytes during an active command
+    if output_logged && logged_start < logged_end {
+            if let Ok(sm) = self.session_manager.lock() {
+        sm.send_log_event(LogEvent::Output { data: out[logged_start..logged_end].to_vec() });
+            }
+        }
+        if let Some((pos, exit_code)) = end_at {
+            self.finish_detected(exit_code);
+            if let Some(lines) = self.after_command_lines() {
+                self.summaries.push((pos, lines));
+            }
+        }
+
+        // summary lines go in after logging so they are only ever shown
+        for (pos, text) in std::mem::take(&mut self.summaries).into_iter().rev() {
+            let line_open = if pos == 0 { self.line_open } else { out[pos - 1] != b'\n' };
+            let mut line = if line_open { b"\r\n".to_vec() } else { Vec::new() };
+            line.extend_from_slice(text.as_bytes());
+            out.splice(pos..pos, line);
+        }
+        if let Some(&last) = out.last() {
+            self.line_open = last != b'\n';
+        }
+
+        // the log already has the bytes, highlighting only changes what is shown
+        if let Some(highlighter) = self.highlighter.as_mut() {
+            return highlighter.process(&out, self.in_command);
+        }
+        out
+    }
+
+    // the summary and note hint lines to show once a command ended, if any
+    fn after_command_lines(&self) -> Option<String> {
+        let mut lines = String::new();
+        if self.summary {
+            lines.push_str(&self.summary_line());
+        }
+        let failed = self.last_exit_code.is_some_and(|code| code != 0);
+        let recorded = self.mode != RecordMode::Skip && !self.paused;
+        if let Some(hint) = self.note_prompt.as_ref().filter(|_| failed && recorded) {
+            lines.push_str(&format!("\x1b[2m\u{21b3} {}\x1b[0m\r\n", hint));
+        }
+        (!lines.is_empty()).then_some(lines)
+    }
+
+    // dim line confirming what was recorded for the command that just ended
+    fn summary_line(&self) -> String {
+        let exit_code = self.last_exit_code.unwrap_or(0);
+        let mut parts = vec![match signal_of_exit_code(exit_code) {
+            Some(signal) => format!("exit {} ({})", exit_code, signal),
+            None => format!("exit {}", exit_code),
+        }];
+        if let Some(start) = self.started_at {
+            parts.push(format_duration_ms(start.elapsed().as_millis() as u64));
+        }
+        let logged = match self.mode {
+            RecordMode::Skip => "not logged (policy)",
+            RecordMode::Metadata => "logged without output (policy)",
+            RecordMode::Full if self.paused => "not logged (paused)",
+            RecordMode::Full => "logged",
+        };
+        parts.push(logged.to_string());
+        let color = if exit_code == 0 { "\x1b[2m" } else { "\x1b[2;31m" };
+        format!("{}\u{21b3} {}\x1b[0m\r\n", color, parts.join(" \u{b7} "))
+    }
+
+    pub fn finish(&mut self) {
+        self.partial_marker = None;
+        if self.in_command {
+            let cwd = self.last_pwd.clone().unwrap_or_else(|| {
+                std::env::current_dir()
+                    .map(|p| p.to_string_lossy().to_string())
+                    .unwrap_or_else(|_| "/unknown".to_string())
+            });
+            self.send_end_event(0, cwd);
+            self.in_command = false;
+            self.pending_exit_code = None;
+            self.pending_pwd = None;
+        }
+    }
+
+    // osc 133: A prompt, B typed input follows, C the command runs, D;exit it finished
+    fn handle_osc133(&mut self, mark: u8, arg: Option<&str>, pos: usize) {
+        tracing::trace!(mark = %(mark as char), arg, "osc 133 mark");
+        match mark {
+            b'A' => {
+                // a new prompt without D: the command is over, how it went is unknown
+                self.end_detected(pos, 0);
+                self.typed = None;
+            }
+            b'B' => self.typed = Some(Vec::new()),
+            b'C' => {
+                let cmd = self.typed.take().map(|t| typed_text(&t)).unwrap_or_default();
+                let cmd = cmd.trim();
+                if !cmd.is_empty() {
+                    // the previous command ended in this very chunk
+                    if let Some((_, exit_code)) = self.end_at.take() {
+                        self.finish_detected(exit_code);
+                    }
+                    self.start_command(cmd.to_string());
+                }
+            }
+            b'D' => {
+                let exit_code = arg.and_then(|a| a.split(';').next()).and_then(|a| a.trim().parse().ok());
+                self.end_detected(pos, exit_code.unwrap_or(0));
+            }
+            _ => {}
+        }
+    }
+
+    // follow the line being drawn; enter after a prompt starts the command typed behind it,
+    // with its output from `pos` of the chunk on
+    fn track_line(&mut self, byte: u8, pos: usize) {
+        match byte {
+            b'\n' => {
+                if std::mem::take(&mut self.at_prompt) {
+                    let text = typed_text(&self.line);
+                    let cmd = self.prompt_match(&text).map(|(end, _)| text[end..].trim().to_string());
+                    if let Some(cmd) = cmd.filter(|c| !c.is_empty()) {
+                        self.start_command(cmd);
+                        self.start_at = Some(pos);
+                    }
+                }
+                self.line.clear();
+                self.line_cr = false;
+            }
+            b'\r' => self.line_cr = true,
+            _ => {
+                // a carriage return not followed by a newline redraws the line
+                if std::mem::take(&mut self.line_cr) {
+                    self.line.clear();
+                }
+                if self.line.len() < LINE_LIMIT {
+                    self.line.push(byte);
+                }
+            }
+        }
+    }
+
+    // the current line, starting at `pos` of the chunk's output, is nothing but a prompt
+    fn check_prompt(&mut self, pos: usize) {
+        let text = typed_text(&self.line);
+        if let Some((_, exit_code)) = self.prompt_match(&text).filter(|(end, _)| *end == text.len()) {
+            self.at_prompt = true;
+            self.end_detected(pos, exit_code.unwrap_or(0));
+        }
+    }
+
+    // where a prompt at the start of `text` ends, and the exit code its `exit` group shows
+    fn prompt_match(&self, text: &str) -> Option<(usize, Option<i32>)> {
+        let Detection::Prompt(patterns) = &self.detection else { return None };
+        patterns.iter().find_map(|re| {
+            let caps = re.captures(text)?;
+            let found = caps.get(0).filter(|m| m.start() == 0)?;
+            Some((found.end(), caps.name("exit").and_then(|m| m.as_str().parse().ok())))
+        })
+    }
+
+    // the running command ended at `pos` of the chunk's output; it is finished once that much is logged
+    fn end_detected(&mut self, pos: usize, exit_code: i32) {
+        if self.in_command && self.end_at.is_none() {
+            self.end_at = Some((pos, exit_code));
+        }
+    }
+
+    // end the running command at a mark, prompt or end marker, in the directory last reported
+    fn finish_detected(&mut self, exit_code: i32) {
+        if self.in_command {
+            self.pending_exit_code = Some(exit_code);
+            self.pending_pwd = Some(self.last_pwd.clone().unwrap_or_else(|| {
+                std::env::current_dir()
+                    .map(|p| p.to_string_lossy().to_string())
+                    .unwrap_or_else(|_| "/unknown".to_string())
+            }));
+            self.try_finish_when_ready();
+        }
+    }
+
+    fn handle_marker(&mut self, marker: &str, pos: usize) {
+        tracing::trace!(marker, "hook marker");
+        let marker = match (marker.strip_prefix('@').and_then(|m| m.split_once(' ')), self.marker_ns.as_deref()) {
+            (Some((ns, rest)), Some(ours)) if ns == ours => rest,
+            (None, None) => marker,
+            _ => {
+                tracing::debug!(marker, "marker of another session ignored");
+                return;
+            }
+        };
+        // without the hook, only its cwd reports are of use
+        if !self.detection.uses_hook() && !marker.starts_with("RECLI_PWD:") {
+            return;
+        }
+        if let Some(rest) = marker.strip_prefix("RECLI_LINE:") {
+            self.cmd_lines.push(rest.to_string());
+            return;
+        }
+        if let Some(rest) = marker.strip_prefix("RECLI_HOOK_VERSION:") {
+            self.hook_checked = true;
+            if let Some(warning) = hook_version_warning(rest) {
+                self.hook_warning(pos, &warning);
+            }
+            return;
+        }
+        if !self.hook_checked && marker.starts_with("RECLI_START:") {
+            // hooks before v9 (zsh) and v3 (bash) don't announce themselves
+            self.hook_checked = true;
+            self.hook_warning(pos, "the shell's recli hook is from an older version");
+        }
+        if let Some(rest) = marker.strip_prefix("RECLI_START:") {
+            // heredocs and continued lines are kept verbatim as one command
+            let cmd = if self.cmd_lines.is_empty() {
+                rest.to_string()
+            } else {
+                std::mem::take(&mut self.cmd_lines).join("\n")
+            };
+            self.start_command(cmd);
+            // what the chunk showed before, such as the command line's echo, isn't its output
+            self.start_at = Some(pos);
+            return;
+        }
+        if let Some(rest) = marker.strip_prefix("RECLI_END:") {
+            self.pending_exit_code = rest.trim().parse::<i32>().ok();
+            self.marker_end_at = Some(pos);
+            self.try_finish_when_ready();
+            return;
+        }
+        if let Some(rest) = marker.strip_prefix("RECLI_PWD:") {
+            let pwd = rest.to_string();
+            self.pending_pwd = Some(pwd.clone());
+            self.last_pwd = Some(pwd);
+            self.try_finish_when_ready();
+            return;
+        }
+        if let Some(rest) = marker.strip_prefix("RECLI_PIPE:") {
+            // expect format like: [0,1,0]
+            let s = rest.trim();
+            if let Some(inner) = s.strip_prefix('[').and_then(|x| x.strip_suffix(']')) {
+                let mut v = Vec::new();
+                for part in inner.split(',') {
+                    if let Ok(n) = part.trim().parse::<i32>() {
+                        v.push(n);
+                    }
+                }
+                if !v.is_empty() {
+                    self.pending_pipestatus = Some(v);
+                    self.try_finish_when_ready();
+                }
+            }
+        }
+        // ignore unknown markers
+    }
+
+    // said once per shell, in the diagnostics log and on screen where the marker was
+    fn hook_warning(&mut self, pos: usize, warning: &str) {
+        let warning = format!("{}; pipeline exit codes and durations may be missing, start a new session to load the current one", warning);
+        tracing::warn!("{}", warning);
+        self.summaries.push((pos, format!("\x1b[33m[recli] {}\x1b[0m\r\n", warning)));
+    }
+
+    fn start_command(&mut self, cmd: String) {
+        if self.in_command {
+            let cwd = self.last_pwd.clone().unwrap_or_else(|| {
+                std::env::current_dir()
+                    .map(|p| p.to_string_lossy().to_string())
+                    .unwrap_or_else(|_| "/unknown".to_string())
+            });
+            self.send_end_event(0, cwd);
+        }
+    self.in_command = true;
+        self.flagged = None;
+        self.redacted = false;
+        if let Some(watch) = self.watch.as_mut() {
+            watch.reset();
+        }
+        self.started_at = Some(Instant::now());
+        // only the hook announces a command before its line is echoed
+        self.skip_until_eol = self.detection.uses_hook();
+        self.skip_started_at = Some(Instant::now());
+        self.pending_exit_code = None;
+    self.pending_pipestatus = None;
+        self.pending_pwd = None;
+
+        self.mode = self.policy.mode(&cmd);
+        if self.mode != RecordMode::Full {
+            self.withheld = true;
+            tracing::debug!(mode = ?self.mode, "command withheld by recording policy");
+        }
+        if self.mode == RecordMode::Skip {
+            return;
+        }
+
+        let cwd = self.last_pwd.clone().unwrap_or_else(|| {
+            std::env::current_dir()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_else(|_| "/unknown".to_string())
+        });
+        self.running = Some(cmd.clone());
+        if let Ok(sm) = self.session_manager.lock() {
+            sm.send_log_event(LogEvent::CommandStart { cmd, cwd });
+            if self.mode == RecordMode::Metadata {
+                sm.send_log_event(LogEvent::Annotate { tag: METADATA_ONLY_TAG.to_string() });
+            }
+        }
+    }
+
+    fn try_finish_when_ready(&mut self) {
+        if self.in_command {
+            if let (Some(ec), Some(pwd)) = (self.pending_exit_code, self.pending_pwd.clone()) {
+                // the end came in the chunk being read; it is finished once that chunk's output is logged
+                if let Some(pos) = self.marker_end_at.take() {
+                    self.end_at = Some((pos, ec));
+                    return;
+                }
+                let pipe = self.pending_pipestatus.clone();
+                self.last_exit_code = Some(ec);
+                self.send_end_event_with_pipe(ec, pipe, pwd);
+                self.in_command = false;
+                self.pending_exit_code = None;
+                self.pending_pipestatus = None;
+                self.pending_pwd = None;
+            }
+        }
+    }
+
+    fn send_end_event(&mut self, exit_code: i32, cwd: String) {
+        if !self.logged() {
+            return;
+        }
+        if let Ok(sm) = self.session_manager.lock() {
+            sm.send_log_event(LogEvent::CommandEnd { exit_code, pipestatus: None, cwd });
+        }
+        self.ended.extend(self.running.take().map(|cmd| self.marker_label(cmd)));
+    }
+
+    fn send_end_event_with_pipe(&mut self, exit_code: i32, pipestatus: Option<Vec<i32>>, cwd: String) {
+        if !self.logged() {
+            return;
+        }
+        if let Ok(sm) = self.session_manager.lock() {
+            sm.send_log_event(LogEvent::CommandEnd { exit_code, pipestatus, cwd });
+        }
+        self.ended.extend(self.running.take().map(|cmd| self.marker_label(cmd)));
+    }
+}
+
+enum Osc133<'a> {
+    None,
+    // the start of a mark, cut off at the end of the chunk
+    Partial,
+    // the mark letter, its argument and the length of the sequence
+    Mark(u8, Option<&'a str>, usize),
+}
+
+// an osc 133 sequence at the start of `buf`
+fn osc133_at(buf: &[u8]) -> Osc133<'_> {
+    const PREFIX: &[u8] = b"\x1b]133;";
+    // marks are short; anything longer isn't one
+    const MAX_LEN: usize = 64;
+    if buf.len() < PREFIX.len() {
+        return if PREFIX.starts_with(buf) { Osc133::Partial } else { Osc133::None };
+    }
+    if !buf.starts_with(PREFIX) {
+        return Osc133::None;
+    }
+    let body = &buf[PREFIX.len()..buf.len().min(MAX_LEN)];
+    let Some((end, term_len)) = body.iter().enumerate().find_map(|(k, b)| match b {
+        0x07 => Some((k, 1)),
+        0x1b if body.get(k + 1) == Some(&b'\\') => Some((k, 2)),
+        _ => None,
+    }) else {
+        return if buf.len() < MAX_LEN { Osc133::Partial } else { Osc133::None };
+    };
+    let Ok(payload) = std::str::from_utf8(&body[..end]) else { return Osc133::None };
+    let Some(&mark) = payload.as_bytes().first() else { return Osc133::None };
+    let arg = payload.get(1..).and_then(|rest| rest.strip_prefix(';'));
+    Osc133::Mark(mark, arg, PREFIX.len() + end + term_len)
+}
+
+// what a line of echoed typing reads as: colors dropped, backspaces applied
+fn typed_text(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for c in strip_ansi(bytes).chars() {
+        match c {
+            '\x08' | '\x7f' => {
+                text.pop();
+            }
+            c if c.is_control() => {}
+            c => text.push(c),
+        }
+    }
+    text
+}
+
+// what to say about a hook announcing itself as `<shell>:<version>`, if it is older than the one
+// recli writes for that shell
+fn hook_version_warning(announced: &str) -> Option<String> {
+    let (shell, version) = announced.trim().split_once(':')?;
+    let version: u32 = version.parse().ok()?;
+    let current = match shell {
+        "zsh" => crate::pty::ZSH_HOOK_VERSION,
+        "bash" => crate::hook_only::BASH_HOOK_VERSION,
+        _ => return None,
+    };
+    (version < current).then(|| format!("the shell's recli hook is v{}, this recli writes v{}", version, current))
+}
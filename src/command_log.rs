@@ -0,0 +1,771 @@
+use crate::error::Result;
+use crate::exit_signal::ExitSignal;
+use crate::file_changes::FileChanges;
+use crate::network::NetworkSummary;
+use crate::process_tree::ProcessSummary;
+use crate::util::time;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandEntry {
+    // uuidv7, the same in commands.json, the index and uploaded documents
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    pub cmd: String, // command
+    pub cwd: String, // current working directory
+    #[serde(deserialize_with = "time::de_stamp")]
+    pub timestamp: String,
+    // place in the session, from 1; keeps commands in order when the clock jumps
+    #[serde(default)]
+    pub seq: u64,
+    pub exit_code: i32,
+    pub output_preview: String,
+    pub output_path: Option<String>,
+    pub pipestatus: Option<Vec<i32>>,
+    pub duration_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    // terminal title while the command ran
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    // stderr on its own when split_stderr is on; output_preview still shows both streams
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr_path: Option<String>,
+    // git toplevel of the directory the command started in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    // files in the cwd the command changed, when track_files is on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files: Option<FileChanges>,
+    // when each part of output_path arrived, one OutputChunk per json line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunks_path: Option<String>,
+    // the longest the command went without printing anything, when long enough to matter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub longest_pause: Option<OutputPause>,
+    // the signal that killed the command, for exit codes of 128+n
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signal: Option<ExitSignal>,
+    // bytes moved and endpoints contacted while the command ran, when track_network is on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<NetworkSummary>,
+    // the processes the command ran, when track_processes is on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub processes: Option<ProcessSummary>,
+    // silences long enough to count as hung under [hung], each lasting until output resumed or the command ended
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stalls: Vec<OutputPause>,
+    // a line on why it went as it did, e.g. "expected, retried with sudo"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// a stretch of output that arrived `offset_ms` after its command started
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OutputChunk {
+    pub offset_ms: u64,
+    pub len: u64, // bytes, following the previous chunk in the .out file
+}
+
+/// a silence in a command's output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputPause {
+    pub at_ms: u64,   // when the last output before it arrived
+    pub ms: u64,      // how long nothing came
+    pub at_byte: u64, // how much output there was by then
+}
+
+/// text pasted into the session, kept apart from typed commands
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasteEntry {
+    #[serde(deserialize_with = "time::de_stamp")]
+    pub timestamp: String,
+    pub bytes: usize, // full size of the paste
+    // capped like the output preview; none when the paste was kept out of the log
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    pub during_cmd: Option<String>, // command running when it was pasted, if any
+}
+
+/// part of a session that began after the shell sat idle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Segment {
+    #[serde(deserialize_with = "time::de_stamp")]
+    pub started_at: String,
+    pub first_entry: usize, // index into entries of its first command
+    pub idle_ms: u64,       // length of the gap before it
+}
+
+/// terminal size from a given moment on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerminalSize {
+    #[serde(deserialize_with = "time::de_stamp")]
+    pub timestamp: String,
+    pub cols: u16,
+    pub rows: u16,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommandLog {
+    pub entries: Vec<CommandEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pastes: Vec<PasteEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub segments: Vec<Segment>,
+    // seq of the last command recorded, kept when entries are removed so none is reused
+    #[serde(default)]
+    pub last_seq: u64,
+    #[serde(skip)]
+    pub current_cmd: String,
+    #[serde(skip)]
+    pub current_preview: OutputPreview,
+    #[serde(skip)]
+    pub current_start_time: Option<std::time::Instant>,
+    #[serde(skip)]
+    pub current_out_file: Option<(PathBuf, std::fs::File)>,
+    #[serde(skip)]
+    pub current_chunks: Option<ChunkTimer>,
+    #[serde(skip)]
+    pub current_tags: Vec<String>,
+    #[serde(skip)]
+    pub current_project: Option<String>,
+    #[serde(skip)]
+    pub current_title: Option<String>,
+    #[serde(skip)]
+    pub cmd_title: Option<String>,
+    #[serde(skip)]
+    pub size_history: Vec<TerminalSize>,
+    // when the last command finished
+    #[serde(skip)]
+    pub last_finished: Option<std::time::Instant>,
+    // where the hook tees stderr when that isn't log_dir/current.err (the session was split)
+    #[serde(skip)]
+    pub stderr_file: Option<PathBuf>,
+    // out of disk or quota: commands are recorded without their output
+    #[serde(skip)]
+    pub output_dropped: bool,
+    #[serde(skip)]
+    pub current_stalls: StallTracker,
+    // typed at the note hotkey while the command ran
+    #[serde(skip)]
+    pub current_note: Option<String>,
+}
+
+/// a new command id, a uuidv7 so ids sort by when they were made
+pub fn new_id() -> String {
+    uuid::Uuid::now_v7().to_string()
+}
+
+/// id of a command recorded before commands had one, the same every time it is read: a uuidv7
+/// with the command's time, the rest derived from its session, time and command line
+pub fn legacy_id(session_id: &str, timestamp: &str, cmd: &str) -> String {
+    let timestamp = time::normalize_stamp(timestamp);
+    let millis = time::parse_stamp(&timestamp).map_or(0, |t| t.timestamp_millis().max(0) as u64);
+    let digest = Sha256::digest(format!("{}|{}|{}", session_id, timestamp, cmd));
+    let mut rest = [0u8; 10];
+    rest.copy_from_slice(&digest[..10]);
+    uuid::Builder::from_unix_timestamp_millis(millis, &rest).into_uuid().to_string()
+}
+
+/// tag on commands whose output wasn't kept for lack of space
+pub const OUTPUT_DROPPED_TAG: &str = "output-dropped";
+
+/// file in the session directory the zsh hook tees a running command's stderr into
+pub const STDERR_FILE: &str = "current.err";
+
+// what a preview keeps of long output: its start, error lines from the middle, and its end
+const PREVIEW_HEAD: usize = 2 * 1024;
+const PREVIEW_TAIL: usize = 6 * 1024;
+const PREVIEW_ERRORS: usize = 2 * 1024;
+// the tail never shrinks below this to make room for error lines
+const PREVIEW_MIN_TAIL: usize = 4 * 1024;
+// longer lines are classified by their start
+const PREVIEW_LINE_LIMIT: usize = 1024;
+
+// output arriving this soon after the chunk before it is timed together with it
+const CHUNK_MERGE_MS: u64 = 50;
+/// silences shorter than this aren't kept as a command's longest pause
+pub const PAUSE_MIN_MS: u64 = 2000;
+
+/// notes where a running command went quiet for long enough to look hung
+#[derive(Debug, Default)]
+pub struct StallTracker {
+    last_ms: u64, // when output last arrived, since the command started
+    bytes: u64,
+    stalls: Vec<OutputPause>,
+    // the last stall goes on until output arrives
+    open: bool,
+}
+
+impl StallTracker {
+    fn output(&mut self, now_ms: u64, len: usize) {
+        self.close(now_ms);
+        self.last_ms = now_ms;
+        self.bytes += len as u64;
+    }
+
+    // a new stall, unless the command is already in one
+    fn mark(&mut self, now_ms: u64) -> bool {
+        if self.open {
+            return false;
+        }
+        self.stalls.push(OutputPause { at_ms: self.last_ms, ms: now_ms.saturating_sub(self.last_ms), at_byte: self.bytes });
+        self.open = true;
+        true
+    }
+
+    fn close(&mut self, now_ms: u64) {
+        if let Some(stall) = self.stalls.last_mut().filter(|_| self.open) {
+            stall.ms = now_ms.saturating_sub(stall.at_ms);
+        }
+        self.open = false;
+    }
+
+    fn finish(mut self, end_ms: u64) -> Vec<OutputPause> {
+        self.close(end_ms);
+        self.stalls
+    }
+}
+
+/// times a running command's output into current.chunks next to current.out
+#[derive(Debug)]
+pub struct ChunkTimer {
+    file: Option<(PathBuf, std::fs::File)>,
+    pending: Option<OutputChunk>,
+    last_ms: u64,
+    bytes: u64,
+    longest: Option<OutputPause>,
+}
+
+impl ChunkTimer {
+    fn create(log_dir: &Path) -> Self {
+        let tmp = log_dir.join("current.chunks");
+        Self {
+            file: std::fs::File::create(&tmp).ok().map(|f| (tmp, f)),
+            pending: None,
+            last_ms: 0,
+            bytes: 0,
+            longest: None,
+        }
+    }
+
+    fn push(&mut self, offset_ms: u64, len: usize) {
+        self.pause(offset_ms);
+        match self.pending.as_mut() {
+            Some(chunk) if offset_ms.saturating_sub(chunk.offset_ms) < CHUNK_MERGE_MS => chunk.len += len as u64,
+            _ => {
+                self.write_pending();
+                self.pending = Some(OutputChunk { offset_ms, len: len as u64 });
+            }
+        }
+        self.last_ms = offset_ms;
+        self.bytes += len as u64;
+    }
+
+    fn pause(&mut self, now_ms: u64) {
+        let ms = now_ms.saturating_sub(self.last_ms);
+        if ms >= PAUSE_MIN_MS && self.longest.as_ref().is_none_or(|p| ms > p.ms) {
+            self.longest = Some(OutputPause { at_ms: self.last_ms, ms, at_byte: self.bytes });
+        }
+    }
+
+    fn write_pending(&mut self) {
+        if let (Some(chunk), Some((_, f))) = (self.pending.take(), self.file.as_mut()) {
+            if let Ok(line) = serde_json::to_string(&chunk) {
+                let _ = writeln!(f, "{}", line);
+            }
+        }
+    }
+
+    // the temp file, unless there was no output, and the longest pause, counting the silence up to `end_ms`
+    fn finish(mut self, end_ms: u64) -> (Option<PathBuf>, Option<OutputPause>) {
+        self.pause(end_ms);
+        self.write_pending();
+        let path = self.file.map(|(path, _)| path);
+        if self.bytes == 0 {
+            if let Some(path) = &path {
+                let _ = std::fs::remove_file(path);
+            }
+            return (None, self.longest);
+        }
+        (path, self.longest)
+    }
+}
+
+/// the first bytes of a zstd frame, which a compressed sidecar starts with
+pub const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// the contents of a sidecar like a command's .out file, decompressed when it was archived with
+/// zstd, either in place or as `<name>.zst` next to where it was
+pub fn read_sidecar(path: &Path) -> std::io::Result<Vec<u8>> {
+    let data = match std::fs::read(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let mut zst = path.as_os_str().to_owned();
+            zst.push(".zst");
+            std::fs::read(PathBuf::from(zst))?
+        }
+        read => read?,
+    };
+    match data.starts_with(ZSTD_MAGIC) {
+        true => crate::util::compress::decompress(&data).map_err(std::io::Error::other),
+        false => Ok(data),
+    }
+}
+
+/// the chunk timings of a recorded command's output; empty when there are none
+pub fn load_chunks(path: &Path) -> Vec<OutputChunk> {
+    std::fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// each line of `data` with the time its first byte arrived, from the chunks it was written in
+pub fn timed_lines<'a>(data: &'a [u8], chunks: &[OutputChunk]) -> Vec<(u64, &'a [u8])> {
+    let mut lines = Vec::new();
+    let mut chunk = chunks.iter().peekable();
+    let mut chunk_end = 0u64;
+    let mut offset_ms = 0;
+    let mut at = 0u64;
+    for line in data.split_inclusive(|b| *b == b'\n') {
+        while at >= chunk_end {
+            let Some(next) = chunk.next() else { break };
+            chunk_end += next.len;
+            offset_ms = next.offset_ms;
+        }
+        lines.push((offset_ms, line));
+        at += line.len() as u64;
+    }
+    lines
+}
+
+/// builds the capped output preview while output streams in; errors usually come last,
+/// so past the cap it keeps the head, the tail, and error lines from what is left out
+#[derive(Debug, Default)]
+pub struct OutputPreview {
+    head: Vec<u8>,
+    // at most twice PREVIEW_TAIL, trimmed in bulk
+    tail: Vec<u8>,
+    // error lines with the offset they start at
+    errors: Vec<(usize, String)>,
+    errors_len: usize,
+    line: Vec<u8>,
+    line_start: usize,
+    total: usize,
+}
+
+impl OutputPreview {
+    /// the preview of output that is already complete
+    pub fn of(data: &[u8]) -> String {
+        let mut preview = Self::default();
+        preview.push(data);
+        preview.render()
+    }
+
+    pub fn push(&mut self, mut data: &[u8]) {
+        if self.head.len() < PREVIEW_HEAD {
+            let n = data.len().min(PREVIEW_HEAD - self.head.len());
+            self.head.extend_from_slice(&data[..n]);
+            self.total += n;
+            data = &data[n..];
+            self.line_start = self.total;
+        }
+        for line in data.split_inclusive(|b| *b == b'\n') {
+            if self.line.len() < PREVIEW_LINE_LIMIT {
+                self.line.extend_from_slice(&line[..line.len().min(PREVIEW_LINE_LIMIT - self.line.len())]);
+            }
+            self.total += line.len();
+            if line.ends_with(b"\n") {
+                self.finish_line();
+            }
+        }
+        self.tail.extend_from_slice(data);
+        if self.tail.len() > 2 * PREVIEW_TAIL {
+            let cut = self.tail.len() - PREVIEW_TAIL;
+            self.tail.drain(..cut);
+        }
+    }
+
+    fn finish_line(&mut self) {
+        let line = String::from_utf8_lossy(&self.line).to_string();
+        if self.errors_len + line.len() <= PREVIEW_ERRORS
+            && matches!(crate::ci::line_severity(&line), Some(crate::ci::Severity::Error))
+        {
+            self.errors_len += line.len();
+            self.errors.push((self.line_start, line));
+        }
+        self.line.clear();
+        self.line_start = self.total;
+    }
+
+    pub fn render(&self) -> String {
+        if self.total <= PREVIEW_HEAD + PREVIEW_TAIL {
+            return format!("{}{}", String::from_utf8_lossy(&self.head), String::from_utf8_lossy(&self.tail));
+        }
+        // end the head and start the tail at a line when one is close by
+        let mut head = &self.head[..];
+        if let Some(nl) = head.iter().rev().take(256).position(|b| *b == b'\n') {
+            head = &head[..head.len() - nl];
+        }
+        // error lines the shortest tail wouldn't show, then the tail gives up the room they take
+        let min_tail_start = self.total - PREVIEW_MIN_TAIL;
+        let errors: Vec<&(usize, String)> = self.errors.iter().filter(|(at, _)| *at < min_tail_start).collect();
+        let errors_len: usize = errors.iter().map(|(_, l)| l.len()).sum();
+        let tail_len = PREVIEW_TAIL.saturating_sub(errors_len).max(PREVIEW_MIN_TAIL).min(self.tail.len());
+        let mut tail = &self.tail[self.tail.len() - tail_len..];
+        if let Some(nl) = tail.iter().take(256).position(|b| *b == b'\n') {
+            tail = &tail[nl + 1..];
+        }
+        let shown_from = self.total - tail.len();
+        let errors: Vec<&str> = errors.iter().filter(|(at, _)| *at < shown_from).map(|(_, l)| l.as_str()).collect();
+
+        let mut out = String::from_utf8_lossy(head).to_string();
+        if !out.ends_with('\n') {
+            out.push('\n');
+        }
+        let omitted = shown_from - head.len() - errors.iter().map(|l| l.len()).sum::<usize>();
+        if errors.is_empty() {
+            out.push_str(&format!("[recli: {} bytes not shown]\n", omitted));
+        } else {
+            out.push_str(&format!("[recli: {} bytes not shown, error lines among them:]\n", omitted));
+            out.extend(errors);
+            out.push_str("[recli: ...]\n");
+        }
+        out.push_str(&String::from_utf8_lossy(tail));
+        out
+    }
+}
+
+// >>> methods >>>
+
+impl Default for CommandLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CommandLog {
+    pub fn new() -> CommandLog {
+        CommandLog {
+            entries: Vec::new(),
+            pastes: Vec::new(),
+            segments: Vec::new(),
+            last_seq: 0,
+            current_cmd: String::new(),
+            current_preview: OutputPreview::default(),
+            current_start_time: None,
+            current_out_file: None,
+            current_chunks: None,
+            current_tags: Vec::new(),
+            current_project: None,
+            current_title: None,
+            cmd_title: None,
+            size_history: Vec::new(),
+            last_finished: None,
+            stderr_file: None,
+            output_dropped: false,
+            current_stalls: StallTracker::default(),
+            current_note: None,
+        }
+    }
+
+    pub fn start_command(&mut self, cmd_string: String, cwd: String, log_dir: &Path) {
+        self.current_cmd = cmd_string;
+        self.current_project = crate::history::project_root(Path::new(&cwd)).map(|p| p.to_string_lossy().to_string());
+        self.current_preview = OutputPreview::default();
+        self.current_start_time = Some(std::time::Instant::now());
+        self.current_stalls = StallTracker::default();
+        self.current_note = None;
+        self.cmd_title = self.current_title.clone();
+        if self.output_dropped {
+            self.current_out_file = None;
+            self.tag_current(OUTPUT_DROPPED_TAG.to_string());
+            return;
+        }
+        // open a temp file to stream raw bytes, will rename on finish
+        let tmp = log_dir.join("current.out");
+        match std::fs::File::create(&tmp) {
+            Ok(f) => self.current_out_file = Some((tmp, f)),
+            Err(_) => self.current_out_file = None,
+        }
+        self.current_chunks = self.current_out_file.is_some().then(|| ChunkTimer::create(log_dir));
+    }
+
+    pub fn append_output(&mut self, output: &str) {
+        // legacy path if used elsewhere: write to preview and file
+        self.append_output_bytes(output.as_bytes());
+    }
+
+    pub fn append_output_bytes(&mut self, bytes: &[u8]) {
+        if let Some(start) = self.current_start_time {
+            self.current_stalls.output(start.elapsed().as_millis() as u64, bytes.len());
+        }
+        if self.output_dropped {
+            return;
+        }
+        if let Some((_, f)) = self.current_out_file.as_mut() {
+            let _ = f.write_all(bytes);
+            let _ = f.flush();
+        }
+        if let (Some(timer), Some(start)) = (self.current_chunks.as_mut(), self.current_start_time) {
+            timer.push(start.elapsed().as_millis() as u64, bytes.len());
+        }
+        // capped preview keeping head, tail and error lines
+        self.current_preview.push(bytes);
+    }
+
+    pub fn finish_command(&mut self, exit_code: i32, pipestatus: Option<Vec<i32>>, cwd: String, log_dir: &Path) {
+        // avoid creating empty entries if no command was started
+        if self.current_cmd.is_empty() {
+            return;
+        }
+    // use rfc3339 utc to be cosmos-ready and schema-stable
+    let timestamp = time::now_stamp();
+
+        let duration_ms = self
+            .current_start_time
+            .map(|start| start.elapsed().as_millis() as u64);
+
+        // finalize sidecar file
+        let mut output_path: Option<String> = None;
+        if let Some((tmp_path, mut f)) = self.current_out_file.take() {
+            let _ = f.flush();
+            let seq = self.entries.len();
+            let filename = format!("{}-{}.out", timestamp.replace(':', "-"), seq);
+            let final_path = log_dir.join(filename);
+            let _ = std::fs::rename(&tmp_path, &final_path);
+            output_path = Some(final_path.file_name().unwrap_or_default().to_string_lossy().to_string());
+        }
+        let mut chunks_path = None;
+        let mut longest_pause = None;
+        if let Some(timer) = self.current_chunks.take() {
+            let (tmp_path, pause) = timer.finish(duration_ms.unwrap_or_default());
+            longest_pause = pause;
+            if let (Some(tmp_path), Some(out)) = (tmp_path, output_path.as_ref()) {
+                let name = format!("{}.chunks", out.trim_end_matches(".out"));
+                if std::fs::rename(&tmp_path, log_dir.join(&name)).is_ok() {
+                    chunks_path = Some(name);
+                }
+            }
+        }
+
+        // stderr tee'd by the shell hook, if capture is on
+        let mut stderr = None;
+        let mut stderr_path = None;
+        let err_tmp = self.stderr_file.clone().unwrap_or_else(|| log_dir.join(STDERR_FILE));
+        if let Ok(bytes) = std::fs::read(&err_tmp) {
+            if !bytes.is_empty() {
+                let seq = self.entries.len();
+                let final_path = log_dir.join(format!("{}-{}.err", timestamp.replace(':', "-"), seq));
+                if std::fs::rename(&err_tmp, &final_path).is_ok() {
+                    stderr_path = Some(final_path.file_name().unwrap_or_default().to_string_lossy().to_string());
+                }
+                stderr = Some(OutputPreview::of(&bytes));
+            }
+        }
+
+        let output_preview = self.current_preview.render();
+        let started = Utc::now() - chrono::Duration::milliseconds(duration_ms.unwrap_or_default() as i64);
+        let signal = ExitSignal::from_exit_code(exit_code, started, &output_preview);
+
+        self.last_seq += 1;
+        let entry = CommandEntry {
+            id: new_id(),
+            cmd: self.current_cmd.clone(),
+            cwd,
+            timestamp,
+            seq: self.last_seq,
+            exit_code,
+            output_preview,
+            output_path,
+            pipestatus,
+            duration_ms,
+            tags: std::mem::take(&mut self.current_tags),
+            title: self.cmd_title.take(),
+            stderr,
+            stderr_path,
+            project: self.current_project.take(),
+            files: None,
+            chunks_path,
+            longest_pause,
+            signal,
+            network: None,
+            processes: None,
+            stalls: std::mem::take(&mut self.current_stalls).finish(duration_ms.unwrap_or_default()),
+            note: self.current_note.take().filter(|n| !n.trim().is_empty()),
+        };
+
+        self.entries.push(entry);
+        self.last_finished = Some(std::time::Instant::now());
+        self.current_cmd = String::new();
+        self.current_preview = OutputPreview::default();
+        self.current_start_time = None;
+        self.current_out_file = None;
+        self.current_chunks = None;
+    }
+
+    /// stop or resume keeping command output; a running command keeps what it has so far
+    pub fn set_output_dropped(&mut self, dropped: bool) {
+        self.output_dropped = dropped;
+        if dropped && !self.current_cmd.is_empty() {
+            self.tag_current(OUTPUT_DROPPED_TAG.to_string());
+        }
+    }
+
+    /// how long the running command has printed nothing; none between commands
+    pub fn output_silence(&self) -> Option<std::time::Duration> {
+        let start = self.current_start_time.filter(|_| !self.current_cmd.is_empty())?;
+        Some(start.elapsed().saturating_sub(std::time::Duration::from_millis(self.current_stalls.last_ms)))
+    }
+
+    /// note that the running command stalled; false when the stall was already noted
+    pub fn mark_stall(&mut self) -> bool {
+        match self.current_start_time.filter(|_| !self.current_cmd.is_empty()) {
+            Some(start) => self.current_stalls.mark(start.elapsed().as_millis() as u64),
+            None => false,
+        }
+    }
+
+    /// time since the last command finished; none before the first one
+    pub fn idle_time(&self) -> Option<std::time::Duration> {
+        self.last_finished.map(|t| t.elapsed())
+    }
+
+    /// start a new segment with the next command
+    pub fn mark_segment(&mut self, idle: std::time::Duration) {
+        self.segments.push(Segment {
+            started_at: time::now_stamp(),
+            first_entry: self.entries.len(),
+            idle_ms: idle.as_millis() as u64,
+        });
+    }
+
+    pub fn record_paste(&mut self, text: Option<String>, bytes: usize) {
+        let during_cmd = (!self.current_cmd.is_empty()).then(|| self.current_cmd.clone());
+        self.pastes.push(PasteEntry {
+            timestamp: time::now_stamp(),
+            bytes,
+            text,
+            during_cmd,
+        });
+    }
+
+    /// remember the terminal title; a title set while a command runs belongs to that command
+    pub fn set_title(&mut self, title: String) {
+        if !self.current_cmd.is_empty() {
+            self.cmd_title = Some(title.clone());
+        }
+        self.current_title = Some(title);
+    }
+
+    pub fn record_size(&mut self, cols: u16, rows: u16) {
+        self.size_history.push(TerminalSize {
+            timestamp: time::now_stamp(),
+            cols,
+            rows,
+        });
+    }
+
+    /// set the note of the `nth`-last finished command, or of the `nth`-last failed one with `failed`;
+    /// an empty note removes it
+    pub fn note(&mut self, nth: usize, failed: bool, text: &str) -> std::result::Result<&CommandEntry, String> {
+        let index = self.nth_last_index(nth, failed).ok_or_else(|| match failed {
+            true => "no such failed command in the session".to_string(),
+            false => "no such command in the session".to_string(),
+        })?;
+        let entry = &mut self.entries[index];
+        let text = text.trim();
+        entry.note = (!text.is_empty()).then(|| text.to_string());
+        Ok(entry)
+    }
+
+    /// the nth-last finished command (1 is the last), counting failed ones only when `failed`
+    pub fn nth_last(&self, nth: usize, failed: bool) -> Option<&CommandEntry> {
+        self.nth_last_index(nth, failed).map(|i| &self.entries[i])
+    }
+
+    fn nth_last_index(&self, nth: usize, failed: bool) -> Option<usize> {
+        let mut candidates = self.entries.iter().enumerate().rev().filter(|(_, e)| !failed || e.exit_code != 0);
+        candidates.nth(nth.max(1) - 1).map(|(i, _)| i)
+    }
+
+    /// tag the running command, or the last finished one when idle
+    pub fn tag_current(&mut self, tag: String) {
+        if !self.current_cmd.is_empty() {
+            if !self.current_tags.contains(&tag) {
+                self.current_tags.push(tag);
+            }
+        } else if let Some(last) = self.entries.last_mut() {
+            if !last.tags.contains(&tag) {
+                last.tags.push(tag);
+            }
+        }
+    }
+
+    pub fn get_recent(&self, count: usize) -> Vec<&CommandEntry> {
+        let start = if self.entries.len() > count {
+            self.entries.len() - count
+        } else {
+            0
+        };
+        self.entries[start..].iter().collect()
+    }
+
+    pub fn get_all(&self) -> &Vec<CommandEntry> {
+        &self.entries
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.current_cmd = String::new();
+    self.current_preview = OutputPreview::default();
+        self.current_start_time = None;
+    self.current_out_file = None;
+    }
+
+    /// give commands of `session_id` recorded before they were numbered their place in the
+    /// session, and those recorded before they had an id the one `legacy_id` derives
+    pub fn backfill(&mut self, session_id: &str) {
+        for entry in &mut self.entries {
+            if entry.seq == 0 {
+                entry.seq = self.last_seq + 1;
+            }
+            self.last_seq = self.last_seq.max(entry.seq);
+            if entry.id.is_empty() {
+                entry.id = legacy_id(session_id, &entry.timestamp, &entry.cmd);
+            }
+        }
+    }
+
+    pub fn save_to_file(&self, log_dir: &Path) -> Result<()> {
+        let json_data = serde_json::to_string_pretty(self)?;
+        crate::lock::write_file(log_dir, "commands.json", json_data.as_bytes())
+    }
+
+    pub fn load_from_file(log_dir: &Path) -> Result<CommandLog> {
+        let commands_file = log_dir.join("commands.json");
+        if !commands_file.exists() {
+            return Ok(CommandLog::new());
+        }
+
+        let json_data = crate::lock::read_file(log_dir, "commands.json")?;
+        let mut log: CommandLog = serde_json::from_str(&json_data)?;
+        let session_id = log_dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        log.backfill(&session_id);
+
+        // initialize non-serialized fields
+    log.current_cmd = String::new();
+    log.current_preview = OutputPreview::default();
+    log.current_start_time = None;
+    log.current_out_file = None;
+
+        Ok(log)
+    }
+}
@@ -0,0 +1,837 @@
+use crate::config_check::ProblemKind;
+use crate::error::RecliError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// top-level config for recli
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Config {
+    pub workspace: Option<String>, // team namespace stamped on uploaded sessions and events
+    pub log_dir: Option<String>,   // where sessions are recorded, defaults to ~/.recli/logs
+    pub strict: bool,              // refuse to run while `recli config validate` finds problems; also RECLI_STRICT_CONFIG=1
+    pub logging: LoggingConfig,
+    pub azure: Option<AzureConfig>,
+    pub telemetry: TelemetryConfig,
+    pub datadog: DatadogConfig,
+    pub sentry: SentryConfig,
+    pub report: ReportConfig,
+    pub recording: RecordingConfig,
+    pub hooks: HooksConfig,
+    pub plugins: PluginsConfig,
+    pub filters: FiltersConfig,
+    pub display: DisplayConfig,
+    pub watch: WatchConfig,
+    pub hung: HungConfig,
+    pub hotkeys: HotkeyConfig,
+    pub anonymize: AnonymizeConfig,
+    pub encryption: EncryptionConfig,
+    pub policy: PolicyConfig,
+    pub detection: DetectionConfig,
+    pub share: ShareConfig,
+    pub team: TeamConfig,
+    pub sinks: SinksConfig,
+    pub upload: UploadConfig,
+    pub retry: RetryConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    pub level: String,       // error|warn|info|debug|trace
+    pub dir: Option<String>, // internal diagnostics dir, defaults to ~/.recli/internal-logs
+    pub max_files: usize,    // daily files kept before the oldest is removed
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            dir: None,
+            max_files: 7,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct AzureConfig {
+    pub cosmos: Option<CosmosConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct CosmosConfig {
+    pub account: Option<String>,
+    pub database: Option<String>,
+    pub container: Option<String>,
+    pub connection_string: Option<String>,
+    pub partition_key: Option<String>, // "/session_id" (default) or "/workspace" for shared containers
+    /// read the container before each upload so connectivity problems show up as such
+    #[serde(default)]
+    pub verify_upload: bool,
+    /// whether finished sessions go up without asking
+    #[serde(default)]
+    pub upload_policy: UploadPolicy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UploadPolicy {
+    /// show what would be uploaded and wait for a yes at session end
+    Ask,
+    #[default]
+    Always,
+    Never,
+}
+
+impl CosmosConfig {
+    /// whether documents are partitioned by workspace instead of by session
+    pub fn partition_by_workspace(&self) -> bool {
+        self.partition_key.as_deref().map(|k| k.trim_start_matches('/')) == Some("workspace")
+    }
+}
+
+/// how much of the cosmos upload setup the RECLI_AZURE__COSMOS__* environment provides
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CosmosSetup {
+    /// nothing is set, uploads are off on purpose
+    Unconfigured,
+    Complete,
+    /// some settings are there but uploads can't work; what is missing, by variable name
+    Incomplete(Vec<String>),
+}
+
+impl CosmosSetup {
+    /// check the variables the uploader reads: credentials, database and container
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let connstr = var("RECLI_AZURE__COSMOS__CONNSTR");
+        let account = var("RECLI_AZURE__COSMOS__ACCOUNT");
+        let key = var("RECLI_AZURE__COSMOS__KEY");
+        let database = var("RECLI_AZURE__COSMOS__DB");
+        let container = var("RECLI_AZURE__COSMOS__CONTAINER");
+        if [&connstr, &account, &key, &database, &container].iter().all(|v| v.is_none()) {
+            return CosmosSetup::Unconfigured;
+        }
+
+        let mut missing = Vec::new();
+        match (&connstr, &account, &key) {
+            (Some(connstr), _, _) => {
+                let has = |part: &str| connstr.split(';').any(|p| p.trim().strip_prefix(part).is_some_and(|v| !v.is_empty()));
+                if !has("AccountEndpoint=") || !has("AccountKey=") {
+                    missing.push("RECLI_AZURE__COSMOS__CONNSTR (needs AccountEndpoint=...;AccountKey=...)".to_string());
+                }
+            }
+            (None, Some(_), Some(_)) => {}
+            (None, None, Some(_)) => missing.push("RECLI_AZURE__COSMOS__ACCOUNT".to_string()),
+            (None, Some(_), None) => missing.push("RECLI_AZURE__COSMOS__KEY".to_string()),
+            (None, None, None) => missing.push(
+                "RECLI_AZURE__COSMOS__CONNSTR (or RECLI_AZURE__COSMOS__ACCOUNT and RECLI_AZURE__COSMOS__KEY)".to_string(),
+            ),
+        }
+        if database.is_none() {
+            missing.push("RECLI_AZURE__COSMOS__DB".to_string());
+        }
+        if container.is_none() {
+            missing.push("RECLI_AZURE__COSMOS__CONTAINER".to_string());
+        }
+        if missing.is_empty() {
+            CosmosSetup::Complete
+        } else {
+            CosmosSetup::Incomplete(missing)
+        }
+    }
+}
+
+/// opentelemetry export of recorded commands; disabled while no endpoint is set
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    pub otlp_endpoint: Option<String>, // otlp/http traces url, e.g. http://localhost:4318/v1/traces
+    pub service_name: Option<String>,  // defaults to "recli"
+    pub tags: Vec<String>,             // attached to every command span
+}
+
+/// datadog logs intake; disabled while no api key is set
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DatadogConfig {
+    pub api_key: Option<String>,
+    pub site: String,            // e.g. datadoghq.eu; logs go to http-intake.logs.<site>
+    pub intake_url: Option<String>, // full url instead, e.g. a local agent or proxy
+    pub service: String,         // `service` on every log
+    pub source: String,          // `ddsource`, picks the log pipeline
+    pub tags: Vec<String>,       // "key:value" pairs added to `ddtags`
+    pub batch_size: usize,       // logs per request, at most 1000
+    pub flush_interval_ms: u64,  // a partial batch is sent after this
+    pub max_retries: usize,      // attempts after the first for 429, 5xx and network errors
+}
+
+impl Default for DatadogConfig {
+    fn default() -> Self {
+        Self {
+            api_key: None,
+            site: "datadoghq.com".to_string(),
+            intake_url: None,
+            service: "recli".to_string(),
+            source: "recli".to_string(),
+            tags: Vec::new(),
+            batch_size: 50,
+            flush_interval_ms: 5000,
+            max_retries: 5,
+        }
+    }
+}
+
+/// sentry events for failed commands; disabled while no dsn is set
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SentryConfig {
+    pub dsn: Option<String>,
+    pub environment: Option<String>,
+    pub report_nonzero: bool,        // report every non-zero exit, not only pattern matches
+    pub ignore_exit_codes: Vec<i32>, // e.g. 130 for ctrl-c
+    pub error_patterns: Vec<String>, // output containing any of these is reported, even on exit 0
+}
+
+impl Default for SentryConfig {
+    fn default() -> Self {
+        Self {
+            dsn: None,
+            environment: None,
+            report_nonzero: true,
+            ignore_exit_codes: vec![130],
+            error_patterns: Vec::new(),
+        }
+    }
+}
+
+/// smtp settings for `recli report --email`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReportConfig {
+    pub smtp_host: Option<String>,
+    pub smtp_port: u16,
+    pub tls: String, // "starttls", "tls" (implicit, usually port 465) or "none" for a local relay
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: Option<String>,
+    pub to: Vec<String>,
+}
+
+impl Default for ReportConfig {
+    fn default() -> Self {
+        Self {
+            smtp_host: None,
+            smtp_port: 587,
+            tls: "starttls".to_string(),
+            username: None,
+            password: None,
+            from: None,
+            to: Vec::new(),
+        }
+    }
+}
+
+/// what a pty session records beyond the terminal output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecordingConfig {
+    pub split_stderr: bool,                // also keep each command's stderr on its own (zsh hook only)
+    pub idle_segment_minutes: Option<u64>, // a gap this long between commands starts a new segment
+    pub idle_new_session: bool,            // finish the session at such a gap and record into a new one
+    pub quota_mb: Option<u64>,             // past this much in the logs directory, output is no longer kept
+    pub min_free_mb: u64,                  // the same when the disk holding the logs has less free
+    pub input: InputRecording,             // keystrokes kept in input.cast next to the session cast
+    pub track_files: bool,                 // note which files in the cwd each command modified, created or deleted
+    pub track_files_depth: usize,          // directory levels looked at, 1 being the cwd itself
+    pub track_files_limit: usize,          // files looked at per snapshot
+    pub track_network: bool,               // note bytes moved and remote endpoints contacted per command
+    pub track_network_interval_ms: u64,    // how often the sockets of a running command are looked at
+    pub track_processes: bool,             // note the processes each command ran, at once and in all
+    pub track_processes_interval_ms: u64,  // how often the processes below the shell are looked at
+}
+
+/// how much of what is typed in a pty session is recorded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum InputRecording {
+    #[default]
+    Off,
+    /// when each key was pressed and what kind of key it was, never the text
+    Timing,
+    /// the keys themselves; anything typed while the terminal doesn't echo is still kept as timing
+    Full,
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        Self {
+            split_stderr: false,
+            idle_segment_minutes: None,
+            idle_new_session: false,
+            quota_mb: None,
+            min_free_mb: 100,
+            input: InputRecording::Off,
+            track_files: false,
+            track_files_depth: 3,
+            track_files_limit: 5000,
+            track_network: false,
+            track_network_interval_ms: 100,
+            track_processes: false,
+            track_processes_interval_ms: 200,
+        }
+    }
+}
+
+/// pseudonymize host, user and cwd in uploaded sessions and emitted events
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct AnonymizeConfig {
+    pub enabled: bool,
+    pub salt: Option<String>, // shared by a team so pseudonyms match; generated per user when unset
+}
+
+/// client-side encryption of command lines and output in uploaded sessions
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct EncryptionConfig {
+    pub enabled: bool,
+    pub key_file: Option<String>,    // base64 256-bit user key, defaults to ~/.recli/upload.key (created on first upload)
+    pub key_command: Option<String>, // prints the key instead, e.g. fetching a key vault secret
+}
+
+/// shell snippets and urls run on session and command events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    pub session_start: Vec<Hook>,
+    pub session_end: Vec<Hook>,
+    pub before_command: Vec<Hook>,
+    pub after_command: Vec<Hook>,
+    pub output_match: Vec<Hook>, // a [watch] pattern matched live output
+    pub command_hung: Vec<Hook>, // a command printed nothing for [hung] after_seconds
+    pub timeout_ms: u64, // a hook still running after this is killed
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            session_start: Vec::new(),
+            session_end: Vec::new(),
+            before_command: Vec::new(),
+            after_command: Vec::new(),
+            output_match: Vec::new(),
+            command_hung: Vec::new(),
+            timeout_ms: 10_000,
+        }
+    }
+}
+
+/// one hook: `run` is passed to `sh -c` with the event json on stdin, `url` gets it as a POST body
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Hook {
+    pub run: Option<String>,
+    pub url: Option<String>,
+}
+
+/// extra feedback drawn on the terminal during a pty session; never recorded
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct DisplayConfig {
+    pub highlight_errors: bool, // colored bar before error and warning lines of command output
+    pub command_summary: bool,  // "↳ exit 1 · 3.2s · logged" after each command
+    pub note_prompt: bool,      // after a failed command, say how to note why it failed
+}
+
+/// regexes matched against each line of live command output during a pty session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WatchConfig {
+    pub patterns: Vec<String>, // e.g. "OOMKilled|panic"; `recli start --watch` adds more
+    pub tag: String,           // added to a command whose output matched
+    pub bell: bool,            // ring the terminal bell with the on-screen alert
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            patterns: Vec::new(),
+            tag: "watch".to_string(),
+            bell: true,
+        }
+    }
+}
+
+/// commands that print nothing for a while and don't exit either
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HungConfig {
+    pub after_seconds: Option<u64>, // silence after which a running command counts as hung; off when unset
+    pub bell: bool,                 // ring the terminal bell with the nudge
+    pub tag: String,                // added by the hung hotkey
+}
+
+impl Default for HungConfig {
+    fn default() -> Self {
+        Self {
+            after_seconds: None,
+            bell: true,
+            tag: "hung".to_string(),
+        }
+    }
+}
+
+/// external `recli-<name>` executables
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct PluginsConfig {
+    pub dir: Option<String>,      // where plugins are looked up, defaults to ~/.recli/plugins
+    pub event_sinks: Vec<String>, // plugins started as `recli-<name> events` to read the event stream
+}
+
+impl PluginsConfig {
+    pub fn plugin_dir(&self) -> PathBuf {
+        match &self.dir {
+            Some(dir) => PathBuf::from(shellexpand::tilde(dir).as_ref()),
+            None => {
+                let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".into());
+                Path::new(&home).join(".recli").join("plugins")
+            }
+        }
+    }
+}
+
+/// wasm output filters for pty sessions, grouped into named profiles
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct FiltersConfig {
+    pub profile: Option<String>, // profile used when `recli start` doesn't name one
+    pub profiles: BTreeMap<String, FilterProfile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct FilterProfile {
+    pub modules: Vec<String>, // .wasm (or .wat) files, applied in order
+}
+
+/// which commands are recorded with output, without it, or not at all; rules are checked in
+/// order (a project's .recli/policy.toml first, then the active profile, then these)
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct PolicyConfig {
+    pub default: RecordMode,
+    pub rules: Vec<PolicyRule>,
+    pub profile: Option<String>, // also RECLI_POLICY_PROFILE
+    pub profiles: BTreeMap<String, PolicyProfile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct PolicyProfile {
+    pub default: Option<RecordMode>,
+    pub rules: Vec<PolicyRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyRule {
+    pub command: String, // leading words, e.g. "gpg" or "kubectl exec"
+    pub mode: RecordMode,
+}
+
+/// how much of a command is recorded; ordered from least to most withheld
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordMode {
+    #[default]
+    Full,
+    /// command line, exit code and timing, no output
+    Metadata,
+    /// nothing at all
+    Skip,
+}
+
+/// how a pty session finds where commands start and end
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct DetectionConfig {
+    pub strategy: DetectionStrategy,
+    pub prompt_patterns: Vec<String>, // regexes for prompt-regex, matched against a prompt line without colors
+    pub shells: BTreeMap<String, ShellDetection>, // by shell name, e.g. [detection.shells.fish]
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct ShellDetection {
+    pub strategy: Option<DetectionStrategy>,
+    pub prompt_patterns: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum DetectionStrategy {
+    /// markers written by recli's zsh hook
+    #[default]
+    HookMarkers,
+    /// osc 133 semantic prompt sequences, sent by starship, p10k, fish and others
+    Osc133,
+    /// prompt lines matching prompt_patterns
+    PromptRegex,
+}
+
+impl DetectionConfig {
+    /// the strategy and prompt patterns for `shell`, a name or path
+    pub fn for_shell(&self, shell: &str) -> (DetectionStrategy, Vec<String>) {
+        let name = Path::new(shell).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let shell = self.shells.get(&name);
+        let strategy = shell.and_then(|s| s.strategy).unwrap_or(self.strategy);
+        let patterns = shell
+            .and_then(|s| s.prompt_patterns.clone())
+            .unwrap_or_else(|| self.prompt_patterns.clone());
+        (strategy, patterns)
+    }
+
+    /// the same for a shell recli didn't start, which never writes hook markers;
+    /// `hook-markers` becomes `prompt-regex`
+    pub fn for_unhooked_shell(&self, shell: &str) -> (DetectionStrategy, Vec<String>) {
+        match self.for_shell(shell) {
+            (DetectionStrategy::HookMarkers, patterns) => (DetectionStrategy::PromptRegex, patterns),
+            other => other,
+        }
+    }
+}
+
+/// keys recli intercepts inside a pty session; sequences are space separated
+/// key names like "ctrl-x ctrl-x". with `enabled = false` every key reaches the shell
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HotkeyConfig {
+    pub enabled: bool,
+    pub terminate: Option<String>, // end the session
+    pub pause: Option<String>,     // toggle output recording
+    pub annotate: Option<String>,  // bookmark the current command
+    pub hung: Option<String>,      // tag the running command with [hung] tag
+    pub interrupt: Option<String>, // send SIGINT to whatever runs in the foreground of the shell
+    pub note: Option<String>,      // type a one-line note on the running or last command
+    pub sequence_timeout_ms: u64,  // a half-typed sequence is passed through after this
+}
+
+impl Default for HotkeyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            terminate: Some("ctrl-x ctrl-x".to_string()),
+            pause: Some("ctrl-x p".to_string()),
+            annotate: Some("ctrl-x a".to_string()),
+            hung: Some("ctrl-x h".to_string()),
+            interrupt: Some("ctrl-x i".to_string()),
+            note: Some("ctrl-x n".to_string()),
+            sequence_timeout_ms: 500,
+        }
+    }
+}
+
+/// where `recli share` uploads sessions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShareConfig {
+    pub target: Option<String>,        // "azure", "s3" or "gist"; defaults to the only one set up
+    pub expires: String,               // lifetime of s3 links, at most 7d
+    pub azure_sas_url: Option<String>, // blob container url with a sas allowing create and write
+    pub azure_read_sas: Option<String>, // sas put in links, read-only; defaults to the one in azure_sas_url
+    pub s3_bucket: Option<String>,     // credentials come from AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY
+    pub s3_region: String,
+    pub s3_endpoint: Option<String>,   // e.g. http://localhost:9000 for minio; path-style urls are used then
+    pub s3_prefix: String,             // prepended to the object keys
+    pub github_token: Option<String>,  // gist scope; also GITHUB_TOKEN
+}
+
+impl Default for ShareConfig {
+    fn default() -> Self {
+        Self {
+            target: None,
+            expires: "7d".to_string(),
+            azure_sas_url: None,
+            azure_read_sas: None,
+            s3_bucket: None,
+            s3_region: "us-east-1".to_string(),
+            s3_endpoint: None,
+            s3_prefix: "recli/".to_string(),
+            github_token: None,
+        }
+    }
+}
+
+/// a team server `recli sync` pushes sessions to and pulls everyone's commands from
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct TeamConfig {
+    pub server: Option<String>, // base url, e.g. https://recli.example.com
+    pub token: Option<String>,  // bearer token the server knows this user by
+}
+
+/// how much of a session an uploader is given, from most to least
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SinkContent {
+    /// everything the uploader normally sends
+    #[default]
+    Full,
+    /// command lines, exit codes and timings, without output
+    Commands,
+    /// the session's own fields, without its commands
+    Metadata,
+}
+
+/// what one uploader gets; sessions carrying any of `skip_tags`, on the session or on
+/// one of its commands, stay local
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct SinkFilter {
+    pub content: SinkContent,
+    pub skip_tags: Vec<String>,
+}
+
+impl SinkFilter {
+    /// the first of `tags` that keeps a session away from this sink
+    pub fn blocked_by<'a>(&self, tags: impl IntoIterator<Item = &'a String>) -> Option<&'a str> {
+        tags.into_iter().find(|t| self.skip_tags.contains(t)).map(String::as_str)
+    }
+}
+
+/// per-uploader filters, so one recording can feed destinations of different sensitivity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SinksConfig {
+    pub cosmos: SinkFilter,
+    pub team: SinkFilter,
+    pub datadog: SinkFilter,
+    pub sentry: SinkFilter,
+    pub diagnostics_container: Option<String>, // cosmos container `recli sinks test` writes to, else the session container
+    pub diagnostics_prefix: String,            // key prefix of the blobs `recli sinks test` writes for [share]
+}
+
+impl Default for SinksConfig {
+    fn default() -> Self {
+        Self {
+            cosmos: SinkFilter::default(),
+            team: SinkFilter::default(),
+            datadog: SinkFilter::default(),
+            sentry: SinkFilter::default(),
+            diagnostics_container: None,
+            diagnostics_prefix: "_recli_diagnostics/".to_string(),
+        }
+    }
+}
+
+/// what uploads may cost the connection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UploadConfig {
+    pub max_kbps: Option<u64>, // average rate uploads are spaced out to, in KiB per second
+    pub low_bandwidth: bool,   // no output, compressed bodies, and sessions wait in the outbox for `recli sync`
+    pub compress: bool,        // zstd for cosmos output fields and shared blobs
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        Self { max_kbps: None, low_bandwidth: false, compress: true }
+    }
+}
+
+/// how failed uploads, pushes and webhook posts are retried before they are given up
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    pub attempts: usize,  // retries after the first try; 0 gives up right away
+    pub backoff_ms: u64,  // first delay, doubled on each retry, plus up to half of it as jitter
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self { attempts: 3, backoff_ms: 500 }
+    }
+}
+
+impl Config {
+    /// load config from a toml file, then overlay with env vars (RECLI_*). A file that doesn't parse
+    /// is reported and left out; in strict mode, any problem `recli config validate` finds is an error.
+    pub fn load(path: Option<&str>) -> Result<Self, RecliError> {
+        let mut cfg = match path {
+            Some(path) => Self::from_file(path, true)?,
+            None => Self::from_file(&Self::default_path(), false)?,
+        };
+        cfg.apply_env();
+        Ok(cfg)
+    }
+
+    /// ~/.recli/recli.toml
+    pub fn default_path() -> String {
+        let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".into());
+        format!("{}/.recli/recli.toml", home)
+    }
+
+    /// RECLI_* variables over what the file set
+    pub fn apply_env(&mut self) {
+        if let Ok(level) = std::env::var("RECLI_LOG_LEVEL") {
+            self.logging.level = level;
+        }
+
+        let mut cosmos = self.azure.take().and_then(|a| a.cosmos).unwrap_or_default();
+        if let Ok(v) = std::env::var("RECLI_AZURE__COSMOS__ACCOUNT") { cosmos.account = Some(v); }
+        if let Ok(v) = std::env::var("RECLI_AZURE__COSMOS__DB") { cosmos.database = Some(v); }
+        if let Ok(v) = std::env::var("RECLI_AZURE__COSMOS__CONTAINER") { cosmos.container = Some(v); }
+        if let Ok(v) = std::env::var("RECLI_AZURE__COSMOS__CONNSTR") { cosmos.connection_string = Some(v); }
+        if let Ok(v) = std::env::var("RECLI_AZURE__COSMOS__PARTITION_KEY") { cosmos.partition_key = Some(v); }
+
+        self.azure = Some(AzureConfig { cosmos: Some(cosmos) });
+
+        if let Ok(v) = std::env::var("RECLI_TELEMETRY__OTLP_ENDPOINT") { self.telemetry.otlp_endpoint = Some(v); }
+        if let Ok(v) = std::env::var("RECLI_DATADOG__API_KEY") { self.datadog.api_key = Some(v); }
+        if let Ok(v) = std::env::var("RECLI_DATADOG__SITE") { self.datadog.site = v; }
+        if let Ok(v) = std::env::var("RECLI_SENTRY__DSN") { self.sentry.dsn = Some(v); }
+        if let Ok(v) = std::env::var("RECLI_REPORT__SMTP_USERNAME") { self.report.username = Some(v); }
+        if let Ok(v) = std::env::var("RECLI_REPORT__SMTP_PASSWORD") { self.report.password = Some(v); }
+        if let Ok(v) = std::env::var("RECLI_WORKSPACE") { self.workspace = Some(v); }
+        if let Ok(v) = std::env::var("RECLI_ANONYMIZE__SALT") { self.anonymize.salt = Some(v); }
+        if let Ok(v) = std::env::var("RECLI_POLICY_PROFILE") { self.policy.profile = Some(v); }
+        if let Ok(v) = std::env::var("RECLI_LOG_DIR") { self.log_dir = Some(v); }
+        if let Ok(v) = std::env::var("RECLI_UPLOAD__LOW_BANDWIDTH") {
+            self.upload.low_bandwidth = matches!(v.trim(), "1" | "true" | "yes");
+        }
+        if let Ok(v) = std::env::var("RECLI_TEAM__SERVER") { self.team.server = Some(v); }
+        if let Ok(v) = std::env::var("RECLI_TEAM__TOKEN") { self.team.token = Some(v); }
+        if let Ok(v) = std::env::var("RECLI_SHARE__AZURE_SAS_URL") { self.share.azure_sas_url = Some(v); }
+        if self.share.github_token.is_none() {
+            self.share.github_token = std::env::var("GITHUB_TOKEN").ok().filter(|t| !t.is_empty());
+        }
+    }
+
+    /// resolve `${VAR}` in string values from the environment, and values of the form `file:/path`
+    /// to the contents of that file, so secrets needn't be written into the config. Shell commands
+    /// (hook `run`, `key_command`) are left for the shell. Returns the references that couldn't be
+    /// resolved, by key; those values stay as written.
+    pub fn expand_references(&mut self) -> Vec<(String, String)> {
+        let Ok(mut value) = serde_json::to_value(&*self) else { return Vec::new() };
+        let mut failed = Vec::new();
+        expand_value(&mut value, "", &mut failed);
+        if let Ok(cfg) = serde_json::from_value(value) {
+            *self = cfg;
+        }
+        failed
+    }
+
+    /// directory holding one folder per recorded session
+    pub fn session_log_dir(&self) -> PathBuf {
+        match &self.log_dir {
+            Some(dir) => PathBuf::from(shellexpand::tilde(dir).as_ref()),
+            None => crate::history::logs_base_dir(),
+        }
+    }
+
+    // `named` is a file asked for with --config, which should be there; the default one needn't be
+    fn from_file(path: &str, named: bool) -> Result<Self, RecliError> {
+        let strict_env = strict_from_env();
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !named => return Ok(Self::default()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !strict_env => {
+                eprintln!("! {}: not found, using the defaults", path);
+                return Ok(Self::default());
+            }
+            Err(e) => return Err(RecliError::Config(format!("{}: {}", path, e))),
+        };
+        let (cfg, problems) = crate::config_check::check(&text);
+        let strict = strict_env || cfg.as_ref().is_some_and(|c| c.strict);
+        if strict && !problems.is_empty() {
+            let list: Vec<String> = problems.iter().map(|p| format!("\n  {}", p)).collect();
+            return Err(RecliError::Config(format!("{} has problems and strict mode is on:{}", path, list.concat())));
+        }
+        match cfg {
+            Some(cfg) => {
+                // a secret that didn't resolve would otherwise only show up as a failed login later
+                for problem in problems.iter().filter(|p| p.kind == ProblemKind::Reference) {
+                    eprintln!("! {}: {}", path, problem);
+                }
+                Ok(cfg)
+            }
+            None => {
+                // the whole file is left out, which shouldn't go unnoticed
+                for problem in &problems {
+                    eprintln!("! {}: {}", path, problem);
+                }
+                eprintln!("! {} is ignored until that is fixed; `recli config validate` checks it", path);
+                Ok(Self::default())
+            }
+        }
+    }
+}
+
+/// RECLI_STRICT_CONFIG asks for strict mode whatever the file says
+pub fn strict_from_env() -> bool {
+    std::env::var("RECLI_STRICT_CONFIG").is_ok_and(|v| matches!(v.trim(), "1" | "true" | "yes"))
+}
+
+// keys holding shell commands, where `${VAR}` is the shell's to expand
+const SHELL_KEYS: [&str; 2] = ["run", "key_command"];
+
+fn expand_value(value: &mut Value, path: &str, failed: &mut Vec<(String, String)>) {
+    match value {
+        Value::String(text) => match expand_str(text) {
+            Ok(expanded) => *text = expanded,
+            Err(e) => failed.push((path.to_string(), e)),
+        },
+        Value::Array(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                expand_value(item, &format!("{}[{}]", path, i), failed);
+            }
+        }
+        Value::Object(map) => {
+            for (key, item) in map.iter_mut().filter(|(key, _)| !SHELL_KEYS.contains(&key.as_str())) {
+                let path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                expand_value(item, &path, failed);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `${NAME}` replaced by that environment variable (`$${` is a literal `${`), then a value starting
+/// with `file:` replaced by the file's contents without the trailing newline
+pub fn expand_str(raw: &str) -> Result<String, String> {
+    let mut out = String::with_capacity(raw.len());
+    let mut rest = raw;
+    while let Some(at) = rest.find("${") {
+        if rest[..at].ends_with('$') {
+            out.push_str(&rest[..at - 1]);
+            out.push_str("${");
+            rest = &rest[at + 2..];
+            continue;
+        }
+        out.push_str(&rest[..at]);
+        let Some(len) = rest[at + 2..].find('}') else { break };
+        let name = &rest[at + 2..at + 2 + len];
+        let reference = &rest[at..at + 3 + len];
+        rest = &rest[at + 3 + len..];
+        // anything but a plain name, like ${X:-y}, is left as written
+        let plain = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !plain {
+            out.push_str(reference);
+            continue;
+        }
+        match std::env::var(name) {
+            Ok(v) => out.push_str(&v),
+            Err(_) => return Err(format!("{} is not set", reference)),
+        }
+    }
+    out.push_str(rest);
+    let Some(path) = out.strip_prefix("file:") else { return Ok(out) };
+    let path = shellexpand::tilde(path.trim()).to_string();
+    let text = fs::read_to_string(&path).map_err(|e| format!("file:{}: {}", path, e))?;
+    Ok(text.trim_end_matches(['\n', '\r']).to_string())
+}
@@ -0,0 +1,354 @@
+use crate::config::Config;
+use crate::io::parse_key_sequence;
+use crate::share::Target;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fmt;
+use std::ops::Range;
+use toml_edit::{ImDocument, Item, TableLike};
+
+/// what is wrong with a setting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProblemKind {
+    /// not toml; none of the file is used
+    Syntax,
+    /// a value of the wrong type or an unknown choice; none of the file is used
+    Type,
+    /// a key recli doesn't know, ignored
+    UnknownKey,
+    /// a `${VAR}` or `file:` reference that can't be resolved; the value is used as written
+    Reference,
+    /// a value recli can't use
+    Invalid,
+    /// settings that contradict each other, so one of them has no effect
+    Conflict,
+}
+
+impl ProblemKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ProblemKind::Syntax => "syntax error",
+            ProblemKind::Type => "wrong type",
+            ProblemKind::UnknownKey => "unknown key",
+            ProblemKind::Reference => "unresolved",
+            ProblemKind::Invalid => "invalid value",
+            ProblemKind::Conflict => "conflict",
+        }
+    }
+}
+
+/// one problem with a config file, where it is when that can be told
+#[derive(Debug, Clone, Serialize)]
+pub struct Problem {
+    pub kind: ProblemKind,
+    pub line: Option<usize>, // 1-based; none for settings from the environment or the defaults
+    pub key: Option<String>, // dotted path, e.g. recording.quota_mb or hooks.after_command[0].url
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        if let Some(key) = &self.key {
+            write!(f, "{}: ", key)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// check config `text` for everything `recli config validate` reports, in the order it appears in
+/// the file; also the config it gives, with references resolved, none when the file can't be used
+/// at all. Settings are checked with the RECLI_* environment applied, as recli would run with them.
+pub fn check(text: &str) -> (Option<Config>, Vec<Problem>) {
+    let doc = match ImDocument::parse(text) {
+        Ok(doc) => doc,
+        Err(e) => {
+            let problem = Problem {
+                kind: ProblemKind::Syntax,
+                line: e.span().map(|s| line_of(text, s.start)),
+                key: None,
+                message: e.message().trim().lines().collect::<Vec<_>>().join("; "),
+            };
+            return (None, vec![problem]);
+        }
+    };
+    let mut located = Vec::new();
+    locate(doc.as_table(), "", &mut located);
+    let line = |path: &str| {
+        let found = located.iter().find(|l| l.path == path)?;
+        Some(line_of(text, found.key.as_ref().or(found.value.as_ref())?.start))
+    };
+
+    let mut cfg = match toml::from_str::<Config>(text) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            // serde stops at the first bad value; the innermost key around it is the one to fix
+            let at = e.span().map(|s| s.start);
+            let key = at.and_then(|at| {
+                located
+                    .iter()
+                    .filter(|l| l.value.as_ref().is_some_and(|v| v.contains(&at)))
+                    .min_by_key(|l| l.value.as_ref().map(|v| v.len()))
+                    .map(|l| l.path.clone())
+            });
+            let problem = Problem {
+                kind: ProblemKind::Type,
+                line: at.map(|at| line_of(text, at)),
+                key,
+                message: e.message().trim().to_string(),
+            };
+            return (None, vec![problem]);
+        }
+    };
+
+    let mut problems = Vec::new();
+    // whatever recli read from the file comes back when the config is written out, so keys that
+    // don't are the ones it dropped
+    let known = serde_json::to_value(&cfg).unwrap_or_default();
+    let given = toml::from_str::<toml::Table>(text).ok().and_then(|t| serde_json::to_value(t).ok()).unwrap_or_default();
+    let mut unknown = Vec::new();
+    unknown_keys(&given, &known, "", &mut unknown);
+    for (path, message) in unknown {
+        problems.push(Problem { kind: ProblemKind::UnknownKey, line: line(&path), key: Some(path), message });
+    }
+
+    for (path, message) in cfg.expand_references() {
+        problems.push(Problem { kind: ProblemKind::Reference, line: line(&path), key: Some(path), message });
+    }
+
+    let mut effective = cfg.clone();
+    effective.apply_env();
+    for (kind, path, message) in settings(&effective, &|path| line(path).is_some()) {
+        problems.push(Problem { kind, line: line(&path), key: Some(path), message });
+    }
+    problems.sort_by_key(|p| p.line.unwrap_or(usize::MAX));
+    (Some(cfg), problems)
+}
+
+// a key of the file and the byte ranges of its name and its value
+struct Located {
+    path: String,
+    key: Option<Range<usize>>,
+    value: Option<Range<usize>>,
+}
+
+fn locate(table: &dyn TableLike, prefix: &str, out: &mut Vec<Located>) {
+    for (name, _) in table.iter() {
+        let Some((key, item)) = table.get_key_value(name) else { continue };
+        let path = join(prefix, name);
+        out.push(Located { path: path.clone(), key: key.span().or_else(|| item.span()), value: item.span() });
+        match item {
+            Item::Table(t) => locate(t, &path, out),
+            Item::ArrayOfTables(tables) => {
+                for (i, t) in tables.iter().enumerate() {
+                    let path = format!("{}[{}]", path, i);
+                    out.push(Located { path: path.clone(), key: t.span(), value: t.span() });
+                    locate(t, &path, out);
+                }
+            }
+            Item::Value(toml_edit::Value::InlineTable(t)) => locate(t, &path, out),
+            Item::Value(toml_edit::Value::Array(values)) => {
+                for (i, v) in values.iter().enumerate() {
+                    let path = format!("{}[{}]", path, i);
+                    out.push(Located { path: path.clone(), key: v.span(), value: v.span() });
+                    if let toml_edit::Value::InlineTable(t) = v {
+                        locate(t, &path, out);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn join(prefix: &str, name: &str) -> String {
+    match prefix {
+        "" => name.to_string(),
+        _ => format!("{}.{}", prefix, name),
+    }
+}
+
+fn line_of(text: &str, offset: usize) -> usize {
+    text.as_bytes()[..offset.min(text.len())].iter().filter(|&&b| b == b'\n').count() + 1
+}
+
+// keys of `given` that `known` doesn't have, with a hint at what was meant
+fn unknown_keys(given: &Value, known: &Value, prefix: &str, out: &mut Vec<(String, String)>) {
+    match (given, known) {
+        (Value::Object(given), Value::Object(known)) => {
+            for (name, value) in given {
+                let path = join(prefix, name);
+                match known.get(name) {
+                    Some(known) => unknown_keys(value, known, &path, out),
+                    None => out.push((path, format!("unknown key{}", hint(name, known)))),
+                }
+            }
+        }
+        (Value::Array(given), Value::Array(known)) => {
+            for (i, (given, known)) in given.iter().zip(known).enumerate() {
+                unknown_keys(given, known, &format!("{}[{}]", prefix, i), out);
+            }
+        }
+        _ => {}
+    }
+}
+
+// a key of the same table spelled almost the same, or the sections that do have `name`
+fn hint(name: &str, siblings: &Map<String, Value>) -> String {
+    let close = siblings
+        .keys()
+        .map(|k| (strsim::damerau_levenshtein(name, k), k))
+        .filter(|(d, k)| *d <= 2 && *d < k.len() / 2 + 1)
+        .min();
+    if let Some((_, key)) = close {
+        return format!(", ignored; did you mean {}?", key);
+    }
+    let mut places = Vec::new();
+    sections_with(&serde_json::to_value(Config::default()).unwrap_or_default(), name, "", &mut places);
+    match places.first() {
+        Some(place) if place.is_empty() => " here, ignored; it goes at the top, before any [section]".to_string(),
+        Some(place) => format!(" here, ignored; it goes under [{}]", place),
+        None => ", ignored".to_string(),
+    }
+}
+
+fn sections_with(value: &Value, name: &str, prefix: &str, out: &mut Vec<String>) {
+    let Value::Object(map) = value else { return };
+    if map.contains_key(name) {
+        out.push(prefix.to_string());
+    }
+    for (key, value) in map {
+        sections_with(value, name, &join(prefix, key), out);
+    }
+}
+
+// values recli can't use and settings that cancel each other out; `in_file` tells the keys the
+// file sets from defaults, so a clash is put on the setting that caused it
+fn settings(cfg: &Config, in_file: &dyn Fn(&str) -> bool) -> Vec<(ProblemKind, String, String)> {
+    use ProblemKind::{Conflict, Invalid};
+    let mut found = Vec::new();
+    let mut problem = |kind, path: &str, message: String| found.push((kind, path.to_string(), message));
+
+    if let Some(key) = cfg.azure.as_ref().and_then(|a| a.cosmos.as_ref()).and_then(|c| c.partition_key.as_deref()) {
+        if !matches!(key.trim_start_matches('/'), "session_id" | "workspace") {
+            problem(Invalid, "azure.cosmos.partition_key", format!("{:?} is neither /session_id nor /workspace", key));
+        }
+    }
+    if !matches!(cfg.report.tls.as_str(), "starttls" | "tls" | "none") {
+        problem(Invalid, "report.tls", format!("{:?} is not starttls, tls or none", cfg.report.tls));
+    }
+    if cfg.recording.idle_new_session && cfg.recording.idle_segment_minutes.is_none() {
+        problem(Conflict, "recording.idle_new_session", "has no effect without idle_segment_minutes".to_string());
+    }
+    if cfg.encryption.key_command.is_some() && cfg.encryption.key_file.is_some() {
+        problem(Conflict, "encryption.key_file", "is not read, key_command is set and takes precedence".to_string());
+    }
+
+    let server = cfg.team.server.as_deref().is_some_and(|s| !s.is_empty());
+    let token = cfg.team.token.as_deref().is_some_and(|t| !t.is_empty());
+    if server && !token {
+        problem(Invalid, "team.server", "needs a token as well (token or RECLI_TEAM__TOKEN)".to_string());
+    } else if token && !server {
+        problem(Conflict, "team.token", "has no effect without a server".to_string());
+    }
+
+    if let Some(target) = cfg.share.target.as_deref() {
+        match Target::parse(target) {
+            Err(e) => problem(Invalid, "share.target", e),
+            Ok(target) => {
+                let (set, needs) = match target {
+                    Target::Azure => (cfg.share.azure_sas_url.is_some(), "azure_sas_url"),
+                    Target::S3 => (cfg.share.s3_bucket.is_some(), "s3_bucket"),
+                    Target::Gist => (cfg.share.github_token.is_some(), "github_token"),
+                };
+                if !set {
+                    problem(Invalid, "share.target", format!("{} isn't set up, it needs {}", target.name(), needs));
+                }
+            }
+        }
+    }
+    if let Some(profile) = cfg.filters.profile.as_deref().filter(|p| !cfg.filters.profiles.contains_key(*p)) {
+        problem(Invalid, "filters.profile", format!("there is no [filters.profiles.{}]", profile));
+    }
+    if let Some(profile) = cfg.policy.profile.as_deref().filter(|p| !cfg.policy.profiles.contains_key(*p)) {
+        problem(Invalid, "policy.profile", format!("there is no [policy.profiles.{}]", profile));
+    }
+
+    let hooks = [
+        ("session_start", &cfg.hooks.session_start),
+        ("session_end", &cfg.hooks.session_end),
+        ("before_command", &cfg.hooks.before_command),
+        ("after_command", &cfg.hooks.after_command),
+        ("output_match", &cfg.hooks.output_match),
+        ("command_hung", &cfg.hooks.command_hung),
+    ];
+    for (event, list) in hooks {
+        for (i, hook) in list.iter().enumerate() {
+            if hook.run.is_none() && hook.url.is_none() {
+                problem(Invalid, &format!("hooks.{}[{}]", event, i), "has neither run nor url, it does nothing".to_string());
+            }
+        }
+    }
+
+    let mut patterns: Vec<(String, &String)> = Vec::new();
+    patterns.extend(cfg.watch.patterns.iter().enumerate().map(|(i, p)| (format!("watch.patterns[{}]", i), p)));
+    patterns.extend(cfg.detection.prompt_patterns.iter().enumerate().map(|(i, p)| (format!("detection.prompt_patterns[{}]", i), p)));
+    for (shell, detection) in &cfg.detection.shells {
+        for (i, p) in detection.prompt_patterns.iter().flatten().enumerate() {
+            patterns.push((format!("detection.shells.{}.prompt_patterns[{}]", shell, i), p));
+        }
+    }
+    for (path, pattern) in patterns {
+        if let Err(e) = regex::Regex::new(pattern) {
+            // the regex error spans several lines to point at the spot
+            let reason = e.to_string().lines().last().unwrap_or_default().trim().trim_start_matches("error: ").to_string();
+            problem(Invalid, &path, format!("{:?} is not a regex: {}", pattern, reason));
+        }
+    }
+
+    // a binding that starts with another's keys can never be typed, the shorter one fires first
+    if cfg.hotkeys.enabled {
+        let h = &cfg.hotkeys;
+        let bindings = [
+            ("terminate", &h.terminate),
+            ("pause", &h.pause),
+            ("annotate", &h.annotate),
+            ("hung", &h.hung),
+            ("interrupt", &h.interrupt),
+            ("note", &h.note),
+        ];
+        let mut parsed: Vec<(&str, &str, Vec<u8>)> = Vec::new();
+        for (name, spec) in bindings {
+            let Some(spec) = spec else { continue };
+            match parse_key_sequence(spec) {
+                Ok(keys) if keys.is_empty() => problem(Invalid, &format!("hotkeys.{}", name), "names no keys".to_string()),
+                Ok(keys) => parsed.push((name, spec, keys)),
+                Err(e) => problem(Invalid, &format!("hotkeys.{}", name), e),
+            }
+        }
+        let path = |name: &str| format!("hotkeys.{}", name);
+        for (i, (name, spec, keys)) in parsed.iter().enumerate() {
+            // reported once per pair, on the binding the file sets rather than on a default
+            for (other, _, _) in parsed[..i].iter().filter(|(_, _, k)| k == keys) {
+                let (at, with) = if in_file(&path(name)) { (name, other) } else { (other, name) };
+                problem(Conflict, &path(at), format!("is bound to the same keys as {}", with));
+            }
+            let longer: Vec<&str> =
+                parsed.iter().filter(|(_, _, k)| k.len() > keys.len() && k.starts_with(keys)).map(|(n, _, _)| *n).collect();
+            let set: Vec<&str> = longer.iter().copied().filter(|o| in_file(&path(o))).collect();
+            if in_file(&path(name)) || set.is_empty() {
+                if !longer.is_empty() {
+                    problem(Conflict, &path(name), format!("is the start of {}, so they can't be typed", longer.join(", ")));
+                }
+            } else {
+                for other in set {
+                    problem(Conflict, &path(other), format!("can't be typed, {} ({}) fires first", name, spec));
+                }
+            }
+        }
+    }
+    found
+}
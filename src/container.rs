@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+
+/// the program that runs the container
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Runtime {
+    Docker,
+    Podman,
+}
+
+impl Runtime {
+    pub fn program(self) -> &'static str {
+        match self {
+            Runtime::Docker => "docker",
+            Runtime::Podman => "podman",
+        }
+    }
+}
+
+/// the container a session was recorded in, kept in its metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContainerInfo {
+    pub runtime: Runtime,
+    pub id: String,
+    pub name: String,
+    pub image: String,
+}
+
+/// look up `container` (a name or id) with `runtime inspect`
+pub fn inspect(runtime: Runtime, container: &str) -> Result<ContainerInfo, String> {
+    let out = Command::new(runtime.program())
+        .args(["inspect", "--type", "container", "--format", "{{.Id}}\t{{.Name}}\t{{.Config.Image}}", container])
+        .output()
+        .map_err(|e| format!("{}: {}", runtime.program(), e))?;
+    if !out.status.success() {
+        return Err(format!(
+            "{} inspect {}: {}",
+            runtime.program(),
+            container,
+            String::from_utf8_lossy(&out.stderr).trim()
+        ));
+    }
+    let text = String::from_utf8_lossy(&out.stdout);
+    let mut fields = text.trim().split('\t');
+    let (Some(id), Some(name), Some(image)) = (fields.next(), fields.next(), fields.next()) else {
+        return Err(format!("{} inspect printed nothing for {}", runtime.program(), container));
+    };
+    Ok(ContainerInfo {
+        runtime,
+        id: id.to_string(),
+        // docker prints the name with a leading slash
+        name: name.trim_start_matches('/').to_string(),
+        image: image.to_string(),
+    })
+}
+
+/// `runtime exec -it` running `command` in the container as `user`
+pub fn exec_argv(info: &ContainerInfo, user: Option<&str>, command: &[String]) -> Vec<String> {
+    let mut argv = vec![info.runtime.program().to_string(), "exec".to_string(), "-it".to_string()];
+    // the host terminal's type, so full-screen programs draw as they would outside
+    if std::env::var_os("TERM").is_some() {
+        argv.push("--env".to_string());
+        argv.push("TERM".to_string());
+    }
+    if let Some(user) = user {
+        argv.push("--user".to_string());
+        argv.push(user.to_string());
+    }
+    argv.push(info.id.clone());
+    argv.extend(command.iter().cloned());
+    argv
+}
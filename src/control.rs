@@ -0,0 +1,375 @@
+use crate::command_log::{CommandEntry, CommandLog};
+use crate::session::{LogEvent, SessionConfig};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::{broadcast, mpsc};
+
+/// failed commands kept in the status a new client gets
+pub const RECENT_FAILURES: usize = 10;
+// events a slow client may fall behind by before it misses some
+const EVENT_BACKLOG: usize = 1024;
+// how long `reload` waits for the recorder, which reads the config between two reads of the pty
+const RELOAD_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// what a recording session knows about itself right now
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Status {
+    pub session_id: String,
+    pub started_at: String,
+    pub shell: String,
+    pub pid: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub running: Option<Running>,
+    pub commands: usize,
+    pub failed: usize,
+    pub recent_failures: VecDeque<Finished>, // newest last
+    pub output_bytes: u64,
+    pub output_dropped: bool, // output isn't stored for lack of space
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Running {
+    pub cmd: String,
+    pub cwd: String,
+    pub started_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Finished {
+    pub cmd: String,
+    pub exit_code: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    pub timestamp: String,
+}
+
+/// one json line sent to subscribers of the control socket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ControlEvent {
+    /// the whole status, first thing a client gets
+    Status(Status),
+    CommandStart(Running),
+    Output { bytes: usize },
+    CommandEnd(Finished),
+    OutputDropped { dropped: bool },
+    ConfigReloaded(ConfigReloaded),
+}
+
+/// `note <json>`: set the note of a finished command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteRequest {
+    #[serde(default = "default_nth")]
+    pub nth: usize, // 1 is the last command
+    #[serde(default)]
+    pub failed: bool, // count failed commands only
+    pub text: String, // empty removes the note
+}
+
+fn default_nth() -> usize {
+    1
+}
+
+/// the command a note went to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Noted {
+    pub cmd: String,
+    pub exit_code: i32,
+}
+
+/// what a session did with its config file when it read it again
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConfigReloaded {
+    pub applied: Vec<String>, // changed settings now in effect, e.g. "filters" or "logging.level"
+    pub restart: Vec<String>, // changed sections that wait for the next session
+    // the file couldn't be used, so nothing changed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// what the socket may change: the commands, and the log task that saves them; the sender is
+// weak since the server lives in that task, which ends when every other sender is gone
+#[derive(Clone)]
+struct Commands {
+    log: Arc<Mutex<CommandLog>>,
+    log_events: mpsc::WeakUnboundedSender<LogEvent>,
+    reload: Arc<AtomicBool>,
+}
+
+/// the control socket of a recording session; clients send one request line:
+/// `status` for the status as one json line, `subscribe` for it followed by every event,
+/// `note <json>` (a NoteRequest) for a Noted or an error, `reload` for a ConfigReloaded,
+/// `log-level [filter]` for `{"filter": ...}` in effect after setting it, if given
+pub struct ControlServer {
+    path: PathBuf,
+    status: Arc<Mutex<Status>>,
+    events: broadcast::Sender<ControlEvent>,
+    listener: tokio::task::JoinHandle<()>,
+}
+
+impl ControlServer {
+    /// listen on `path`, replacing a socket left behind by a recorder that died; notes are set
+    /// on `log` and saved by the log task behind `log_events`; `reload` is raised for the recorder
+    /// to read its config again
+    #[cfg(unix)]
+    pub fn start(
+        path: &Path,
+        config: &SessionConfig,
+        log: Arc<Mutex<CommandLog>>,
+        log_events: mpsc::WeakUnboundedSender<LogEvent>,
+        reload: Arc<AtomicBool>,
+    ) -> std::io::Result<Self> {
+        let _ = std::fs::remove_file(path);
+        let listener = tokio::net::UnixListener::bind(path)?;
+        let status = Arc::new(Mutex::new(Status {
+            session_id: config.session_id.clone(),
+            started_at: config.started_at.clone(),
+            shell: config.shell.clone(),
+            pid: std::process::id(),
+            ..Status::default()
+        }));
+        let (events, _) = broadcast::channel(EVENT_BACKLOG);
+        let (shared, sender) = (status.clone(), events.clone());
+        let commands = Commands { log, log_events, reload };
+        let listener = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, shared.clone(), sender.subscribe(), commands.clone()));
+            }
+        });
+        Ok(Self { path: path.to_path_buf(), status, events, listener })
+    }
+
+    #[cfg(not(unix))]
+    pub fn start(
+        _path: &Path,
+        _config: &SessionConfig,
+        _log: Arc<Mutex<CommandLog>>,
+        _log_events: mpsc::WeakUnboundedSender<LogEvent>,
+        _reload: Arc<AtomicBool>,
+    ) -> std::io::Result<Self> {
+        Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "no control socket on this platform"))
+    }
+
+    pub fn command_started(&self, cmd: &str, cwd: &str) {
+        let running = Running {
+            cmd: cmd.to_string(),
+            cwd: cwd.to_string(),
+            started_at: crate::util::time::now_stamp(),
+        };
+        self.update(|s| s.running = Some(running.clone()));
+        let _ = self.events.send(ControlEvent::CommandStart(running));
+    }
+
+    pub fn output(&self, bytes: usize) {
+        self.update(|s| s.output_bytes += bytes as u64);
+        let _ = self.events.send(ControlEvent::Output { bytes });
+    }
+
+    pub fn command_finished(&self, entry: &CommandEntry) {
+        let finished = Finished {
+            cmd: entry.cmd.clone(),
+            exit_code: entry.exit_code,
+            duration_ms: entry.duration_ms,
+            timestamp: entry.timestamp.clone(),
+        };
+        self.update(|s| {
+            s.running = None;
+            s.commands += 1;
+            if finished.exit_code != 0 {
+                s.failed += 1;
+                s.recent_failures.push_back(finished.clone());
+                if s.recent_failures.len() > RECENT_FAILURES {
+                    s.recent_failures.pop_front();
+                }
+            }
+        });
+        let _ = self.events.send(ControlEvent::CommandEnd(finished));
+    }
+
+    pub fn output_dropped(&self, dropped: bool) {
+        self.update(|s| s.output_dropped = dropped);
+        let _ = self.events.send(ControlEvent::OutputDropped { dropped });
+    }
+
+    pub fn config_reloaded(&self, reloaded: ConfigReloaded) {
+        let _ = self.events.send(ControlEvent::ConfigReloaded(reloaded));
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Status)) {
+        if let Ok(mut status) = self.status.lock() {
+            f(&mut status);
+        }
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.listener.abort();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(unix)]
+async fn serve(
+    stream: tokio::net::UnixStream,
+    status: Arc<Mutex<Status>>,
+    mut events: broadcast::Receiver<ControlEvent>,
+    commands: Commands,
+) {
+    let (read, mut write) = stream.into_split();
+    let mut request = String::new();
+    if BufReader::new(read).read_line(&mut request).await.is_err() {
+        return;
+    }
+    if let Some(body) = request.trim().strip_prefix("note ") {
+        let reply = match apply_note(&commands, body) {
+            Ok(noted) => serde_json::to_value(noted).unwrap_or_default(),
+            Err(e) => serde_json::json!({ "error": e }),
+        };
+        let _ = write.write_all(format!("{}\n", reply).as_bytes()).await;
+        return;
+    }
+    if let Some(filter) = request.trim().strip_prefix("log-level").filter(|f| f.is_empty() || f.starts_with(' ')) {
+        let reply = match set_log_level(filter.trim()) {
+            Ok(filter) => serde_json::json!({ "filter": filter }),
+            Err(e) => serde_json::json!({ "error": e }),
+        };
+        let _ = write.write_all(format!("{}\n", reply).as_bytes()).await;
+        return;
+    }
+    if request.trim() == "reload" {
+        commands.reload.store(true, Ordering::Relaxed);
+        let reply = match tokio::time::timeout(RELOAD_TIMEOUT, next_reload(&mut events)).await {
+            Ok(Some(reloaded)) => serde_json::to_value(reloaded).unwrap_or_default(),
+            _ => serde_json::json!({ "error": "the session didn't read its config; sessions recorded with shell hooks only don't" }),
+        };
+        let _ = write.write_all(format!("{}\n", reply).as_bytes()).await;
+        return;
+    }
+    let reply = match request.trim() {
+        "status" | "subscribe" => {
+            let status = status.lock().map(|s| s.clone()).unwrap_or_default();
+            ControlEvent::Status(status)
+        }
+        other => {
+            let error = serde_json::json!({ "error": format!("unknown request: {}", other) });
+            let _ = write.write_all(format!("{}\n", error).as_bytes()).await;
+            return;
+        }
+    };
+    if send(&mut write, &reply).await.is_err() || request.trim() != "subscribe" {
+        return;
+    }
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                if send(&mut write, &event).await.is_err() {
+                    return;
+                }
+            }
+            // a client that can't keep up gets a fresh status instead of what it missed
+            Err(broadcast::error::RecvError::Lagged(_)) => {
+                let status = status.lock().map(|s| s.clone()).unwrap_or_default();
+                if send(&mut write, &ControlEvent::Status(status)).await.is_err() {
+                    return;
+                }
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
+
+// the report of the next reload, which may have been started by the file changing just before
+async fn next_reload(events: &mut broadcast::Receiver<ControlEvent>) -> Option<ConfigReloaded> {
+    loop {
+        match events.recv().await {
+            Ok(ControlEvent::ConfigReloaded(reloaded)) => return Some(reloaded),
+            Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+            Err(broadcast::error::RecvError::Closed) => return None,
+        }
+    }
+}
+
+// diagnostics are the recorder's own, so the filter is set here rather than by the session
+fn set_log_level(filter: &str) -> Result<String, String> {
+    if !filter.is_empty() {
+        crate::util::telemetry::set_filter(filter).map_err(|e| format!("bad filter {:?}: {}", filter, e))?;
+        tracing::info!(filter, "diagnostics filter changed through the control socket");
+    }
+    crate::util::telemetry::current_filter().ok_or_else(|| "diagnostics are not set up in this session".to_string())
+}
+
+fn apply_note(commands: &Commands, body: &str) -> Result<Noted, String> {
+    let request: NoteRequest = serde_json::from_str(body).map_err(|e| format!("bad note request: {}", e))?;
+    let noted = {
+        let mut log = commands.log.lock().map_err(|_| "the command log is unavailable".to_string())?;
+        let entry = log.note(request.nth, request.failed, &request.text)?;
+        Noted { cmd: entry.cmd.clone(), exit_code: entry.exit_code }
+    };
+    if let Some(log_events) = commands.log_events.upgrade() {
+        let _ = log_events.send(LogEvent::Save);
+    }
+    Ok(noted)
+}
+
+async fn send(write: &mut (impl AsyncWriteExt + Unpin), event: &ControlEvent) -> std::io::Result<()> {
+    let mut line = serde_json::to_vec(event)?;
+    line.push(b'\n');
+    write.write_all(&line).await
+}
+
+/// set a note through the control socket at `path`
+#[cfg(unix)]
+pub async fn note(path: &Path, request: &NoteRequest) -> std::io::Result<Result<Noted, String>> {
+    let mut lines = connect(path, &format!("note {}", serde_json::to_string(request)?)).await?;
+    let line = lines.next_line().await?.unwrap_or_default();
+    let reply: serde_json::Value = serde_json::from_str(&line)?;
+    if let Some(error) = reply.get("error").and_then(|e| e.as_str()) {
+        return Ok(Err(error.to_string()));
+    }
+    Ok(Ok(serde_json::from_value(reply)?))
+}
+
+/// have the session behind the control socket at `path` read its config again
+#[cfg(unix)]
+pub async fn reload(path: &Path) -> std::io::Result<Result<ConfigReloaded, String>> {
+    let mut lines = connect(path, "reload").await?;
+    let line = lines.next_line().await?.unwrap_or_default();
+    let reply: ConfigReloaded = serde_json::from_str(&line)?;
+    match reply.error {
+        Some(error) => Ok(Err(error)),
+        None => Ok(Ok(reply)),
+    }
+}
+
+/// set the diagnostics filter of the session behind the control socket at `path`, or just read it
+/// when `filter` is none; returns the filter in effect
+#[cfg(unix)]
+pub async fn log_level(path: &Path, filter: Option<&str>) -> std::io::Result<Result<String, String>> {
+    let request = match filter {
+        Some(filter) => format!("log-level {}", filter),
+        None => "log-level".to_string(),
+    };
+    let mut lines = connect(path, &request).await?;
+    let line = lines.next_line().await?.unwrap_or_default();
+    let reply: serde_json::Value = serde_json::from_str(&line)?;
+    if let Some(error) = reply.get("error").and_then(|e| e.as_str()) {
+        return Ok(Err(error.to_string()));
+    }
+    Ok(Ok(reply.get("filter").and_then(|f| f.as_str()).unwrap_or_default().to_string()))
+}
+
+/// connect to the control socket at `path` and send `request`
+#[cfg(unix)]
+pub async fn connect(path: &Path, request: &str) -> std::io::Result<tokio::io::Lines<BufReader<tokio::net::unix::OwnedReadHalf>>> {
+    let stream = tokio::net::UnixStream::connect(path).await?;
+    let (read, mut write) = stream.into_split();
+    write.write_all(format!("{}\n", request).as_bytes()).await?;
+    Ok(BufReader::new(read).lines())
+}
@@ -0,0 +1,248 @@
+use crate::control::{self, ControlEvent, Status};
+use crate::error::{RecliError, Result};
+use crate::util::time::format_duration_ms;
+use chrono::{DateTime, Local, Utc};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, Paragraph, Sparkline};
+use ratatui::{Frame, Terminal};
+use std::collections::VecDeque;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+// seconds of output rate kept for the graph
+const RATE_SECONDS: usize = 60;
+const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+
+enum Update {
+    Event(ControlEvent),
+    Key(KeyCode, KeyModifiers),
+    // the session closed its socket
+    Ended,
+}
+
+struct Dashboard {
+    status: Status,
+    upload: Vec<String>,
+    // bytes of output per second, newest last, and the second being counted
+    rate: VecDeque<u64>,
+    counting: u64,
+    second_started: Instant,
+}
+
+/// draw the session behind the control socket at `path` until it ends or q is pressed;
+/// `upload` describes where the session goes when it ends
+pub async fn run(path: &Path, upload: Vec<String>) -> Result<()> {
+    let mut lines = control::connect(path, "subscribe").await.map_err(RecliError::Control)?;
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    let events = tx.clone();
+    tokio::spawn(async move {
+        while let Ok(Some(line)) = lines.next_line().await {
+            match serde_json::from_str::<ControlEvent>(&line) {
+                Ok(event) => {
+                    if events.send(Update::Event(event)).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => tracing::debug!(error = %e, line, "unreadable control event"),
+            }
+        }
+        let _ = events.send(Update::Ended);
+    });
+    // crossterm's reads block, so keys are read on a thread of their own
+    std::thread::spawn(move || loop {
+        match event::poll(REDRAW_INTERVAL) {
+            Ok(true) => {
+                if let Ok(Event::Key(key)) = event::read() {
+                    if key.kind != KeyEventKind::Release && tx.send(Update::Key(key.code, key.modifiers)).is_err() {
+                        return;
+                    }
+                }
+            }
+            Ok(false) if tx.is_closed() => return,
+            Ok(false) => {}
+            Err(_) => return,
+        }
+    });
+
+    enable_raw_mode().map_err(RecliError::Terminal)?;
+    let mut stdout = std::io::stdout();
+    execute!(stdout, EnterAlternateScreen).map_err(RecliError::Terminal)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout)).map_err(RecliError::Terminal)?;
+
+    let mut dashboard = Dashboard {
+        status: Status::default(),
+        upload,
+        rate: VecDeque::from(vec![0; RATE_SECONDS]),
+        counting: 0,
+        second_started: Instant::now(),
+    };
+    let mut ended = false;
+    let result = loop {
+        dashboard.tick();
+        if let Err(e) = terminal.draw(|frame| dashboard.draw(frame)) {
+            break Err(RecliError::Terminal(e));
+        }
+        let update = tokio::time::timeout(REDRAW_INTERVAL, rx.recv()).await;
+        match update {
+            Ok(Some(Update::Event(event))) => dashboard.apply(event),
+            Ok(Some(Update::Key(code, modifiers))) => {
+                let ctrl_c = code == KeyCode::Char('c') && modifiers.contains(KeyModifiers::CONTROL);
+                if ctrl_c || matches!(code, KeyCode::Char('q') | KeyCode::Esc) {
+                    break Ok(());
+                }
+            }
+            Ok(Some(Update::Ended)) | Ok(None) => {
+                ended = true;
+                break Ok(());
+            }
+            Err(_) => {}
+        }
+    };
+
+    let _ = disable_raw_mode();
+    let _ = execute!(terminal.backend_mut(), LeaveAlternateScreen);
+    if ended {
+        println!("session {} ended", dashboard.status.session_id);
+    }
+    result
+}
+
+impl Dashboard {
+    fn apply(&mut self, event: ControlEvent) {
+        let status = &mut self.status;
+        match event {
+            ControlEvent::Status(s) => *status = s,
+            ControlEvent::CommandStart(running) => status.running = Some(running),
+            ControlEvent::Output { bytes } => {
+                status.output_bytes += bytes as u64;
+                self.counting += bytes as u64;
+            }
+            ControlEvent::CommandEnd(finished) => {
+                status.running = None;
+                status.commands += 1;
+                if finished.exit_code != 0 {
+                    status.failed += 1;
+                    status.recent_failures.push_back(finished);
+                    if status.recent_failures.len() > control::RECENT_FAILURES {
+                        status.recent_failures.pop_front();
+                    }
+                }
+            }
+            ControlEvent::OutputDropped { dropped } => status.output_dropped = dropped,
+            ControlEvent::ConfigReloaded(_) => {}
+        }
+    }
+
+    // move the rate graph on by the seconds that passed
+    fn tick(&mut self) {
+        while self.second_started.elapsed() >= Duration::from_secs(1) {
+            self.rate.push_back(std::mem::take(&mut self.counting));
+            if self.rate.len() > RATE_SECONDS {
+                self.rate.pop_front();
+            }
+            self.second_started += Duration::from_secs(1);
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(4),
+                Constraint::Length(5),
+                Constraint::Length(7),
+                Constraint::Min(4),
+                Constraint::Length(2 + self.upload.len().max(1) as u16),
+            ])
+            .split(frame.size());
+        let dim = Style::default().add_modifier(Modifier::DIM);
+        let status = &self.status;
+
+        let session = vec![
+            Line::from(vec![
+                Span::styled(status.session_id.clone(), Style::default().add_modifier(Modifier::BOLD)),
+                Span::styled(format!("  {} · pid {}", status.shell, status.pid), dim),
+            ]),
+            Line::from(format!(
+                "{} commands, {} failed · recording for {}",
+                status.commands,
+                status.failed,
+                since(&status.started_at).unwrap_or_default()
+            )),
+        ];
+        frame.render_widget(Paragraph::new(session).block(panel("session (q to quit)")), rows[0]);
+
+        let running = match &status.running {
+            Some(r) => vec![
+                Line::from(Span::styled(r.cmd.clone(), Style::default().fg(Color::Cyan))),
+                Line::from(Span::styled(r.cwd.clone(), dim)),
+                Line::from(format!("running for {}", since(&r.started_at).unwrap_or_default())),
+            ],
+            None => vec![Line::from(Span::styled("at the prompt", dim))],
+        };
+        frame.render_widget(Paragraph::new(running).block(panel("running")), rows[1]);
+
+        let now = self.rate.back().copied().unwrap_or(0);
+        let title = format!("output · {}/s now · {} recorded", bytes(now), bytes(status.output_bytes));
+        let data: Vec<u64> = self.rate.iter().copied().collect();
+        frame.render_widget(
+            Sparkline::default().block(panel(&title)).data(&data).style(Style::default().fg(Color::Green)),
+            rows[2],
+        );
+
+        let failures: Vec<ListItem> = status
+            .recent_failures
+            .iter()
+            .rev()
+            .map(|f| {
+                let at = DateTime::parse_from_rfc3339(&f.timestamp)
+                    .map(|t| t.with_timezone(&Local).format("%H:%M:%S").to_string())
+                    .unwrap_or_default();
+                let took = f.duration_ms.map(format_duration_ms).unwrap_or_default();
+                ListItem::new(Line::from(vec![
+                    Span::styled(format!("{} ", at), dim),
+                    Span::styled(format!("exit {:<4}", f.exit_code), Style::default().fg(Color::Red)),
+                    Span::raw(f.cmd.lines().next().unwrap_or_default().to_string()),
+                    Span::styled(format!("  {}", took), dim),
+                ]))
+            })
+            .collect();
+        frame.render_widget(List::new(failures).block(panel("recent failures")), rows[3]);
+
+        let mut upload: Vec<Line> = self.upload.iter().map(|l| Line::from(l.clone())).collect();
+        if status.output_dropped {
+            upload.push(Line::from(Span::styled(
+                "output is not being stored: log quota or free space limit reached",
+                Style::default().fg(Color::Yellow),
+            )));
+        }
+        frame.render_widget(Paragraph::new(upload).block(panel("upload")), rows[4]);
+    }
+}
+
+fn panel(title: &str) -> Block<'_> {
+    Block::default().borders(Borders::ALL).title(title)
+}
+
+// time since an rfc3339 timestamp, e.g. "2m 5s"
+fn since(timestamp: &str) -> Option<String> {
+    let at = DateTime::parse_from_rfc3339(timestamp).ok()?.with_timezone(&Utc);
+    let ms = (Utc::now() - at).num_milliseconds().max(0) as u64;
+    Some(format_duration_ms(ms - ms % 1000))
+}
+
+fn bytes(n: u64) -> String {
+    match n {
+        0..=1023 => format!("{} B", n),
+        1024..=1_048_575 => format!("{:.1} KiB", n as f64 / 1024.0),
+        _ => format!("{:.1} MiB", n as f64 / 1_048_576.0),
+    }
+}
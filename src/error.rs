@@ -0,0 +1,71 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RecliError {
+    /// IO-related errors
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    /// PTY-related errors
+    #[error("PTY error: {0}")]
+    Pty(#[source] Box<dyn std::error::Error + Send + Sync>),
+    /// terminal-related errors, e.g. switching raw mode
+    #[error("Terminal error: {0}")]
+    Terminal(#[source] std::io::Error),
+    /// shell process errors
+    #[error("Shell error: {0}")]
+    Shell(String),
+    /// session management errors
+    #[error("Session error: {0}")]
+    Session(String),
+    /// JSON serialization errors
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    /// local sqlite index errors
+    #[error("Index error: {0}")]
+    Index(#[from] rusqlite::Error),
+    /// a config file, filter profile or pattern that can't be used
+    #[error("Config error: {0}")]
+    Config(String),
+    /// sending data to cosmos, smtp or another sink failed
+    #[error("Upload to {target} failed: {source}")]
+    Upload {
+        target: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// arguments or input that don't make sense
+    #[error("Invalid input: {0}")]
+    Validation(String),
+    /// talking to a running session over its control socket
+    #[error("Control socket error: {0}")]
+    Control(#[source] std::io::Error),
+}
+
+impl RecliError {
+    /// process exit code for this error, so scripts can tell failures apart
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            RecliError::Config(_) => 3,
+            RecliError::Validation(_) => 4,
+            RecliError::Session(_) => 5,
+            RecliError::Upload { .. } => 6,
+            RecliError::Control(_) => 7,
+            RecliError::Pty(_) | RecliError::Terminal(_) | RecliError::Shell(_) => 8,
+            RecliError::Index(_) => 9,
+            RecliError::Io(_) | RecliError::Json(_) => 1,
+        }
+    }
+}
+
+// keeps the error itself as the cause, so main can still map it to an exit code
+impl From<RecliError> for std::io::Error {
+    fn from(error: RecliError) -> Self {
+        match error {
+            RecliError::Io(e) => e,
+            other => std::io::Error::other(other),
+        }
+    }
+}
+
+/// result type alias for Recli operations
+pub type Result<T> = std::result::Result<T, RecliError>;
@@ -0,0 +1,228 @@
+use crate::schema::log_event::LogEventV1;
+use crate::util::anonymize::Anonymizer;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// events a sink may fall behind by; past that they are dropped for it rather than block recording
+const SINK_QUEUE: usize = 1024;
+// how long sinks get to take what is queued for them when the stream is closed
+const SINK_DRAIN: Duration = Duration::from_secs(2);
+
+/// newline-delimited LogEventV1 json written live to stdout, an inherited fd, a file
+/// and any plugin sinks
+pub struct EventStream {
+    outs: Mutex<Vec<Box<dyn Write + Send>>>,
+    sinks: Mutex<Vec<Sink>>,
+    to_stdout: bool,
+    host: String,
+    workspace: Option<String>,
+    notices: Mutex<Notices>,
+    anonymizer: Option<Anonymizer>,
+}
+
+impl EventStream {
+    /// `-` is stdout, `fd:N` an already open descriptor, anything else a file appended to
+    pub fn open(dest: &str, workspace: Option<String>) -> io::Result<Self> {
+        let out: Box<dyn Write + Send> = if dest == "-" {
+            Box::new(io::stdout())
+        } else if let Some(fd) = dest.strip_prefix("fd:") {
+            let fd: i32 = fd
+                .parse()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("invalid fd: {}", dest)))?;
+            open_fd(fd)?
+        } else {
+            Box::new(OpenOptions::new().create(true).append(true).open(dest)?)
+        };
+        let mut stream = Self::new(workspace);
+        stream.add_output(out);
+        stream.to_stdout = dest == "-";
+        Ok(stream)
+    }
+
+    /// a stream with nowhere to write yet
+    pub fn new(workspace: Option<String>) -> Self {
+        let host = hostname::get()
+            .ok()
+            .and_then(|h| h.into_string().ok())
+            .unwrap_or_else(|| "unknown".to_string());
+        Self {
+            outs: Mutex::new(Vec::new()),
+            sinks: Mutex::new(Vec::new()),
+            to_stdout: false,
+            host,
+            workspace,
+            notices: Mutex::new(Notices::default()),
+            anonymizer: None,
+        }
+    }
+
+    /// pseudonymize the host and the cwd of recorded commands in every event
+    pub fn with_anonymizer(mut self, anonymizer: Option<Anonymizer>) -> Self {
+        if let Some(a) = &anonymizer {
+            self.host = a.name(&self.host);
+        }
+        self.anonymizer = anonymizer;
+        self
+    }
+
+    /// also write every event to `out`
+    pub fn add_output(&mut self, out: Box<dyn Write + Send>) {
+        if let Ok(outs) = self.outs.get_mut() {
+            outs.push(out);
+        }
+    }
+
+    /// also write every event to `out`, a plugin that may stop reading: it is written from a
+    /// thread of its own, and events it can't keep up with are dropped for it
+    pub fn add_sink(&mut self, mut out: Box<dyn Write + Send>) {
+        let (tx, rx) = mpsc::sync_channel::<String>(SINK_QUEUE);
+        let (done_tx, done) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in rx {
+                if writeln!(out, "{}", line).and_then(|_| out.flush()).is_err() {
+                    break;
+                }
+            }
+            let _ = done_tx.send(());
+        });
+        if let Ok(sinks) = self.sinks.get_mut() {
+            sinks.push(Sink { tx, done, dropped: 0 });
+        }
+    }
+
+    /// true when events share stdout, so other output has to move to stderr
+    pub fn is_stdout(&self) -> bool {
+        self.to_stdout
+    }
+
+    /// a session picked up again, whose commands so far go up to `last_seq`; notices count on from there
+    pub fn resume(&self, session_id: &str, last_seq: u64) {
+        if let Ok(mut notices) = self.notices.lock() {
+            *notices = Notices { session_id: session_id.to_string(), after: last_seq, count: 0 };
+        }
+    }
+
+    /// a finished command, `seq` its place in the session as recorded with it
+    #[allow(clippy::too_many_arguments)]
+    pub fn command(&self, session_id: &str, seq: u64, timestamp: &str, cmd: &str, exit_code: i32, message: &str, mut raw: Option<serde_json::Value>) {
+        if let (Some(a), Some(cwd)) = (&self.anonymizer, raw.as_mut().and_then(|r| r.get_mut("cwd"))) {
+            if let Some(path) = cwd.as_str() {
+                *cwd = a.path(path).into();
+            }
+        }
+        let event = LogEventV1::for_command(
+            &self.host,
+            session_id,
+            self.workspace.as_deref(),
+            seq,
+            timestamp,
+            cmd,
+            exit_code,
+            message,
+            raw,
+        );
+        self.resume(session_id, seq);
+        self.emit(&event);
+    }
+
+    pub fn message(&self, session_id: &str, message: &str) {
+        let (after, nth) = self.next_notice(session_id);
+        let event = LogEventV1::for_message(&self.host, session_id, self.workspace.as_deref(), after, nth, message);
+        self.emit(&event);
+    }
+
+    /// like message, at level WARN
+    pub fn warning(&self, session_id: &str, message: &str) {
+        let (after, nth) = self.next_notice(session_id);
+        let mut event = LogEventV1::for_message(&self.host, session_id, self.workspace.as_deref(), after, nth, message);
+        event.level = "WARN".to_string();
+        self.emit(&event);
+    }
+
+    // where a notice falls: after which command of the session, and the how manieth since
+    fn next_notice(&self, session_id: &str) -> (u64, u64) {
+        let Ok(mut notices) = self.notices.lock() else { return (0, 0) };
+        // a session this stream hasn't seen a command of yet, such as the one an idle gap started
+        if notices.session_id != session_id {
+            *notices = Notices { session_id: session_id.to_string(), after: 0, count: 0 };
+        }
+        notices.count += 1;
+        (notices.after, notices.count - 1)
+    }
+
+    // one line per event, flushed so readers see it immediately; an output that
+    // fails (a sink that exited) is dropped
+    fn emit(&self, event: &LogEventV1) {
+        let Ok(line) = serde_json::to_string(event) else { return };
+        if let Ok(mut outs) = self.outs.lock() {
+            outs.retain_mut(|out| writeln!(out, "{}", line).and_then(|_| out.flush()).is_ok());
+        }
+        if let Ok(mut sinks) = self.sinks.lock() {
+            sinks.retain_mut(|sink| match sink.tx.try_send(line.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    if sink.dropped == 0 {
+                        tracing::warn!("event sink is not keeping up, dropping events for it");
+                    }
+                    sink.dropped += 1;
+                    true
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            });
+        }
+    }
+}
+
+// a plugin sink's queue, and word from its writer thread that it is done
+struct Sink {
+    tx: SyncSender<String>,
+    done: Receiver<()>,
+    dropped: u64,
+}
+
+// the last command the stream saw of a session, and the notices emitted since
+#[derive(Default)]
+struct Notices {
+    session_id: String,
+    after: u64,
+    count: u64,
+}
+
+impl Drop for EventStream {
+    // let the sinks take what is queued, but not wait on one that stopped reading
+    fn drop(&mut self) {
+        let Ok(sinks) = self.sinks.get_mut() else { return };
+        let deadline = Instant::now() + SINK_DRAIN;
+        for Sink { tx, done, dropped } in sinks.drain(..) {
+            drop(tx);
+            let _ = done.recv_timeout(deadline.saturating_duration_since(Instant::now()));
+            if dropped > 0 {
+                tracing::warn!(dropped, "event sink missed events it could not keep up with");
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for EventStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventStream").field("to_stdout", &self.to_stdout).finish_non_exhaustive()
+    }
+}
+
+#[cfg(unix)]
+fn open_fd(fd: i32) -> io::Result<Box<dyn Write + Send>> {
+    use std::os::fd::FromRawFd;
+    // the descriptor must be open for writing, e.g. `recli --json-events fd:3 exec ... 3>events.ndjson`
+    if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Box::new(unsafe { std::fs::File::from_raw_fd(fd) }))
+}
+
+#[cfg(not(unix))]
+fn open_fd(_fd: i32) -> io::Result<Box<dyn Write + Send>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "fd targets need a unix platform"))
+}
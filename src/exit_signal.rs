@@ -0,0 +1,173 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::process::{Command, ExitStatus, Stdio};
+use std::time::Duration;
+
+// systemd-coredump writes its entry a moment after the shell saw the process die
+const COREDUMP_POLLS: u32 = 3;
+const COREDUMP_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// how a command that died from a signal went
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExitSignal {
+    pub name: String, // e.g. SIGSEGV
+    pub number: i32,
+    // none when neither the shell nor coredumpctl said
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub core_dumped: Option<bool>,
+    // how to look at the dump, e.g. `coredumpctl info 4242`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coredump: Option<String>,
+}
+
+impl ExitSignal {
+    /// the signal behind a shell's exit code of 128+n; `output_tail` is searched for the shell's
+    /// "(core dumped)" notice and coredumpctl is asked for dumps made since `started`
+    pub fn from_exit_code(exit_code: i32, started: DateTime<Utc>, output_tail: &str) -> Option<Self> {
+        let name = signal_of_exit_code(exit_code)?;
+        let number = exit_code - 128;
+        let mut signal = Self { name: name.to_string(), number, core_dumped: None, coredump: None };
+        if output_tail.contains("(core dumped)") {
+            signal.core_dumped = Some(true);
+        }
+        signal.look_up_coredump(started);
+        Some(signal)
+    }
+
+    /// the signal that ended a process recli waited for itself
+    pub fn from_status(status: &ExitStatus, started: DateTime<Utc>) -> Option<Self> {
+        use std::os::unix::process::ExitStatusExt;
+        let number = status.signal()?;
+        let name = signal_name(number).map(str::to_string).unwrap_or_else(|| format!("SIG{}", number));
+        let mut signal = Self { name, number, core_dumped: Some(status.core_dumped()), coredump: None };
+        signal.look_up_coredump(started);
+        Some(signal)
+    }
+
+    /// e.g. "killed by SIGSEGV (core dumped, coredumpctl info 4242)"
+    pub fn describe(&self) -> String {
+        let mut notes = Vec::new();
+        match self.core_dumped {
+            Some(true) => notes.push("core dumped".to_string()),
+            Some(false) if dumps_core(self.number) => notes.push("no core".to_string()),
+            _ => {}
+        }
+        notes.extend(self.coredump.clone());
+        match notes.is_empty() {
+            true => format!("killed by {}", self.name),
+            false => format!("killed by {} ({})", self.name, notes.join(", ")),
+        }
+    }
+
+    // ask coredumpctl for the newest dump of this signal since the command started
+    fn look_up_coredump(&mut self, started: DateTime<Utc>) {
+        if !dumps_core(self.number) || self.core_dumped == Some(false) {
+            return;
+        }
+        for poll in 0..COREDUMP_POLLS {
+            if poll > 0 {
+                std::thread::sleep(COREDUMP_POLL_INTERVAL);
+            }
+            match coredumps_since(started) {
+                // no coredumpctl, or no systemd-coredump behind it
+                None => return,
+                Some(dumps) => {
+                    let Some(dump) = dumps.iter().rev().find(|d| d.sig == self.number) else { continue };
+                    // "present" or "journal"; "missing", "none" and the like mean nothing was kept
+                    let kept = matches!(dump.corefile.as_deref(), Some("present" | "journal"));
+                    self.core_dumped = Some(kept || self.core_dumped == Some(true));
+                    self.coredump = Some(format!("coredumpctl info {}", dump.pid));
+                    return;
+                }
+            }
+        }
+    }
+}
+
+// one line of `coredumpctl list --json=short`
+#[derive(Debug, Deserialize)]
+struct CoredumpEntry {
+    pid: u64,
+    sig: i32,
+    #[serde(default)]
+    corefile: Option<String>,
+}
+
+fn coredumps_since(started: DateTime<Utc>) -> Option<Vec<CoredumpEntry>> {
+    let output = Command::new("coredumpctl")
+        .args(["list", "--no-pager", "--no-legend", "--json=short"])
+        .arg(format!("--since=@{}", started.timestamp()))
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    // it exits non-zero when nothing matched
+    if output.stdout.is_empty() {
+        return Some(Vec::new());
+    }
+    serde_json::from_slice(&output.stdout).ok()
+}
+
+/// the name of the signal a shell's exit code of 128+n says killed the command
+pub fn signal_of_exit_code(exit_code: i32) -> Option<&'static str> {
+    let number = exit_code.checked_sub(128)?;
+    // a job stopped with ^Z also reports 128+n, but it is still there
+    if [libc::SIGSTOP, libc::SIGTSTP, libc::SIGTTIN, libc::SIGTTOU].contains(&number) {
+        return None;
+    }
+    signal_name(number)
+}
+
+/// whether the default action of `signal` dumps core
+pub fn dumps_core(signal: i32) -> bool {
+    [
+        libc::SIGQUIT,
+        libc::SIGILL,
+        libc::SIGTRAP,
+        libc::SIGABRT,
+        libc::SIGBUS,
+        libc::SIGFPE,
+        libc::SIGSEGV,
+        libc::SIGSYS,
+        libc::SIGXCPU,
+        libc::SIGXFSZ,
+    ]
+    .contains(&signal)
+}
+
+/// the name of a standard signal
+pub fn signal_name(signal: i32) -> Option<&'static str> {
+    let name = match signal {
+        libc::SIGHUP => "SIGHUP",
+        libc::SIGINT => "SIGINT",
+        libc::SIGQUIT => "SIGQUIT",
+        libc::SIGILL => "SIGILL",
+        libc::SIGTRAP => "SIGTRAP",
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGUSR1 => "SIGUSR1",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGUSR2 => "SIGUSR2",
+        libc::SIGPIPE => "SIGPIPE",
+        libc::SIGALRM => "SIGALRM",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGCHLD => "SIGCHLD",
+        libc::SIGCONT => "SIGCONT",
+        libc::SIGSTOP => "SIGSTOP",
+        libc::SIGTSTP => "SIGTSTP",
+        libc::SIGTTIN => "SIGTTIN",
+        libc::SIGTTOU => "SIGTTOU",
+        libc::SIGURG => "SIGURG",
+        libc::SIGXCPU => "SIGXCPU",
+        libc::SIGXFSZ => "SIGXFSZ",
+        libc::SIGVTALRM => "SIGVTALRM",
+        libc::SIGPROF => "SIGPROF",
+        libc::SIGWINCH => "SIGWINCH",
+        libc::SIGIO => "SIGIO",
+        libc::SIGSYS => "SIGSYS",
+        _ => return None,
+    };
+    Some(name)
+}
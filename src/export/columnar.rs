@@ -0,0 +1,145 @@
+use crate::command_log::{CommandEntry, CommandLog};
+use parquet::basic::Compression;
+use parquet::data_type::{ByteArray, ByteArrayType, DataType, Int32Type, Int64Type};
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::{SerializedFileWriter, SerializedRowGroupWriter};
+use parquet::schema::parser::parse_message_type;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+// one row per recorded command; columns are written in this order
+const SCHEMA: &str = "
+message command {
+  required binary id (STRING);
+  required binary session_id (STRING);
+  required int32 seq;
+  optional int64 timestamp (TIMESTAMP(MICROS,true));
+  required binary cmd (STRING);
+  required binary cwd (STRING);
+  required int32 exit_code;
+  optional int64 duration_ms;
+  optional group pipestatus (LIST) {
+    repeated group list {
+      required int32 element;
+    }
+  }
+  required group tags (LIST) {
+    repeated group list {
+      required binary element (STRING);
+    }
+  }
+  optional binary title (STRING);
+  required binary output_preview (STRING);
+  optional binary stderr (STRING);
+  optional binary output_path (STRING);
+}
+";
+
+/// the commands of the given sessions as a parquet file, for duckdb, spark and friends
+pub fn commands_parquet(log_dirs: &[PathBuf]) -> Result<Vec<u8>, String> {
+    let mut rows: Vec<(String, CommandEntry)> = Vec::new();
+    for dir in log_dirs {
+        let session_id = dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let log = CommandLog::load_from_file(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        rows.extend(log.entries.into_iter().map(|e| (session_id.clone(), e)));
+    }
+    write(&rows).map_err(|e| e.to_string())
+}
+
+fn write(rows: &[(String, CommandEntry)]) -> parquet::errors::Result<Vec<u8>> {
+    let schema = Arc::new(parse_message_type(SCHEMA)?);
+    let props = Arc::new(WriterProperties::builder().set_compression(Compression::SNAPPY).build());
+    let mut out = Vec::new();
+    let mut writer = SerializedFileWriter::new(&mut out, schema, props)?;
+    let mut group = writer.next_row_group()?;
+
+    let text = |s: &str| ByteArray::from(s);
+    let entries = || rows.iter().map(|(_, e)| e);
+
+    required::<ByteArrayType>(&mut group, entries().map(|e| text(&e.id)).collect())?;
+    required::<ByteArrayType>(&mut group, rows.iter().map(|(id, _)| text(id)).collect())?;
+    required::<Int32Type>(&mut group, entries().map(|e| e.seq as i32).collect())?;
+    let micros = |e: &CommandEntry| {
+        chrono::DateTime::parse_from_rfc3339(&e.timestamp)
+            .ok()
+            .map(|t| t.timestamp_micros())
+    };
+    optional::<Int64Type>(&mut group, entries().map(micros).collect())?;
+    required::<ByteArrayType>(&mut group, entries().map(|e| text(&e.cmd)).collect())?;
+    required::<ByteArrayType>(&mut group, entries().map(|e| text(&e.cwd)).collect())?;
+    required::<Int32Type>(&mut group, entries().map(|e| e.exit_code).collect())?;
+    optional::<Int64Type>(&mut group, entries().map(|e| e.duration_ms.map(|ms| ms as i64)).collect())?;
+    list::<Int32Type>(&mut group, entries().map(|e| e.pipestatus.clone()).collect(), true)?;
+    list::<ByteArrayType>(
+        &mut group,
+        entries().map(|e| Some(e.tags.iter().map(|t| text(t)).collect())).collect(),
+        false,
+    )?;
+    optional::<ByteArrayType>(&mut group, entries().map(|e| e.title.as_deref().map(text)).collect())?;
+    required::<ByteArrayType>(&mut group, entries().map(|e| text(&e.output_preview)).collect())?;
+    optional::<ByteArrayType>(&mut group, entries().map(|e| e.stderr.as_deref().map(text)).collect())?;
+    optional::<ByteArrayType>(&mut group, entries().map(|e| e.output_path.as_deref().map(text)).collect())?;
+
+    group.close()?;
+    writer.close()?;
+    Ok(out)
+}
+
+fn required<T: DataType>(group: &mut SerializedRowGroupWriter<'_, &mut Vec<u8>>, values: Vec<T::T>) -> parquet::errors::Result<()> {
+    let mut column = next_column(group)?;
+    column.typed::<T>().write_batch(&values, None, None)?;
+    column.close()
+}
+
+fn optional<T: DataType>(
+    group: &mut SerializedRowGroupWriter<'_, &mut Vec<u8>>,
+    values: Vec<Option<T::T>>,
+) -> parquet::errors::Result<()> {
+    let def: Vec<i16> = values.iter().map(|v| v.is_some() as i16).collect();
+    let present: Vec<T::T> = values.into_iter().flatten().collect();
+    let mut column = next_column(group)?;
+    column.typed::<T>().write_batch(&present, Some(&def), None)?;
+    column.close()
+}
+
+// a list column; a missing list is only allowed when the list itself is optional
+fn list<T: DataType>(
+    group: &mut SerializedRowGroupWriter<'_, &mut Vec<u8>>,
+    rows: Vec<Option<Vec<T::T>>>,
+    nullable: bool,
+) -> parquet::errors::Result<()> {
+    // definition levels count how far down the path is present: the list, then an element
+    let list_level = nullable as i16;
+    let (mut values, mut def, mut rep) = (Vec::new(), Vec::new(), Vec::new());
+    for row in rows {
+        match row {
+            None => {
+                def.push(0);
+                rep.push(0);
+            }
+            Some(items) if items.is_empty() => {
+                def.push(list_level);
+                rep.push(0);
+            }
+            Some(items) => {
+                for (i, item) in items.into_iter().enumerate() {
+                    def.push(list_level + 1);
+                    rep.push((i > 0) as i16);
+                    values.push(item);
+                }
+            }
+        }
+    }
+    let mut column = next_column(group)?;
+    column.typed::<T>().write_batch(&values, Some(&def), Some(&rep))?;
+    column.close()
+}
+
+fn next_column<'a>(
+    group: &'a mut SerializedRowGroupWriter<'_, &mut Vec<u8>>,
+) -> parquet::errors::Result<parquet::file::writer::SerializedColumnWriter<'a>> {
+    group
+        .next_column()?
+        .ok_or_else(|| ParquetError::General("more columns written than the schema has".to_string()))
+}
@@ -0,0 +1,501 @@
+use crate::cast::{Cast, CastEvent};
+use crate::command_log::{CommandEntry, CommandLog};
+use crate::session;
+use crate::util::time::format_duration_ms;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+mod columnar;
+mod svg;
+mod video;
+
+pub use columnar::commands_parquet;
+pub use svg::session_svg;
+pub use video::{session_gif, session_webm};
+
+// longest pause kept between frames of an animation
+const MAX_FRAME_GAP: f64 = 2.0;
+// output arriving closer together than this is drawn as one frame
+const MIN_FRAME: f64 = 0.05;
+// terminal colors of the animated formats
+const TERM_FG: Color = Color::Rgb(0xd4, 0xd4, 0xd4);
+const TERM_BG: Color = Color::Rgb(0x1e, 0x1e, 0x1e);
+
+const PAGE_STYLE: &str = "body{background:#1e1e1e;color:#d4d4d4;font-family:sans-serif;margin:2em}\
+h1{font-size:1.2em}.meta{color:#888;font-size:.85em;margin:.2em 0}\
+section{margin:1.5em 0}.cmd{font-family:monospace;font-weight:bold;color:#fff}\
+.fail{color:#f44}pre{background:#111;padding:.8em;overflow-x:auto;margin:.4em 0;line-height:1.25}";
+
+// colors of the <pre> blocks on the html page
+const PRE_FG: Color = Color::Rgb(0xd4, 0xd4, 0xd4);
+const PRE_BG: Color = Color::Rgb(0x11, 0x11, 0x11);
+
+/// a recorded session as a standalone html page, output rendered with its colors
+pub fn session_html(log_dir: &Path) -> Result<String, String> {
+    let log = CommandLog::load_from_file(log_dir).map_err(|e| e.to_string())?;
+    let meta = session::read_metadata(log_dir);
+    let session_id = log_dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+
+    let mut html = String::new();
+    let _ = writeln!(html, "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">");
+    let _ = writeln!(html, "<title>{}</title><style>{}</style></head><body>", escape(&session_id), PAGE_STYLE);
+    let _ = writeln!(html, "<h1>{}</h1>", escape(&session_id));
+    if let Some(meta) = &meta {
+        let ended = meta.ended_at.as_deref().unwrap_or("still recording");
+        let _ = writeln!(
+            html,
+            "<div class=\"meta\">{} &middot; {} to {} &middot; {} commands</div>",
+            escape(&meta.shell),
+            escape(&meta.started_at),
+            escape(ended),
+            log.entries.len()
+        );
+    }
+    for entry in &log.entries {
+        write_entry(&mut html, log_dir, entry);
+    }
+    html.push_str("</body></html>\n");
+    Ok(html)
+}
+
+fn write_entry(html: &mut String, log_dir: &Path, entry: &CommandEntry) {
+    // the sidecar has the full output; the preview is capped
+    let output = entry
+        .output_path
+        .as_ref()
+        .and_then(|p| fs::read(log_dir.join(p)).ok())
+        .unwrap_or_else(|| entry.output_preview.as_bytes().to_vec());
+
+    let status = match (entry.exit_code, &entry.signal) {
+        (0, _) => "exit 0".to_string(),
+        (code, Some(signal)) => format!("<span class=\"fail\">exit {}, {}</span>", code, escape(&signal.describe())),
+        (code, None) => format!("<span class=\"fail\">exit {}</span>", code),
+    };
+    let duration = entry.duration_ms.map(|ms| format!(" &middot; {}", format_duration_ms(ms))).unwrap_or_default();
+    let _ = writeln!(html, "<section><div class=\"cmd\">$ {}</div>", escape(&entry.cmd));
+    let _ = writeln!(
+        html,
+        "<div class=\"meta\">{} &middot; {} &middot; {}{}</div>",
+        escape(&entry.timestamp),
+        escape(&entry.cwd),
+        status,
+        duration
+    );
+    let rendered = ansi_to_html(&output);
+    if !rendered.trim().is_empty() {
+        let _ = writeln!(html, "<pre>{}</pre>", rendered.trim_matches('\n'));
+    }
+    html.push_str("</section>\n");
+}
+
+/// color as set by sgr: one of the 256 palette entries or 24-bit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// css hex for the color, using xterm's default palette
+    pub fn css(self) -> String {
+        let (r, g, b) = match self {
+            Color::Rgb(r, g, b) => (r, g, b),
+            Color::Indexed(i) => palette(i),
+        };
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    }
+}
+
+/// text attributes in effect for a cell
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub inverse: bool,
+}
+
+impl Style {
+    /// apply the parameters of one `esc [ ... m` sequence
+    pub fn apply_sgr(&mut self, params: &[u16]) {
+        if params.is_empty() {
+            *self = Style::default();
+            return;
+        }
+        let mut i = 0;
+        while i < params.len() {
+            match params[i] {
+                0 => *self = Style::default(),
+                1 => self.bold = true,
+                2 => self.dim = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                7 => self.inverse = true,
+                22 => {
+                    self.bold = false;
+                    self.dim = false;
+                }
+                23 => self.italic = false,
+                24 => self.underline = false,
+                27 => self.inverse = false,
+                n @ 30..=37 => self.fg = Some(Color::Indexed((n - 30) as u8)),
+                39 => self.fg = None,
+                n @ 40..=47 => self.bg = Some(Color::Indexed((n - 40) as u8)),
+                49 => self.bg = None,
+                n @ 90..=97 => self.fg = Some(Color::Indexed((n - 90 + 8) as u8)),
+                n @ 100..=107 => self.bg = Some(Color::Indexed((n - 100 + 8) as u8)),
+                n @ (38 | 48) => {
+                    let (color, used) = extended_color(&params[i + 1..]);
+                    if n == 38 {
+                        self.fg = color.or(self.fg);
+                    } else {
+                        self.bg = color.or(self.bg);
+                    }
+                    i += used;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    /// foreground and background as drawn, none meaning the page default
+    pub fn colors(&self, default_fg: Color, default_bg: Color) -> (Option<Color>, Option<Color>) {
+        let (mut fg, mut bg) = (self.fg, self.bg);
+        if self.inverse {
+            // the defaults swap too, so spell them out
+            fg = Some(bg.unwrap_or(default_bg));
+            bg = Some(self.fg.unwrap_or(default_fg));
+        }
+        // bold text in the first eight colors shows in the bright variant, like xterm
+        if let (true, Some(Color::Indexed(i @ 0..=7))) = (self.bold, fg) {
+            fg = Some(Color::Indexed(i + 8));
+        }
+        (fg, bg)
+    }
+
+    // inline css, empty for the default style
+    fn css(&self) -> String {
+        let (fg, bg) = self.colors(PRE_FG, PRE_BG);
+        let mut css = Vec::new();
+        if let Some(c) = fg {
+            css.push(format!("color:{}", c.css()));
+        }
+        if let Some(c) = bg {
+            css.push(format!("background:{}", c.css()));
+        }
+        if self.bold {
+            css.push("font-weight:bold".to_string());
+        }
+        if self.dim {
+            css.push("opacity:.6".to_string());
+        }
+        if self.italic {
+            css.push("font-style:italic".to_string());
+        }
+        if self.underline {
+            css.push("text-decoration:underline".to_string());
+        }
+        css.join(";")
+    }
+}
+
+// `5;n` or `2;r;g;b` after a 38/48; returns the color and how many params it used
+fn extended_color(params: &[u16]) -> (Option<Color>, usize) {
+    match params {
+        [5, n, ..] => (Some(Color::Indexed(*n as u8)), 2),
+        [2, r, g, b, ..] => (Some(Color::Rgb(*r as u8, *g as u8, *b as u8)), 4),
+        [5] | [2, ..] => (None, params.len()),
+        _ => (None, 0),
+    }
+}
+
+/// xterm's default rgb for a palette index
+pub fn palette(i: u8) -> (u8, u8, u8) {
+    const BASE: [(u8, u8, u8); 16] = [
+        (0x00, 0x00, 0x00),
+        (0xcd, 0x00, 0x00),
+        (0x00, 0xcd, 0x00),
+        (0xcd, 0xcd, 0x00),
+        (0x00, 0x00, 0xee),
+        (0xcd, 0x00, 0xcd),
+        (0x00, 0xcd, 0xcd),
+        (0xe5, 0xe5, 0xe5),
+        (0x7f, 0x7f, 0x7f),
+        (0xff, 0x00, 0x00),
+        (0x00, 0xff, 0x00),
+        (0xff, 0xff, 0x00),
+        (0x5c, 0x5c, 0xff),
+        (0xff, 0x00, 0xff),
+        (0x00, 0xff, 0xff),
+        (0xff, 0xff, 0xff),
+    ];
+    match i {
+        0..=15 => BASE[i as usize],
+        16..=231 => {
+            let n = i - 16;
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            (level(n / 36), level((n / 6) % 6), level(n % 6))
+        }
+        _ => {
+            let v = 8 + (i - 232) * 10;
+            (v, v, v)
+        }
+    }
+}
+
+/// render recorded terminal output as html: sgr colors become styled spans, carriage
+/// returns and erase-line overwrite like they did on screen, and full-screen programs
+/// are left out since their output is screen updates rather than scrollback
+pub fn ansi_to_html(data: &[u8]) -> String {
+    let mut html = String::new();
+    for line in render_lines(data) {
+        let mut style = Style::default();
+        let mut open = false;
+        for (ch, cell_style) in line {
+            if cell_style != style {
+                if open {
+                    html.push_str("</span>");
+                    open = false;
+                }
+                let css = cell_style.css();
+                if !css.is_empty() {
+                    let _ = write!(html, "<span style=\"{}\">", css);
+                    open = true;
+                }
+                style = cell_style;
+            }
+            push_escaped(&mut html, ch);
+        }
+        if open {
+            html.push_str("</span>");
+        }
+        html.push('\n');
+    }
+    html
+}
+
+/// one rendered line of output: characters with the style they were drawn in
+pub type Line = Vec<(char, Style)>;
+
+/// replay output into lines the way a terminal's scrollback would end up
+pub fn render_lines(data: &[u8]) -> Vec<Line> {
+    let text = String::from_utf8_lossy(data);
+    let chars: Vec<char> = text.chars().collect();
+    let mut lines: Vec<Line> = Vec::new();
+    let mut line: Line = Vec::new();
+    let mut col = 0usize;
+    let mut style = Style::default();
+    let mut alt_screen = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+        if c == '\x1b' {
+            match chars.get(i) {
+                Some('[') => {
+                    let start = i + 1;
+                    let mut end = start;
+                    while end < chars.len() && !('@'..='~').contains(&chars[end]) {
+                        end += 1;
+                    }
+                    let Some(&fin) = chars.get(end) else { break };
+                    let raw: String = chars[start..end].iter().collect();
+                    i = end + 1;
+                    if raw == "?1049" || raw == "?47" || raw == "?1047" {
+                        match fin {
+                            'h' if !alt_screen => {
+                                alt_screen = true;
+                                if !line.is_empty() {
+                                    lines.push(std::mem::take(&mut line));
+                                }
+                                let note = "[full-screen program output not shown]";
+                                lines.push(note.chars().map(|c| (c, Style { dim: true, italic: true, ..Style::default() })).collect());
+                                col = 0;
+                            }
+                            'l' => alt_screen = false,
+                            _ => {}
+                        }
+                        continue;
+                    }
+                    if alt_screen || raw.starts_with('?') {
+                        continue;
+                    }
+                    let params: Vec<u16> = raw.split(';').map(|p| p.parse().unwrap_or(0)).collect();
+                    let first = params.first().copied().unwrap_or(0);
+                    match fin {
+                        'm' => style.apply_sgr(if raw.is_empty() { &[] } else { &params }),
+                        'K' => match first {
+                            0 => line.truncate(col),
+                            1 => line.iter_mut().take(col + 1).for_each(|cell| *cell = (' ', Style::default())),
+                            _ => line.clear(),
+                        },
+                        'G' => col = first.max(1) as usize - 1,
+                        'C' => col += first.max(1) as usize,
+                        'D' => col = col.saturating_sub(first.max(1) as usize),
+                        _ => {}
+                    }
+                }
+                // osc and other string sequences end at bel or st
+                Some(']' | 'P' | '_' | '^') => {
+                    while i < chars.len() && chars[i] != '\x07' && !(chars[i] == '\x1b' && chars.get(i + 1) == Some(&'\\')) {
+                        i += 1;
+                    }
+                    i += if chars.get(i) == Some(&'\x07') { 1 } else { 2 };
+                }
+                Some(_) => {
+                    while i < chars.len() && (' '..='/').contains(&chars[i]) {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                None => {}
+            }
+            continue;
+        }
+        if alt_screen {
+            continue;
+        }
+        match c {
+            '\n' => {
+                lines.push(std::mem::take(&mut line));
+                col = 0;
+            }
+            '\r' => col = 0,
+            '\x08' => col = col.saturating_sub(1),
+            '\t' => {
+                let next = (col / 8 + 1) * 8;
+                while col < next {
+                    put(&mut line, col, ' ', style);
+                    col += 1;
+                }
+            }
+            c if c.is_control() => {}
+            c => {
+                put(&mut line, col, c, style);
+                col += 1;
+            }
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+// draw a character at `col`, padding with blanks when the cursor moved past the end
+fn put(line: &mut Line, col: usize, c: char, style: Style) {
+    while line.len() < col {
+        line.push((' ', Style::default()));
+    }
+    if col < line.len() {
+        line[col] = (c, style);
+    } else {
+        line.push((c, style));
+    }
+}
+
+// a cast played back through a terminal emulator, for the animated formats
+struct Replay {
+    // when each distinct screen appeared on the animation clock, and the screen
+    frames: Vec<(f64, vt100::Screen)>,
+    // largest size the terminal had
+    cols: u16,
+    rows: u16,
+    // length of the animation, holding the last screen before it loops
+    duration: f64,
+}
+
+impl Replay {
+    // long pauses are cut to MAX_FRAME_GAP and unchanged screens dropped
+    fn new(cast: &Cast) -> Result<Self, String> {
+        let mut parser = vt100::Parser::new(cast.height, cast.width, 0);
+        let (mut cols, mut rows) = (cast.width, cast.height);
+        let mut frames: Vec<(f64, vt100::Screen)> = Vec::new();
+        let mut clock = 0.0;
+        let mut last_time = 0.0;
+
+        for (i, event) in cast.events.iter().enumerate() {
+            clock += (event.time() - last_time).clamp(0.0, MAX_FRAME_GAP);
+            last_time = event.time();
+            match event {
+                CastEvent::Output { data, .. } => parser.process(data.as_bytes()),
+                CastEvent::Resize { cols: c, rows: r, .. } => {
+                    if *r == 0 || *c == 0 {
+                        continue;
+                    }
+                    parser.screen_mut().set_size(*r, *c);
+                    cols = cols.max(*c);
+                    rows = rows.max(*r);
+                }
+            }
+            // wait for the output that follows right after
+            if cast.events.get(i + 1).is_some_and(|next| next.time() - event.time() < MIN_FRAME) {
+                continue;
+            }
+            let screen = parser.screen();
+            let unchanged = frames.last().is_some_and(|(_, prev)| {
+                prev.contents_formatted() == screen.contents_formatted()
+                    && prev.cursor_position() == screen.cursor_position()
+                    && prev.hide_cursor() == screen.hide_cursor()
+            });
+            if !unchanged {
+                frames.push((clock, screen.clone()));
+            }
+        }
+        let Some((last, _)) = frames.last() else {
+            return Err("the cast has no output to animate".to_string());
+        };
+        Ok(Self {
+            duration: last + MAX_FRAME_GAP,
+            frames,
+            cols,
+            rows,
+        })
+    }
+
+    // when frame `i` gives way to the next one
+    fn frame_end(&self, i: usize) -> f64 {
+        self.frames.get(i + 1).map(|(t, _)| *t).unwrap_or(self.duration)
+    }
+}
+
+fn cell_style(cell: &vt100::Cell) -> Style {
+    let color = |c: vt100::Color| match c {
+        vt100::Color::Default => None,
+        vt100::Color::Idx(i) => Some(Color::Indexed(i)),
+        vt100::Color::Rgb(r, g, b) => Some(Color::Rgb(r, g, b)),
+    };
+    Style {
+        fg: color(cell.fgcolor()),
+        bg: color(cell.bgcolor()),
+        bold: cell.bold(),
+        dim: cell.dim(),
+        italic: cell.italic(),
+        underline: cell.underline(),
+        inverse: cell.inverse(),
+    }
+}
+
+fn push_escaped(html: &mut String, c: char) {
+    match c {
+        '<' => html.push_str("&lt;"),
+        '>' => html.push_str("&gt;"),
+        '&' => html.push_str("&amp;"),
+        '"' => html.push_str("&quot;"),
+        c => html.push(c),
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        push_escaped(&mut out, c);
+    }
+    out
+}
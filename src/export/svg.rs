@@ -0,0 +1,157 @@
+use super::{cell_style, escape, Replay, Style, TERM_BG, TERM_FG};
+use crate::cast::Cast;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::Path;
+
+const CELL_WIDTH: f64 = 8.4;
+const CELL_HEIGHT: f64 = 17.0;
+const FONT_SIZE: f64 = 14.0;
+const PADDING: f64 = 10.0;
+
+/// the session's cast as an animated svg that loops, like termtosvg makes;
+/// identical rows are drawn once and reused so long sessions stay small
+pub fn session_svg(log_dir: &Path) -> Result<String, String> {
+    let replay = Replay::new(&Cast::load(log_dir)?)?;
+    let mut row_ids: HashMap<String, usize> = HashMap::new();
+    let mut row_defs: Vec<String> = Vec::new();
+    // each frame as the ids of its rows
+    let mut frames: Vec<Vec<usize>> = Vec::new();
+    for (_, screen) in &replay.frames {
+        let (height, width) = screen.size();
+        let rows = (0..height)
+            .map(|r| {
+                let drawing = svg_row(screen, r, width);
+                let next_id = row_defs.len();
+                *row_ids.entry(drawing.clone()).or_insert_with(|| {
+                    row_defs.push(drawing);
+                    next_id
+                })
+            })
+            .collect();
+        frames.push(rows);
+    }
+
+    let total = replay.duration;
+    let width = replay.cols as f64 * CELL_WIDTH + 2.0 * PADDING;
+    let height = replay.rows as f64 * CELL_HEIGHT + 2.0 * PADDING;
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"http://www.w3.org/1999/xlink\" \
+         width=\"{w:.0}\" height=\"{h:.0}\" viewBox=\"0 0 {w:.0} {h:.0}\" xml:space=\"preserve\" \
+         font-family=\"Menlo, Consolas, 'DejaVu Sans Mono', monospace\" font-size=\"{}\">",
+        FONT_SIZE,
+        w = width,
+        h = height
+    );
+    let _ = writeln!(svg, "<rect width=\"100%\" height=\"100%\" rx=\"6\" fill=\"{}\"/>", TERM_BG.css());
+    svg.push_str("<defs>\n");
+    for (id, drawing) in row_defs.iter().enumerate() {
+        let _ = writeln!(svg, "<g id=\"r{}\">{}</g>", id, drawing);
+    }
+    svg.push_str("</defs>\n");
+    // every frame keys off this one timer so the whole thing loops
+    let _ = writeln!(
+        svg,
+        "<rect width=\"0\" height=\"0\"><animate id=\"clock\" attributeName=\"x\" from=\"0\" to=\"0\" \
+         dur=\"{:.3}s\" begin=\"0s;clock.end\"/></rect>",
+        total
+    );
+    let _ = writeln!(svg, "<g transform=\"translate({p},{p})\">", p = PADDING);
+    for (i, rows) in frames.iter().enumerate() {
+        let (start, screen) = &replay.frames[i];
+        let end = replay.frame_end(i);
+        let _ = writeln!(
+            svg,
+            "<g visibility=\"hidden\"><set attributeName=\"visibility\" to=\"visible\" begin=\"clock.begin+{:.3}s\" dur=\"{:.3}s\"/>",
+            start,
+            (end - start).max(0.001)
+        );
+        for (r, id) in rows.iter().enumerate() {
+            let _ = writeln!(svg, "<use xlink:href=\"#r{}\" y=\"{:.1}\"/>", id, r as f64 * CELL_HEIGHT);
+        }
+        if !screen.hide_cursor() {
+            let (r, c) = screen.cursor_position();
+            let _ = writeln!(
+                svg,
+                "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{}\" height=\"{}\" fill=\"{}\" opacity=\".6\"/>",
+                c as f64 * CELL_WIDTH,
+                r as f64 * CELL_HEIGHT,
+                CELL_WIDTH,
+                CELL_HEIGHT,
+                TERM_FG.css()
+            );
+        }
+        svg.push_str("</g>\n");
+    }
+    svg.push_str("</g>\n</svg>\n");
+    Ok(svg)
+}
+
+// backgrounds and text of one screen row, positioned at y=0
+fn svg_row(screen: &vt100::Screen, row: u16, width: u16) -> String {
+    // runs of cells sharing a style: (first column, style, text)
+    let mut runs: Vec<(u16, Style, String)> = Vec::new();
+    for col in 0..width {
+        let Some(cell) = screen.cell(row, col) else { continue };
+        if cell.is_wide_continuation() {
+            continue;
+        }
+        let style = cell_style(cell);
+        let text = if cell.has_contents() { cell.contents() } else { " " };
+        match runs.last_mut() {
+            Some((_, s, t)) if *s == style => t.push_str(text),
+            _ => runs.push((col, style, text.to_string())),
+        }
+    }
+
+    let mut backgrounds = String::new();
+    let mut text = String::new();
+    for (col, style, content) in &runs {
+        let (fg, bg) = style.colors(TERM_FG, TERM_BG);
+        let x = *col as f64 * CELL_WIDTH;
+        if let Some(bg) = bg {
+            let _ = write!(
+                backgrounds,
+                "<rect x=\"{:.1}\" width=\"{:.1}\" height=\"{}\" fill=\"{}\"/>",
+                x,
+                content.chars().count() as f64 * CELL_WIDTH,
+                CELL_HEIGHT,
+                bg.css()
+            );
+        }
+        let content = if *style == Style::default() { content.trim_end() } else { content.as_str() };
+        if content.trim().is_empty() {
+            continue;
+        }
+        let mut attrs = format!(" x=\"{:.1}\"", x);
+        if let Some(fg) = fg {
+            let _ = write!(attrs, " fill=\"{}\"", fg.css());
+        }
+        if style.bold {
+            attrs.push_str(" font-weight=\"bold\"");
+        }
+        if style.italic {
+            attrs.push_str(" font-style=\"italic\"");
+        }
+        if style.underline {
+            attrs.push_str(" text-decoration=\"underline\"");
+        }
+        if style.dim {
+            attrs.push_str(" opacity=\".6\"");
+        }
+        let _ = write!(text, "<tspan{}>{}</tspan>", attrs, escape(content));
+    }
+    if text.is_empty() {
+        return backgrounds;
+    }
+    format!(
+        "{}<text y=\"{:.1}\" fill=\"{}\">{}</text>",
+        backgrounds,
+        CELL_HEIGHT - 4.0,
+        TERM_FG.css(),
+        text
+    )
+}
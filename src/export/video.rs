@@ -0,0 +1,210 @@
+use super::{cell_style, palette, Color, Replay, TERM_BG, TERM_FG};
+use crate::cast::Cast;
+use font8x8::{UnicodeFonts, BASIC_FONTS, BLOCK_FONTS, BOX_FONTS, GREEK_FONTS, LATIN_FONTS};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+const GLYPH_WIDTH: usize = 8;
+// the 8x8 font is drawn with every row doubled, close to a terminal cell's shape
+const GLYPH_HEIGHT: usize = 16;
+const PADDING: usize = 8;
+// webm is constant frame rate, so a screen is repeated for as long as it shows
+const VIDEO_FPS: f64 = 10.0;
+
+/// the session's cast as a looping gif, drawn with a built-in bitmap font
+pub fn session_gif(log_dir: &Path) -> Result<Vec<u8>, String> {
+    let replay = Replay::new(&Cast::load(log_dir)?)?;
+    let mut painter = Painter::new(&replay);
+    if painter.width > u16::MAX as usize || painter.height > u16::MAX as usize {
+        return Err("terminal too large for a gif".to_string());
+    }
+
+    let colors: Vec<u8> = (0..=255u8).flat_map(|i| <[u8; 3]>::from(palette(i))).collect();
+    let mut out = Vec::new();
+    {
+        let mut encoder = gif::Encoder::new(&mut out, painter.width as u16, painter.height as u16, &colors)
+            .map_err(|e| e.to_string())?;
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(|e| e.to_string())?;
+
+        let mut previous: Option<Vec<u8>> = None;
+        for (i, (start, screen)) in replay.frames.iter().enumerate() {
+            let pixels = painter.paint(screen);
+            // gif delays are in hundredths; viewers stretch anything under two
+            let delay = ((replay.frame_end(i) - start) * 100.0).round().clamp(2.0, u16::MAX as f64) as u16;
+            // only the part that changed is stored, drawn over the frame before
+            let (left, top, width, height) = match &previous {
+                Some(prev) => changed_area(prev, &pixels, painter.width).unwrap_or((0, 0, 1, 1)),
+                None => (0, 0, painter.width, painter.height),
+            };
+            let buffer: Vec<u8> = (top..top + height)
+                .flat_map(|y| pixels[y * painter.width + left..y * painter.width + left + width].iter().copied())
+                .collect();
+            let frame = gif::Frame {
+                left: left as u16,
+                top: top as u16,
+                width: width as u16,
+                height: height as u16,
+                delay,
+                dispose: gif::DisposalMethod::Keep,
+                buffer: Cow::Owned(buffer),
+                ..gif::Frame::default()
+            };
+            encoder.write_frame(&frame).map_err(|e| e.to_string())?;
+            previous = Some(pixels);
+        }
+    }
+    Ok(out)
+}
+
+/// the session's cast as a webm video, encoded by ffmpeg; written to stdout without a path
+pub fn session_webm(log_dir: &Path, output: Option<&Path>) -> Result<(), String> {
+    let replay = Replay::new(&Cast::load(log_dir)?)?;
+    let mut painter = Painter::new(&replay);
+    // yuv420p needs even dimensions
+    let (width, height) = (painter.width + painter.width % 2, painter.height + painter.height % 2);
+
+    let target = output.map(|p| p.to_string_lossy().to_string()).unwrap_or_else(|| "pipe:1".to_string());
+    let mut ffmpeg = Command::new("ffmpeg")
+        .args(["-loglevel", "error", "-y", "-f", "rawvideo", "-pixel_format", "rgb24"])
+        .args(["-video_size", &format!("{}x{}", width, height)])
+        .args(["-framerate", &VIDEO_FPS.to_string(), "-i", "-"])
+        .args(["-c:v", "libvpx-vp9", "-pix_fmt", "yuv420p", "-f", "webm", &target])
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("webm export needs ffmpeg on PATH: {}", e))?;
+    let mut stdin = ffmpeg.stdin.take().ok_or("ffmpeg has no stdin")?;
+
+    let rgb: Vec<[u8; 3]> = (0..=255u8).map(|i| palette(i).into()).collect();
+    let mut written = 0u64;
+    for (i, (_, screen)) in replay.frames.iter().enumerate() {
+        let pixels = painter.paint(screen);
+        let mut frame = Vec::with_capacity(width * height * 3);
+        for y in 0..height {
+            for x in 0..width {
+                let index = if x < painter.width && y < painter.height { pixels[y * painter.width + x] } else { painter.bg };
+                frame.extend_from_slice(&rgb[index as usize]);
+            }
+        }
+        // repeat up to where the next screen starts, so rounding doesn't drift
+        let until = ((replay.frame_end(i) * VIDEO_FPS).round() as u64).max(written + 1);
+        while written < until {
+            if stdin.write_all(&frame).is_err() {
+                break;
+            }
+            written += 1;
+        }
+    }
+    drop(stdin);
+    let status = ffmpeg.wait().map_err(|e| e.to_string())?;
+    if !status.success() {
+        return Err(format!("ffmpeg failed ({})", status));
+    }
+    Ok(())
+}
+
+// draws screens as palette indices
+struct Painter {
+    width: usize,
+    height: usize,
+    fg: u8,
+    bg: u8,
+    // 24-bit colors mapped to their closest palette entry
+    nearest: HashMap<(u8, u8, u8), u8>,
+}
+
+impl Painter {
+    fn new(replay: &Replay) -> Self {
+        let mut painter = Self {
+            width: replay.cols as usize * GLYPH_WIDTH + 2 * PADDING,
+            height: replay.rows as usize * GLYPH_HEIGHT + 2 * PADDING,
+            fg: 0,
+            bg: 0,
+            nearest: HashMap::new(),
+        };
+        painter.fg = painter.index(TERM_FG);
+        painter.bg = painter.index(TERM_BG);
+        painter
+    }
+
+    fn index(&mut self, color: Color) -> u8 {
+        match color {
+            Color::Indexed(i) => i,
+            Color::Rgb(r, g, b) => *self.nearest.entry((r, g, b)).or_insert_with(|| {
+                let distance = |(pr, pg, pb): (u8, u8, u8)| {
+                    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+                    d(pr, r) + d(pg, g) + d(pb, b)
+                };
+                (0..=255u8).min_by_key(|&i| distance(palette(i))).unwrap_or(0)
+            }),
+        }
+    }
+
+    fn paint(&mut self, screen: &vt100::Screen) -> Vec<u8> {
+        let mut pixels = vec![self.bg; self.width * self.height];
+        let (rows, cols) = screen.size();
+        let cursor = (!screen.hide_cursor()).then(|| screen.cursor_position());
+        for row in 0..rows {
+            for col in 0..cols {
+                let Some(cell) = screen.cell(row, col) else { continue };
+                if cell.is_wide_continuation() {
+                    continue;
+                }
+                let style = cell_style(cell);
+                let (fg, bg) = style.colors(TERM_FG, TERM_BG);
+                let mut fg = fg.map(|c| self.index(c)).unwrap_or(self.fg);
+                let mut bg = bg.map(|c| self.index(c)).unwrap_or(self.bg);
+                if cursor == Some((row, col)) {
+                    std::mem::swap(&mut fg, &mut bg);
+                }
+                let x0 = PADDING + col as usize * GLYPH_WIDTH;
+                let y0 = PADDING + row as usize * GLYPH_HEIGHT;
+                let glyph = cell.contents().chars().next().map(glyph).unwrap_or([0; 8]);
+                for dy in 0..GLYPH_HEIGHT {
+                    let bits = glyph[dy / 2];
+                    let line = &mut pixels[(y0 + dy) * self.width + x0..(y0 + dy) * self.width + x0 + GLYPH_WIDTH];
+                    for (dx, pixel) in line.iter_mut().enumerate() {
+                        let on = bits & (1 << dx) != 0
+                            // bold: thicken strokes by a pixel
+                            || (style.bold && dx > 0 && bits & (1 << (dx - 1)) != 0)
+                            || (style.underline && dy == GLYPH_HEIGHT - 1);
+                        *pixel = if on { fg } else { bg };
+                    }
+                }
+            }
+        }
+        pixels
+    }
+}
+
+// 8x8 bitmap for a character, '?' when the font doesn't have it
+fn glyph(c: char) -> [u8; 8] {
+    if c == ' ' {
+        return [0; 8];
+    }
+    BASIC_FONTS
+        .get(c)
+        .or_else(|| LATIN_FONTS.get(c))
+        .or_else(|| BOX_FONTS.get(c))
+        .or_else(|| BLOCK_FONTS.get(c))
+        .or_else(|| GREEK_FONTS.get(c))
+        .or_else(|| BASIC_FONTS.get('?'))
+        .unwrap_or([0; 8])
+}
+
+// bounding box (left, top, width, height) of the pixels that differ
+fn changed_area(prev: &[u8], next: &[u8], width: usize) -> Option<(usize, usize, usize, usize)> {
+    let (mut left, mut top, mut right, mut bottom) = (usize::MAX, usize::MAX, 0, 0);
+    for (i, (a, b)) in prev.iter().zip(next).enumerate() {
+        if a != b {
+            let (x, y) = (i % width, i / width);
+            left = left.min(x);
+            right = right.max(x);
+            top = top.min(y);
+            bottom = bottom.max(y);
+        }
+    }
+    (left != usize::MAX).then(|| (left, top, right - left + 1, bottom - top + 1))
+}
@@ -0,0 +1,156 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// paths listed per kind of change; the summary still counts all of them
+const MAX_LISTED: usize = 50;
+// directories never looked into: version control internals and recli's own files
+const SKIPPED_DIRS: &[&str] = &[".git", ".recli"];
+
+/// how much of a directory a snapshot may look at
+#[derive(Debug, Clone, Copy)]
+pub struct TrackLimits {
+    pub depth: usize,     // 1 is only the files directly in the directory
+    pub max_files: usize, // the walk stops after this many files
+}
+
+/// size and mtime of the files below a directory at one moment
+#[derive(Debug)]
+pub struct FileSnapshot {
+    pub dir: PathBuf,
+    files: HashMap<PathBuf, (Option<SystemTime>, u64)>,
+    // the last path looked at when the walk stopped at max_files
+    cut_at: Option<PathBuf>,
+}
+
+/// what a command did to the files of the directory it ran in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileChanges {
+    pub dir: String,
+    pub summary: String, // e.g. "3 files modified, 1 created"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modified: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub created: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deleted: Vec<String>,
+    // the lists were cut, or the directory is larger than a snapshot covers
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub partial: bool,
+}
+
+impl FileSnapshot {
+    pub fn take(dir: &Path, limits: TrackLimits) -> Self {
+        let mut snapshot = Self {
+            dir: dir.to_path_buf(),
+            files: HashMap::new(),
+            cut_at: None,
+        };
+        snapshot.walk(dir, Path::new(""), 1, limits);
+        snapshot
+    }
+
+    // names are visited sorted, so a walk cut short always covers the same leading paths
+    fn walk(&mut self, dir: &Path, rel: &Path, depth: usize, limits: TrackLimits) {
+        let Ok(entries) = fs::read_dir(dir) else { return };
+        let mut entries: Vec<_> = entries.filter_map(|e| e.ok()).collect();
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            if self.cut_at.is_some() {
+                return;
+            }
+            let name = entry.file_name();
+            let rel = rel.join(&name);
+            let Ok(file_type) = entry.file_type() else { continue };
+            if file_type.is_dir() {
+                if depth < limits.depth && !SKIPPED_DIRS.iter().any(|d| name == *d) {
+                    self.walk(&entry.path(), &rel, depth + 1, limits);
+                }
+                continue;
+            }
+            // symlinks are not followed
+            let Ok(meta) = entry.metadata() else { continue };
+            self.files.insert(rel.clone(), (meta.modified().ok(), meta.len()));
+            if self.files.len() >= limits.max_files {
+                self.cut_at = Some(rel);
+            }
+        }
+    }
+
+    /// what changed between this snapshot and `after` of the same directory; none when nothing did
+    pub fn diff(&self, after: &FileSnapshot) -> Option<FileChanges> {
+        // past where either walk stopped, a path missing on one side says nothing
+        let bound = match (&self.cut_at, &after.cut_at) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.as_ref().or(b.as_ref()),
+        };
+        let covered = |p: &PathBuf| bound.is_none_or(|b| p <= b);
+        let mut modified: Vec<&PathBuf> = Vec::new();
+        let mut created: Vec<&PathBuf> = Vec::new();
+        for (path, state) in &after.files {
+            match self.files.get(path) {
+                Some(before) if before != state => modified.push(path),
+                None if covered(path) => created.push(path),
+                _ => {}
+            }
+        }
+        let mut deleted: Vec<&PathBuf> = self
+            .files
+            .keys()
+            .filter(|p| !after.files.contains_key(*p) && covered(p))
+            .collect();
+        if modified.is_empty() && created.is_empty() && deleted.is_empty() {
+            return None;
+        }
+        let summary = summarize(modified.len(), created.len(), deleted.len());
+        let partial = bound.is_some() || [&modified, &created, &deleted].iter().any(|l| l.len() > MAX_LISTED);
+        Some(FileChanges {
+            dir: self.dir.to_string_lossy().to_string(),
+            summary,
+            modified: listed(&mut modified),
+            created: listed(&mut created),
+            deleted: listed(&mut deleted),
+            partial,
+        })
+    }
+}
+
+impl FileChanges {
+    /// how `path` (absolute) changed: modified, created or deleted
+    pub fn change_of(&self, path: &Path) -> Option<&'static str> {
+        let rel = path.strip_prefix(&self.dir).ok()?.to_string_lossy().to_string();
+        if self.modified.contains(&rel) {
+            Some("modified")
+        } else if self.created.contains(&rel) {
+            Some("created")
+        } else if self.deleted.contains(&rel) {
+            Some("deleted")
+        } else {
+            None
+        }
+    }
+}
+
+fn listed(paths: &mut [&PathBuf]) -> Vec<String> {
+    paths.sort();
+    paths.iter().take(MAX_LISTED).map(|p| p.to_string_lossy().to_string()).collect()
+}
+
+// "3 files modified, 1 created", naming files once with whichever count comes first
+fn summarize(modified: usize, created: usize, deleted: usize) -> String {
+    let mut parts = Vec::new();
+    for (count, what) in [(modified, "modified"), (created, "created"), (deleted, "deleted")] {
+        if count == 0 {
+            continue;
+        }
+        if parts.is_empty() {
+            let noun = if count == 1 { "file" } else { "files" };
+            parts.push(format!("{} {} {}", count, noun, what));
+        } else {
+            parts.push(format!("{} {}", count, what));
+        }
+    }
+    parts.join(", ")
+}
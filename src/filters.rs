@@ -0,0 +1,284 @@
+use crate::config::FiltersConfig;
+use std::fmt;
+use wasmi::{Engine, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc};
+
+// instructions a module may spend on one chunk of output
+const FUEL_PER_CALL: u64 = 5_000_000;
+// linear memory a module may grow to
+const MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+// filtered chunks are capped so a broken module can't flood the terminal
+const MAX_OUTPUT: usize = 1024 * 1024;
+// bytes of the previous chunk filtered again with the next, longer than any secret worth matching
+const CARRY: usize = 256;
+
+/// tag on commands whose output a filter rewrote
+pub const REDACTED_TAG: &str = "redacted";
+
+/// what a classifier made of a chunk of output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verdict {
+    Warning,
+    Error,
+}
+
+impl Verdict {
+    /// tag given to the running command
+    pub fn tag(self) -> &'static str {
+        match self {
+            Verdict::Warning => "warning",
+            Verdict::Error => "error",
+        }
+    }
+}
+
+/// the wasm modules of one filter profile, run in order over every chunk of pty output.
+///
+/// a module exports `memory` and `recli_alloc(len: i32) -> i32`, plus either or both of
+/// `recli_filter(ptr: i32, len: i32) -> i64`, returning `out_ptr << 32 | out_len` for the
+/// replacement bytes or -1 to keep the chunk, and `recli_classify(ptr: i32, len: i32) -> i32`,
+/// returning 0 for nothing, 1 for a warning and 2 for an error. modules get no imports.
+/// a module that also exports `recli_free(ptr: i32, len: i32)` keeps its instance and is handed
+/// back every buffer once recli is done with it; others start from a fresh instance each chunk.
+pub struct FilterChain {
+    filters: Vec<WasmFilter>,
+    // the end of the previous chunk, filtered again in front of the next one so that text split
+    // across two reads is still matched
+    carry: Vec<u8>,
+}
+
+/// what the chain made of a chunk
+#[derive(Debug, Default)]
+pub struct Filtered {
+    /// the bytes to show, and to log unless `withheld`
+    pub bytes: Vec<u8>,
+    /// the strongest verdict any classifier gave
+    pub verdict: Option<Verdict>,
+    /// a filter changed the bytes
+    pub rewritten: bool,
+    /// a redacting module failed on the chunk, so it is shown as it was but must not be recorded
+    pub withheld: bool,
+}
+
+struct WasmFilter {
+    name: String,
+    module: Module,
+    // kept between chunks only for modules that free what they allocate
+    instance: Option<FilterInstance>,
+    // exports recli_filter, so a failure could let a secret through
+    redacts: bool,
+    // the last chunk failed; a failure is only reported when it starts
+    failing: bool,
+}
+
+struct FilterInstance {
+    store: Store<StoreLimits>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    free: Option<TypedFunc<(i32, i32), ()>>,
+    filter: Option<TypedFunc<(i32, i32), i64>>,
+    classify: Option<TypedFunc<(i32, i32), i32>>,
+}
+
+impl FilterChain {
+    /// load the modules of `profile`, or of the configured default profile;
+    /// none when no profile applies
+    pub fn load(cfg: &FiltersConfig, profile: Option<&str>) -> Result<Option<Self>, String> {
+        let Some(name) = profile.or(cfg.profile.as_deref()) else { return Ok(None) };
+        let profile = cfg
+            .profiles
+            .get(name)
+            .ok_or_else(|| format!("no filter profile named {}", name))?;
+        if profile.modules.is_empty() {
+            return Ok(None);
+        }
+
+        let mut config = wasmi::Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let filters = profile
+            .modules
+            .iter()
+            .map(|path| WasmFilter::load(&engine, &shellexpand::tilde(path)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Some(Self { filters, carry: Vec::new() }))
+    }
+
+    /// run the next chunk of a stream of output through every module. the tail of the previous
+    /// chunk goes in front of it, and only what the filters made of the rest is returned, so the
+    /// part of a secret after a read boundary is still replaced
+    pub fn apply(&mut self, chunk: Vec<u8>) -> Filtered {
+        let carry = std::mem::take(&mut self.carry);
+        let keep = chunk.len().min(CARRY);
+        self.carry = chunk[chunk.len() - keep..].to_vec();
+        if carry.is_empty() {
+            return self.apply_text(chunk);
+        }
+
+        let mut joined = carry.clone();
+        joined.extend_from_slice(&chunk);
+        let mut filtered = self.apply_text(joined);
+        if filtered.withheld {
+            filtered.bytes = chunk;
+            return filtered;
+        }
+        if !filtered.rewritten {
+            filtered.bytes.drain(..carry.len());
+            return filtered;
+        }
+        // the carried bytes were already shown as the filters left them then; whatever they make
+        // of them now in front of this chunk is dropped up to where the two first differ
+        let before = self.apply_text(carry).bytes;
+        let shown = before.iter().zip(&filtered.bytes).take_while(|(a, b)| a == b).count();
+        filtered.bytes.drain(..shown);
+        filtered.rewritten = filtered.bytes != chunk;
+        filtered
+    }
+
+    /// run text that stands on its own, such as a paste, through every module
+    pub fn apply_text(&mut self, mut chunk: Vec<u8>) -> Filtered {
+        let original = chunk.clone();
+        let mut verdict = None;
+        let mut rewritten = false;
+        for f in &mut self.filters {
+            match f.run(&chunk) {
+                Ok((replaced, v)) => {
+                    f.failing = false;
+                    if let Some(replaced) = replaced.filter(|r| *r != chunk) {
+                        chunk = replaced;
+                        rewritten = true;
+                    }
+                    verdict = verdict.max(v);
+                }
+                Err(e) => {
+                    if !f.failing {
+                        tracing::warn!(module = %f.name, error = %e, redacts = f.redacts, "output filter failed");
+                    }
+                    f.failing = true;
+                    // fail closed: output a redacting module couldn't look at isn't recorded
+                    if f.redacts {
+                        return Filtered { bytes: original, verdict, rewritten: false, withheld: true };
+                    }
+                }
+            }
+        }
+        Filtered { bytes: chunk, verdict, rewritten, withheld: false }
+    }
+
+    /// name of a redacting module that failed on the last chunk, if any
+    pub fn failing(&self) -> Option<&str> {
+        self.filters.iter().find(|f| f.failing && f.redacts).map(|f| f.name.as_str())
+    }
+}
+
+impl fmt::Debug for FilterChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = self.filters.iter().map(|f| f.name.as_str()).collect();
+        f.debug_struct("FilterChain").field("modules", &names).finish()
+    }
+}
+
+impl WasmFilter {
+    fn load(engine: &Engine, path: &str) -> Result<Self, String> {
+        let err = |e: &dyn fmt::Display| format!("output filter {}: {}", path, e);
+        let bytes = std::fs::read(path).map_err(|e| err(&e))?;
+        let module = Module::new(engine, &bytes).map_err(|e| err(&e))?;
+        let instance = FilterInstance::new(&module).map_err(|e| err(&e))?;
+        Ok(Self {
+            name: path.to_string(),
+            redacts: instance.filter.is_some(),
+            module,
+            instance: Some(instance),
+            failing: false,
+        })
+    }
+
+    fn run(&mut self, chunk: &[u8]) -> Result<(Option<Vec<u8>>, Option<Verdict>), String> {
+        let mut instance = match self.instance.take() {
+            Some(instance) => instance,
+            None => FilterInstance::new(&self.module)?,
+        };
+        let result = instance.run(chunk);
+        // after a trap the module's own state can't be trusted either
+        if result.is_ok() && instance.free.is_some() {
+            self.instance = Some(instance);
+        }
+        result
+    }
+}
+
+impl FilterInstance {
+    fn new(module: &Module) -> Result<Self, String> {
+        let engine = module.engine();
+        let limits = StoreLimitsBuilder::new().memory_size(MEMORY_LIMIT).build();
+        let mut store = Store::new(engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(FUEL_PER_CALL).map_err(|e| e.to_string())?;
+        // an empty linker: modules can't reach anything outside their own memory
+        let instance = Linker::<StoreLimits>::new(engine)
+            .instantiate_and_start(&mut store, module)
+            .map_err(|e| e.to_string())?;
+
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or_else(|| "no exported memory".to_string())?;
+        let alloc = instance
+            .get_typed_func::<i32, i32>(&store, "recli_alloc")
+            .map_err(|e| e.to_string())?;
+        let free = instance.get_typed_func::<(i32, i32), ()>(&store, "recli_free").ok();
+        let filter = instance.get_typed_func::<(i32, i32), i64>(&store, "recli_filter").ok();
+        let classify = instance.get_typed_func::<(i32, i32), i32>(&store, "recli_classify").ok();
+        if filter.is_none() && classify.is_none() {
+            return Err("exports neither recli_filter nor recli_classify".to_string());
+        }
+        Ok(Self { store, memory, alloc, free, filter, classify })
+    }
+
+    fn run(&mut self, chunk: &[u8]) -> Result<(Option<Vec<u8>>, Option<Verdict>), String> {
+        self.store.set_fuel(FUEL_PER_CALL).map_err(|e| e.to_string())?;
+        let len = i32::try_from(chunk.len()).map_err(|_| "chunk too large".to_string())?;
+        let ptr = self.alloc.call(&mut self.store, len).map_err(|e| e.to_string())?;
+        self.memory
+            .write(&mut self.store, ptr as u32 as usize, chunk)
+            .map_err(|e| e.to_string())?;
+
+        let verdict = match &self.classify {
+            Some(classify) => match classify.call(&mut self.store, (ptr, len)).map_err(|e| e.to_string())? {
+                0 => None,
+                1 => Some(Verdict::Warning),
+                _ => Some(Verdict::Error),
+            },
+            None => None,
+        };
+
+        let mut freed = vec![(ptr, len)];
+        let replaced = match &self.filter {
+            Some(filter) => {
+                let packed = filter.call(&mut self.store, (ptr, len)).map_err(|e| e.to_string())?;
+                if packed < 0 {
+                    None
+                } else {
+                    let out_ptr = (packed >> 32) as u32 as usize;
+                    let out_len = (packed & 0xffff_ffff) as usize;
+                    if out_len > MAX_OUTPUT {
+                        return Err(format!("returned {} bytes", out_len));
+                    }
+                    let mut out = vec![0u8; out_len];
+                    self.memory
+                        .read(&self.store, out_ptr, &mut out)
+                        .map_err(|e| e.to_string())?;
+                    if out_ptr != ptr as u32 as usize {
+                        freed.push((out_ptr as i32, out_len as i32));
+                    }
+                    Some(out)
+                }
+            }
+            None => None,
+        };
+        if let Some(free) = &self.free {
+            for (ptr, len) in freed {
+                free.call(&mut self.store, (ptr, len)).map_err(|e| e.to_string())?;
+            }
+        }
+        Ok((replaced, verdict))
+    }
+}
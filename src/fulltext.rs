@@ -0,0 +1,218 @@
+use crate::command_log::{CommandEntry, CommandLog};
+use crate::history;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tantivy::collector::TopDocs;
+use tantivy::query::QueryParser;
+use tantivy::schema::{Field, Schema, Value, INDEXED, STORED, STRING, TEXT};
+use tantivy::snippet::SnippetGenerator;
+use tantivy::{doc, Index, IndexWriter, TantivyDocument, Term};
+
+// output beyond this is left out of the index, the head of it is what people search for
+const MAX_OUTPUT_BYTES: usize = 64 * 1024;
+// what is already indexed per session, so refreshes only add new commands
+const STATE_FILE: &str = "indexed.json";
+const WRITER_MEMORY: usize = 50_000_000;
+
+/// default location of the full-text index, next to the sqlite one
+pub fn default_path() -> PathBuf {
+    let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+    PathBuf::from(home).join(".recli").join("fulltext")
+}
+
+/// a ranked match with a snippet of the text around it
+#[derive(Debug, Clone, Serialize)]
+pub struct Hit {
+    pub score: f32,
+    pub session_id: String,
+    pub seq: u64,
+    pub timestamp: String,
+    pub cmd: String,
+    pub cwd: String,
+    pub exit_code: i64,
+    /// where the query matched in the output, with byte ranges of the matched terms
+    pub snippet: Option<Snippet>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Snippet {
+    pub text: String,
+    pub highlighted: Vec<(usize, usize)>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Indexed {
+    entries: usize,
+    // commands.json's mtime in nanoseconds; unchanged sessions aren't parsed again
+    modified: u128,
+}
+
+struct Fields {
+    id: Field,
+    session_id: Field,
+    seq: Field,
+    timestamp: Field,
+    cmd: Field,
+    cwd: Field,
+    exit_code: Field,
+    output: Field,
+}
+
+/// tantivy index over the command lines and output of the local sessions
+pub struct FullText {
+    dir: PathBuf,
+    index: Index,
+    fields: Fields,
+}
+
+impl FullText {
+    pub fn open(dir: &Path) -> Result<Self, String> {
+        let mut builder = Schema::builder();
+        let fields = Fields {
+            // "<session>/<seq>", the key a command is replaced by
+            id: builder.add_text_field("id", STRING),
+            session_id: builder.add_text_field("session_id", STORED),
+            seq: builder.add_u64_field("seq", STORED),
+            timestamp: builder.add_text_field("timestamp", STORED),
+            cmd: builder.add_text_field("cmd", TEXT | STORED),
+            cwd: builder.add_text_field("cwd", STORED),
+            exit_code: builder.add_i64_field("exit_code", STORED | INDEXED),
+            output: builder.add_text_field("output", TEXT | STORED),
+        };
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        let directory = tantivy::directory::MmapDirectory::open(dir).map_err(|e| e.to_string())?;
+        let index = Index::open_or_create(directory, builder.build()).map_err(|e| {
+            format!("{}: {} (remove the directory to rebuild it)", dir.display(), e)
+        })?;
+        Ok(Self { dir: dir.to_path_buf(), index, fields })
+    }
+
+    /// have the next import index `session_id` again from its start, replacing what is indexed of it
+    pub fn forget_session(&mut self, session_id: &str) -> Result<(), String> {
+        let state_path = self.dir.join(STATE_FILE);
+        let Ok(text) = fs::read_to_string(&state_path) else { return Ok(()) };
+        let mut indexed: HashMap<String, Indexed> = serde_json::from_str(&text).unwrap_or_default();
+        if indexed.remove(session_id).is_some() {
+            let state = serde_json::to_string(&indexed).map_err(|e| e.to_string())?;
+            fs::write(&state_path, state).map_err(|e| format!("{}: {}", state_path.display(), e))?;
+        }
+        Ok(())
+    }
+
+    /// index the commands recorded under `base` since the last call; returns how many were added
+    pub fn import_local(&mut self, base: &Path) -> Result<usize, String> {
+        let state_path = self.dir.join(STATE_FILE);
+        let mut indexed: HashMap<String, Indexed> = fs::read_to_string(&state_path)
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+
+        let mut writer: Option<IndexWriter> = None;
+        let mut added = 0;
+        let mut changed = false;
+        for dir in history::session_dirs(base) {
+            let session_id = dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            let modified = fs::metadata(dir.join("commands.json"))
+                .and_then(|m| m.modified())
+                .map(|t| t.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos())
+                .unwrap_or(0);
+            let done = indexed.get(&session_id).map(|i| (i.entries, i.modified)).unwrap_or_default();
+            if done.1 == modified {
+                continue;
+            }
+            let Ok(log) = CommandLog::load_from_file(&dir) else { continue };
+            changed = true;
+            let done = done.0;
+            if log.entries.len() <= done {
+                indexed.insert(session_id, Indexed { entries: done, modified });
+                continue;
+            }
+            if writer.is_none() {
+                writer = Some(self.index.writer(WRITER_MEMORY).map_err(|e| e.to_string())?);
+            }
+            let Some(writer) = writer.as_mut() else { continue };
+            for (seq, entry) in log.entries.iter().enumerate().skip(done) {
+                let id = format!("{}/{}", session_id, seq);
+                writer.delete_term(Term::from_field_text(self.fields.id, &id));
+                writer
+                    .add_document(self.document(&dir, &session_id, seq, &id, entry))
+                    .map_err(|e| e.to_string())?;
+                added += 1;
+            }
+            indexed.insert(session_id, Indexed { entries: log.entries.len(), modified });
+        }
+
+        if let Some(mut writer) = writer {
+            writer.commit().map_err(|e| e.to_string())?;
+        }
+        if changed {
+            let state = serde_json::to_string(&indexed).map_err(|e| e.to_string())?;
+            fs::write(&state_path, state).map_err(|e| format!("{}: {}", state_path.display(), e))?;
+        }
+        Ok(added)
+    }
+
+    fn document(&self, log_dir: &Path, session_id: &str, seq: usize, id: &str, entry: &CommandEntry) -> TantivyDocument {
+        let f = &self.fields;
+        let raw = entry
+            .output_path
+            .as_ref()
+            .and_then(|p| crate::command_log::read_sidecar(&log_dir.join(p)).ok())
+            .unwrap_or_else(|| entry.output_preview.as_bytes().to_vec());
+        let mut output = crate::io::screen_text(&raw[..raw.len().min(MAX_OUTPUT_BYTES)]);
+        if let Some(stderr) = &entry.stderr {
+            output.push('\n');
+            output.push_str(&crate::io::screen_text(stderr.as_bytes()));
+        }
+        doc!(
+            f.id => id,
+            f.session_id => session_id,
+            f.seq => seq as u64,
+            f.timestamp => entry.timestamp.as_str(),
+            f.cmd => entry.cmd.as_str(),
+            f.cwd => entry.cwd.as_str(),
+            f.exit_code => entry.exit_code as i64,
+            f.output => output,
+        )
+    }
+
+    /// best matches for `text` in command lines (weighted higher) and output
+    pub fn search(&self, text: &str, limit: usize) -> Result<Vec<Hit>, String> {
+        let f = &self.fields;
+        let reader = self.index.reader().map_err(|e| e.to_string())?;
+        let searcher = reader.searcher();
+        let mut parser = QueryParser::for_index(&self.index, vec![f.cmd, f.output]);
+        parser.set_field_boost(f.cmd, 2.0);
+        // typos in query syntax shouldn't fail a search, whatever parses is used
+        let (query, _) = parser.parse_query_lenient(text);
+        let top = searcher
+            .search(&query, &TopDocs::with_limit(limit.max(1)).order_by_score())
+            .map_err(|e| e.to_string())?;
+        let mut snippets = SnippetGenerator::create(&searcher, &*query, f.output).map_err(|e| e.to_string())?;
+        snippets.set_max_num_chars(120);
+
+        let mut hits = Vec::new();
+        for (score, address) in top {
+            let doc: TantivyDocument = searcher.doc(address).map_err(|e| e.to_string())?;
+            let text_of = |field: Field| doc.get_first(field).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+            let snippet = snippets.snippet_from_doc(&doc);
+            hits.push(Hit {
+                score,
+                session_id: text_of(f.session_id),
+                seq: doc.get_first(f.seq).and_then(|v| v.as_u64()).unwrap_or(0),
+                timestamp: text_of(f.timestamp),
+                cmd: text_of(f.cmd),
+                cwd: text_of(f.cwd),
+                exit_code: doc.get_first(f.exit_code).and_then(|v| v.as_i64()).unwrap_or(0),
+                snippet: (!snippet.highlighted().is_empty()).then(|| Snippet {
+                    text: snippet.fragment().to_string(),
+                    highlighted: snippet.highlighted().iter().map(|r| (r.start, r.end)).collect(),
+                }),
+            });
+        }
+        Ok(hits)
+    }
+}
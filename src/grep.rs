@@ -0,0 +1,109 @@
+use crate::command_log::{read_sidecar, CommandEntry, CommandLog};
+use crate::history;
+use crate::io::screen_text;
+use regex::Regex;
+use serde::Serialize;
+use std::path::Path;
+
+/// a recorded command whose output matched, with the lines that did
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputMatch {
+    pub session_id: String,
+    pub seq: usize, // 1 for the session's first command, as `recli show` numbers them
+    pub cmd: String,
+    pub cwd: String,
+    pub timestamp: String,
+    pub exit_code: i32,
+    pub lines: Vec<MatchedLine>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MatchedLine {
+    pub stream: &'static str, // "output", or "stderr" when split_stderr kept it apart
+    pub line_no: usize,
+    pub text: String,
+    // whether it matched or is context around a line that did
+    pub matched: bool,
+}
+
+/// what to look through
+#[derive(Debug, Default)]
+pub struct Scope<'a> {
+    pub session: Option<&'a str>,
+    // git toplevel, or directory outside one, the commands ran in
+    pub project: Option<&'a str>,
+}
+
+/// commands below `base` whose output has a line matching `pattern`, newest first, up to `limit`
+/// of them; each with `context` lines around its matches. Output is searched as the text it left
+/// on screen, so colors and progress-bar redraws don't get in the way.
+pub fn search(base: &Path, scope: &Scope, pattern: &Regex, context: usize, limit: usize) -> Vec<OutputMatch> {
+    let mut found = Vec::new();
+    for dir in history::session_dirs(base).into_iter().rev() {
+        let session_id = dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        if scope.session.is_some_and(|s| s != session_id) {
+            continue;
+        }
+        let Ok(log) = CommandLog::load_from_file(&dir) else { continue };
+        for (i, entry) in log.entries.iter().enumerate().rev() {
+            if scope.project.is_some_and(|p| entry.project.as_deref().unwrap_or(&entry.cwd) != p) {
+                continue;
+            }
+            let mut lines = matching_lines(&output(&dir, entry, false), "output", pattern, context);
+            lines.extend(matching_lines(&output(&dir, entry, true), "stderr", pattern, context));
+            if lines.is_empty() {
+                continue;
+            }
+            found.push(OutputMatch {
+                session_id: session_id.clone(),
+                seq: i + 1,
+                cmd: entry.cmd.clone(),
+                cwd: entry.cwd.clone(),
+                timestamp: entry.timestamp.clone(),
+                exit_code: entry.exit_code,
+                lines,
+            });
+            if found.len() >= limit {
+                return found;
+            }
+        }
+    }
+    found
+}
+
+// the whole output from its sidecar when there is one, else what commands.json kept of it
+fn output(log_dir: &Path, entry: &CommandEntry, stderr: bool) -> String {
+    let (path, preview) = match stderr {
+        true => (entry.stderr_path.as_ref(), entry.stderr.as_deref().unwrap_or_default()),
+        false => (entry.output_path.as_ref(), entry.output_preview.as_str()),
+    };
+    let bytes = path
+        .and_then(|p| read_sidecar(&log_dir.join(p)).ok())
+        .unwrap_or_else(|| preview.as_bytes().to_vec());
+    screen_text(&bytes)
+}
+
+// lines matching `pattern` and `context` lines on either side, like grep -C
+fn matching_lines(text: &str, stream: &'static str, pattern: &Regex, context: usize) -> Vec<MatchedLine> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut keep = vec![None; lines.len()];
+    for (i, line) in lines.iter().enumerate() {
+        if pattern.is_match(line) {
+            let from = i.saturating_sub(context);
+            for (k, kept) in keep.iter_mut().enumerate().take(i + context + 1).skip(from) {
+                *kept = Some(*kept == Some(true) || k == i);
+            }
+        }
+    }
+    keep.iter()
+        .enumerate()
+        .filter_map(|(i, kept)| {
+            kept.map(|matched| MatchedLine {
+                stream,
+                line_no: i + 1,
+                text: lines[i].to_string(),
+                matched,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,176 @@
+use crate::file_changes::FileChanges;
+use crate::util::time;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// the fields of a recorded command that history features rely on
+/// (tolerant of both the simple logger and pty session layouts)
+#[derive(Debug, Clone, Deserialize)]
+pub struct HistoryEntry {
+    #[serde(default)]
+    pub id: String,
+    pub cmd: String,
+    #[serde(default)]
+    pub cwd: String,
+    #[serde(default, deserialize_with = "time::de_stamp")]
+    pub timestamp: String,
+    #[serde(default)]
+    pub exit_code: i32,
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
+    #[serde(default)]
+    pub files: Option<FileChanges>,
+    #[serde(default)]
+    pub project: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct HistoryFile {
+    #[serde(default)]
+    entries: Vec<HistoryEntry>,
+}
+
+/// symlink in a log directory to the session most recently started or finished there
+pub const LATEST_LINK: &str = "latest";
+
+/// default base directory holding one folder per session
+pub fn logs_base_dir() -> PathBuf {
+    let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+    PathBuf::from(home).join(".recli").join("logs")
+}
+
+/// load every recorded command below `base`, oldest session first
+pub fn load_all(base: &Path) -> Vec<HistoryEntry> {
+    load_sessions(base).into_iter().flat_map(|(_, entries)| entries).collect()
+}
+
+/// recorded commands grouped by session directory name, oldest session first
+pub fn load_sessions(base: &Path) -> Vec<(String, Vec<HistoryEntry>)> {
+    let mut sessions = Vec::new();
+    for dir in session_dirs(base) {
+        if let Some(entries) = load_session(&dir) {
+            let id = dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            sessions.push((id, entries));
+        }
+    }
+    sessions
+}
+
+/// the commands of the session recorded in `dir`
+pub fn load_session(dir: &Path) -> Option<Vec<HistoryEntry>> {
+    let text = fs::read_to_string(dir.join("commands.json")).ok()?;
+    let mut entries = serde_json::from_str::<HistoryFile>(&text).ok()?.entries;
+    let session_id = dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    for e in entries.iter_mut().filter(|e| e.id.is_empty()) {
+        e.id = crate::command_log::legacy_id(&session_id, &e.timestamp, &e.cmd);
+    }
+    Some(entries)
+}
+
+/// directories of the sessions below `base` that recorded commands, oldest first
+pub fn session_dirs(base: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = fs::read_dir(base)
+        .map(|rd| {
+            rd.filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.join("commands.json").is_file() && !is_latest_link(p))
+                .collect()
+        })
+        .unwrap_or_default();
+    dirs.sort();
+    dirs
+}
+
+/// root of the git repository `dir` is in (the directory holding `.git`), resolved
+pub fn project_root(dir: &Path) -> Option<PathBuf> {
+    let root = dir.ancestors().find(|d| d.join(".git").exists())?;
+    Some(fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf()))
+}
+
+/// directory of the most recently started session below `base`
+pub fn latest_session(base: &Path) -> Option<PathBuf> {
+    session_dirs(base).pop()
+}
+
+/// the session the `latest` link below `base` points at, if it is there
+pub fn linked_latest(base: &Path) -> Option<PathBuf> {
+    let target = fs::read_link(base.join(LATEST_LINK)).ok()?;
+    Some(base.join(target)).filter(|dir| dir.is_dir())
+}
+
+fn is_latest_link(path: &Path) -> bool {
+    path.file_name().is_some_and(|n| n == LATEST_LINK) && path.symlink_metadata().is_ok_and(|m| m.file_type().is_symlink())
+}
+
+/// rank previously recorded command lines that start with `prefix`
+/// score per occurrence: 1.0, doubled when run in `cwd`, +0.5 when it succeeded,
+/// then decayed by age so recent habits win over old ones
+pub fn complete(entries: &[HistoryEntry], prefix: &str, cwd: &str, limit: usize) -> Vec<String> {
+    let now = Utc::now();
+    let mut scores: HashMap<&str, f64> = HashMap::new();
+
+    for entry in entries {
+        let cmd = entry.cmd.trim();
+        if cmd.is_empty() || cmd == prefix || !cmd.starts_with(prefix) {
+            continue;
+        }
+        let mut score = 1.0;
+        if !cwd.is_empty() && entry.cwd == cwd {
+            score *= 2.0;
+        }
+        if entry.exit_code == 0 {
+            score += 0.5;
+        }
+        let age_days = DateTime::parse_from_rfc3339(&entry.timestamp)
+            .map(|t| (now - t.with_timezone(&Utc)).num_hours().max(0) as f64 / 24.0)
+            .unwrap_or(30.0);
+        score /= 1.0 + age_days / 7.0;
+
+        *scores.entry(cmd).or_insert(0.0) += score;
+    }
+
+    let mut ranked: Vec<(&str, f64)> = scores.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    ranked.into_iter().take(limit).map(|(cmd, _)| cmd.to_string()).collect()
+}
+
+/// readline/zle glue that replaces the current line with the best history match
+/// bound to Ctrl-X h; other candidates are listed below the prompt
+pub fn shell_snippet(shell: &str) -> Option<&'static str> {
+    match shell {
+        "bash" => Some(
+            r#"# --- recli history completion (bash) ---
+_recli_complete_line() {
+    local IFS=$'\n'
+    local -a matches=($(recli complete-line --cwd "$PWD" -- "$READLINE_LINE"))
+    (( ${#matches[@]} )) || return
+    if (( ${#matches[@]} > 1 )); then
+        printf '%s\n' "${matches[@]:1}"
+    fi
+    READLINE_LINE="${matches[0]}"
+    READLINE_POINT=${#READLINE_LINE}
+}
+bind -x '"\C-xh": _recli_complete_line'
+"#,
+        ),
+        "zsh" => Some(
+            r#"# --- recli history completion (zsh) ---
+_recli_complete_line() {
+    local -a matches
+    matches=("${(@f)$(recli complete-line --cwd "$PWD" -- "$BUFFER")}")
+    [[ -n "${matches[1]}" ]] || return
+    BUFFER="${matches[1]}"
+    CURSOR=${#BUFFER}
+    (( ${#matches} > 1 )) && zle -M "${(F)matches[2,-1]}"
+}
+zle -N _recli_complete_line
+bindkey '^Xh' _recli_complete_line
+"#,
+        ),
+        _ => None,
+    }
+}
@@ -0,0 +1,156 @@
+use crate::command_detector::CommandDetector;
+use crate::error::{RecliError, Result};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+/// file in the session directory the shell hook writes its markers to when there is no pty
+pub const MARKER_FILE: &str = "hook.markers";
+
+// how often the marker file is read
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// version of the bash hook, in its first line and announced by it when loaded
+pub(crate) const BASH_HOOK_VERSION: u32 = 4;
+
+// bash has no preexec; a DEBUG trap armed by the last PROMPT_COMMAND stands in for it.
+// markers go to the marker file, or to stderr in a pty such as `recli ssh` opens; those of a
+// nested session start with its namespace
+pub(crate) const BASH_HOOK: &str = r#"# --- recli bash hook (v4) ---
+[[ -r ~/.bashrc ]] && source ~/.bashrc
+
+__recli_ready=
+__recli_in=
+
+__recli_emit() {
+    local marker="${RECLI_MARKER_NS:+@$RECLI_MARKER_NS }$1"
+    if [[ -n "${RECLI_MARKER_FILE:-}" ]]; then
+        printf '\036%s\n' "$marker" >> "$RECLI_MARKER_FILE"
+    else
+        printf '\036%s\n' "$marker" >&2
+    fi
+}
+
+__recli_preexec() {
+    [[ -n "$__recli_ready" && -z "${COMP_LINE:-}" ]] || return 0
+    [[ "$BASH_COMMAND" == __recli_precmd* ]] && return 0
+    __recli_ready=
+    __recli_in=1
+    local cmd line
+    cmd=$(HISTTIMEFORMAT= builtin history 1)
+    cmd="${cmd#*[0-9]  }"
+    if [[ "$cmd" == *$'\n'* ]]; then
+        while IFS= read -r line; do
+            __recli_emit "RECLI_LINE:${line//$'\r'/}"
+        done <<< "$cmd"
+    fi
+    __recli_emit "RECLI_START:${cmd%%$'\n'*}"
+}
+
+__recli_precmd() {
+    local exit_code=$? ps=("${PIPESTATUS[@]}")
+    # an empty line goes straight to the prompt command, none of which is recorded
+    __recli_ready=
+    if [[ -n "$__recli_in" ]]; then
+        __recli_in=
+        local IFS=,
+        __recli_emit "RECLI_END:$exit_code"
+        __recli_emit "RECLI_PIPE:[${ps[*]}]"
+        __recli_emit "RECLI_PWD:$PWD"
+    fi
+}
+
+__recli_prompt_ready() {
+    __recli_ready=1
+}
+
+trap '__recli_preexec' DEBUG
+PROMPT_COMMAND="__recli_precmd${PROMPT_COMMAND:+; $PROMPT_COMMAND}; __recli_prompt_ready"
+__recli_emit "RECLI_HOOK_VERSION:bash:4"
+"#;
+
+/// run `shell` on the real terminal with recli's hook writing markers to the session directory,
+/// feeding them to `detector` until the shell exits; commands, exit codes and directories are
+/// recorded, their output isn't
+pub async fn run(
+    shell: &str,
+    log_dir: &Path,
+    zdotdir: Option<PathBuf>,
+    env: &[(&str, Option<String>)],
+    detector: &mut CommandDetector,
+) -> Result<()> {
+    let markers = log_dir.join(MARKER_FILE);
+    fs::File::create(&markers)?;
+    let mut cmd = shell_command(shell, zdotdir)?;
+    // what the shell is told of the session, set or removed; the marker file is always ours
+    for (key, value) in env {
+        match value {
+            Some(value) => cmd.env(key, value),
+            None => cmd.env_remove(key),
+        };
+    }
+    cmd.env("RECLI_MARKER_FILE", &markers);
+
+    // ctrl-c and the like belong to the shell; recli waits for it either way
+    #[cfg(unix)]
+    let (_interrupt, mut term) = {
+        use tokio::signal::unix::{signal, SignalKind};
+        (signal(SignalKind::interrupt())?, signal(SignalKind::terminate())?)
+    };
+
+    let mut child = cmd.spawn().map_err(|e| RecliError::Shell(format!("{}: {}", shell, e)))?;
+    let mut reader = fs::File::open(&markers)?;
+    let mut buf = Vec::new();
+    loop {
+        let exited = child.try_wait()?.is_some();
+        buf.clear();
+        reader.read_to_end(&mut buf)?;
+        if !buf.is_empty() {
+            detector.process_output(&buf);
+        }
+        if exited {
+            break;
+        }
+        #[cfg(unix)]
+        tokio::select! {
+            _ = tokio::time::sleep(POLL_INTERVAL) => {}
+            // `recli end`: hang up the shell as a closing terminal would
+            _ = term.recv() => unsafe {
+                libc::kill(child.id() as i32, libc::SIGHUP);
+            },
+        }
+        #[cfg(not(unix))]
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+    detector.finish();
+    let _ = fs::remove_file(&markers);
+    Ok(())
+}
+
+// the shell with recli's hook loaded after the user's own config
+fn shell_command(shell: &str, zdotdir: Option<PathBuf>) -> Result<Command> {
+    let mut cmd = Command::new(shell);
+    if shell.contains("zsh") {
+        let zdotdir = zdotdir.ok_or_else(|| RecliError::Shell("the zsh hook files could not be written".to_string()))?;
+        match crate::pty::user_zdotdir(std::env::var("ZDOTDIR").ok().as_deref(), &zdotdir) {
+            Some(dir) => cmd.env("RECLI_USER_ZDOTDIR", dir),
+            None => cmd.env_remove("RECLI_USER_ZDOTDIR"),
+        };
+        cmd.env("ZDOTDIR", zdotdir).arg("-i");
+    } else if shell.contains("bash") {
+        let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+        let dir = PathBuf::from(home).join(".recli");
+        fs::create_dir_all(&dir)?;
+        let rc = dir.join("recli.bash");
+        crate::pty::refresh_hook_file(&rc, BASH_HOOK)?;
+        cmd.arg("--rcfile").arg(rc).arg("-i");
+    } else {
+        return Err(RecliError::Shell(format!(
+            "recording without a pty needs bash or zsh, recli has no hook for {}",
+            shell
+        )));
+    }
+    Ok(cmd)
+}
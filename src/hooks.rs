@@ -0,0 +1,241 @@
+use crate::config::{HooksConfig, RetryConfig};
+use crate::outbox::{self, Post};
+use crate::util::retry::{retry_blocking_if, transient_http};
+use reqwest::blocking::Client;
+use serde_json::{json, Value};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Mutex, OnceLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// points in a session where configured hooks run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    SessionStart,
+    SessionEnd,
+    BeforeCommand,
+    AfterCommand,
+    OutputMatch,
+    CommandHung,
+}
+
+impl HookEvent {
+    pub fn name(self) -> &'static str {
+        match self {
+            HookEvent::SessionStart => "session_start",
+            HookEvent::SessionEnd => "session_end",
+            HookEvent::BeforeCommand => "before_command",
+            HookEvent::AfterCommand => "after_command",
+            HookEvent::OutputMatch => "output_match",
+            HookEvent::CommandHung => "command_hung",
+        }
+    }
+}
+
+// what the runner is asked to do, in order
+enum Job {
+    Fire(HookEvent, Value),
+    // hooks changed in the config of a running session
+    Reload(HooksConfig, RetryConfig),
+    Stop,
+}
+
+// runner installed by init when any hook is configured
+struct Runner {
+    tx: Sender<Job>,
+    worker: Mutex<Option<JoinHandle<()>>>,
+    workspace: Option<String>,
+}
+
+static RUNNER: OnceLock<Runner> = OnceLock::new();
+
+/// start the hook runner; no-op unless [hooks] lists something. Posts to a hook's url are
+/// retried as `retry` says, then left in the outbox for `recli sync`.
+pub fn init(cfg: &HooksConfig, retry: &RetryConfig, workspace: Option<&str>) {
+    let all = [
+        &cfg.session_start,
+        &cfg.session_end,
+        &cfg.before_command,
+        &cfg.after_command,
+        &cfg.output_match,
+        &cfg.command_hung,
+    ];
+    if all.iter().all(|hooks| hooks.is_empty()) {
+        return;
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let (hooks, retry) = (cfg.clone(), retry.clone());
+    let worker = match std::thread::Builder::new()
+        .name("recli-hooks".to_string())
+        .spawn(move || run(rx, hooks, retry))
+    {
+        Ok(w) => w,
+        Err(e) => {
+            tracing::warn!(error = %e, "hooks disabled");
+            return;
+        }
+    };
+    let _ = RUNNER.set(Runner {
+        tx,
+        worker: Mutex::new(Some(worker)),
+        workspace: workspace.map(str::to_string),
+    });
+}
+
+/// queue the hooks for `event`; `data` is merged into the json they receive
+pub fn fire(event: HookEvent, session_id: &str, data: Value) {
+    let Some(runner) = RUNNER.get() else { return };
+    let mut payload = json!({
+        "event": event.name(),
+        "session_id": session_id,
+        "timestamp": crate::util::time::now_stamp(),
+    });
+    if let Some(ws) = &runner.workspace {
+        payload["workspace"] = ws.as_str().into();
+    }
+    if let (Value::Object(payload), Value::Object(data)) = (&mut payload, data) {
+        payload.extend(data);
+    }
+    let _ = runner.tx.send(Job::Fire(event, payload));
+}
+
+/// use the hooks of a config read again while the session runs; hooks queued before it still run
+/// as they were configured. Starts the runner when the session began without any hook.
+pub fn reload(cfg: &HooksConfig, retry: &RetryConfig, workspace: Option<&str>) {
+    match RUNNER.get() {
+        Some(runner) => {
+            let _ = runner.tx.send(Job::Reload(cfg.clone(), retry.clone()));
+        }
+        None => init(cfg, retry, workspace),
+    }
+}
+
+/// let queued hooks finish; call before the process exits
+pub fn shutdown() {
+    let Some(runner) = RUNNER.get() else { return };
+    let worker = runner.worker.lock().ok().and_then(|mut w| w.take());
+    if let Some(worker) = worker {
+        let _ = runner.tx.send(Job::Stop);
+        let _ = worker.join();
+    }
+}
+
+// hooks run one at a time, in the order their events happened
+fn run(rx: Receiver<Job>, mut cfg: HooksConfig, mut retry: RetryConfig) {
+    let mut timeout = Duration::from_millis(cfg.timeout_ms.max(1));
+    // built lazily, most setups only have shell hooks
+    let mut client: Option<Client> = None;
+    loop {
+        let (event, payload) = match rx.recv() {
+            Ok(Job::Fire(event, payload)) => (event, payload),
+            Ok(Job::Reload(hooks, retrying)) => {
+                (cfg, retry) = (hooks, retrying);
+                timeout = Duration::from_millis(cfg.timeout_ms.max(1));
+                // the client carries the old timeout
+                client = None;
+                continue;
+            }
+            Ok(Job::Stop) | Err(_) => break,
+        };
+        let hooks = match event {
+            HookEvent::SessionStart => &cfg.session_start,
+            HookEvent::SessionEnd => &cfg.session_end,
+            HookEvent::BeforeCommand => &cfg.before_command,
+            HookEvent::AfterCommand => &cfg.after_command,
+            HookEvent::OutputMatch => &cfg.output_match,
+            HookEvent::CommandHung => &cfg.command_hung,
+        };
+        for hook in hooks {
+            if let Some(script) = &hook.run {
+                run_script(script, event, &payload, timeout);
+            }
+            if let Some(url) = &hook.url {
+                if client.is_none() {
+                    client = Client::builder().timeout(timeout).build().ok();
+                }
+                if let Some(client) = &client {
+                    post(client, url, event, &payload, &retry);
+                }
+            }
+            if hook.run.is_none() && hook.url.is_none() {
+                tracing::warn!(event = event.name(), "hook has neither run nor url, skipped");
+            }
+        }
+    }
+}
+
+fn run_script(script: &str, event: HookEvent, payload: &Value, timeout: Duration) {
+    // output is dropped, a pty session shares the terminal with the shell
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(script)
+        .env("RECLI_HOOK", event.name())
+        .env("RECLI_SESSION_ID", payload["session_id"].as_str().unwrap_or_default())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    let mut child = match child {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::warn!(event = event.name(), error = %e, "hook failed to start");
+            return;
+        }
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // written from a thread of its own, as a hook that never reads would block a payload larger
+        // than the pipe holds; killing it at the deadline ends the write. A hook that ignores its
+        // input closes the pipe early, which is fine
+        let payload = payload.to_string();
+        std::thread::spawn(move || {
+            let _ = stdin.write_all(payload.as_bytes());
+        });
+    }
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return,
+            Ok(Some(status)) => {
+                tracing::warn!(event = event.name(), script, status = %status, "hook failed");
+                return;
+            }
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(20)),
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                tracing::warn!(event = event.name(), script, "hook timed out and was killed");
+                return;
+            }
+            Err(e) => {
+                tracing::warn!(event = event.name(), error = %e, "hook wait failed");
+                return;
+            }
+        }
+    }
+}
+
+fn post(client: &Client, url: &str, event: HookEvent, payload: &Value, retry: &RetryConfig) {
+    let body = payload.to_string();
+    let send = || {
+        client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.clone())
+            .send()
+            .and_then(|resp| resp.error_for_status())
+    };
+    match retry_blocking_if(send, retry, transient_http) {
+        Ok(_) => tracing::debug!(event = event.name(), url, "hook posted"),
+        Err(e) if transient_http(&e) => {
+            tracing::warn!(event = event.name(), url, error = %e, "hook url unreachable, post queued for recli sync");
+            if let Err(e) = outbox::add_post(&Post { url: url.to_string(), body }) {
+                tracing::warn!(event = event.name(), error = %e, "hook post could not be queued");
+            }
+        }
+        Err(e) => tracing::warn!(event = event.name(), url, error = %e, "hook url rejected event"),
+    }
+}
@@ -0,0 +1,349 @@
+use crate::command_log;
+use crate::error::Result;
+use crate::history;
+use crate::util::time;
+use chrono::{DateTime, Utc};
+use rusqlite::types::ValueRef;
+use rusqlite::{params, Batch, Connection, OpenFlags, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// sync_state key set once the indexed timestamps were rewritten as util::time::stamp writes them
+const STAMPS_KEY: &str = "timestamps";
+
+/// one recorded command as kept in the local index, from this machine or pulled from the cloud
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedCommand {
+    /// the command's uuidv7, as in commands.json and uploaded documents
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    pub session_id: String,
+    #[serde(default)]
+    pub host: String,
+    #[serde(default)]
+    pub user: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+    pub cmd: String,
+    #[serde(default)]
+    pub exit_code: i32,
+    #[serde(default)]
+    pub cwd: String,
+    #[serde(default)]
+    pub timestamp: String,
+    #[serde(default)]
+    pub duration_ms: u64,
+    /// git toplevel the command ran in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+}
+
+/// a session as seen through its indexed commands
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexedSession {
+    pub session_id: String,
+    pub host: String,
+    pub user: String,
+    pub started: String, // first command
+    pub ended: String,   // last command
+    pub commands: usize,
+    pub failed: usize,
+}
+
+/// sqlite index merging local sessions and cloud history for `recli history` / `recli search`
+pub struct Index {
+    conn: Connection,
+}
+
+/// default index location next to the session logs
+pub fn default_path() -> PathBuf {
+    let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+    PathBuf::from(home).join(".recli").join("index.db")
+}
+
+impl Index {
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS commands (
+                session_id  TEXT NOT NULL,
+                timestamp   TEXT NOT NULL,
+                cmd         TEXT NOT NULL,
+                host        TEXT NOT NULL,
+                user        TEXT NOT NULL,
+                workspace   TEXT,
+                cwd         TEXT NOT NULL,
+                exit_code   INTEGER NOT NULL,
+                duration_ms INTEGER NOT NULL,
+                PRIMARY KEY (session_id, timestamp, cmd)
+            );
+            CREATE INDEX IF NOT EXISTS commands_by_time ON commands (timestamp);
+            CREATE TABLE IF NOT EXISTS sync_state (key TEXT PRIMARY KEY, value TEXT NOT NULL);",
+        )?;
+        // indexes created before commands were grouped by project
+        let has_project: bool = conn
+            .prepare("SELECT 1 FROM pragma_table_info('commands') WHERE name = 'project'")?
+            .exists([])?;
+        if !has_project {
+            conn.execute_batch("ALTER TABLE commands ADD COLUMN project TEXT")?;
+        }
+        conn.execute_batch("CREATE INDEX IF NOT EXISTS commands_by_project ON commands (project, timestamp)")?;
+        // and before commands had ids
+        let has_id: bool = conn.prepare("SELECT 1 FROM pragma_table_info('commands') WHERE name = 'id'")?.exists([])?;
+        if !has_id {
+            conn.execute_batch("ALTER TABLE commands ADD COLUMN id TEXT")?;
+        }
+        conn.execute_batch("CREATE INDEX IF NOT EXISTS commands_by_id ON commands (id)")?;
+        let mut index = Self { conn };
+        index.normalize_stamps()?;
+        Ok(index)
+    }
+
+    // timestamps indexed before every one was written the same way are rewritten once, or the
+    // same command read again in the new form would be indexed twice
+    fn normalize_stamps(&mut self) -> Result<()> {
+        if self.get_state(STAMPS_KEY)?.is_some() {
+            return Ok(());
+        }
+        let tx = self.conn.transaction()?;
+        {
+            let rows: Vec<(i64, String)> = tx
+                .prepare("SELECT rowid, timestamp FROM commands")?
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<std::result::Result<_, _>>()?;
+            // a row already indexed again in the new form replaces the old one
+            let mut stmt = tx.prepare("UPDATE OR REPLACE commands SET timestamp = ?1 WHERE rowid = ?2")?;
+            for (rowid, stamp) in rows {
+                let normalized = time::normalize_stamp(&stamp);
+                if normalized != stamp {
+                    stmt.execute(params![normalized, rowid])?;
+                }
+            }
+            tx.execute(
+                "INSERT INTO sync_state (key, value) VALUES (?1, 'rfc3339-utc')",
+                params![STAMPS_KEY],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// insert commands, updating rows already present; returns how many rows were new
+    pub fn merge(&mut self, commands: &[IndexedCommand]) -> Result<usize> {
+        let before = self.count()?;
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO commands (session_id, timestamp, cmd, host, user, workspace, cwd, exit_code, duration_ms, project, id)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+                 ON CONFLICT (session_id, timestamp, cmd) DO UPDATE SET
+                    host = excluded.host, user = excluded.user, workspace = excluded.workspace,
+                    cwd = excluded.cwd, exit_code = excluded.exit_code, duration_ms = excluded.duration_ms,
+                    project = COALESCE(excluded.project, commands.project), id = excluded.id",
+            )?;
+            for c in commands {
+                // documents uploaded before commands had ids
+                let id = match c.id.is_empty() {
+                    true => command_log::legacy_id(&c.session_id, &c.timestamp, &c.cmd),
+                    false => c.id.clone(),
+                };
+                stmt.execute(params![
+                    c.session_id,
+                    time::normalize_stamp(&c.timestamp),
+                    c.cmd,
+                    c.host,
+                    c.user,
+                    c.workspace,
+                    c.cwd,
+                    c.exit_code,
+                    c.duration_ms as i64,
+                    c.project,
+                    id,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(self.count()? - before)
+    }
+
+    fn count(&self) -> Result<usize> {
+        let n: i64 = self.conn.query_row("SELECT COUNT(*) FROM commands", [], |row| row.get(0))?;
+        Ok(n as usize)
+    }
+
+    /// drop the commands of `session_id`, e.g. before importing it again after it was scrubbed
+    pub fn forget_session(&mut self, session_id: &str) -> Result<usize> {
+        Ok(self.conn.execute("DELETE FROM commands WHERE session_id = ?1", params![session_id])?)
+    }
+
+    /// add every session recorded on this machine below `base`
+    pub fn import_local(&mut self, base: &Path, host: &str, user: &str) -> Result<usize> {
+        let mut commands = Vec::new();
+        // sessions recorded before entries carried their project get it looked up from the cwd
+        let mut projects: HashMap<String, Option<String>> = HashMap::new();
+        for (session_id, entries) in history::load_sessions(base) {
+            commands.extend(entries.into_iter().map(|e| IndexedCommand {
+                project: e.project.or_else(|| {
+                    projects
+                        .entry(e.cwd.clone())
+                        .or_insert_with(|| history::project_root(Path::new(&e.cwd)).map(|p| p.to_string_lossy().to_string()))
+                        .clone()
+                }),
+                id: e.id,
+                session_id: session_id.clone(),
+                host: host.to_string(),
+                user: user.to_string(),
+                workspace: None,
+                cmd: e.cmd,
+                exit_code: e.exit_code,
+                cwd: e.cwd,
+                timestamp: e.timestamp,
+                duration_ms: e.duration_ms.unwrap_or(0),
+            }));
+        }
+        self.merge(&commands)
+    }
+
+    /// newest commands first, optionally only those containing `text` and run in `project`
+    /// (a git toplevel, or a directory outside any repository)
+    pub fn search(&self, text: Option<&str>, project: Option<&str>, limit: usize) -> Result<Vec<IndexedCommand>> {
+        let pattern = format!("%{}%", text.unwrap_or_default());
+        let mut stmt = self.conn.prepare(
+            "SELECT session_id, host, user, workspace, cmd, exit_code, cwd, timestamp, duration_ms, project, id
+             FROM commands WHERE cmd LIKE ?1 AND (?2 IS NULL OR project = ?2 OR (project IS NULL AND cwd = ?2))
+             ORDER BY timestamp DESC LIMIT ?3",
+        )?;
+        let rows = stmt.query_map(params![pattern, project, limit as i64], command_from_row)?;
+        Ok(rows.collect::<std::result::Result<Vec<_>, _>>()?)
+    }
+
+    /// every command run between `since` and `until`, oldest first
+    pub fn between(&self, since: DateTime<Utc>, until: DateTime<Utc>) -> Result<Vec<IndexedCommand>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT session_id, host, user, workspace, cmd, exit_code, cwd, timestamp, duration_ms, project, id FROM commands",
+        )?;
+        let rows = stmt.query_map([], command_from_row)?;
+        // timestamps carry their own offsets, so they are compared as times rather than text
+        let mut commands: Vec<(DateTime<Utc>, IndexedCommand)> = Vec::new();
+        for row in rows {
+            let command = row?;
+            let Ok(at) = DateTime::parse_from_rfc3339(&command.timestamp) else { continue };
+            let at = at.with_timezone(&Utc);
+            if at >= since && at < until {
+                commands.push((at, command));
+            }
+        }
+        commands.sort_by_key(|(at, _)| *at);
+        Ok(commands.into_iter().map(|(_, c)| c).collect())
+    }
+
+    /// every command, a session at a time and oldest first within each, so what ran after what
+    /// can be read off
+    pub fn in_order(&self) -> Result<Vec<IndexedCommand>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT session_id, host, user, workspace, cmd, exit_code, cwd, timestamp, duration_ms, project, id
+             FROM commands ORDER BY session_id, timestamp",
+        )?;
+        let rows = stmt.query_map([], command_from_row)?;
+        Ok(rows.collect::<std::result::Result<Vec<_>, _>>()?)
+    }
+
+    /// sessions newest first, optionally only those that ran commands in `project`
+    pub fn sessions(&self, project: Option<&str>, limit: usize) -> Result<Vec<IndexedSession>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT session_id, host, user, MIN(timestamp), MAX(timestamp), COUNT(*), SUM(exit_code != 0)
+             FROM commands WHERE ?1 IS NULL OR project = ?1 OR (project IS NULL AND cwd = ?1)
+             GROUP BY session_id ORDER BY MIN(timestamp) DESC LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![project, limit as i64], |row| {
+            Ok(IndexedSession {
+                session_id: row.get(0)?,
+                host: row.get(1)?,
+                user: row.get(2)?,
+                started: row.get(3)?,
+                ended: row.get(4)?,
+                commands: row.get::<_, i64>(5)? as usize,
+                failed: row.get::<_, i64>(6)? as usize,
+            })
+        })?;
+        Ok(rows.collect::<std::result::Result<Vec<_>, _>>()?)
+    }
+
+    /// run one read-only sql statement against the index; returns column names and rows
+    pub fn query(&self, sql: &str) -> Result<(Vec<String>, Vec<Vec<serde_json::Value>>)> {
+        // a connection of its own that can't write: sqlite counts attach, detach and transaction
+        // statements as read-only, so the check below isn't enough on its own
+        let path = self.conn.path().ok_or(rusqlite::Error::InvalidQuery)?;
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)?;
+        conn.execute_batch("PRAGMA query_only = ON")?;
+        // one statement only: prepare would run the first and quietly drop whatever follows it
+        let mut batch = Batch::new(&conn, sql);
+        let mut stmt = batch.next()?.ok_or(rusqlite::Error::InvalidQuery)?;
+        if batch.next()?.is_some() {
+            return Err(rusqlite::Error::MultipleStatement.into());
+        }
+        if !stmt.readonly() {
+            return Err(rusqlite::Error::InvalidQuery.into());
+        }
+        let columns: Vec<String> = stmt.column_names().into_iter().map(str::to_string).collect();
+        let mut rows = stmt.query([])?;
+        let mut out = Vec::new();
+        while let Some(row) = rows.next()? {
+            let mut values = Vec::with_capacity(columns.len());
+            for i in 0..columns.len() {
+                values.push(match row.get_ref(i)? {
+                    ValueRef::Null => serde_json::Value::Null,
+                    ValueRef::Integer(n) => n.into(),
+                    ValueRef::Real(f) => f.into(),
+                    ValueRef::Text(t) => String::from_utf8_lossy(t).into(),
+                    ValueRef::Blob(b) => format!("<{} bytes>", b.len()).into(),
+                });
+            }
+            out.push(values);
+        }
+        Ok((columns, out))
+    }
+
+    pub fn get_state(&self, key: &str) -> Result<Option<String>> {
+        Ok(self
+            .conn
+            .query_row("SELECT value FROM sync_state WHERE key = ?1", params![key], |row| row.get(0))
+            .optional()?)
+    }
+
+    pub fn set_state(&self, key: &str, value: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO sync_state (key, value) VALUES (?1, ?2)
+             ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+            params![key, value],
+        )?;
+        Ok(())
+    }
+}
+
+// a row selected as session_id, host, user, workspace, cmd, exit_code, cwd, timestamp, duration_ms, project, id
+fn command_from_row(row: &rusqlite::Row) -> rusqlite::Result<IndexedCommand> {
+    let mut command = IndexedCommand {
+        id: row.get::<_, Option<String>>(10)?.unwrap_or_default(),
+        session_id: row.get(0)?,
+        host: row.get(1)?,
+        user: row.get(2)?,
+        workspace: row.get(3)?,
+        cmd: row.get(4)?,
+        exit_code: row.get(5)?,
+        cwd: row.get(6)?,
+        timestamp: row.get(7)?,
+        duration_ms: row.get::<_, i64>(8)? as u64,
+        project: row.get(9)?,
+    };
+    // indexed before commands had ids and not merged again since
+    if command.id.is_empty() {
+        command.id = command_log::legacy_id(&command.session_id, &command.timestamp, &command.cmd);
+    }
+    Ok(command)
+}
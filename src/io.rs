@@ -0,0 +1,928 @@
+use crate::config::{HotkeyConfig, WatchConfig};
+use crate::error::Result;
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use std::io::Write;
+
+/// handles the conversion of terminal events to pty input
+pub struct InputHandler;
+
+impl InputHandler {
+    /// convert a crossterm keyevent to bytes that can be sent to pty
+    /// returns none if this is a special hotkey that shouldn't be forwarded
+    pub fn key_to_bytes(key_event: KeyEvent) -> Option<Vec<u8>> {
+        if key_event.kind != KeyEventKind::Press {
+            return None;
+        }
+
+    // no hotkey interception; all keys pass through to the shell
+
+        match key_event.code {
+            // regular characters encode as utf-8
+            KeyCode::Char(c) => {
+                let mut bytes = [0u8; 4];
+                let encoded = c.encode_utf8(&mut bytes);
+                Some(encoded.as_bytes().to_vec())
+            }
+            // special keys
+            KeyCode::Enter => Some(b"\r".to_vec()),
+            KeyCode::Backspace => Some(vec![127]), // DEL character
+            KeyCode::Tab => Some(b"\t".to_vec()),
+
+            // arrow keys ansi escape sequences
+            KeyCode::Up => Some(b"\x1b[A".to_vec()),
+            KeyCode::Down => Some(b"\x1b[B".to_vec()),
+            KeyCode::Right => Some(b"\x1b[C".to_vec()),
+            KeyCode::Left => Some(b"\x1b[D".to_vec()),
+
+            // function keys
+            KeyCode::F(n) => match n {
+                1..=12 => {
+                    // f1-f12 escape sequences
+                    Some(format!("\x1b[{};2~", n + 10).into_bytes())
+                }
+                _ => None,
+            },
+
+            // page navigation
+            KeyCode::PageUp => Some(b"\x1b[5~".to_vec()),
+            KeyCode::PageDown => Some(b"\x1b[6~".to_vec()),
+            KeyCode::Home => Some(b"\x1b[H".to_vec()),
+            KeyCode::End => Some(b"\x1b[F".to_vec()),
+
+            // insert/delete
+            KeyCode::Insert => Some(b"\x1b[2~".to_vec()),
+            KeyCode::Delete => Some(b"\x1b[3~".to_vec()),
+
+            // escape key
+            KeyCode::Esc => Some(b"\x1b".to_vec()),
+
+            // ignore other keys for now
+            _ => None,
+        }
+    }
+
+    /// special key combinations
+    pub fn handle_control_key(c: char) -> Option<Vec<u8>> {
+        match c {
+            'c' => Some(vec![3]),  // ctrl+c
+            'd' => Some(vec![4]),  // ctrl+d (eof)
+            'z' => Some(vec![26]), // ctrl+z (suspend)
+            'l' => Some(vec![12]), // ctrl+l (clear screen)
+            _ => None,
+        }
+    }
+}
+
+/// action bound to an intercepted key sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotkeyAction {
+    Terminate,
+    Pause,
+    Annotate,
+    Hung,
+    Interrupt,
+    Note,
+}
+
+/// byte-level matcher for configured hotkey sequences in raw stdin
+/// bytes that turn out not to be a hotkey are handed back unchanged and in order
+#[derive(Debug, Default)]
+pub struct HotkeyMatcher {
+    bindings: Vec<(Vec<u8>, HotkeyAction)>,
+    pending: Vec<u8>,
+}
+
+impl HotkeyMatcher {
+    /// build from config; a disabled config yields a matcher that passes everything through
+    pub fn from_config(cfg: &HotkeyConfig) -> std::result::Result<Self, String> {
+        let mut matcher = Self::default();
+        if !cfg.enabled {
+            return Ok(matcher);
+        }
+        for (spec, action) in [
+            (&cfg.terminate, HotkeyAction::Terminate),
+            (&cfg.pause, HotkeyAction::Pause),
+            (&cfg.annotate, HotkeyAction::Annotate),
+            (&cfg.hung, HotkeyAction::Hung),
+            (&cfg.interrupt, HotkeyAction::Interrupt),
+            (&cfg.note, HotkeyAction::Note),
+        ] {
+            if let Some(spec) = spec {
+                matcher.bindings.push((parse_key_sequence(spec)?, action));
+            }
+        }
+        Ok(matcher)
+    }
+
+    /// true while the start of a sequence is held back waiting for its next key
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// split input into bytes to forward and the hotkeys it completed
+    pub fn feed(&mut self, data: &[u8]) -> (Vec<u8>, Vec<HotkeyAction>) {
+        let mut forward = Vec::with_capacity(data.len());
+        let mut actions = Vec::new();
+        for &b in data {
+            self.pending.push(b);
+            loop {
+                if let Some((_, action)) = self.bindings.iter().find(|(seq, _)| *seq == self.pending) {
+                    actions.push(*action);
+                    self.pending.clear();
+                    break;
+                }
+                if self.bindings.iter().any(|(seq, _)| seq.starts_with(&self.pending)) {
+                    break;
+                }
+                // not a hotkey: release the first byte and retry the remainder
+                forward.push(self.pending.remove(0));
+                if self.pending.is_empty() {
+                    break;
+                }
+            }
+        }
+        (forward, actions)
+    }
+
+    /// give up on a half-typed sequence and return its bytes for forwarding
+    pub fn flush(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+/// parse "ctrl-x ctrl-x" / "^X p" style key names into the bytes a terminal sends
+pub fn parse_key_sequence(spec: &str) -> std::result::Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    for token in spec.split_whitespace() {
+        let lower = token.to_ascii_lowercase();
+        let ctrl = lower
+            .strip_prefix("ctrl-")
+            .or_else(|| lower.strip_prefix("ctrl+"))
+            .or_else(|| lower.strip_prefix("c-"))
+            .or_else(|| lower.strip_prefix('^'));
+        match ctrl {
+            Some(key) if key.len() == 1 => match key.as_bytes()[0] {
+                c @ b'a'..=b'z' => bytes.push(c - b'a' + 1),
+                c @ (b'@' | b'[' | b'\\' | b']' | b'^' | b'_') => bytes.push(c ^ 0x40),
+                _ => return Err(format!("unsupported control key: {}", token)),
+            },
+            Some(_) => return Err(format!("unsupported control key: {}", token)),
+            None => match lower.as_str() {
+                "esc" => bytes.push(0x1b),
+                "space" => bytes.push(b' '),
+                _ if token.len() == 1 => bytes.push(token.as_bytes()[0]),
+                _ => return Err(format!("unknown key: {}", token)),
+            },
+        }
+    }
+    if bytes.is_empty() {
+        return Err("empty key sequence".to_string());
+    }
+    Ok(bytes)
+}
+
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+const PASTE_MODE_ON: &[u8] = b"\x1b[?2004h";
+const PASTE_MODE_OFF: &[u8] = b"\x1b[?2004l";
+
+/// piece of the stdin stream, split on bracketed paste framing and mouse reports
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputChunk {
+    /// typed input, subject to hotkey matching
+    Keys(Vec<u8>),
+    PasteStart,
+    /// pasted bytes without framing
+    PasteData(Vec<u8>),
+    PasteEnd,
+    Mouse(MouseEvent),
+}
+
+/// splits raw stdin into typed keys and bracketed pastes
+/// a framing sequence cut across two reads is held back until the next read or `flush`
+#[derive(Debug, Default)]
+pub struct PasteSplitter {
+    in_paste: bool,
+    held: Vec<u8>,
+}
+
+impl PasteSplitter {
+    pub fn feed(&mut self, data: &[u8]) -> Vec<InputChunk> {
+        let mut buf = std::mem::take(&mut self.held);
+        buf.extend_from_slice(data);
+
+        let mut chunks = Vec::new();
+        let mut rest: &[u8] = &buf;
+        while !rest.is_empty() {
+            let marker = if self.in_paste {
+                PASTE_END
+            } else {
+                PASTE_START
+            };
+            match find(rest, marker) {
+                Some(i) => {
+                    self.push_data(&mut chunks, &rest[..i]);
+                    chunks.push(if self.in_paste {
+                        InputChunk::PasteEnd
+                    } else {
+                        InputChunk::PasteStart
+                    });
+                    self.in_paste = !self.in_paste;
+                    rest = &rest[i + marker.len()..];
+                }
+                None => {
+                    // hold a trailing partial marker; a lone esc is never held so it isn't delayed
+                    let keep = partial_suffix(rest, marker);
+                    let keep = if keep >= 2 { keep } else { 0 };
+                    self.push_data(&mut chunks, &rest[..rest.len() - keep]);
+                    self.held = rest[rest.len() - keep..].to_vec();
+                    break;
+                }
+            }
+        }
+        chunks
+    }
+
+    /// true while a possible framing sequence is held back
+    pub fn has_held(&self) -> bool {
+        !self.held.is_empty()
+    }
+
+    /// release held bytes when no continuation arrived
+    pub fn flush(&mut self) -> Vec<InputChunk> {
+        let held = std::mem::take(&mut self.held);
+        let mut chunks = Vec::new();
+        self.push_data(&mut chunks, &held);
+        chunks
+    }
+
+    fn push_data(&self, chunks: &mut Vec<InputChunk>, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        chunks.push(if self.in_paste {
+            InputChunk::PasteData(data.to_vec())
+        } else {
+            InputChunk::Keys(data.to_vec())
+        });
+    }
+}
+
+/// follows the child's bracketed paste mode (csi ?2004 h/l) in its output
+#[derive(Debug, Default)]
+pub struct PasteModeTracker {
+    enabled: bool,
+    tail: Vec<u8>,
+}
+
+impl PasteModeTracker {
+    pub fn observe(&mut self, data: &[u8]) {
+        let mut buf = std::mem::take(&mut self.tail);
+        buf.extend_from_slice(data);
+        // the last switch in the chunk wins
+        let on = rfind(&buf, PASTE_MODE_ON);
+        let off = rfind(&buf, PASTE_MODE_OFF);
+        match (on, off) {
+            (Some(a), Some(b)) => self.enabled = a > b,
+            (Some(_), None) => self.enabled = true,
+            (None, Some(_)) => self.enabled = false,
+            (None, None) => {}
+        }
+        let keep = (PASTE_MODE_ON.len() - 1).min(buf.len());
+        self.tail = buf[buf.len() - keep..].to_vec();
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+/// the framing to send around a paste when the child asked for it
+pub fn paste_framing(start: bool) -> &'static [u8] {
+    if start {
+        PASTE_START
+    } else {
+        PASTE_END
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).rposition(|w| w == needle)
+}
+
+// length of the longest suffix of `data` that is a proper prefix of `marker`
+fn partial_suffix(data: &[u8], marker: &[u8]) -> usize {
+    (1..marker.len().min(data.len() + 1))
+        .rev()
+        .find(|&n| data.ends_with(&marker[..n]))
+        .unwrap_or(0)
+}
+
+const ALT_SCREEN_ON: &[u8] = b"\x1b[?1049h";
+const ALT_SCREEN_OFF: &[u8] = b"\x1b[?1049l";
+const ERROR_GUTTER: &[u8] = b"\x1b[31m\xe2\x96\x8c\x1b[0m";
+const WARNING_GUTTER: &[u8] = b"\x1b[33m\xe2\x96\x8c\x1b[0m";
+
+/// whether a full-screen program has the terminal, from the alternate screen switches in its output
+#[derive(Debug, Default)]
+pub struct AltScreenTracker {
+    active: bool,
+    // the end of the last chunk, in case a switch was split across reads
+    tail: Vec<u8>,
+}
+
+impl AltScreenTracker {
+    pub fn observe(&mut self, data: &[u8]) {
+        let mut buf = std::mem::take(&mut self.tail);
+        buf.extend_from_slice(data);
+        match (rfind(&buf, ALT_SCREEN_ON), rfind(&buf, ALT_SCREEN_OFF)) {
+            (Some(a), Some(b)) => self.active = a > b,
+            (Some(_), None) => self.active = true,
+            (None, Some(_)) => self.active = false,
+            (None, None) => {}
+        }
+        let keep = (ALT_SCREEN_ON.len() - 1).min(buf.len());
+        self.tail = buf[buf.len() - keep..].to_vec();
+    }
+
+    pub fn active(&self) -> bool {
+        self.active
+    }
+}
+
+/// puts a colored bar in front of error and warning lines on screen.
+/// only lines that arrive whole are marked, and full-screen programs are left alone
+#[derive(Debug)]
+pub struct ErrorHighlighter {
+    at_line_start: bool,
+    alt_screen: AltScreenTracker,
+}
+
+impl Default for ErrorHighlighter {
+    fn default() -> Self {
+        Self {
+            at_line_start: true,
+            alt_screen: AltScreenTracker::default(),
+        }
+    }
+}
+
+impl ErrorHighlighter {
+    /// the bytes to display for `data`; lines are only marked when `mark` is set
+    pub fn process(&mut self, data: &[u8], mark: bool) -> Vec<u8> {
+        let was_alt = self.alt_screen.active();
+        self.alt_screen.observe(data);
+        let starts_at_line = self.at_line_start;
+        if let Some(&last) = data.last() {
+            self.at_line_start = last == b'\n';
+        }
+        if !mark || was_alt || self.alt_screen.active() {
+            return data.to_vec();
+        }
+
+        let mut out = Vec::with_capacity(data.len() + 16);
+        let mut line_start = starts_at_line;
+        for line in data.split_inclusive(|b| *b == b'\n') {
+            if line_start && line.ends_with(b"\n") {
+                match crate::ci::line_severity(&String::from_utf8_lossy(line)) {
+                    Some(crate::ci::Severity::Error) => out.extend_from_slice(ERROR_GUTTER),
+                    Some(crate::ci::Severity::Warning) => out.extend_from_slice(WARNING_GUTTER),
+                    None => {}
+                }
+            }
+            out.extend_from_slice(line);
+            line_start = true;
+        }
+        out
+    }
+}
+
+// a line longer than this is matched in pieces
+const WATCH_LINE_LIMIT: usize = 4096;
+
+/// finds output lines matching the [watch] patterns; each pattern alerts once per command
+#[derive(Debug)]
+pub struct OutputWatch {
+    patterns: Vec<regex::Regex>,
+    matched: Vec<bool>,
+    line: Vec<u8>,
+    pub tag: String,
+    pub bell: bool,
+}
+
+impl OutputWatch {
+    /// none when no pattern is configured
+    pub fn new(cfg: &WatchConfig) -> std::result::Result<Option<Self>, String> {
+        if cfg.patterns.is_empty() {
+            return Ok(None);
+        }
+        let patterns = cfg
+            .patterns
+            .iter()
+            .map(|p| regex::Regex::new(p).map_err(|e| format!("invalid watch pattern {:?}: {}", p, e)))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(Some(Self {
+            matched: vec![false; patterns.len()],
+            patterns,
+            line: Vec::new(),
+            tag: cfg.tag.clone(),
+            bell: cfg.bell,
+        }))
+    }
+
+    /// a new command started: every pattern may alert again
+    pub fn reset(&mut self) {
+        self.matched.fill(false);
+        self.line.clear();
+    }
+
+    /// (pattern, line) for each pattern first matched by a line completed in `data`
+    pub fn scan(&mut self, data: &[u8]) -> Vec<(String, String)> {
+        let mut hits = Vec::new();
+        for &b in data {
+            // progress bars redraw with \r, so that ends a line too
+            if b != b'\n' && b != b'\r' {
+                self.line.push(b);
+                if self.line.len() < WATCH_LINE_LIMIT {
+                    continue;
+                }
+            }
+            if self.line.is_empty() {
+                continue;
+            }
+            let text = strip_ansi(&std::mem::take(&mut self.line));
+            for (i, pattern) in self.patterns.iter().enumerate() {
+                if !self.matched[i] && pattern.is_match(&text) {
+                    self.matched[i] = true;
+                    hits.push((pattern.as_str().to_string(), text.trim().to_string()));
+                }
+            }
+        }
+        hits
+    }
+}
+
+const MOUSE_MODES_OFF: &[u8] = b"\x1b[?1006l\x1b[?1003l\x1b[?1002l\x1b[?1000l\x1b[?9l";
+
+/// mouse reporting the child asked for with dec private modes
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MouseMode {
+    /// any of ?9, ?1000, ?1002 or ?1003 is set
+    pub reporting: bool,
+    /// ?1006 sgr encoding instead of x10 bytes
+    pub sgr: bool,
+}
+
+/// follows the child's mouse modes in its output
+#[derive(Debug, Default)]
+pub struct MouseModeTracker {
+    tracking: [bool; 4], // ?9, ?1000, ?1002, ?1003
+    sgr: bool,
+    tail: Vec<u8>,
+}
+
+impl MouseModeTracker {
+    pub fn observe(&mut self, data: &[u8]) {
+        let mut buf = std::mem::take(&mut self.tail);
+        buf.extend_from_slice(data);
+
+        let mut i = 0;
+        while let Some(start) = find(&buf[i..], b"\x1b[?").map(|p| p + i) {
+            let params_start = start + 3;
+            let Some(len) = buf[params_start..]
+                .iter()
+                .position(|b| !(b.is_ascii_digit() || *b == b';'))
+            else {
+                // sequence continues in the next chunk
+                self.tail = buf[start..].to_vec();
+                return;
+            };
+            let end = params_start + len;
+            let set = match buf[end] {
+                b'h' => Some(true),
+                b'l' => Some(false),
+                _ => None,
+            };
+            if let Some(set) = set {
+                let params = String::from_utf8_lossy(&buf[params_start..end]).to_string();
+                for param in params.split(';') {
+                    match param {
+                        "9" => self.tracking[0] = set,
+                        "1000" => self.tracking[1] = set,
+                        "1002" => self.tracking[2] = set,
+                        "1003" => self.tracking[3] = set,
+                        "1006" => self.sgr = set,
+                        _ => {}
+                    }
+                }
+            }
+            i = end;
+        }
+        // a lone trailing esc or "esc [" may start the next sequence
+        let keep = partial_suffix(&buf, b"\x1b[?");
+        self.tail = buf[buf.len() - keep..].to_vec();
+    }
+
+    pub fn mode(&self) -> MouseMode {
+        MouseMode {
+            reporting: self.tracking.iter().any(|t| *t),
+            sgr: self.sgr,
+        }
+    }
+}
+
+/// a mouse report read from the terminal, independent of its wire encoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MouseEvent {
+    /// button and modifier bits as in xterm's cb, without the x10 offset
+    pub button: u16,
+    /// 1-based cell coordinates
+    pub col: u16,
+    pub row: u16,
+    pub release: bool,
+}
+
+impl MouseEvent {
+    /// encode for the child; x10 cannot express coordinates past 223
+    pub fn encode(&self, sgr: bool) -> Option<Vec<u8>> {
+        if sgr {
+            let end = if self.release { 'm' } else { 'M' };
+            return Some(format!("\x1b[<{};{};{}{}", self.button, self.col, self.row, end).into_bytes());
+        }
+        // x10 has no per-button release, only "button 3"
+        let button = if self.release { (self.button & !3) | 3 } else { self.button };
+        let encode = |v: u16| u8::try_from(v + 32).ok();
+        Some(vec![0x1b, b'[', b'M', encode(button)?, encode(self.col)?, encode(self.row)?])
+    }
+}
+
+/// split typed input into keys and mouse reports (x10 `esc [ M b x y` or sgr `esc [ < b;x;y M/m`)
+pub fn split_mouse(data: &[u8]) -> Vec<InputChunk> {
+    let mut chunks = Vec::new();
+    let mut keys_start = 0;
+    let mut i = 0;
+    while i < data.len() {
+        match parse_mouse(&data[i..]) {
+            Some((event, len)) => {
+                if keys_start < i {
+                    chunks.push(InputChunk::Keys(data[keys_start..i].to_vec()));
+                }
+                chunks.push(InputChunk::Mouse(event));
+                i += len;
+                keys_start = i;
+            }
+            None => i += 1,
+        }
+    }
+    if keys_start < data.len() {
+        chunks.push(InputChunk::Keys(data[keys_start..].to_vec()));
+    }
+    chunks
+}
+
+/// terminal reset for mouse modes a child left enabled
+pub fn mouse_modes_off() -> &'static [u8] {
+    MOUSE_MODES_OFF
+}
+
+fn parse_mouse(data: &[u8]) -> Option<(MouseEvent, usize)> {
+    if data.len() >= 6 && data.starts_with(b"\x1b[M") {
+        let value = |b: u8| (b as u16).checked_sub(32);
+        let button = value(data[3])?;
+        // x10 reports every release as button 3; motion and wheel events keep their code
+        let release = button & 3 == 3 && button & (32 | 64) == 0;
+        let event = MouseEvent {
+            button: if release { button & !3 } else { button },
+            col: value(data[4])?,
+            row: value(data[5])?,
+            release,
+        };
+        return Some((event, 6));
+    }
+    if data.starts_with(b"\x1b[<") {
+        let len = data[3..].iter().position(|b| *b == b'M' || *b == b'm')?;
+        let params = std::str::from_utf8(&data[3..3 + len]).ok()?;
+        let mut fields = params.split(';').map(|f| f.parse::<u16>().ok());
+        let (button, col, row) = (fields.next()??, fields.next()??, fields.next()??);
+        if fields.next().is_some() {
+            return None;
+        }
+        let event = MouseEvent {
+            button,
+            col,
+            row,
+            release: data[3 + len] == b'm',
+        };
+        return Some((event, 3 + len + 1));
+    }
+    None
+}
+
+// longest unterminated osc kept across output chunks
+const OSC_MAX: usize = 4096;
+
+/// state reported by the child through osc sequences
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OscEvent {
+    /// osc 0 / osc 2 window title
+    Title(String),
+    /// osc 7 working directory, decoded from its file:// url
+    Cwd(String),
+}
+
+/// picks title and cwd reports out of the output stream; the bytes themselves are not touched
+#[derive(Debug, Default)]
+pub struct OscScanner {
+    tail: Vec<u8>,
+}
+
+impl OscScanner {
+    pub fn observe(&mut self, data: &[u8]) -> Vec<OscEvent> {
+        let mut buf = std::mem::take(&mut self.tail);
+        buf.extend_from_slice(data);
+
+        let mut events = Vec::new();
+        let mut i = 0;
+        while let Some(start) = find(&buf[i..], b"\x1b]").map(|p| p + i) {
+            let body = start + 2;
+            // terminated by bel or st (esc \)
+            let Some((end, term_len)) = buf[body..].iter().enumerate().find_map(|(k, b)| match b {
+                0x07 => Some((body + k, 1)),
+                0x1b if buf.get(body + k + 1) == Some(&b'\\') => Some((body + k, 2)),
+                _ => None,
+            }) else {
+                if buf.len() - start <= OSC_MAX {
+                    self.tail = buf[start..].to_vec();
+                }
+                return events;
+            };
+            let payload = String::from_utf8_lossy(&buf[body..end]);
+            if let Some((code, text)) = payload.split_once(';') {
+                match code {
+                    "0" | "2" => events.push(OscEvent::Title(text.to_string())),
+                    "7" => {
+                        if let Some(path) = file_url_path(text) {
+                            events.push(OscEvent::Cwd(path));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            i = end + term_len;
+        }
+        if buf.last() == Some(&0x1b) {
+            self.tail = vec![0x1b];
+        }
+        events
+    }
+}
+
+// file://host/some%20dir -> /some dir
+fn file_url_path(url: &str) -> Option<String> {
+    let rest = url.strip_prefix("file://")?;
+    let path = &rest[rest.find('/')?..];
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = (bytes[i] == b'%')
+            .then(|| path.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match hex {
+            Some(b) => {
+                out.push(b);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    Some(String::from_utf8_lossy(&out).to_string())
+}
+
+/// recorded output as plain text: escape sequences and carriage returns removed; cheap enough for
+/// lines as they stream by, whole outputs read better through `screen_text`
+pub fn strip_ansi(data: &[u8]) -> String {
+    let mut out = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        match data[i] {
+            0x1b => {
+                i += 1;
+                match data.get(i) {
+                    // csi: parameters up to a final byte in @..~
+                    Some(b'[') => {
+                        i += 1;
+                        while i < data.len() && !(0x40..=0x7e).contains(&data[i]) {
+                            i += 1;
+                        }
+                        i += 1;
+                    }
+                    // osc and friends: up to bel or st
+                    Some(b']' | b'P' | b'_' | b'^') => {
+                        i += 1;
+                        while i < data.len() && data[i] != 0x07 && !(data[i] == 0x1b && data.get(i + 1) == Some(&b'\\')) {
+                            i += 1;
+                        }
+                        i += if data.get(i) == Some(&0x07) { 1 } else { 2 };
+                    }
+                    // two-byte escapes, with charset designations like esc ( b
+                    Some(_) => {
+                        while i < data.len() && (0x20..=0x2f).contains(&data[i]) {
+                            i += 1;
+                        }
+                        i += 1;
+                    }
+                    None => {}
+                }
+            }
+            b'\r' => i += 1,
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+// grid output is played onto by screen_text; long lines wrap and are joined again, so the width only
+// matters to programs that redraw several lines with cursor movement
+const SCREEN_COLS: u16 = 240;
+const SCREEN_ROWS: u16 = 100;
+// rows that scrolled off are taken out once there are this many; the grid keeps twice as many
+const SCREEN_FLUSH: usize = 1000;
+// output is fed in pieces small enough to never scroll more rows than the grid keeps between flushes
+const PIECE_LINES: usize = 400;
+
+/// recorded output as the text it left on screen, played through a terminal emulator: carriage
+/// returns, cursor movement and erased lines overwrite as they did, so progress bars keep only their
+/// final state, and what full-screen programs drew is gone as it is from a terminal's scrollback
+pub fn screen_text(data: &[u8]) -> String {
+    // output captured from a pipe, like split stderr, has bare newlines a tty would have turned into \r\n
+    let mut crlf = Vec::with_capacity(data.len());
+    for (i, b) in data.iter().enumerate() {
+        if *b == b'\n' && (i == 0 || data[i - 1] != b'\r') {
+            crlf.push(b'\r');
+        }
+        crlf.push(*b);
+    }
+    let data = &crlf[..];
+    let mut parser = vt100::Parser::new(SCREEN_ROWS, SCREEN_COLS, 2 * SCREEN_FLUSH);
+    let mut text = ScreenText::default();
+    let mut piece_start = 0;
+    let mut lines = 0;
+    for (i, b) in data.iter().enumerate() {
+        if *b == b'\n' {
+            lines += 1;
+        }
+        if lines >= PIECE_LINES || i + 1 - piece_start >= PIECE_LINES * SCREEN_COLS as usize {
+            parser.process(&data[piece_start..=i]);
+            piece_start = i + 1;
+            lines = 0;
+            text.flush_scrollback(&mut parser);
+        }
+    }
+    parser.process(&data[piece_start..]);
+    text.finish(parser.screen_mut())
+}
+
+// lines taken from the emulated screen, with a row wrapped onto the next one held until it ends
+#[derive(Default)]
+struct ScreenText {
+    out: String,
+    line: String,
+}
+
+impl ScreenText {
+    fn row(&mut self, row: String, wrapped: bool) {
+        if wrapped {
+            self.line.push_str(&row);
+        } else {
+            self.out.push_str(&self.line);
+            self.out.push_str(row.trim_end());
+            self.out.push('\n');
+            self.line.clear();
+        }
+    }
+
+    // take the rows that scrolled off, then start over from a fresh grid showing the same screen;
+    // vt100 keeps scrollback it can't hand over, and full-screen output is left alone until it ends
+    fn flush_scrollback(&mut self, parser: &mut vt100::Parser) {
+        let screen = parser.screen_mut();
+        screen.set_scrollback(usize::MAX);
+        let scrolled = screen.scrollback();
+        screen.set_scrollback(0);
+        if scrolled < SCREEN_FLUSH || screen.alternate_screen() {
+            return;
+        }
+        self.scrollback(screen);
+        let state = screen.state_formatted();
+        *parser = vt100::Parser::new(SCREEN_ROWS, SCREEN_COLS, 2 * SCREEN_FLUSH);
+        parser.process(&state);
+    }
+
+    // every row in the scrollback, oldest first, a screenful at a time
+    fn scrollback(&mut self, screen: &mut vt100::Screen) {
+        screen.set_scrollback(usize::MAX);
+        let mut offset = screen.scrollback();
+        while offset > 0 {
+            screen.set_scrollback(offset);
+            let take = offset.min(SCREEN_ROWS as usize);
+            let rows: Vec<String> = screen.rows(0, SCREEN_COLS).take(take).collect();
+            for (i, row) in rows.into_iter().enumerate() {
+                self.row(row, screen.row_wrapped(i as u16));
+            }
+            offset -= take;
+        }
+        screen.set_scrollback(0);
+    }
+
+    // the rows still on screen, down to the cursor or the last one written, whichever is lower
+    fn finish(mut self, screen: &mut vt100::Screen) -> String {
+        self.scrollback(screen);
+        let rows: Vec<String> = screen.rows(0, SCREEN_COLS).collect();
+        let last_written = rows.iter().rposition(|r| !r.trim().is_empty()).unwrap_or(0);
+        let last = last_written.max(screen.cursor_position().0 as usize);
+        for (i, row) in rows.into_iter().enumerate().take(last + 1) {
+            if i == last {
+                self.out.push_str(&self.line);
+                self.out.push_str(row.trim_end());
+            } else {
+                self.row(row, screen.row_wrapped(i as u16));
+            }
+        }
+        self.out
+    }
+}
+
+/// wait up to `timeout_ms` for stdin to become readable
+pub fn stdin_ready(timeout_ms: u64) -> bool {
+    #[cfg(unix)]
+    {
+        let mut fds = libc::pollfd {
+            fd: 0,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = timeout_ms.min(i32::MAX as u64) as i32;
+        unsafe { libc::poll(&mut fds, 1, timeout) > 0 }
+    }
+
+    #[cfg(not(unix))]
+    {
+        // no cheap readiness check; let the blocking read decide
+        let _ = timeout_ms;
+        true
+    }
+}
+
+/// whether the terminal behind `fd` reads lines without echoing them, the way passwords are read
+/// (full screen programs turn echo off too, but also leave line mode)
+pub fn input_hidden(fd: i32) -> bool {
+    #[cfg(unix)]
+    {
+        let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut termios) } != 0 {
+            return false;
+        }
+        termios.c_lflag & libc::ECHO == 0 && termios.c_lflag & libc::ICANON != 0
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = fd;
+        false
+    }
+}
+
+/// handles the output from pty to terminal
+pub struct OutputHandler;
+
+impl OutputHandler {
+    /// forward pty output to stdout with error handling
+    pub fn forward_to_stdout(buffer: &[u8]) -> Result<()> {
+        let mut stdout = std::io::stdout();
+        stdout.write_all(buffer)?;
+        stdout.flush()?;
+        Ok(())
+    }
+
+    /// process and potentially filter pty output
+    /// (where ill add features like error detection)
+    pub fn process_output(buffer: &[u8]) -> Vec<u8> {
+        // for now just pass through unchanged
+        // TODO: later enhancements:
+        // - detect error patterns
+        // - log command output
+        // - parse ANSI escape sequences
+        buffer.to_vec()
+    }
+}
@@ -0,0 +1,48 @@
+/// Recli library
+pub mod bench;
+pub mod cast;
+pub mod ci;
+pub mod cli;
+pub mod command_detector;
+pub mod command_log;
+pub mod config;
+pub mod config_check;
+pub mod container;
+pub mod control;
+pub mod dashboard;
+pub mod error;
+pub mod events;
+pub mod exit_signal;
+pub mod export;
+pub mod file_changes;
+pub mod filters;
+#[cfg(feature = "fulltext")]
+pub mod fulltext;
+pub mod grep;
+pub mod history;
+pub mod hook_only;
+pub mod hooks;
+pub mod index;
+pub mod io;
+pub mod lock;
+pub mod network;
+pub mod outbox;
+pub mod pick;
+pub mod plugins;
+pub mod policy;
+pub mod process_tree;
+pub mod pty;
+pub mod reload;
+pub mod replay;
+pub mod report;
+pub mod schema;
+pub mod scrub;
+pub mod session;
+pub mod share;
+pub mod snippets;
+pub mod ssh;
+pub mod suggest;
+pub mod sync;
+pub mod tmux;
+pub mod uninstall;
+pub mod util;
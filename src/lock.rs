@@ -0,0 +1,109 @@
+use crate::error::{RecliError, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// lock file in each session directory; recli processes touching the directory take it first
+pub const LOCK_FILE: &str = ".lock";
+// how long to wait for another process before giving up
+const LOCK_WAIT: Duration = Duration::from_secs(3);
+
+/// advisory flock on a session directory, released on drop
+#[derive(Debug)]
+pub struct SessionLock {
+    file: File,
+}
+
+impl SessionLock {
+    /// exclusive lock on `log_dir`, for writing its files
+    pub fn exclusive(log_dir: &Path) -> Result<Self> {
+        Self::acquire(log_dir, true)
+    }
+
+    /// shared lock on `log_dir`, for reading them
+    pub fn shared(log_dir: &Path) -> Result<Self> {
+        Self::acquire(log_dir, false)
+    }
+
+    fn acquire(log_dir: &Path, exclusive: bool) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(log_dir.join(LOCK_FILE))?;
+        let deadline = Instant::now() + LOCK_WAIT;
+        loop {
+            match try_lock(&file, exclusive) {
+                Ok(()) => return Ok(Self { file }),
+                Err(err) if err.kind() != io::ErrorKind::WouldBlock => return Err(err.into()),
+                Err(_) => {}
+            }
+            if Instant::now() >= deadline {
+                return Err(RecliError::Session(format!(
+                    "{} is locked by another recli process, try again once it is done",
+                    log_dir.display()
+                )));
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
+
+impl Drop for SessionLock {
+    fn drop(&mut self) {
+        unlock(&self.file);
+    }
+}
+
+#[cfg(unix)]
+fn try_lock(file: &File, exclusive: bool) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    let operation = if exclusive { libc::LOCK_EX } else { libc::LOCK_SH };
+    if unsafe { libc::flock(file.as_raw_fd(), operation | libc::LOCK_NB) } == 0 {
+        return Ok(());
+    }
+    Err(io::Error::last_os_error())
+}
+
+#[cfg(unix)]
+fn unlock(file: &File) {
+    use std::os::unix::io::AsRawFd;
+    unsafe {
+        libc::flock(file.as_raw_fd(), libc::LOCK_UN);
+    }
+}
+
+// std's file locks, which are LockFileEx on windows
+#[cfg(not(unix))]
+fn try_lock(file: &File, exclusive: bool) -> io::Result<()> {
+    let locked = if exclusive { file.try_lock() } else { file.try_lock_shared() };
+    locked.map_err(|e| match e {
+        fs::TryLockError::WouldBlock => io::ErrorKind::WouldBlock.into(),
+        fs::TryLockError::Error(e) => e,
+    })
+}
+
+#[cfg(not(unix))]
+fn unlock(file: &File) {
+    let _ = file.unlock();
+}
+
+/// replace `log_dir/name` under an exclusive lock; readers see the old file or the new one, never part of it
+pub fn write_file(log_dir: &Path, name: &str, data: &[u8]) -> Result<()> {
+    let _lock = SessionLock::exclusive(log_dir)?;
+    let tmp = log_dir.join(format!(".{}.tmp", name));
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, log_dir.join(name))?;
+    Ok(())
+}
+
+/// read `log_dir/name` under a shared lock; directories recli can't write to are read without one
+pub fn read_file(log_dir: &Path, name: &str) -> Result<String> {
+    let _lock = match SessionLock::shared(log_dir) {
+        Ok(lock) => Some(lock),
+        Err(RecliError::Io(e)) if matches!(e.kind(), io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem) => None,
+        Err(e) => return Err(e),
+    };
+    Ok(fs::read_to_string(log_dir.join(name))?)
+}
@@ -1,25 +1,82 @@
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
 use std::env;
 use std::error::Error as StdError;
 use std::fs;
-use std::io::{self, Write};
-use std::path::PathBuf;
-use std::process::Command;
-use std::time::Instant;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+use std::time::{Duration, Instant, SystemTime};
 use azure_data_cosmos::prelude::*;
 use azure_data_cosmos::CosmosEntity;
+use sha2::{Digest, Sha256};
 use azure_core::error::{Error as AzureError, ErrorKind as AzureErrorKind};
+use recli::ci;
+use recli::cli::{Cli, CloudCommands, CloudSearchArgs, ConfigCommands, RecliCommands, SinksCommands, SnippetsCommands, TmuxCommands};
+use recli::command_detector::Detection;
+use recli::command_log::OutputPreview;
+use recli::container::{self, ContainerInfo, Runtime};
+use recli::dashboard;
+use recli::config_check::ProblemKind;
+use recli::config::{Config, CosmosSetup, EncryptionConfig, RecordMode, RetryConfig, SinkContent, SinkFilter, UploadPolicy};
+use recli::error::RecliError;
+use recli::events::EventStream;
+use recli::exit_signal::ExitSignal;
+use recli::network::{NetworkSummary, NetworkWatch};
+use recli::process_tree::{ProcessSummary, ProcessWatch};
+use recli::history;
+use recli::hooks::{self, HookEvent};
+use recli::index::{self, Index, IndexedCommand};
+use recli::file_changes::TrackLimits;
+use recli::filters::{FilterChain, REDACTED_TAG};
+use recli::io::OutputWatch;
+use recli::outbox;
+use recli::pick;
+use recli::plugins;
+use recli::policy::RecordingPolicy;
+use recli::cast::Cast;
+use recli::pty::PtySession;
+use recli::reload::ConfigReload;
+use recli::replay::{self, ReplayOptions};
+use recli::report::{self, Report};
+use recli::session::{self, check_session_id, current_host, current_user, SessionConfig, SessionEnvironment, SessionManager};
+use recli::share;
+use recli::snippets::{self, Snippet, Snippets};
+use recli::ssh;
+use recli::suggest::{self, Model};
+use recli::sync::{SessionPush, TeamClient};
+use recli::tmux;
+use recli::uninstall;
+use recli::util::encryption::{Envelope, SessionCipher, UserKey};
+use recli::util::{anonymize::Anonymizer, compress, datadog, retry, sentry, telemetry, throttle::Throttle};
+use recli::util::time::{display_stamp, format_duration_ms, normalize_stamp, now_stamp, parse_age, parse_stamp, parse_time_bound};
+use futures::StreamExt;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct CommandEntry {
+    // uuidv7, as in commands.json and the index
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    id: String,
     cmd: String,
     exit_code: i32,
     output: String,
     stderr: String,
     cwd: String,
     timestamp: String,
+    // place in the session, from 1
+    #[serde(default)]
+    seq: u64,
     duration_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signal: Option<ExitSignal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    network: Option<NetworkSummary>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    processes: Option<ProcessSummary>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -32,11 +89,146 @@ struct CommandLog {
 struct SessionDoc {
     id: String,          // e.g., same as session_id or a new uuid
     session_id: String,  // pk: must match container pk (/session_id)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    workspace: Option<String>, // pk instead of session_id when the container uses /workspace
     host: String,
     user: String,
     started_at: String,  // iso8601
     ended_at: String,    // iso8601
+    // os, terminal, shell and recli versions and starting directory, when the metadata has them
+    #[serde(flatten)]
+    environment: SessionEnvironment,
     entries: Vec<CommandEntry>,
+    #[serde(default)]
+    content_hash: String, // lets repeated uploads of an unchanged session be skipped
+    // wrapped data key when cmd, output and stderr of the entries are encrypted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encryption: Option<Envelope>,
+    // "zstd" when long output and stderr of the entries are compressed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    compression: Option<String>,
+    #[serde(skip)]
+    partition: String,
+}
+
+impl SessionDoc {
+    /// stamp the workspace and pick the partition key value for the configured strategy
+    fn assign_workspace(&mut self, workspace: Option<String>, by_workspace: bool) {
+        (self.workspace, self.partition) = partition_for(workspace, by_workspace, &self.session_id);
+    }
+
+    // hash over everything but ended_at, which moves on every save, and the environment, which
+    // never changes once recorded, plus how the copy is stored, so turning compression or encryption
+    // on or changing the key uploads again. with a key it is an hmac under that key, as it is stored
+    // in clear next to the encrypted fields
+    fn compute_hash(&self, compress: bool, key: Option<&UserKey>) -> String {
+        let content = (
+            &self.session_id,
+            &self.workspace,
+            &self.host,
+            &self.user,
+            &self.started_at,
+            &self.entries,
+            compress,
+            key.map(|k| k.id()),
+        );
+        let bytes = serde_json::to_vec(&content).unwrap_or_default();
+        match key {
+            Some(key) => key.mac(&bytes),
+            None => hex::encode(Sha256::digest(&bytes)),
+        }
+    }
+
+    /// encrypt command lines, output and notes under a new data key wrapped by `key`
+    fn encrypt(&mut self, key: &UserKey) -> Result<(), String> {
+        let (cipher, envelope) = SessionCipher::generate(key, &self.session_id)?;
+        for entry in &mut self.entries {
+            entry.cmd = cipher.encrypt(&entry.cmd)?;
+            entry.output = cipher.encrypt(&entry.output)?;
+            entry.stderr = cipher.encrypt(&entry.stderr)?;
+            if let Some(note) = &entry.note {
+                entry.note = Some(cipher.encrypt(note)?);
+            }
+        }
+        self.encryption = Some(envelope);
+        Ok(())
+    }
+
+    /// undo encrypt; documents uploaded without encryption are left as they are
+    fn decrypt(&mut self, key: Option<&UserKey>) -> Result<(), String> {
+        let Some(envelope) = &self.encryption else { return Ok(()) };
+        let key = key.ok_or_else(|| {
+            format!("session {} is encrypted; enable [encryption] with the key it was uploaded with", self.session_id)
+        })?;
+        let cipher = SessionCipher::open(key, envelope, &self.session_id)?;
+        for entry in &mut self.entries {
+            entry.cmd = cipher.decrypt(&entry.cmd)?;
+            entry.output = cipher.decrypt(&entry.output)?;
+            entry.stderr = cipher.decrypt(&entry.stderr)?;
+            if let Some(note) = &entry.note {
+                entry.note = Some(cipher.decrypt(note)?);
+            }
+        }
+        self.encryption = None;
+        Ok(())
+    }
+
+    /// compress output and stderr of the entries where that makes them shorter
+    fn compress(&mut self) -> Result<(), String> {
+        for entry in &mut self.entries {
+            entry.output = compress::compress_field(&entry.output)?;
+            entry.stderr = compress::compress_field(&entry.stderr)?;
+        }
+        self.compression = Some(compress::ENCODING.to_string());
+        Ok(())
+    }
+
+    /// undo compress, after decrypt; documents uploaded uncompressed are left as they are
+    fn decompress(&mut self) -> Result<(), String> {
+        if self.compression.take().is_none() {
+            return Ok(());
+        }
+        for entry in &mut self.entries {
+            entry.output = compress::decompress_field(&entry.output)?;
+            entry.stderr = compress::decompress_field(&entry.stderr)?;
+        }
+        Ok(())
+    }
+
+    /// leave out what the sink's content setting keeps from it
+    fn restrict(&mut self, content: SinkContent) {
+        match content {
+            SinkContent::Full => {}
+            SinkContent::Commands => {
+                for entry in &mut self.entries {
+                    entry.output.clear();
+                    entry.stderr.clear();
+                }
+            }
+            SinkContent::Metadata => self.entries.clear(),
+        }
+    }
+
+    /// replace host, user and every cwd with their pseudonyms
+    fn anonymize(&mut self, anonymizer: &Anonymizer) {
+        self.host = anonymizer.name(&self.host);
+        self.user = anonymizer.name(&self.user);
+        self.environment.cwd = self.environment.cwd.as_deref().map(|cwd| anonymizer.path(cwd));
+        for entry in &mut self.entries {
+            entry.cwd = anonymizer.path(&entry.cwd);
+        }
+    }
+}
+
+// (workspace field, partition key value) for a document of `session_id`
+fn partition_for(workspace: Option<String>, by_workspace: bool, session_id: &str) -> (Option<String>, String) {
+    if by_workspace {
+        // every document needs a partition value, so unnamed teams share "default"
+        let workspace = workspace.unwrap_or_else(|| "default".to_string());
+        (Some(workspace.clone()), workspace)
+    } else {
+        (workspace, session_id.to_string())
+    }
 }
 
 struct CommandLogger {
@@ -47,24 +239,35 @@ struct CommandLogger {
     cosmos_client: Option<CosmosClient>,
     cosmos_database: Option<String>,
     cosmos_container: Option<String>,
+    workspace: Option<String>,
+    partition_by_workspace: bool,
+    verify_upload: bool,
+    upload_policy: UploadPolicy,
+    cosmos_filter: SinkFilter,
+    max_kbps: Option<u64>,
+    compress: bool,
+    retry: RetryConfig,
+    track_network: Option<Duration>,
+    track_processes: Option<Duration>,
+    anonymizer: Option<Anonymizer>,
+    encryption: EncryptionConfig,
+    policy: RecordingPolicy,
+    // --json-events destination; when it is stdout, human output moves to stderr
+    events: Option<EventStream>,
 }
 
 impl CosmosEntity for SessionDoc {
     type Entity = String;
-    fn partition_key(&self) -> Self::Entity { self.session_id.clone() }
+    fn partition_key(&self) -> Self::Entity { self.partition.clone() }
 }
 
 impl CommandLogger {
-    async fn new() -> io::Result<Self> {
+    async fn new(cfg: &Config) -> io::Result<Self> {
         // load .env file if it exists
         dotenv::dotenv().ok();
         
         let session_id = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
-        let home = env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
-        let primary_log_dir = PathBuf::from(home)
-            .join(".recli")
-            .join("logs")
-            .join(&session_id);
+        let primary_log_dir = cfg.session_log_dir().join(&session_id);
 
         fs::create_dir_all(&primary_log_dir)?;
 
@@ -107,11 +310,16 @@ impl CommandLogger {
             }
         }
         
+        tracing::info!(session_id = %session_id, log_dir = %primary_log_dir.display(), "session started");
+        hooks::fire(HookEvent::SessionStart, &session_id, json!({ "log_dir": primary_log_dir }));
+
         // initialize cosmos db client if credentials are available
         let cosmos_client = Self::init_cosmos_client();
         let cosmos_database = env::var("RECLI_AZURE__COSMOS__DB").ok();
         let cosmos_container = env::var("RECLI_AZURE__COSMOS__CONTAINER").ok();
         
+        let policy = RecordingPolicy::load(&cfg.policy, &env::current_dir()?).map_err(RecliError::Config)?;
+
         Ok(CommandLogger {
             session_id,
             primary_log_dir,
@@ -120,14 +328,40 @@ impl CommandLogger {
             cosmos_client,
             cosmos_database,
             cosmos_container,
+            workspace: cfg.workspace.clone(),
+            partition_by_workspace: partition_by_workspace(cfg),
+            verify_upload: verify_upload(cfg),
+            upload_policy: upload_policy(cfg),
+            cosmos_filter: cosmos_filter(cfg),
+            max_kbps: cfg.upload.max_kbps,
+            compress: cfg.upload.compress,
+            retry: cfg.retry.clone(),
+            track_network: sample_interval(cfg.recording.track_network, cfg.recording.track_network_interval_ms),
+            track_processes: sample_interval(cfg.recording.track_processes, cfg.recording.track_processes_interval_ms),
+            anonymizer: Anonymizer::from_config(&cfg.anonymize),
+            encryption: cfg.encryption.clone(),
+            policy,
+            events: None,
         })
     }
-    
-    // debug output is enabled when env RECLI_DEBUG is set to 1 or true
-    fn debug_enabled() -> bool {
-        env::var("RECLI_DEBUG")
-            .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
-            .unwrap_or(false)
+
+    fn with_events(mut self, events: Option<EventStream>) -> Self {
+        if let Some(events) = &events {
+            events.message(&self.session_id, "session started");
+        }
+        self.events = events;
+        self
+    }
+
+    // print human-facing text without corrupting an event stream on stdout
+    fn echo(&self, text: &str) {
+        if self.events.as_ref().is_some_and(EventStream::is_stdout) {
+            eprint!("{}", text);
+            let _ = io::stderr().flush();
+        } else {
+            print!("{}", text);
+            let _ = io::stdout().flush();
+        }
     }
     
     fn init_cosmos_client() -> Option<CosmosClient> {
@@ -177,12 +411,8 @@ impl CommandLogger {
                 if let Some(account_name) = extract_account_name(&endpoint) {
                     // create the authorization token and client
                     if let Ok(auth) = AuthorizationToken::primary_key(&key) {
-                        if Self::debug_enabled() {
-                            eprintln!("debug: parsed endpoint: {}", endpoint);
-                            eprintln!("debug: extracted account: {}", account_name);
-                            eprintln!("debug: creating client with account name");
-                        }
-                        return Some(CosmosClient::new(account_name, auth));
+                        tracing::debug!(endpoint, account = %account_name, "cosmos client from connection string");
+                        return Some(cosmos_client(account_name, auth));
                     }
                 }
             }
@@ -195,10 +425,8 @@ impl CommandLogger {
         ) {
             let account_name = account.trim().to_string();
             if let Ok(auth) = AuthorizationToken::primary_key(&key) {
-                if Self::debug_enabled() {
-                    eprintln!("debug: using cosmos account: {}", account_name);
-                }
-                return Some(CosmosClient::new(account_name, auth));
+                tracing::debug!(account = %account_name, "cosmos client from account and key");
+                return Some(cosmos_client(account_name, auth));
             }
         }
         
@@ -227,7 +455,7 @@ impl CommandLogger {
         }
     }
 
-    async fn upload_session_to_cosmos(&self) -> azure_core::error::Result<()> {
+    async fn upload_session_to_cosmos(&self) -> azure_core::error::Result<CosmosUpload> {
         // single upsert of the entire session document at the very end
         let (client, db_name, container_name) = match (
             &self.cosmos_client,
@@ -235,73 +463,55 @@ impl CommandLogger {
             &self.cosmos_container,
         ) {
             (Some(c), Some(d), Some(k)) => (c, d, k),
-            _ => return Ok(()), // cosmos not configured → nothing to do
+            (client, _, _) => return Ok(CosmosUpload::skipped(client.is_some())),
         };
 
-        let host = hostname::get()
-            .ok()
-            .and_then(|h| h.into_string().ok())
-            .unwrap_or_else(|| "unknown".to_string());
-        let user = std::env::var("USER").unwrap_or_else(|_| "unknown".to_string());
+        let (host, user) = (current_host(), current_user());
 
         // started_at: first entry or now; ended_at: now
         let started_at = self
             .entries
             .first()
             .map(|e| e.timestamp.clone())
-            .unwrap_or_else(|| chrono::Utc::now().to_rfc3339());
-        let ended_at = chrono::Utc::now().to_rfc3339();
-
-        // 0) warm-up tiny upsert to validate connectivity/auth
-        #[derive(Debug, Clone, Serialize, Deserialize)]
-        struct PingDoc { id: String, session_id: String, kind: &'static str, ts: String }
-        impl CosmosEntity for PingDoc { type Entity = String; fn partition_key(&self) -> Self::Entity { self.session_id.clone() } }
-
-        let ping = PingDoc {
-            id: format!("_recli_ping_{}", self.session_id),
-            session_id: self.session_id.clone(),
-            kind: "recli_ping",
-            ts: chrono::Utc::now().to_rfc3339(),
-        };
-
-        let db = client.database_client(db_name.clone());
-        let col = db.collection_client(container_name.clone());
-
-        if let Err(e) = col
-            .create_document(ping)
-            .is_upsert(true)
-            .into_future()
-            .await
-        {
-            Self::log_cosmos_error("cosmos ping upsert failed", &e);
-            return Err(e);
-        }
+            .unwrap_or_else(now_stamp);
+        let ended_at = now_stamp();
 
-        // 1) real session upsert
-        let doc = SessionDoc {
+        let mut doc = SessionDoc {
             id: self.session_id.clone(),         // upsert by session_id
             session_id: self.session_id.clone(), // pk=/session_id
+            workspace: None,
             host,
             user,
             started_at,
             ended_at,
+            environment: SessionEnvironment::detect(None),
             entries: self.entries.clone(),
+            content_hash: String::new(),
+            encryption: None,
+            compression: None,
+            partition: String::new(),
         };
-
-        if let Err(e) = col
-            .create_document(doc)
-            .is_upsert(true)
-            .into_future()
-            .await
-        {
-            Self::log_cosmos_error("cosmos session upsert failed", &e);
-            return Err(e);
+        doc.assign_workspace(self.workspace.clone(), self.partition_by_workspace);
+        doc.restrict(self.cosmos_filter.content);
+        if let Some(anonymizer) = &self.anonymizer {
+            doc.anonymize(anonymizer);
+        }
+        // interactive sessions have no output filters, so nothing was redacted
+        if !upload_approved(self.upload_policy, &doc, 0) {
+            return Ok(CosmosUpload::Declined);
         }
+        let Some(key) = upload_key(&self.encryption, &doc.session_id) else {
+            return Ok(CosmosUpload::Declined);
+        };
+        let mut throttle = Throttle::new(self.max_kbps);
+        let uploaded =
+            upsert_session_doc(client, db_name, container_name, doc, self.verify_upload, self.compress, key.as_ref(), &mut throttle, &self.retry)
+                .await?;
 
-        if Self::debug_enabled() {
-            eprintln!("session uploaded to cosmos db");
+        if uploaded {
+            tracing::info!(session_id = %self.session_id, "session uploaded to cosmos db");
         }
-        Ok(())
+        Ok(if uploaded { CosmosUpload::Uploaded } else { CosmosUpload::Unchanged })
     }
     
     async fn run_command(&mut self, cmd: &str) -> i32 {
@@ -309,8 +519,9 @@ impl CommandLogger {
             .map(|p| p.to_string_lossy().to_string())
             .unwrap_or_else(|_| String::from("/"));
         
-        let timestamp = Utc::now().to_rfc3339();
+        let timestamp = now_stamp();
         let start = Instant::now();
+        hooks::fire(HookEvent::BeforeCommand, &self.session_id, json!({ "cmd": cmd, "cwd": cwd }));
         
     // special handling for cd command
         if cmd.trim().starts_with("cd ") {
@@ -328,96 +539,172 @@ impl CommandLogger {
                         .unwrap_or_else(|_| String::from("/"));
                     
                     let entry = CommandEntry {
+                        id: String::new(),
                         cmd: cmd.to_string(),
                         exit_code: 0,
                         output: String::new(),
                         stderr: String::new(),
                         cwd: new_cwd,
                         timestamp,
+                        seq: 0,
                         duration_ms: start.elapsed().as_millis() as u64,
+                        signal: None,
+                        network: None,
+                        processes: None,
+                        note: None,
                     };
                     
-                    self.entries.push(entry);
+                    self.record_entry(entry);
                     return 0;
                 }
                 Err(e) => {
                     let entry = CommandEntry {
+                        id: String::new(),
                         cmd: cmd.to_string(),
                         exit_code: 1,
                         output: String::new(),
                         stderr: format!("cd: {}", e),
                         cwd,
                         timestamp,
+                        seq: 0,
                         duration_ms: start.elapsed().as_millis() as u64,
+                        signal: None,
+                        network: None,
+                        processes: None,
+                        note: None,
                     };
                     
                     eprintln!("cd: {}", e);
                     
-                    self.entries.push(entry);
+                    self.record_entry(entry);
                     return 1;
                 }
             }
         }
         
     // run regular commands
+        let network = self.track_network.map(|interval| NetworkWatch::start(std::process::id(), interval));
+        let processes = self.track_processes.map(|interval| ProcessWatch::start(std::process::id(), interval));
         let output = if cfg!(target_os = "windows") {
             Command::new("cmd")
-                .args(&["/C", cmd])
+                .args(["/C", cmd])
                 .current_dir(&cwd)
                 .output()
         } else {
             Command::new("sh")
-                .args(&["-c", cmd])
+                .args(["-c", cmd])
                 .current_dir(&cwd)
                 .output()
         };
         
         let duration_ms = start.elapsed().as_millis() as u64;
+        let network = network.map(NetworkWatch::finish);
+        let processes = processes.map(ProcessWatch::finish);
         
         match output {
             Ok(output) => {
                 let stdout = String::from_utf8_lossy(&output.stdout).to_string();
                 let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-                let exit_code = output.status.code().unwrap_or(-1);
+                let signal = ExitSignal::from_status(&output.status, Utc::now() - chrono::Duration::milliseconds(duration_ms as i64));
+                // the shell's way of reporting a signal, 128+n
+                let exit_code = output.status.code().or(signal.as_ref().map(|s| 128 + s.number)).unwrap_or(-1);
                 
                 // print to terminal
-                print!("{}", stdout);
+                self.echo(&stdout);
                 eprint!("{}", stderr);
-                let _ = io::stdout().flush();
                 let _ = io::stderr().flush();
                 
                 let entry = CommandEntry {
+                    id: String::new(),
                     cmd: cmd.to_string(),
                     exit_code,
                     output: stdout,
                     stderr,
                     cwd,
                     timestamp,
+                    seq: 0,
                     duration_ms,
+                    signal,
+                    network,
+                    processes,
+                    note: None,
                 };
                 
-                self.entries.push(entry);
+                self.record_entry(entry);
                 exit_code
             }
             Err(e) => {
                 eprintln!("error: {}", e);
                 
                 let entry = CommandEntry {
+                    id: String::new(),
                     cmd: cmd.to_string(),
                     exit_code: -1,
                     output: String::new(),
                     stderr: format!("error: {}", e),
                     cwd,
                     timestamp,
+                    seq: 0,
                     duration_ms,
+                    signal: None,
+                    network: None,
+                    processes: None,
+                    note: None,
                 };
                 
-                self.entries.push(entry);
+                self.record_entry(entry);
                 -1
             }
         }
     }
     
+    // keep the entry and export it as a span when otlp telemetry is configured
+    fn record_entry(&mut self, mut entry: CommandEntry) {
+        match self.policy.mode(&entry.cmd) {
+            RecordMode::Skip => return,
+            RecordMode::Metadata => {
+                entry.output.clear();
+                entry.stderr.clear();
+            }
+            RecordMode::Full => {}
+        }
+        entry.seq = self.entries.last().map_or(0, |e| e.seq) + 1;
+        entry.id = recli::command_log::new_id();
+        let started_at = chrono::DateTime::parse_from_rfc3339(&entry.timestamp)
+            .map(SystemTime::from)
+            .unwrap_or_else(|_| SystemTime::now());
+        telemetry::record_command(&entry.cmd, entry.exit_code, &entry.cwd, started_at, entry.duration_ms);
+        datadog::record_command(
+            &self.session_id,
+            &entry.cmd,
+            entry.exit_code,
+            &entry.cwd,
+            &entry.timestamp,
+            Some(entry.duration_ms),
+            &entry.stderr,
+            &[],
+        );
+        // stderr last so it survives the excerpt cut
+        sentry::report_command(
+            &self.session_id,
+            &entry.cmd,
+            entry.exit_code,
+            &entry.cwd,
+            &entry.timestamp,
+            Some(entry.duration_ms),
+            &format!("{}{}", entry.output, entry.stderr),
+            &[],
+        );
+        tracing::debug!(cmd = %entry.cmd, exit_code = entry.exit_code, duration_ms = entry.duration_ms, "command recorded");
+        hooks::fire(HookEvent::AfterCommand, &self.session_id, json!({ "command": entry }));
+        if let Some(events) = &self.events {
+            let message = entry.stderr.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+            let raw = serde_json::to_value(&entry).ok();
+            events.command(&self.session_id, entry.seq, &entry.timestamp, &entry.cmd, entry.exit_code, message, raw);
+        }
+        self.entries.push(entry);
+    }
+
     async fn save_async(&self) -> io::Result<()> {
         let log_file = self.primary_log_dir.join("commands.json");
         let log = CommandLog {
@@ -425,40 +712,48 @@ impl CommandLogger {
         };
 
         let json = serde_json::to_string_pretty(&log)?;
-        fs::write(&log_file, json.as_bytes())?;
+        recli::lock::write_file(&self.primary_log_dir, "commands.json", json.as_bytes())
+            .map_err(io::Error::from)?;
 
-        println!("session saved to: {}", log_file.display());
+        self.echo(&format!("session saved to: {}\n", log_file.display()));
 
         for dir in &self.additional_log_dirs {
             let mirror = dir.join("commands.json");
-            if let Err(e) = fs::write(&mirror, json.as_bytes()) {
+            if let Err(e) = recli::lock::write_file(dir, "commands.json", json.as_bytes()) {
                 eprintln!(
                     "warning: failed to write mirrored log to {}: {}",
                     mirror.display(),
                     e
                 );
             } else {
-                println!("session also saved to: {}", mirror.display());
+                self.echo(&format!("session also saved to: {}\n", mirror.display()));
             }
         }
 
         // try to upload once; never block the repl earlier
-        if let Err(e) = self.upload_session_to_cosmos().await {
-            Self::log_cosmos_error("Cosmos upload failed", &e);
+        match self.upload_session_to_cosmos().await {
+            Ok(CosmosUpload::Incomplete(missing)) => warn_cosmos_incomplete(self.events.as_ref(), &self.session_id, &missing),
+            Ok(_) => {}
+            Err(e) => Self::log_cosmos_error("Cosmos upload failed", &e),
+        }
+
+        if let Some(events) = &self.events {
+            events.message(&self.session_id, "session ended");
         }
+        hooks::fire(HookEvent::SessionEnd, &self.session_id, json!({ "log_dir": self.primary_log_dir }));
         
         Ok(())
     }
     
     async fn interactive_shell(&mut self) -> io::Result<()> {
-        println!("recording session to: {}", self.primary_log_dir.display());
+        self.echo(&format!("recording session to: {}\n", self.primary_log_dir.display()));
         if !self.additional_log_dirs.is_empty() {
             for dir in &self.additional_log_dirs {
-                println!("mirroring session logs to: {}", dir.display());
+                self.echo(&format!("mirroring session logs to: {}\n", dir.display()));
             }
         }
 
-        println!("type 'exit' to quit");
+        self.echo("type 'exit' to quit\n");
         
         loop {
             // Show prompt
@@ -466,8 +761,7 @@ impl CommandLogger {
                 .map(|p| p.to_string_lossy().to_string())
                 .unwrap_or_else(|_| String::from("/"));
             
-            print!("{} $ ", cwd);
-            io::stdout().flush()?;
+            self.echo(&format!("{} $ ", cwd));
             
             // Read command
             let mut cmd = String::new();
@@ -490,17 +784,227 @@ impl CommandLogger {
     }
 }
 
-/// Minimal Cosmos connectivity & schema check.
-async fn cosmos_doctor() -> io::Result<()> {
+// what is left of a connectivity-check document older versions upserted with every session
+#[derive(Debug, Clone, Deserialize)]
+struct PingDoc {
+    id: String,
+    session_id: String,
+    #[serde(default)]
+    workspace: Option<String>,
+}
+
+fn partition_by_workspace(cfg: &Config) -> bool {
+    cfg.azure
+        .as_ref()
+        .and_then(|a| a.cosmos.as_ref())
+        .map(|c| c.partition_by_workspace())
+        .unwrap_or(false)
+}
+
+fn verify_upload(cfg: &Config) -> bool {
+    cfg.azure
+        .as_ref()
+        .and_then(|a| a.cosmos.as_ref())
+        .is_some_and(|c| c.verify_upload)
+}
+
+// the key an upload is encrypted with; the outer none means encryption is on but the key
+// can't be had, and then the session stays local rather than going up in the clear
+fn upload_key(cfg: &EncryptionConfig, session_id: &str) -> Option<Option<UserKey>> {
+    match UserKey::load(cfg, true) {
+        Ok(key) => Some(key),
+        Err(e) => {
+            eprintln!("! session {} not uploaded, it can't be encrypted: {}", session_id, e);
+            tracing::warn!(session_id, "upload skipped, no encryption key: {}", e);
+            None
+        }
+    }
+}
+
+// how often a running command is sampled by track_network or track_processes, when on
+fn sample_interval(on: bool, interval_ms: u64) -> Option<Duration> {
+    on.then(|| Duration::from_millis(interval_ms.max(10)))
+}
+
+// [sinks.cosmos], without output in low-bandwidth mode
+fn cosmos_filter(cfg: &Config) -> SinkFilter {
+    let mut filter = cfg.sinks.cosmos.clone();
+    if cfg.upload.low_bandwidth {
+        filter.content = filter.content.max(SinkContent::Commands);
+    }
+    filter
+}
+
+fn upload_policy(cfg: &Config) -> UploadPolicy {
+    cfg.azure
+        .as_ref()
+        .and_then(|a| a.cosmos.as_ref())
+        .map(|c| c.upload_policy)
+        .unwrap_or_default()
+}
+
+// never keeps sessions local; ask shows what would go up and waits for a yes on the terminal
+fn upload_approved(policy: UploadPolicy, doc: &SessionDoc, redacted: usize) -> bool {
+    match policy {
+        UploadPolicy::Always => return true,
+        UploadPolicy::Never => {
+            tracing::info!(session_id = %doc.session_id, "upload_policy is never, session kept local");
+            return false;
+        }
+        UploadPolicy::Ask => {}
+    }
+    let stdin = io::stdin();
+    if !stdin.is_terminal() {
+        eprintln!("! upload_policy is \"ask\" but there is no terminal to ask on; session {} kept local", doc.session_id);
+        return false;
+    }
+
+    let bytes = serde_json::to_vec(doc).map(|b| b.len()).unwrap_or(0);
+    let redactions = match redacted {
+        0 => "no output redacted by filters".to_string(),
+        1 => "output of 1 command redacted by filters".to_string(),
+        n => format!("output of {} commands redacted by filters", n),
+    };
+    eprintln!("session {} is ready to upload to Cosmos DB:", doc.session_id);
+    eprintln!("  {} commands, {:.1} KB, {}", doc.entries.len(), bytes as f64 / 1024.0, redactions);
+    eprint!("upload it? [y/N] ");
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    let _ = stdin.read_line(&mut answer);
+    let approved = matches!(answer.trim().to_lowercase().as_str(), "y" | "yes");
+    if !approved {
+        eprintln!("session {} kept local", doc.session_id);
+    }
+    approved
+}
+
+// upsert one session document, after a read of the container when `verify` is set,
+// compressed when `compress` is set and encrypted with `key` if there is one; returns false
+// without writing when the stored copy already has the same content hash
+#[allow(clippy::too_many_arguments)]
+async fn upsert_session_doc(
+    client: &CosmosClient,
+    db_name: &str,
+    container_name: &str,
+    mut doc: SessionDoc,
+    verify: bool,
+    compress: bool,
+    key: Option<&UserKey>,
+    throttle: &mut Throttle,
+    retry: &RetryConfig,
+) -> azure_core::error::Result<bool> {
+    let db = client.database_client(db_name.to_string());
+    let col = db.collection_client(container_name.to_string());
+
+    // read-only, so a bad key or a missing container is reported before the document is built up
+    if verify {
+        if let Err(e) = retry::retry_if(|| col.get_collection().into_future(), retry, transient_cosmos).await {
+            CommandLogger::log_cosmos_error("cosmos container check failed", &e);
+            return Err(e);
+        }
+    }
+
+    doc.content_hash = doc.compute_hash(compress, key);
+    if remote_content_hash(&col, &doc).await.as_deref() == Some(doc.content_hash.as_str()) {
+        tracing::debug!(session_id = %doc.session_id, "session unchanged, upload skipped");
+        return Ok(false);
+    }
+    // after hashing, so the hash stays the same across uploads with fresh data keys,
+    // and compressed first, as encrypted text doesn't compress
+    if compress {
+        doc.compress().map_err(|e| AzureError::message(AzureErrorKind::Other, e))?;
+    }
+    if let Some(key) = key {
+        doc.encrypt(key).map_err(|e| AzureError::message(AzureErrorKind::Other, e))?;
+    }
+    throttle.wait(serde_json::to_vec(&doc).map(|b| b.len()).unwrap_or(0)).await;
+
+    let upsert = || col.create_document(doc.clone()).is_upsert(true).into_future();
+    if let Err(e) = retry::retry_if(upsert, retry, transient_cosmos).await {
+        CommandLogger::log_cosmos_error("cosmos session upsert failed", &e);
+        return Err(e);
+    }
+    Ok(true)
+}
+
+// content hash of the stored copy of `doc`; a failed lookup just means "upload"
+async fn remote_content_hash(col: &CollectionClient, doc: &SessionDoc) -> Option<String> {
+    #[derive(Deserialize)]
+    struct Stored {
+        #[serde(default)]
+        content_hash: String,
+    }
+
+    let client = col.document_client(doc.id.clone(), &doc.partition).ok()?;
+    match client.get_document::<Stored>().into_future().await {
+        Ok(GetDocumentResponse::Found(found)) => Some(found.document.document.content_hash),
+        _ => None,
+    }
+}
+
+/// what happened to a session at the end-of-session upload
+#[derive(Debug)]
+enum CosmosUpload {
+    Uploaded,
+    Unchanged,
+    /// not uploaded on purpose: upload_policy said no, nobody said yes, or it couldn't be encrypted
+    Declined,
+    NotConfigured,
+    /// some settings are there but not all, the names of the missing ones
+    Incomplete(Vec<String>),
+}
+
+impl CosmosUpload {
+    // the upload couldn't start; `has_client` is whether the credentials gave a client
+    fn skipped(has_client: bool) -> Self {
+        match CosmosSetup::from_env() {
+            CosmosSetup::Unconfigured => CosmosUpload::NotConfigured,
+            CosmosSetup::Incomplete(missing) => CosmosUpload::Incomplete(missing),
+            // everything is set, so the credentials themselves were rejected
+            CosmosSetup::Complete if !has_client => CosmosUpload::Incomplete(vec![
+                "usable credentials (RECLI_AZURE__COSMOS__CONNSTR or RECLI_AZURE__COSMOS__ACCOUNT/KEY could not be parsed)"
+                    .to_string(),
+            ]),
+            CosmosSetup::Complete => CosmosUpload::NotConfigured,
+        }
+    }
+}
+
+// a partly configured upload shouldn't look like a disabled one
+fn warn_cosmos_incomplete(events: Option<&EventStream>, session_id: &str, missing: &[String]) {
+    let message = format!("session not uploaded, Cosmos DB settings are incomplete: missing {}", missing.join(", "));
+    eprintln!("! {}; run `recli doctor` for details", message);
+    tracing::warn!(session_id, "{}", message);
+    if let Some(events) = events {
+        events.warning(session_id, &message);
+    }
+}
+
+/// Cosmos settings completeness, connectivity & schema check.
+async fn doctor(cfg: &Config) -> io::Result<()> {
     dotenv::dotenv().ok();
 
-    let client = match CommandLogger::init_cosmos_client() {
-        Some(c) => c,
-        None => {
-            eprintln!("! Cosmos client init failed. Check env vars:");
-            eprintln!("  RECLI_AZURE__COSMOS__CONNSTR  or  (RECLI_AZURE__COSMOS__ACCOUNT + RECLI_AZURE__COSMOS__KEY)");
+    match CosmosSetup::from_env() {
+        CosmosSetup::Unconfigured => {
+            eprintln!("- Cosmos DB upload is not configured, sessions stay local");
+            eprintln!("  set RECLI_AZURE__COSMOS__CONNSTR (or RECLI_AZURE__COSMOS__ACCOUNT + RECLI_AZURE__COSMOS__KEY),");
+            eprintln!("  RECLI_AZURE__COSMOS__DB and RECLI_AZURE__COSMOS__CONTAINER to enable it");
             return Ok(());
         }
+        CosmosSetup::Incomplete(missing) => {
+            eprintln!("! Cosmos DB settings are incomplete, sessions will not be uploaded. Missing:");
+            for name in &missing {
+                eprintln!("  {}", name);
+            }
+            return Err(RecliError::Config(format!("incomplete Cosmos DB settings: missing {}", missing.join(", "))).into());
+        }
+        CosmosSetup::Complete => eprintln!("  Cosmos DB settings complete"),
+    }
+
+    let Some(client) = CommandLogger::init_cosmos_client() else {
+        eprintln!("! Cosmos client init failed, check the account name and key in:");
+        eprintln!("  RECLI_AZURE__COSMOS__CONNSTR  or  (RECLI_AZURE__COSMOS__ACCOUNT + RECLI_AZURE__COSMOS__KEY)");
+        return Err(RecliError::Config("unusable Cosmos DB credentials".to_string()).into());
     };
     let db = match std::env::var("RECLI_AZURE__COSMOS__DB") {
         Ok(v) => v,
@@ -528,7 +1032,8 @@ async fn cosmos_doctor() -> io::Result<()> {
         Ok(_) => {
             // try to extract partition key info if available
             eprintln!("  container exists");
-            eprintln!("  note: verify container has partition key '/session_id'");
+            let pk = if partition_by_workspace(cfg) { "/workspace" } else { "/session_id" };
+            eprintln!("  note: verify container has partition key '{}'", pk);
         }
         Err(e) => {
             CommandLogger::log_cosmos_error("get_container failed", &e);
@@ -536,61 +1041,3012 @@ async fn cosmos_doctor() -> io::Result<()> {
         }
     }
 
-    // Try a tiny ping doc in the right PK
-    #[derive(Debug, Clone, Serialize, Deserialize)]
-    struct PingDoc { id: String, session_id: String, kind: &'static str, ts: String }
-    impl CosmosEntity for PingDoc { type Entity = String; fn partition_key(&self) -> Self::Entity { self.session_id.clone() } }
-    let ping = PingDoc {
-        id: "_recli_doctor_ping".into(),
-        session_id: "doctor_pk".into(),
-        kind: "doctor_ping",
-        ts: chrono::Utc::now().to_rfc3339(),
+    // read access in the right partition, without writing anything
+    eprintln!("-> reading session documents");
+    let query = Query::new("SELECT TOP 1 s.id FROM s".to_string());
+    let mut stream = cc.query_documents(query).query_cross_partition(true).into_stream::<serde_json::Value>();
+    match stream.next().await {
+        Some(Err(e)) => CommandLogger::log_cosmos_error("query failed", &e),
+        _ => eprintln!("  read ok"),
+    }
+    match find_ping_docs(&cc).await {
+        Ok(pings) if !pings.is_empty() => {
+            eprintln!("  {} leftover ping documents, remove them with `recli cloud prune-pings`", pings.len());
+        }
+        Ok(_) => {}
+        Err(e) => CommandLogger::log_cosmos_error("ping document lookup failed", &e),
+    }
+    Ok(())
+}
+
+// ping documents written by older versions: one per session plus the doctor's
+async fn find_ping_docs(col: &CollectionClient) -> azure_core::error::Result<Vec<PingDoc>> {
+    let query = Query::new(
+        "SELECT s.id, s.session_id, s.workspace FROM s \
+         WHERE STARTSWITH(s.id, \"_recli_ping_\") OR s.id = \"_recli_doctor_ping\""
+            .to_string(),
+    );
+    let mut stream = col.query_documents(query).query_cross_partition(true).into_stream::<PingDoc>();
+    let mut found = Vec::new();
+    while let Some(page) = stream.next().await {
+        found.extend(page?.results.into_iter().map(|(doc, _)| doc));
+    }
+    Ok(found)
+}
+
+/// Delete the ping documents older versions left in the container.
+async fn cloud_prune_pings(cfg: &Config, dry_run: bool) -> io::Result<()> {
+    let (Some(client), Ok(db), Ok(container)) = (
+        CommandLogger::init_cosmos_client(),
+        env::var("RECLI_AZURE__COSMOS__DB"),
+        env::var("RECLI_AZURE__COSMOS__CONTAINER"),
+    ) else {
+        return Err(RecliError::Config(COSMOS_NOT_CONFIGURED.to_string()).into());
     };
-    eprintln!("-> upserting ping doc");
-    match cc.create_document(ping).is_upsert(true).into_future().await {
-        Ok(_) => eprintln!("  ping upsert ok"),
-        Err(e) => {
-            CommandLogger::log_cosmos_error("ping upsert failed", &e);
+    let col = client.database_client(db).collection_client(container);
+    let pings = find_ping_docs(&col).await.map_err(cosmos_error)?;
+    if pings.is_empty() {
+        println!("no ping documents found");
+        return Ok(());
+    }
+    if dry_run {
+        for ping in &pings {
+            println!("{}", ping.id);
+        }
+        println!("{} ping documents would be deleted", pings.len());
+        return Ok(());
+    }
+
+    let by_workspace = partition_by_workspace(cfg);
+    let mut deleted = 0;
+    for ping in pings {
+        let (_, partition) = partition_for(ping.workspace, by_workspace, &ping.session_id);
+        let result = match col.document_client(ping.id.clone(), &partition) {
+            Ok(doc) => doc.delete_document().into_future().await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => deleted += 1,
+            Err(e) => CommandLogger::log_cosmos_error(&format!("deleting {} failed", ping.id), &e),
         }
     }
+    println!("deleted {} ping documents", deleted);
     Ok(())
 }
 
-#[tokio::main]
-async fn main() -> io::Result<()> {
-    let args: Vec<String> = env::args().collect();
-    
-    // handle start/end commands for compatibility
-    if args.len() > 1 {
-        match args[1].as_str() {
-            "start" => {
-                // interactive mode
-                let mut logger = CommandLogger::new().await?;
-                logger.interactive_shell().await?;
+// a document `recli sinks test` writes and deletes again
+#[derive(Debug, Clone, Serialize)]
+struct CanaryDoc {
+    id: String,
+    session_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    workspace: Option<String>,
+    #[serde(skip)]
+    partition: String,
+}
+
+impl CosmosEntity for CanaryDoc {
+    type Entity = String;
+    fn partition_key(&self) -> Self::Entity { self.partition.clone() }
+}
+
+// what each step of a sink test took
+type SinkTimings = Vec<(&'static str, Duration)>;
+
+const SINK_NAMES: [&str; 5] = ["cosmos", "team", "azure", "s3", "gist"];
+
+/// Write a canary to each configured sink and delete it again, printing how long each step took.
+async fn sinks_test(cfg: &Config, only: Option<&str>) -> io::Result<()> {
+    if let Some(name) = only.filter(|name| !SINK_NAMES.contains(name)) {
+        return Err(RecliError::Validation(format!("unknown sink: {} ({})", name, SINK_NAMES.join(", "))).into());
+    }
+    let wanted = |name: &str| only.is_none_or(|only| only == name);
+    let mut results: Vec<(&str, Result<SinkTimings, String>)> = Vec::new();
+
+    if wanted("cosmos") && CosmosSetup::from_env() != CosmosSetup::Unconfigured {
+        results.push(("cosmos", cosmos_canary(cfg).await));
+    }
+    if wanted("team") {
+        if let Some(team) = TeamClient::from_config(&cfg.team).map_err(RecliError::Config)? {
+            let start = Instant::now();
+            results.push(("team", team.probe().await.map(|_| vec![("read", start.elapsed())])));
+        }
+    }
+    let share_targets = [
+        (share::Target::Azure, cfg.share.azure_sas_url.is_some()),
+        (share::Target::S3, cfg.share.s3_bucket.is_some()),
+        (share::Target::Gist, cfg.share.github_token.is_some()),
+    ];
+    for (target, configured) in share_targets {
+        if wanted(target.name()) && configured {
+            // reqwest's blocking client can't run on the runtime's own threads
+            let (share_cfg, prefix) = (cfg.share.clone(), cfg.sinks.diagnostics_prefix.clone());
+            let result = tokio::task::spawn_blocking(move || share::canary(&share_cfg, target, &prefix))
+                .await
+                .map_err(io::Error::other)?;
+            results.push((target.name(), result));
+        }
+    }
+
+    if results.is_empty() {
+        match only {
+            Some(name) => println!("{} is not configured", name),
+            None => println!("no sinks are configured"),
+        }
+        return Ok(());
+    }
+    let mut failed = Vec::new();
+    for (name, result) in &results {
+        match result {
+            Ok(steps) => {
+                let steps: Vec<String> = steps.iter().map(|(step, took)| format!("{} {} ms", step, took.as_millis())).collect();
+                println!("{:<8} ok      {}", name, steps.join(", "));
+            }
+            Err(e) => {
+                println!("{:<8} failed  {}", name, e);
+                failed.push(*name);
             }
-            "end" => {
-                println!("session already ended (this version doesn't need 'end')");
+        }
+    }
+    if failed.is_empty() {
+        return Ok(());
+    }
+    Err(RecliError::Upload {
+        target: failed.join(", "),
+        source: format!("{} of {} sinks failed the test", failed.len(), results.len()).into(),
+    }
+    .into())
+}
+
+// read the container, then write a canary document to it and delete it; the container is
+// [sinks] diagnostics_container when set. Nothing is retried, so latency shows as it is.
+async fn cosmos_canary(cfg: &Config) -> Result<SinkTimings, String> {
+    if let CosmosSetup::Incomplete(missing) = CosmosSetup::from_env() {
+        return Err(format!("settings are incomplete: missing {}", missing.join(", ")));
+    }
+    let client = CommandLogger::init_cosmos_client().ok_or("the credentials could not be parsed")?;
+    let db = env::var("RECLI_AZURE__COSMOS__DB").unwrap_or_default();
+    let container = match &cfg.sinks.diagnostics_container {
+        Some(container) => container.clone(),
+        None => env::var("RECLI_AZURE__COSMOS__CONTAINER").unwrap_or_default(),
+    };
+    let col = client.database_client(db).collection_client(container.clone());
+    let failed = |step: &str, e: AzureError| match e.kind() {
+        AzureErrorKind::HttpResponse { status, error_code } => {
+            format!("{} ({}): {} {}", step, container, status, error_code.as_deref().unwrap_or_default())
+        }
+        _ => {
+            let mut cause: &dyn std::error::Error = &e;
+            while let Some(source) = cause.source() {
+                cause = source;
+            }
+            format!("{}: {}", step, cause)
+        }
+    };
+
+    let start = Instant::now();
+    col.get_collection().into_future().await.map_err(|e| failed("auth", e))?;
+    let auth = start.elapsed();
+
+    let id = format!("_recli_sinks_test_{}", uuid::Uuid::new_v4());
+    let (workspace, partition) = partition_for(cfg.workspace.clone(), partition_by_workspace(cfg), &id);
+    let canary = CanaryDoc { id: id.clone(), session_id: id.clone(), workspace, partition: partition.clone() };
+    let start = Instant::now();
+    col.create_document(canary).into_future().await.map_err(|e| failed("write", e))?;
+    let write = start.elapsed();
+
+    let start = Instant::now();
+    let document = col.document_client(id, &partition).map_err(|e| failed("delete", e))?;
+    document.delete_document().into_future().await.map_err(|e| failed("delete", e))?;
+    Ok(vec![("auth", auth), ("write", write), ("delete", start.elapsed())])
+}
+
+// parameterized sql over every command of every uploaded session
+// `uploaded_since` keeps sessions whose document was written at or after that server time (`_ts`)
+fn build_search_query(args: &CloudSearchArgs, workspace: Option<&str>, uploaded_since: Option<i64>) -> Result<Query, String> {
+    let mut clauses: Vec<&str> = Vec::new();
+    let mut params = Vec::new();
+    let mut filter = |clause: &'static str, name: &str, value: serde_json::Value| {
+        clauses.push(clause);
+        params.push(Param::new(name.to_string(), value));
+    };
+
+    if let Some(host) = &args.host {
+        filter("s.host = @host", "@host", host.clone().into());
+    }
+    if let Some(user) = &args.user {
+        filter("s.user = @user", "@user", user.clone().into());
+    }
+    if let Some(workspace) = workspace {
+        filter("s.workspace = @workspace", "@workspace", workspace.into());
+    }
+    // stored timestamps are rfc3339 utc, but older uploads end in +00:00 rather than Z and differ
+    // in precision. both start with the same seconds, so the raw field (and its range index) is
+    // compared with bounds cut to whole seconds, which any form of a moment inside them passes;
+    // fetch_commands narrows to the exact bounds
+    let seconds = |t: chrono::DateTime<Utc>| t.format("%Y-%m-%dT%H:%M:%S").to_string();
+    if let Some(since) = &args.since {
+        let t = parse_time_bound(since)?;
+        filter("e.timestamp >= @since", "@since", seconds(t).into());
+    }
+    if let Some(until) = &args.until {
+        let t = parse_time_bound(until)?;
+        let next = t.checked_add_signed(chrono::Duration::seconds(1)).unwrap_or(t);
+        filter("e.timestamp < @until", "@until", seconds(next).into());
+    }
+    if let Some(text) = &args.contains {
+        filter("CONTAINS(e.cmd, @contains)", "@contains", text.clone().into());
+    }
+    if let Some(code) = args.exit_code {
+        filter("e.exit_code = @exit_code", "@exit_code", code.into());
+    }
+    if let Some(ts) = uploaded_since {
+        filter("s._ts >= @uploaded", "@uploaded", ts.into());
+    }
+
+    let mut sql = String::from(
+        "SELECT s.session_id, s.host, s.user, s.workspace, s.encryption, s._ts, e.id, e.cmd, e.exit_code, e.cwd, e.timestamp, e.duration_ms \
+         FROM s JOIN e IN s.entries",
+    );
+    if !clauses.is_empty() {
+        sql.push_str(" WHERE ");
+        sql.push_str(&clauses.join(" AND "));
+    }
+    Ok(Query::with_params(sql, params))
+}
+
+const COSMOS_NOT_CONFIGURED: &str = "Cosmos DB is not configured; run `recli doctor` for details";
+
+fn cosmos_error(e: AzureError) -> io::Error {
+    RecliError::Upload { target: "cosmos".to_string(), source: Box::new(e) }.into()
+}
+
+// run a command query against the configured container; also gives the newest upload time among
+// the sessions matched
+async fn fetch_commands(
+    cfg: &Config,
+    args: &CloudSearchArgs,
+    uploaded_since: Option<i64>,
+) -> io::Result<(Vec<IndexedCommand>, Option<i64>)> {
+    let (Some(client), Ok(db), Ok(container)) = (
+        CommandLogger::init_cosmos_client(),
+        env::var("RECLI_AZURE__COSMOS__DB"),
+        env::var("RECLI_AZURE__COSMOS__CONTAINER"),
+    ) else {
+        return Err(RecliError::Config(COSMOS_NOT_CONFIGURED.to_string()).into());
+    };
+    let workspace = args.workspace.as_deref().or(cfg.workspace.as_deref());
+    // uploads carry pseudonyms, so plain names are looked up by theirs
+    let mut args = args.clone();
+    if let Some(anonymizer) = Anonymizer::from_config(&cfg.anonymize) {
+        for name in [&mut args.host, &mut args.user].into_iter().flatten() {
+            if !name.starts_with("anon-") {
+                *name = anonymizer.name(name);
             }
-            "status" => {
-                println!("no active session (this version doesn't track sessions)");
+        }
+    }
+    // the server can't look into encrypted command lines, so text is matched here after decrypting
+    let key = UserKey::load(&cfg.encryption, false).map_err(RecliError::Config)?;
+    let contains = if key.is_some() { args.contains.take() } else { None };
+    let query = build_search_query(&args, workspace, uploaded_since).map_err(RecliError::Validation)?;
+    let bound = |spec: &Option<String>| spec.as_deref().map(parse_time_bound).transpose().map_err(RecliError::Validation);
+    let (since, until) = (bound(&args.since)?, bound(&args.until)?);
+
+    let col = client.database_client(db).collection_client(container);
+    let mut stream = col
+        .query_documents(query)
+        .query_cross_partition(true)
+        .into_stream::<CommandRow>();
+
+    let mut hits = Vec::new();
+    let mut newest_upload = None;
+    let mut ciphers: HashMap<String, Option<SessionCipher>> = HashMap::new();
+    let mut locked = 0;
+    while let Some(page) = stream.next().await {
+        let page = page.map_err(cosmos_error)?;
+        for (row, _) in page.results {
+            newest_upload = newest_upload.max(Some(row.uploaded));
+            let mut hit = row.command;
+            // the query matched whole seconds; a timestamp that can't be read can't be said to be in range
+            let bounded = since.is_some() || until.is_some();
+            match parse_stamp(&hit.timestamp) {
+                Some(t) if since.is_some_and(|s| t < s) || until.is_some_and(|u| t > u) => continue,
+                None if bounded => continue,
+                _ => {}
             }
-            "cosmos_doctor" => {
-                cosmos_doctor().await?;
+            hit.timestamp = normalize_stamp(&hit.timestamp);
+            if let Some(envelope) = row.encryption {
+                let cipher = ciphers.entry(hit.session_id.clone()).or_insert_with(|| {
+                    let key = key.as_ref()?;
+                    SessionCipher::open(key, &envelope, &hit.session_id)
+                        .map_err(|e| tracing::warn!(session_id = %hit.session_id, "{}", e))
+                        .ok()
+                });
+                match cipher.as_ref().map(|c| c.decrypt(&hit.cmd)) {
+                    Some(Ok(cmd)) => hit.cmd = cmd,
+                    _ => locked += 1,
+                }
             }
-            _ => {
-                // run as single command
-                let mut logger = CommandLogger::new().await?;
-                let cmd = args[1..].join(" ");
-                let exit_code = logger.run_command(&cmd).await;
-                logger.save_async().await?;
-                std::process::exit(exit_code);
+            if contains.as_deref().is_none_or(|text| hit.cmd.contains(text)) {
+                hits.push(hit);
             }
         }
+    }
+    if locked > 0 {
+        eprintln!("! {} command lines are encrypted with a key recli doesn't have, shown as stored", locked);
+    }
+    Ok((hits, newest_upload))
+}
+
+// a row of the command query with its session's envelope, if the session was encrypted
+#[derive(Deserialize)]
+struct CommandRow {
+    #[serde(flatten)]
+    command: IndexedCommand,
+    #[serde(default)]
+    encryption: Option<Envelope>,
+    // when the session's document was last written, in the server's clock
+    #[serde(rename = "_ts", default)]
+    uploaded: i64,
+}
+
+fn print_commands(commands: &[IndexedCommand], json: bool) -> io::Result<()> {
+    if commands.is_empty() && !json {
+        println!("no matching commands");
+    }
+    for c in commands {
+        if json {
+            println!("{}", serde_json::to_string(c)?);
+        } else {
+            println!("{}  {}@{}  [{}]  {}  ({})", c.timestamp, c.user, c.host, c.exit_code, c.cmd, c.cwd);
+        }
+    }
+    Ok(())
+}
+
+/// Search commands uploaded from any machine.
+async fn cloud_search(cfg: &Config, args: &CloudSearchArgs) -> io::Result<()> {
+    let (mut hits, _) = fetch_commands(cfg, args, None).await?;
+    hits.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+    hits.truncate(args.limit);
+    print_commands(&hits, args.json)
+}
+
+fn open_index() -> io::Result<Index> {
+    Index::open(&index::default_path()).map_err(io::Error::from)
+}
+
+// pull the local session directories into the index so queries see this machine too
+fn refresh_local_index(index: &mut Index, cfg: &Config) -> io::Result<usize> {
+    index
+        .import_local(&cfg.session_log_dir(), &current_host(), &current_user())
+        .map_err(io::Error::from)
+}
+
+/// Replace what matches `pattern` throughout a recorded session, then in the local indexes and,
+/// with `upload`, in the cloud.
+async fn scrub_session(cfg: &Config, session: &str, pattern: &str, replace: &str, dry_run: bool, upload: bool) -> io::Result<()> {
+    check_session_id(session)?;
+    let log_dir = cfg.session_log_dir().join(session);
+    if !log_dir.is_dir() {
+        return Err(RecliError::Validation(format!("no recorded session at {}", log_dir.display())).into());
+    }
+    // the recorder rewrites commands.json as it goes and would put the secret back
+    if SessionManager::new().active_log_dir().is_some_and(|active| active == log_dir) {
+        return Err(RecliError::Session(format!("{} is still recording; end it first", session)).into());
+    }
+    let report = recli::scrub::scrub_session(&log_dir, pattern, replace, dry_run)?;
+    for (file, count) in &report.files {
+        println!("{:>6}  {}", count, file);
+    }
+    let verb = if dry_run { "would replace" } else { "replaced" };
+    println!("{} {} matches in {} files", verb, report.total(), report.files.len());
+    if dry_run || report.files.is_empty() {
+        return Ok(());
+    }
+
+    let mut index = open_index()?;
+    index.forget_session(session).map_err(io::Error::from)?;
+    refresh_local_index(&mut index, cfg)?;
+    #[cfg(feature = "fulltext")]
+    if let Err(e) = open_fulltext().and_then(|mut ft| {
+        ft.forget_session(session)?;
+        ft.import_local(&cfg.session_log_dir())
+    }) {
+        eprintln!("! full-text index: {}", e);
+    }
+    if upload {
+        upload_finished_session(cfg, &log_dir, None).await;
+        // the team server takes sessions whose content changed on each sync
+        sync_index(cfg, false).await?;
     } else {
-        // default to interactive mode
-        let mut logger = CommandLogger::new().await?;
-        logger.interactive_shell().await?;
+        println!("copies already uploaded are unchanged; `recli scrub {} --upload` replaces them", session);
+    }
+    Ok(())
+}
+
+/// Rebuild the local part of the index and, with `pull`, merge this user's uploads since the last pull.
+async fn sync_index(cfg: &Config, pull: bool) -> io::Result<()> {
+    let mut index = open_index()?;
+    let local = refresh_local_index(&mut index, cfg)?;
+    println!("indexed {} new local commands", local);
+    #[cfg(feature = "fulltext")]
+    match open_fulltext().and_then(|mut ft| ft.import_local(&cfg.session_log_dir())) {
+        Ok(added) => println!("added {} commands to the full-text index", added),
+        Err(e) => eprintln!("! full-text index: {}", e),
+    }
+    let mut throttle = Throttle::new(cfg.upload.max_kbps);
+    upload_outbox(cfg, &mut throttle).await;
+    let team = TeamClient::from_config(&cfg.team)
+        .map_err(RecliError::Config)?
+        .map(|t| t.with_compression(cfg.upload.low_bandwidth).with_retry(&cfg.retry));
+    if let Some(team) = &team {
+        team_sync(cfg, team, &mut index, pull, &mut throttle).await?;
+    }
+    // with a team server the cosmos pull is only done when cosmos is set up as well
+    if !pull || (team.is_some() && CosmosSetup::from_env() != CosmosSetup::Complete) {
+        return Ok(());
+    }
+
+    // incremental by upload time, not by when commands ran: a long session uploaded when it ends holds
+    // commands older than ones already pulled, and the server's clock is the same for every machine
+    let uploaded_since = index
+        .get_state(PULLED_UPLOADS_KEY)
+        .map_err(io::Error::from)?
+        .and_then(|ts| ts.parse::<i64>().ok());
+    let args = CloudSearchArgs {
+        contains: None,
+        host: None,
+        user: Some(current_user()),
+        since: None,
+        until: None,
+        exit_code: None,
+        workspace: None,
+        limit: usize::MAX,
+        json: false,
+    };
+    let (remote, newest_upload) = fetch_commands(cfg, &args, uploaded_since).await?;
+
+    let added = index.merge(&remote).map_err(io::Error::from)?;
+    // an upload committing while the query ran can carry a slightly older _ts than one already seen,
+    // so the next pull overlaps a little; merging the same commands again changes nothing
+    if let Some(newest) = newest_upload {
+        let next = (newest - PULL_OVERLAP_SECS).max(uploaded_since.unwrap_or(0));
+        index.set_state(PULLED_UPLOADS_KEY, &next.to_string()).map_err(io::Error::from)?;
+    }
+    println!("pulled {} commands ({} new) from the cloud", remote.len(), added);
+    Ok(())
+}
+
+// sync_state key of the upload time (cosmos `_ts`) the next pull starts from
+const PULLED_UPLOADS_KEY: &str = "pulled_uploads";
+const PULL_OVERLAP_SECS: i64 = 60;
+
+/// Print every problem with the config file, or that it has none.
+fn validate_config(path: Option<&str>, json: bool) -> io::Result<()> {
+    let path = path.map(str::to_string).unwrap_or_else(Config::default_path);
+    let text = fs::read_to_string(&path).map_err(|e| RecliError::Config(format!("{}: {}", path, e)))?;
+    let (_, problems) = recli::config_check::check(&text);
+    if json {
+        println!("{}", json!({ "path": path, "valid": problems.is_empty(), "problems": problems }));
+    } else if problems.is_empty() {
+        println!("{}: ok", path);
+    } else {
+        for p in &problems {
+            let line = p.line.map(|l| format!("line {}", l)).unwrap_or_else(|| "-".to_string());
+            let key = p.key.as_deref().map(|k| format!("{}: ", k)).unwrap_or_default();
+            println!("{:<9} {:<14} {}{}", line, p.kind.name(), key, p.message);
+        }
+    }
+    if problems.is_empty() {
+        return Ok(());
+    }
+    let unused = problems.iter().any(|p| matches!(p.kind, ProblemKind::Syntax | ProblemKind::Type));
+    let outcome = if unused { ", none of the file is used until they are fixed" } else { "" };
+    let count = match problems.len() {
+        1 => "1 problem".to_string(),
+        n => format!("{} problems", n),
+    };
+    Err(RecliError::Config(format!("{} in {}{}", count, path, outcome)).into())
+}
+
+/// Have the recording session read its config file again, and say what took effect.
+async fn reload_session_config() -> io::Result<()> {
+    let session_manager = SessionManager::new();
+    if !session_manager.is_session_active() {
+        return Err(RecliError::Session("no active session; the next one starts with the config as it is".to_string()).into());
+    }
+    let reloaded = recli::control::reload(&session_manager.control_socket())
+        .await
+        .map_err(RecliError::Control)?
+        .map_err(|e| RecliError::Config(format!("config not reloaded: {}", e)))?;
+    if reloaded.applied.is_empty() && reloaded.restart.is_empty() {
+        println!("nothing changed");
+    }
+    if !reloaded.applied.is_empty() {
+        println!("applied: {}", reloaded.applied.join(", "));
+    }
+    if !reloaded.restart.is_empty() {
+        println!("needs a new session: {}", reloaded.restart.join(", "));
     }
-    
     Ok(())
 }
+
+/// Print what `recli sync` would send to each sink without sending it or touching the index:
+/// the queued cosmos uploads, the sessions the team server hasn't got as they are now and the
+/// queued webhook posts. With `session`, that session as each sink would get it, configured or
+/// not. `show` prints the documents themselves.
+fn sync_dry_run(cfg: &Config, show: bool, session: Option<&str>) -> io::Result<()> {
+    let base = cfg.session_log_dir();
+    if let Some(id) = session {
+        check_session_id(id)?;
+    }
+    let only = match session {
+        Some(id) if !base.join(id).is_dir() => {
+            return Err(RecliError::Validation(format!("no recorded session at {}", base.join(id).display())).into());
+        }
+        Some(id) => Some(base.join(id)),
+        None => None,
+    };
+    let size = |bytes: usize| format!("{:.1} KB", bytes as f64 / 1024.0);
+
+    // cosmos: the outbox is what sync uploads, sessions that end go up on their own
+    let cosmos = CosmosSetup::from_env() == CosmosSetup::Complete;
+    println!("== cosmos{} ==", if cosmos { "" } else { " (not configured)" });
+    let dirs = match &only {
+        Some(dir) => vec![dir.clone()],
+        None if cosmos => outbox::pending(),
+        None => Vec::new(),
+    };
+    if dirs.is_empty() {
+        println!("  nothing to upload");
+    }
+    // the hash shown is the one the upload would store
+    let key = match dirs.is_empty() {
+        true => None,
+        false => UserKey::load(&cfg.encryption, false).ok().flatten(),
+    };
+    for dir in &dirs {
+        let id = dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        match cosmos_document(dir, cfg) {
+            None => println!("  {}  nothing recorded", id),
+            Some(Err(tag)) => println!("  {}  kept local by [sinks.cosmos] skip_tags ({})", id, tag),
+            Some(Ok((mut doc, redacted))) => {
+                doc.content_hash = doc.compute_hash(cfg.upload.compress, key.as_ref());
+                let body = serde_json::to_string_pretty(&doc)?;
+                let redactions = match redacted {
+                    0 => String::new(),
+                    n => format!(", output of {} redacted by filters", n),
+                };
+                println!("  {}  {} commands, {}{}", id, doc.entries.len(), size(body.len()), redactions);
+                if show {
+                    println!("{}", body);
+                }
+            }
+        }
+    }
+    if !dirs.is_empty() {
+        match upload_policy(cfg) {
+            UploadPolicy::Always => {}
+            UploadPolicy::Ask => println!("  upload_policy is \"ask\": each session is uploaded only once you say yes"),
+            UploadPolicy::Never => println!("  upload_policy is \"never\": nothing is uploaded"),
+        }
+        if cfg.upload.compress {
+            println!("  long output and stderr are sent zstd-compressed");
+        }
+        if cfg.encryption.enabled {
+            println!("  cmd, output and stderr are sent encrypted with your key");
+        }
+    }
+
+    // the team server: sessions whose content changed since they were pushed
+    let server = cfg.team.server.as_deref().filter(|s| !s.is_empty());
+    println!("== team{} ==", server.map(|s| format!(" {}", s)).unwrap_or_else(|| " (not configured)".to_string()));
+    let (host, user) = (current_host(), current_user());
+    let anonymizer = Anonymizer::from_config(&cfg.anonymize);
+    let index = open_index()?;
+    let dirs = match &only {
+        Some(dir) => vec![dir.clone()],
+        None if server.is_some() => history::session_dirs(&base),
+        None => Vec::new(),
+    };
+    let mut pushed = 0;
+    for dir in &dirs {
+        let id = dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        match team_session(cfg, dir, &host, &user, anonymizer.as_ref()) {
+            None => println!("  {}  nothing recorded", id),
+            Some(Err(tag)) => println!("  {}  kept local by [sinks.team] skip_tags ({})", id, tag),
+            Some(Ok(session)) => {
+                let key = format!("team_pushed:{}", session.session_id);
+                let unchanged = index.get_state(&key).map_err(io::Error::from)?.as_deref() == Some(session.content_hash().as_str());
+                if unchanged && only.is_none() {
+                    continue;
+                }
+                pushed += 1;
+                let body = serde_json::to_string_pretty(&session)?;
+                let state = if unchanged { ", already on the server" } else { "" };
+                println!("  {}  {} commands, {}{}", id, session.commands.len(), size(body.len()), state);
+                if show {
+                    println!("{}", body);
+                }
+            }
+        }
+    }
+    if pushed == 0 && only.is_none() {
+        println!("  nothing to push");
+    }
+    if only.is_some() {
+        return Ok(());
+    }
+
+    println!("== webhooks ==");
+    let posts = outbox::pending_posts();
+    if posts.is_empty() {
+        println!("  nothing queued");
+    }
+    for (_, post) in &posts {
+        println!("  {}  {}", post.url, size(post.body.len()));
+        if show {
+            match serde_json::from_str::<serde_json::Value>(&post.body) {
+                Ok(body) => println!("{}", serde_json::to_string_pretty(&body)?),
+                Err(_) => println!("{}", post.body),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Upload the sessions low-bandwidth mode or a failed upload left in the outbox and send the
+/// webhook posts that failed; they stay queued until they made it.
+async fn upload_outbox(cfg: &Config, throttle: &mut Throttle) {
+    send_queued_posts(cfg).await;
+    let queued = outbox::pending();
+    if queued.is_empty() {
+        return;
+    }
+    let mut uploaded = 0;
+    for dir in &queued {
+        match upload_pty_session(dir, cfg, throttle).await {
+            Ok(CosmosUpload::Uploaded | CosmosUpload::Unchanged | CosmosUpload::Declined) => {
+                outbox::remove(dir);
+                uploaded += 1;
+            }
+            Ok(CosmosUpload::Incomplete(missing)) => {
+                eprintln!("! queued sessions not uploaded, cosmos settings are incomplete: {}", missing.join(", "));
+                break;
+            }
+            Ok(CosmosUpload::NotConfigured) => break,
+            Err(e) => CommandLogger::log_cosmos_error("Cosmos upload failed", &e),
+        }
+    }
+    println!("uploaded {} of {} queued sessions", uploaded, queued.len());
+}
+
+// webhook posts go out in the order they were queued; a url that still can't be reached
+// keeps its posts for the next sync
+async fn send_queued_posts(cfg: &Config) {
+    let posts = outbox::pending_posts();
+    if posts.is_empty() {
+        return;
+    }
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_millis(cfg.hooks.timeout_ms.max(1)))
+        .build()
+        .unwrap_or_default();
+    let mut sent = 0;
+    for (path, post) in &posts {
+        let send = || async {
+            client
+                .post(&post.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(post.body.clone())
+                .send()
+                .await
+                .and_then(|resp| resp.error_for_status())
+        };
+        match retry::retry_if(send, &cfg.retry, retry::transient_http).await {
+            Ok(_) => {
+                outbox::remove_post(path);
+                sent += 1;
+            }
+            Err(e) if retry::transient_http(&e) => eprintln!("! webhook post to {} kept for later: {}", post.url, e.without_url()),
+            Err(e) => {
+                eprintln!("! webhook post to {} dropped: {}", post.url, e.without_url());
+                outbox::remove_post(path);
+            }
+        }
+    }
+    println!("sent {} of {} queued webhook posts", sent, posts.len());
+}
+
+/// Push sessions that changed since they were last pushed to the team server and,
+/// with `pull`, merge in the commands the server received from everyone since the last pull.
+async fn team_sync(cfg: &Config, team: &TeamClient, index: &mut Index, pull: bool, throttle: &mut Throttle) -> io::Result<()> {
+    let (host, user) = (current_host(), current_user());
+    let anonymizer = Anonymizer::from_config(&cfg.anonymize);
+    let upload_error = |e: String| RecliError::Upload { target: "team server".to_string(), source: e.into() };
+    let (mut pushed, mut kept) = (0, 0);
+    for dir in history::session_dirs(&cfg.session_log_dir()) {
+        let session = match team_session(cfg, &dir, &host, &user, anonymizer.as_ref()) {
+            None => continue,
+            Some(Err(_)) => {
+                kept += 1;
+                continue;
+            }
+            Some(Ok(session)) => session,
+        };
+        let key = format!("team_pushed:{}", session.session_id);
+        let hash = session.content_hash();
+        if index.get_state(&key).map_err(io::Error::from)?.as_deref() == Some(hash.as_str()) {
+            continue;
+        }
+        team.push(&session, throttle).await.map_err(upload_error)?;
+        index.set_state(&key, &hash).map_err(io::Error::from)?;
+        pushed += 1;
+    }
+    println!("pushed {} sessions to {}", pushed, team.server());
+    if kept > 0 {
+        println!("{} sessions kept local by [sinks.team] skip_tags", kept);
+    }
+    if !pull {
+        return Ok(());
+    }
+
+    // our own commands come back as pushed, possibly under pseudonyms, and are already indexed
+    let (own_host, own_user) = match &anonymizer {
+        Some(a) => (a.name(&host), a.name(&user)),
+        None => (host, user),
+    };
+    let mut cursor = index.get_state("team_cursor").map_err(io::Error::from)?;
+    let (mut pulled, mut added) = (0, 0);
+    loop {
+        let page = team.pull(cursor.as_deref()).await.map_err(upload_error)?;
+        let commands: Vec<IndexedCommand> = page
+            .commands
+            .into_iter()
+            .filter(|c| c.host != own_host || c.user != own_user)
+            .collect();
+        pulled += commands.len();
+        added += index.merge(&commands).map_err(io::Error::from)?;
+        if let Some(next) = &page.cursor {
+            index.set_state("team_cursor", next).map_err(io::Error::from)?;
+            cursor = Some(next.clone());
+        }
+        if !page.more || page.cursor.is_none() {
+            break;
+        }
+    }
+    println!("pulled {} team commands ({} new) from {}", pulled, added, team.server());
+    Ok(())
+}
+
+// what the team server gets of the session in `log_dir`; none when nothing was recorded there,
+// the tag keeping it local when [sinks.team] skip_tags does
+fn team_session(cfg: &Config, log_dir: &Path, host: &str, user: &str, anonymizer: Option<&Anonymizer>) -> Option<Result<SessionPush, String>> {
+    let mut session = SessionPush::load(log_dir, host, user, cfg.workspace.as_deref(), anonymizer)?;
+    if let Some(tag) = cfg.sinks.team.blocked_by(&session_tags(log_dir)) {
+        return Some(Err(tag.to_string()));
+    }
+    // the team server never gets output, so full and commands are the same
+    if cfg.sinks.team.content == SinkContent::Metadata {
+        session.commands.clear();
+    }
+    Some(Ok(session))
+}
+
+// tags of a session and of every command in it, for [sinks] skip_tags
+fn session_tags(log_dir: &Path) -> Vec<String> {
+    let mut tags = session::read_metadata(log_dir).map(|m| m.tags).unwrap_or_default();
+    tags.extend(history::load_session(log_dir).into_iter().flatten().flat_map(|e| e.tags));
+    tags
+}
+
+/// Query the merged index; local sessions are re-read first so fresh recordings show up.
+fn search_index(cfg: &Config, text: Option<&str>, project: Option<&str>, limit: usize, json: bool) -> io::Result<()> {
+    #[cfg(feature = "fulltext")]
+    if let Some(text) = text {
+        match open_fulltext().and_then(|mut ft| {
+            ft.import_local(&cfg.session_log_dir())?;
+            ft.search(text, limit)
+        }) {
+            Ok(hits) => return print_hits(&hits, json),
+            // the sqlite index still answers, just without ranking
+            Err(e) => eprintln!("! full-text index: {}", e),
+        }
+    }
+    let mut index = open_index()?;
+    refresh_local_index(&mut index, cfg)?;
+    let commands = index.search(text, project, limit).map_err(io::Error::from)?;
+    print_commands(&commands, json)
+}
+
+/// print commands whose recorded output matches `pattern`, with the lines that do; false when none do
+#[allow(clippy::too_many_arguments)]
+fn grep_output(
+    cfg: &Config,
+    pattern: &str,
+    ignore_case: bool,
+    fixed_strings: bool,
+    context: usize,
+    session: Option<&str>,
+    here: bool,
+    limit: usize,
+    json: bool,
+) -> io::Result<bool> {
+    let pattern = match fixed_strings {
+        true => regex::escape(pattern),
+        false => pattern.to_string(),
+    };
+    let pattern = regex::RegexBuilder::new(&pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|e| RecliError::Validation(format!("bad pattern: {}", e)))?;
+    let project = match here {
+        true => Some(project_key(&env::current_dir()?)),
+        false => None,
+    };
+    let scope = recli::grep::Scope { session, project: project.as_deref() };
+    let found = recli::grep::search(&cfg.session_log_dir(), &scope, &pattern, context, limit);
+    let color = io::stdout().is_terminal();
+    for (n, m) in found.iter().enumerate() {
+        if json {
+            println!("{}", serde_json::to_string(m)?);
+            continue;
+        }
+        if n > 0 {
+            println!();
+        }
+        let exit = match m.exit_code {
+            0 => String::new(),
+            code => format!("  [exit {}]", code),
+        };
+        let heading = format!("{} #{}  {}  {}{}", m.session_id, m.seq, m.timestamp, m.cmd, exit);
+        match color {
+            true => println!("\x1b[1m{}\x1b[0m", heading),
+            false => println!("{}", heading),
+        }
+        let mut previous: Option<(&str, usize)> = None;
+        for line in &m.lines {
+            // a gap between groups of lines, as grep marks it
+            if previous.is_some_and(|(stream, no)| stream != line.stream || no + 1 != line.line_no) {
+                println!("  --");
+            }
+            previous = Some((line.stream, line.line_no));
+            let stream = if line.stream == "stderr" { "err " } else { "" };
+            let text = match (color, line.matched) {
+                (true, true) => pattern.replace_all(&line.text, "\x1b[1;31m$0\x1b[0m").to_string(),
+                _ => line.text.clone(),
+            };
+            println!("  {}{:>5}{} {}", stream, line.line_no, if line.matched { ':' } else { '-' }, text);
+        }
+    }
+    if found.is_empty() && !json {
+        eprintln!("no recorded output matches");
+    }
+    Ok(!found.is_empty())
+}
+
+// what commands run in `dir` are grouped under: its git toplevel, or the directory itself outside a repository
+fn project_key(dir: &Path) -> String {
+    history::project_root(dir)
+        .unwrap_or_else(|| fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf()))
+        .to_string_lossy()
+        .to_string()
+}
+
+/// print the command line picked from history; false when nothing was
+fn pick_command(cfg: &Config, query: &str, here: bool) -> io::Result<bool> {
+    let project = match here {
+        true => Some(project_key(&env::current_dir()?)),
+        false => None,
+    };
+    let candidates = pick::candidates(&cfg.session_log_dir(), project.as_deref());
+    if candidates.is_empty() {
+        eprintln!("no recorded commands to pick from");
+        return Ok(false);
+    }
+    let Some(cmd) = pick::run(candidates, query)? else { return Ok(false) };
+    println!("{}", cmd);
+    Ok(true)
+}
+
+/// play back the cast of `session`, warning first when this terminal is smaller than it was recorded at
+fn replay_session(cfg: &Config, session: Option<&str>, speed: f64, idle_limit: f64, raw: bool) -> io::Result<()> {
+    let Some(log_dir) = session_dir(&cfg.session_log_dir(), session)? else { return Ok(()) };
+    if speed <= 0.0 {
+        return Err(RecliError::Validation("--speed must be above 0".to_string()).into());
+    }
+    let cast = Cast::load(&log_dir).map_err(io::Error::other)?;
+    if !io::stdout().is_terminal() {
+        return Err(RecliError::Validation("recli replay needs a terminal".to_string()).into());
+    }
+    let (cols, rows) = replay::largest_size(&cast);
+    let sizes = replay::recorded_sizes(&cast);
+    if sizes.len() > 1 {
+        let sizes: Vec<String> = sizes.iter().map(|(_, c, r)| format!("{}x{}", c, r)).collect();
+        println!("recorded at {}", sizes.join(" -> "));
+    }
+    if let Ok((term_cols, term_rows)) = crossterm::terminal::size() {
+        if term_cols < cols || term_rows < rows {
+            eprintln!(
+                "warning: this terminal is {}x{} but the session was recorded at up to {}x{}; {}",
+                term_cols,
+                term_rows,
+                cols,
+                rows,
+                match raw {
+                    true => "output may wrap",
+                    false => "only the part around the cursor is shown",
+                }
+            );
+            std::thread::sleep(Duration::from_secs(2));
+        }
+    }
+    let idle_limit = Some(idle_limit).filter(|&l| l > 0.0);
+    Ok(replay::play(&cast, ReplayOptions { speed, idle_limit, raw })?)
+}
+
+/// list the commands of `session` with when they ran, in utc or with `local` local time, or with
+/// `screen_at`, print the screen as it was when that one finished
+fn show_session(cfg: &Config, session: Option<&str>, screen_at: Option<usize>, local: bool) -> io::Result<()> {
+    let Some(log_dir) = session_dir(&cfg.session_log_dir(), session)? else { return Ok(()) };
+    let entries = history::load_session(&log_dir).unwrap_or_default();
+    let Some(n) = screen_at else {
+        for (i, entry) in entries.iter().enumerate() {
+            let at = display_stamp(&entry.timestamp, local);
+            match entry.exit_code {
+                0 => println!("{:>4}  {}  {}", i + 1, at, entry.cmd),
+                code => println!("{:>4}  {}  {}  (exit {})", i + 1, at, entry.cmd, code),
+            }
+        }
+        return Ok(());
+    };
+    let Some(entry) = n.checked_sub(1).and_then(|i| entries.get(i)) else {
+        return Err(RecliError::Validation(format!("--screen-at must be from 1 to {}, the session's commands", entries.len())).into());
+    };
+    let cast = Cast::load(&log_dir).map_err(io::Error::other)?;
+    let Some(end) = replay::command_end(&cast, &entries, n) else {
+        return Err(RecliError::Session(format!("cannot place command {} in the session's cast", n)).into());
+    };
+    let screen = replay::screen_at(&cast, end, entry.duration_ms.unwrap_or(0) as f64 / 1000.0);
+    let (rows, cols) = screen.size();
+    let color = io::stdout().is_terminal();
+    let dim = |text: String| if color { format!("\x1b[2m{}\x1b[0m", text) } else { text };
+    println!("{}", dim(format!("command {}: {}  ({}x{}, {:.1}s in)", n, entry.cmd, cols, rows, end)));
+    // trailing blank rows are left out
+    let plain: Vec<String> = screen.rows(0, cols).collect();
+    let used = plain.iter().rposition(|row| !row.trim().is_empty()).map_or(0, |i| i + 1);
+    let mut out = io::stdout().lock();
+    for (row, formatted) in plain.iter().zip(screen.rows_formatted(0, cols)).take(used) {
+        match color {
+            true => {
+                out.write_all(&formatted)?;
+                writeln!(out, "\x1b[0m")?;
+            }
+            false => writeln!(out, "{}", row.trim_end())?,
+        }
+    }
+    Ok(())
+}
+
+/// print the commands most likely to follow `after` in `cwd`'s project, learned from the index
+fn suggest_next(cfg: &Config, prefix: &str, after: Option<String>, cwd: Option<String>, count: usize, json: bool) -> io::Result<()> {
+    let cwd = cwd.unwrap_or_else(|| env::current_dir().map(|p| p.to_string_lossy().to_string()).unwrap_or_default());
+    let project = project_key(Path::new(&cwd));
+    let mut index = open_index()?;
+    refresh_local_index(&mut index, cfg)?;
+    // the shell widget passes its own last history line; otherwise the session's, or the newest here
+    let after = after.filter(|a| !a.trim().is_empty()).or_else(|| {
+        let log_dir = SessionManager::new().active_log_dir()?;
+        history::load_session(&log_dir)?.pop().map(|e| e.cmd)
+    });
+    let after = match after {
+        Some(after) => Some(after),
+        None => index.search(None, Some(&project), 1).map_err(io::Error::from)?.pop().map(|c| c.cmd),
+    };
+    let model = Model::build(&index.in_order().map_err(io::Error::from)?, &project, Utc::now());
+    for suggestion in model.suggest(after.as_deref(), prefix, count) {
+        if json {
+            println!("{}", serde_json::to_string(&suggestion)?);
+        } else {
+            println!("{}", suggestion.cmd);
+        }
+    }
+    Ok(())
+}
+
+/// List indexed sessions, optionally only those that ran commands in `project`'s repository.
+/// Times are shown in utc, or with `local` in the local time zone; json keeps them as stored.
+fn list_sessions(cfg: &Config, project: Option<&Path>, limit: usize, json: bool, local: bool) -> io::Result<()> {
+    let mut index = open_index()?;
+    refresh_local_index(&mut index, cfg)?;
+    let project = project.map(project_key);
+    let sessions = index.sessions(project.as_deref(), limit).map_err(io::Error::from)?;
+    if sessions.is_empty() && !json {
+        match &project {
+            Some(project) => println!("no recorded sessions in {}", project),
+            None => println!("no recorded sessions"),
+        }
+    }
+    for s in &sessions {
+        if json {
+            println!("{}", serde_json::to_string(s)?);
+        } else {
+            println!(
+                "{}  {}@{}  {} commands, {} failed  {} .. {}",
+                s.session_id,
+                s.user,
+                s.host,
+                s.commands,
+                s.failed,
+                display_stamp(&s.started, local),
+                display_stamp(&s.ended, local)
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "fulltext")]
+fn open_fulltext() -> Result<recli::fulltext::FullText, String> {
+    recli::fulltext::FullText::open(&recli::fulltext::default_path())
+}
+
+#[cfg(feature = "fulltext")]
+fn print_hits(hits: &[recli::fulltext::Hit], json: bool) -> io::Result<()> {
+    if hits.is_empty() && !json {
+        println!("no matching commands");
+    }
+    for hit in hits {
+        if json {
+            println!("{}", serde_json::to_string(hit)?);
+            continue;
+        }
+        println!("{}  [{}]  {}  ({})", hit.timestamp, hit.exit_code, hit.cmd, hit.cwd);
+        if let Some(snippet) = &hit.snippet {
+            // matched terms in bold, the fragment kept on one line
+            let mut line = String::new();
+            let mut at = 0;
+            for &(start, end) in &snippet.highlighted {
+                line.push_str(&snippet.text[at..start]);
+                line.push_str(&format!("\x1b[1m{}\x1b[0m", &snippet.text[start..end]));
+                at = end;
+            }
+            line.push_str(&snippet.text[at..]);
+            println!("    {}", line.split_whitespace().collect::<Vec<_>>().join(" "));
+        }
+    }
+    Ok(())
+}
+
+fn query_index(cfg: &Config, sql: &str, json: bool) -> io::Result<()> {
+    let mut index = open_index()?;
+    refresh_local_index(&mut index, cfg)?;
+    // the statement is what's wrong, not the index
+    let (columns, rows) = index.query(sql).map_err(|e| match e {
+        RecliError::Index(e) => RecliError::Validation(e.to_string()),
+        e => e,
+    })?;
+    if json {
+        for row in rows {
+            let object: serde_json::Map<String, serde_json::Value> = columns.iter().cloned().zip(row).collect();
+            println!("{}", serde_json::Value::Object(object));
+        }
+        return Ok(());
+    }
+
+    let cells: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|v| match v {
+                    serde_json::Value::String(s) => s.clone(),
+                    serde_json::Value::Null => String::new(),
+                    other => other.to_string(),
+                })
+                .collect()
+        })
+        .collect();
+    let widths: Vec<usize> = (0..columns.len())
+        .map(|i| cells.iter().map(|r| r[i].chars().count()).chain([columns[i].len()]).max().unwrap_or(0))
+        .collect();
+    let line = |values: &[String]| {
+        let padded: Vec<String> = values.iter().zip(&widths).map(|(v, w)| format!("{:<w$}", v, w = *w)).collect();
+        println!("{}", padded.join("  ").trim_end());
+    };
+    line(&columns);
+    line(&widths.iter().map(|w| "-".repeat(*w)).collect::<Vec<_>>());
+    for row in &cells {
+        line(row);
+    }
+    println!("({} rows)", cells.len());
+    Ok(())
+}
+
+// the partition strategy may differ between containers, so sessions are looked up by field
+async fn find_session_doc(col: &CollectionClient, session_id: &str) -> io::Result<Option<SessionDoc>> {
+    let query = Query::with_params(
+        "SELECT * FROM s WHERE s.session_id = @id AND IS_DEFINED(s.entries)".to_string(),
+        vec![Param::new("@id".to_string(), session_id.to_string())],
+    );
+    let mut stream = col
+        .query_documents(query)
+        .query_cross_partition(true)
+        .into_stream::<SessionDoc>();
+
+    while let Some(page) = stream.next().await {
+        match page {
+            Ok(page) => {
+                if let Some((doc, _)) = page.results.into_iter().next() {
+                    return Ok(Some(doc));
+                }
+            }
+            Err(e) => return Err(cosmos_error(e)),
+        }
+    }
+    Ok(None)
+}
+
+/// Print an uploaded session as json with its encrypted fields decrypted.
+async fn cloud_decrypt(cfg: &Config, session_id: &str, output: Option<&Path>) -> io::Result<()> {
+    let (Some(client), Ok(db), Ok(container)) = (
+        CommandLogger::init_cosmos_client(),
+        env::var("RECLI_AZURE__COSMOS__DB"),
+        env::var("RECLI_AZURE__COSMOS__CONTAINER"),
+    ) else {
+        return Err(RecliError::Config(COSMOS_NOT_CONFIGURED.to_string()).into());
+    };
+    let col = client.database_client(db).collection_client(container);
+    let Some(mut doc) = find_session_doc(&col, session_id).await? else {
+        eprintln!("no uploaded session named {}", session_id);
+        return Ok(());
+    };
+    if doc.encryption.is_none() {
+        eprintln!("session {} was uploaded without encryption", session_id);
+    }
+    // decrypting on purpose, so a missing key is an error even with [encryption] off
+    let mut encryption = cfg.encryption.clone();
+    encryption.enabled = true;
+    let key = UserKey::load(&encryption, false).map_err(RecliError::Config)?;
+    doc.decrypt(key.as_ref()).map_err(RecliError::Config)?;
+    doc.decompress().map_err(RecliError::Config)?;
+
+    let json = serde_json::to_string_pretty(&doc)?;
+    match output {
+        Some(path) => fs::write(path, json + "\n")?,
+        None => println!("{}", json),
+    }
+    Ok(())
+}
+
+/// Fetch an uploaded session and write it out like a locally recorded pty session.
+async fn cloud_pull(cfg: &Config, session_id: &str, force: bool) -> io::Result<()> {
+    let (Some(client), Ok(db), Ok(container)) = (
+        CommandLogger::init_cosmos_client(),
+        env::var("RECLI_AZURE__COSMOS__DB"),
+        env::var("RECLI_AZURE__COSMOS__CONTAINER"),
+    ) else {
+        return Err(RecliError::Config(COSMOS_NOT_CONFIGURED.to_string()).into());
+    };
+
+    check_session_id(session_id)?;
+    let log_dir = cfg.session_log_dir().join(session_id);
+    if log_dir.exists() && !force {
+        eprintln!("{} already exists locally (use --force to replace it)", log_dir.display());
+        return Ok(());
+    }
+
+    let col = client.database_client(db).collection_client(container);
+    let Some(mut doc) = find_session_doc(&col, session_id).await? else {
+        eprintln!("no uploaded session named {}", session_id);
+        return Ok(());
+    };
+    let key = UserKey::load(&cfg.encryption, false).map_err(RecliError::Config)?;
+    doc.decrypt(key.as_ref()).map_err(RecliError::Config)?;
+    doc.decompress().map_err(RecliError::Config)?;
+
+    // a replaced session starts empty, so no output files of the old copy are left behind
+    if log_dir.exists() {
+        fs::remove_dir_all(&log_dir)?;
+    }
+    fs::create_dir_all(&log_dir)?;
+    let mut log = recli::command_log::CommandLog::new();
+    for (n, e) in doc.entries.into_iter().enumerate() {
+        // full output goes to a sidecar file like a live recording; the preview stays capped. it is
+        // named by position only, since nothing from the downloaded document may pick the path
+        let mut output = e.output;
+        output.push_str(&e.stderr);
+        let stderr = (!e.stderr.is_empty()).then(|| OutputPreview::of(e.stderr.as_bytes()));
+        let output_path = (!output.is_empty()).then(|| {
+            let name = format!("pulled-{}.out", n + 1);
+            fs::write(log_dir.join(&name), output.as_bytes()).map(|_| name)
+        });
+        let output_path = output_path.transpose()?;
+        let preview = OutputPreview::of(output.as_bytes());
+
+        log.entries.push(recli::command_log::CommandEntry {
+            id: e.id,
+            cmd: e.cmd,
+            cwd: e.cwd,
+            timestamp: e.timestamp,
+            seq: e.seq,
+            exit_code: e.exit_code,
+            output_preview: preview,
+            output_path,
+            pipestatus: None,
+            duration_ms: Some(e.duration_ms),
+            tags: Vec::new(),
+            title: None,
+            stderr,
+            stderr_path: None,
+            project: None,
+            files: None,
+            chunks_path: None,
+            longest_pause: None,
+            signal: e.signal,
+            network: e.network,
+            processes: e.processes,
+            stalls: Vec::new(),
+            note: e.note,
+        });
+    }
+    // documents uploaded before commands were numbered or had ids
+    log.backfill(&doc.session_id);
+    log.save_to_file(&log_dir).map_err(io::Error::from)?;
+
+    let meta = SessionConfig {
+        session_id: doc.session_id,
+        log_dir: log_dir.clone(),
+        started_at: doc.started_at,
+        shell: String::new(),
+        last_title: None,
+        size_history: Vec::new(),
+        ended_at: Some(doc.ended_at),
+        resumed_at: Vec::new(),
+        continues: None,
+        continued_in: None,
+        container: None,
+        remote: None,
+        tags: Vec::new(),
+        host: Some(doc.host.clone()),
+        user: Some(doc.user.clone()),
+        environment: doc.environment,
+    };
+    recli::lock::write_file(&log_dir, "session_metadata.json", serde_json::to_string_pretty(&meta)?.as_bytes())
+        .map_err(io::Error::from)?;
+
+    println!("session {} ({}@{}) saved to {}", session_id, doc.user, doc.host, log_dir.display());
+    Ok(())
+}
+
+/// What runs in the pty in place of the local shell.
+enum WrappedSession {
+    /// `recli exec`: a shell or command inside a container
+    Container { info: ContainerInfo, user: Option<String>, command: Vec<String> }, // command is never empty
+    /// `recli ssh`: a login on another host, with recli's hook when it was installed there
+    Ssh { destination: String, options: Vec<String>, hooked: bool },
+}
+
+impl WrappedSession {
+    fn argv(&self) -> Vec<String> {
+        match self {
+            WrappedSession::Container { info, user, command } => container::exec_argv(info, user.as_deref(), command),
+            WrappedSession::Ssh { destination, options, hooked } => ssh::argv(destination, options, *hooked),
+        }
+    }
+}
+
+/// The session recording the shell recli runs in, if any. Recording inside it would capture every
+/// byte twice, so that is refused unless `force_nested` is given.
+fn outer_session(force_nested: bool) -> io::Result<Option<String>> {
+    // the variable outlives a recorder that was killed; its pid file doesn't
+    let Some(outer) = env::var("RECLI_SESSION_ID").ok().filter(|_| SessionManager::new().is_session_active()) else {
+        return Ok(None);
+    };
+    if !force_nested {
+        return Err(RecliError::Session(format!(
+            "this shell is recorded by session {} already; a session inside it would record its output twice (--force-nested to record anyway)",
+            outer
+        ))
+        .into());
+    }
+    Ok(Some(outer))
+}
+
+/// Record a full pty shell session until the shell exits or `recli end` is run.
+#[allow(clippy::too_many_arguments)]
+async fn start_pty_session(
+    cli: &Cli,
+    cfg: &Config,
+    resume: Option<&str>,
+    filter_profile: Option<&str>,
+    watch: &[String],
+    tags: &[String],
+    no_pty: bool,
+    wrapped: Option<WrappedSession>,
+    outer: Option<String>,
+) -> io::Result<()> {
+    // a filter that fails to load must stop the session, it may be the one redacting secrets
+    let filters = FilterChain::load(&cfg.filters, filter_profile).map_err(RecliError::Config)?;
+    let (log_dir, verbose, verify_upload) = (cli.log_dir.clone(), cli.verbose, cli.verify_upload);
+    let reload = ConfigReload::new(cli.config.as_deref(), cfg, filter_profile, watch)
+        .with_overrides(move |cfg| apply_cli_overrides(cfg, log_dir.as_deref(), verbose, verify_upload));
+    let mut watch_cfg = cfg.watch.clone();
+    watch_cfg.patterns.extend_from_slice(watch);
+    let watch = OutputWatch::new(&watch_cfg).map_err(RecliError::Config)?;
+    let policy = RecordingPolicy::load(&cfg.policy, &env::current_dir()?).map_err(RecliError::Config)?;
+    let idle_segment = cfg.recording.idle_segment_minutes.map(|m| Duration::from_secs(m * 60));
+    let track_files = cfg.recording.track_files.then_some(TrackLimits {
+        depth: cfg.recording.track_files_depth,
+        max_files: cfg.recording.track_files_limit,
+    });
+    let mut session_manager = SessionManager::new()
+        .with_log_dir(cfg.session_log_dir())
+        .with_idle_segmentation(idle_segment, cfg.recording.idle_new_session)
+        .with_storage_limits(cfg.recording.quota_mb, cfg.recording.min_free_mb)
+        .with_file_tracking(track_files)
+        .with_network_tracking(sample_interval(cfg.recording.track_network, cfg.recording.track_network_interval_ms))
+        .with_process_tracking(sample_interval(cfg.recording.track_processes, cfg.recording.track_processes_interval_ms))
+        .with_hung_detection(&cfg.hung, &cfg.hotkeys)
+        .with_tags(tags);
+    if let Some(events) = open_event_stream(cli, cfg)? {
+        // the pty owns stdout, so json lines would be drawn into the recorded terminal
+        if events.is_stdout() {
+            return Err(RecliError::Validation(
+                "recli start cannot stream events to stdout; use --json-events=fd:N or --json-events=PATH".to_string(),
+            )
+            .into());
+        }
+        session_manager = session_manager.with_event_stream(events);
+    }
+    // inside another session the pid file is one of its own, so both can be told apart
+    if let Some(outer) = &outer {
+        session_manager = session_manager.with_pid_file(session::nested_pid_file(outer));
+    }
+    // a recorder that was killed leaves its pid file and an unfinished session behind
+    if let Some(orphan) = session_manager.cleanup_stale() {
+        println!("closed unfinished session: {}", orphan);
+    }
+    if session_manager.is_session_active() {
+        println!("session already active");
+        return Ok(());
+    }
+
+    // inside a container the shell or program is named by the exec command and has no hook
+    let (shell, (strategy, prompt_patterns)) = match &wrapped {
+        Some(WrappedSession::Container { info, command, .. }) => {
+            session_manager = session_manager.with_container(info.clone());
+            (command[0].clone(), cfg.detection.for_unhooked_shell(&command[0]))
+        }
+        // whatever shell runs on the other end, it is configured as "ssh" under [detection.shells]
+        Some(WrappedSession::Ssh { destination, hooked, .. }) => {
+            session_manager = session_manager.with_remote(destination);
+            let detection = if *hooked {
+                cfg.detection.for_shell("ssh")
+            } else {
+                cfg.detection.for_unhooked_shell("ssh")
+            };
+            ("ssh".to_string(), detection)
+        }
+        None => {
+            let shell = cli.get_shell();
+            let detection = cfg.detection.for_shell(&shell);
+            (shell, detection)
+        }
+    };
+    let detection = Detection::new(strategy, &prompt_patterns).map_err(RecliError::Config)?;
+    let config = match resume {
+        Some(id) => session_manager.resume_session(id, &shell, cli.verbose),
+        None => session_manager.start_session(&shell, cli.verbose),
+    }
+    .map_err(io::Error::from)?;
+    if resume.is_some() {
+        println!("resumed session: {}", config.session_id);
+    } else {
+        println!("session started with id: {}", config.session_id);
+    }
+    println!("logs will be saved to: {}", config.log_dir.display());
+    if let Some(outer) = &outer {
+        println!("nested in session {}, which records this one's output too", outer);
+    }
+    match &wrapped {
+        Some(WrappedSession::Container { info, .. }) => {
+            println!("recording in {} container {} ({})", info.runtime.program(), info.name, info.image)
+        }
+        Some(WrappedSession::Ssh { destination, .. }) => println!("recording ssh session to {}", destination),
+        None => {}
+    }
+
+    // the hotkey works mid-session, the command whenever
+    let note_prompt = cfg.display.note_prompt.then(|| match cfg.hotkeys.note.as_ref().filter(|_| cfg.hotkeys.enabled) {
+        Some(key) => format!("{} adds a note", key),
+        None => "`recli annotate-last` adds a note".to_string(),
+    });
+    let events = session_manager.event_stream();
+    let mut pty = PtySession::new_with_logging(cli.verbose, session_manager).with_hotkeys(cfg.hotkeys.clone())
+        .with_split_stderr(cfg.recording.split_stderr)
+        .with_output_filters(filters)
+        .with_error_highlighting(cfg.display.highlight_errors)
+        .with_command_summary(cfg.display.command_summary)
+        .with_note_prompt(note_prompt)
+        .with_output_watch(watch)
+        .with_recording_policy(policy)
+        .with_input_recording(cfg.recording.input)
+        .with_detection(detection)
+        .with_hook_only(no_pty)
+        .with_config_reload(reload)
+        .with_nested(outer.is_some());
+    if let Some(wrapped) = &wrapped {
+        pty = pty.with_command(wrapped.argv());
+    }
+    let result = pty.run(&shell).await.map_err(io::Error::from);
+
+    upload_finished_session(cfg, &config.log_dir, events.as_deref()).await;
+    result
+}
+
+// sdk retries are off, upserts go through util::retry with the [retry] settings instead
+fn cosmos_client(account: String, auth: AuthorizationToken) -> CosmosClient {
+    CosmosClient::builder(account, auth).retry(azure_core::RetryOptions::none()).build()
+}
+
+// whether a cosmos call may succeed when made again
+fn transient_cosmos(e: &AzureError) -> bool {
+    match e.kind() {
+        AzureErrorKind::HttpResponse { status, .. } => {
+            let code = u16::from(*status);
+            code == 408 || code == 429 || code >= 500
+        }
+        AzureErrorKind::Io => true,
+        _ => false,
+    }
+}
+
+// pty sessions go up as they end so `recli start --resume` has a cloud document whose ended_at it
+// can update: the session id is the document id, so a resumed session refreshes its cloud copy;
+// a session split at idle gaps goes up as one document per part. In low-bandwidth mode
+// the parts wait in the outbox for `recli sync` instead, as do parts whose upload failed
+// after its retries.
+async fn upload_finished_session(cfg: &Config, log_dir: &Path, events: Option<&EventStream>) {
+    let session_id = log_dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let defer = cfg.upload.low_bandwidth && CosmosSetup::from_env() == CosmosSetup::Complete;
+    let mut throttle = Throttle::new(cfg.upload.max_kbps);
+    let mut next = Some(log_dir.to_path_buf());
+    while let Some(dir) = next {
+        if defer {
+            match outbox::add(&dir) {
+                Ok(()) => println!("low-bandwidth mode: {} waits for `recli sync`", dir.display()),
+                Err(e) => eprintln!("! could not queue {} for upload: {}", dir.display(), e),
+            }
+        } else {
+            match upload_pty_session(&dir, cfg, &mut throttle).await {
+                Ok(CosmosUpload::Incomplete(missing)) => {
+                    warn_cosmos_incomplete(events, &session_id, &missing);
+                    break;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    CommandLogger::log_cosmos_error("Cosmos upload failed", &e);
+                    match outbox::add(&dir) {
+                        Ok(()) => eprintln!("! {} not uploaded, `recli sync` tries again", dir.display()),
+                        Err(e) => eprintln!("! could not queue {} for upload: {}", dir.display(), e),
+                    }
+                }
+            }
+        }
+        next = session::read_metadata(&dir)
+            .and_then(|meta| meta.continued_in)
+            .and_then(|next| dir.parent().map(|base| base.join(next)));
+    }
+}
+
+/// Annotate the ci job with diagnostics found in a command's output and leave a summary.
+fn ci_report(provider: ci::Provider, entry: &CommandEntry) {
+    let output = format!("{}{}", entry.output, entry.stderr);
+    let findings = ci::scan(&output);
+    let summary = ci::job_summary(&entry.cmd, entry.exit_code, entry.duration_ms, &findings, &output);
+
+    let written = match provider {
+        ci::Provider::GitHub => {
+            // workflow commands are read from stdout
+            for f in &findings {
+                println!("{}", ci::github_annotation(f));
+            }
+            match env::var("GITHUB_STEP_SUMMARY") {
+                Ok(path) => fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .and_then(|mut f| f.write_all(summary.as_bytes())),
+                Err(_) => Ok(()),
+            }
+        }
+        ci::Provider::GitLab => {
+            let dir = env::var("CI_PROJECT_DIR").map(PathBuf::from).unwrap_or_else(|_| PathBuf::from("."));
+            serde_json::to_vec_pretty(&ci::gitlab_code_quality(&findings))
+                .map_err(io::Error::from)
+                .and_then(|report| fs::write(dir.join("gl-code-quality-report.json"), report))
+                .and_then(|_| fs::write(dir.join("recli-summary.md"), summary.as_bytes()))
+        }
+    };
+    if let Err(e) = written {
+        eprintln!("warning: failed to write ci summary: {}", e);
+    }
+}
+
+// a pause between commands at least this long is shown in the timeline
+const TIMELINE_GAP_MINUTES: i64 = 30;
+
+/// Print every indexed command between `since` and `until` in the order it ran, in local time.
+fn timeline(cfg: &Config, since: &str, until: Option<&str>, by_host: bool, json: bool) -> io::Result<()> {
+    let since = parse_time_bound(since).map_err(RecliError::Validation)?;
+    let until = until.map(parse_time_bound).transpose().map_err(RecliError::Validation)?.unwrap_or_else(Utc::now);
+    if until <= since {
+        return Err(RecliError::Validation("--until must be after --since".to_string()).into());
+    }
+    let mut index = open_index()?;
+    refresh_local_index(&mut index, cfg)?;
+    let commands = index.between(since, until).map_err(io::Error::from)?;
+    if json {
+        for c in &commands {
+            println!("{}", serde_json::to_string(c)?);
+        }
+        return Ok(());
+    }
+    if commands.is_empty() {
+        println!("no commands recorded between {} and {}", local_time(since), local_time(until));
+        return Ok(());
+    }
+    let mut groups: Vec<(Option<&str>, Vec<&IndexedCommand>)> = Vec::new();
+    if by_host {
+        let mut hosts: Vec<&str> = commands.iter().map(|c| c.host.as_str()).collect();
+        hosts.sort();
+        hosts.dedup();
+        for host in hosts {
+            groups.push((Some(host), commands.iter().filter(|c| c.host == host).collect()));
+        }
+    } else {
+        groups.push((None, commands.iter().collect()));
+    }
+    let several_hosts = commands.iter().any(|c| c.host != commands[0].host);
+    let host_width = commands.iter().map(|c| c.host.len()).max().unwrap_or(0);
+    for (i, (host, commands)) in groups.into_iter().enumerate() {
+        if let Some(host) = host {
+            if i > 0 {
+                println!();
+            }
+            println!("== {} ==", host);
+        }
+        let mut day = None;
+        let mut last: Option<chrono::DateTime<chrono::Local>> = None;
+        for c in commands {
+            let Ok(at) = chrono::DateTime::parse_from_rfc3339(&c.timestamp) else { continue };
+            let at = at.with_timezone(&chrono::Local);
+            if day != Some(at.date_naive()) {
+                day = Some(at.date_naive());
+                println!("{}", at.format("%A %Y-%m-%d"));
+            } else if let Some(gap) = last.map(|l| at - l).filter(|g| *g >= chrono::Duration::minutes(TIMELINE_GAP_MINUTES)) {
+                println!("  ... {} idle", format_duration_ms(gap.num_milliseconds() as u64));
+            }
+            last = Some(at);
+            let who = if several_hosts && !by_host { format!("  {:<width$}", c.host, width = host_width) } else { String::new() };
+            println!(
+                "  {}{}  [{}]  {:>7}  {}  ({})",
+                at.format("%H:%M:%S"),
+                who,
+                c.exit_code,
+                format_duration_ms(c.duration_ms),
+                c.cmd,
+                c.cwd
+            );
+        }
+    }
+    Ok(())
+}
+
+fn local_time(t: chrono::DateTime<Utc>) -> String {
+    t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string()
+}
+
+/// Print duration statistics of the indexed runs of commands matching `pattern`.
+fn bench(cfg: &Config, pattern: &str, regex: bool, since: Option<&str>, here: bool, include_failed: bool, json: bool) -> io::Result<()> {
+    let matcher = if regex {
+        Some(regex::Regex::new(pattern).map_err(|e| RecliError::Validation(format!("invalid pattern: {}", e)))?)
+    } else {
+        None
+    };
+    let since = match since {
+        Some(since) => parse_time_bound(since).map_err(RecliError::Validation)?,
+        None => chrono::DateTime::<Utc>::MIN_UTC,
+    };
+    let project = if here { Some(project_key(&env::current_dir()?)) } else { None };
+    let mut index = open_index()?;
+    refresh_local_index(&mut index, cfg)?;
+    let runs: Vec<(chrono::DateTime<Utc>, u64)> = index
+        .between(since, Utc::now())
+        .map_err(io::Error::from)?
+        .into_iter()
+        .filter(|c| match &matcher {
+            Some(re) => re.is_match(&c.cmd),
+            None => c.cmd.contains(pattern),
+        })
+        .filter(|c| include_failed || c.exit_code == 0)
+        // commands recorded without a duration say nothing about speed
+        .filter(|c| c.duration_ms > 0)
+        .filter(|c| {
+            project
+                .as_deref()
+                .is_none_or(|p| c.project.as_deref() == Some(p) || (c.project.is_none() && c.cwd == p))
+        })
+        .filter_map(|c| {
+            let at = chrono::DateTime::parse_from_rfc3339(&c.timestamp).ok()?;
+            Some((at.with_timezone(&Utc), c.duration_ms))
+        })
+        .collect();
+    let Some(stats) = recli::bench::Bench::build(pattern, runs) else {
+        if !json {
+            println!("no recorded runs of {}", pattern);
+        }
+        return Ok(());
+    };
+    if json {
+        println!("{}", serde_json::to_string(&stats)?);
+    } else {
+        print!("{}", stats.render());
+    }
+    Ok(())
+}
+
+/// Print the recorded commands that changed `path`, oldest first.
+fn file_history(cfg: &Config, path: &Path, json: bool) -> io::Result<()> {
+    let cwd = env::current_dir()?;
+    // a deleted file can't be canonicalized, but its directory usually can
+    let path = fs::canonicalize(path).or_else(|_| match (path.parent(), path.file_name()) {
+        (Some(dir), Some(name)) => fs::canonicalize(cwd.join(dir)).map(|d| d.join(name)),
+        _ => Ok(cwd.join(path)),
+    })?;
+    let mut found = 0;
+    for (session_id, entries) in history::load_sessions(&cfg.session_log_dir()) {
+        for e in entries {
+            let Some(change) = e.files.as_ref().and_then(|f| f.change_of(&path)) else { continue };
+            found += 1;
+            if json {
+                println!(
+                    "{}",
+                    json!({ "session_id": session_id, "timestamp": e.timestamp, "cmd": e.cmd, "exit_code": e.exit_code, "change": change })
+                );
+            } else {
+                println!("{}  {:<8}  [{}]  {}  ({})", e.timestamp, change, e.exit_code, e.cmd, session_id);
+            }
+        }
+    }
+    if found == 0 && !json {
+        println!("no recorded command changed {}", path.display());
+        if !cfg.recording.track_files {
+            println!("file changes are only recorded with track_files = true under [recording]");
+        }
+    }
+    Ok(())
+}
+
+/// Print or mail a summary of the sessions recorded since `since`.
+fn session_report(cfg: &Config, since: &str, email: bool) -> io::Result<()> {
+    let since = parse_time_bound(since).map_err(RecliError::Validation)?;
+    let base = cfg.session_log_dir();
+    let report = Report::build(history::load_sessions(&base), since).with_typing(&base);
+    if !email {
+        print!("{}", report.render());
+        return Ok(());
+    }
+    report::send_email(&cfg.report, &report.subject(), &report.render())
+        .map_err(|e| RecliError::Upload { target: "smtp".to_string(), source: e.into() })?;
+    println!("report sent to {}", cfg.report.to.join(", "));
+    Ok(())
+}
+
+// --json-events destination plus any plugin event sinks, if there are any
+fn open_event_stream(cli: &Cli, cfg: &Config) -> io::Result<Option<EventStream>> {
+    let mut stream = cli
+        .json_events
+        .as_deref()
+        .map(|dest| EventStream::open(dest, cfg.workspace.clone()))
+        .transpose()?;
+    if !cfg.plugins.event_sinks.is_empty() {
+        let env = plugins::context_env(cfg, cli.config.as_deref());
+        for sink in plugins::start_sinks(&cfg.plugins, &env) {
+            stream
+                .get_or_insert_with(|| EventStream::new(cfg.workspace.clone()))
+                .add_sink(Box::new(sink));
+        }
+    }
+    Ok(stream.map(|s| s.with_anonymizer(Anonymizer::from_config(&cfg.anonymize))))
+}
+
+/// Print the installed plugins and where they are looked up.
+fn list_plugins(cfg: &Config) {
+    let found = plugins::discover(&cfg.plugins);
+    if found.is_empty() {
+        println!("no plugins in {}", cfg.plugins.plugin_dir().display());
+        return;
+    }
+    for plugin in found {
+        let sink = if cfg.plugins.event_sinks.contains(&plugin.name) { "  (event sink)" } else { "" };
+        println!("{:<20} {}{}", plugin.name, plugin.path.display(), sink);
+    }
+}
+
+/// Upload a finished pty session as one SessionDoc when Cosmos is configured.
+async fn upload_pty_session(log_dir: &Path, cfg: &Config, throttle: &mut Throttle) -> azure_core::error::Result<CosmosUpload> {
+    let client = CommandLogger::init_cosmos_client();
+    let (Some(client), Ok(db), Ok(container)) = (
+        client.as_ref(),
+        env::var("RECLI_AZURE__COSMOS__DB"),
+        env::var("RECLI_AZURE__COSMOS__CONTAINER"),
+    ) else {
+        return Ok(CosmosUpload::skipped(client.is_some()));
+    };
+    let (doc, redacted) = match cosmos_document(log_dir, cfg) {
+        // nothing was recorded there
+        None => return Ok(CosmosUpload::Unchanged),
+        Some(Err(tag)) => {
+            tracing::info!(log_dir = %log_dir.display(), tag, "session tagged for [sinks.cosmos] skip_tags, kept local");
+            return Ok(CosmosUpload::Declined);
+        }
+        Some(Ok(prepared)) => prepared,
+    };
+    if !upload_approved(upload_policy(cfg), &doc, redacted) {
+        return Ok(CosmosUpload::Declined);
+    }
+    let Some(key) = upload_key(&cfg.encryption, &doc.session_id) else {
+        return Ok(CosmosUpload::Declined);
+    };
+    let uploaded =
+        upsert_session_doc(client, &db, &container, doc, verify_upload(cfg), cfg.upload.compress, key.as_ref(), throttle, &cfg.retry)
+            .await?;
+    if uploaded {
+        tracing::info!(log_dir = %log_dir.display(), "session uploaded to cosmos db");
+    }
+    Ok(if uploaded { CosmosUpload::Uploaded } else { CosmosUpload::Unchanged })
+}
+
+// the document the session in `log_dir` goes up to cosmos as, after [sinks.cosmos] and anonymizing
+// and before compression and encryption, with the number of commands filters redacted; none when
+// nothing was recorded there, the tag keeping it local when skip_tags does
+fn cosmos_document(log_dir: &Path, cfg: &Config) -> Option<Result<(SessionDoc, usize), String>> {
+    let meta = session::read_metadata(log_dir)?;
+    let log = recli::command_log::CommandLog::load_from_file(log_dir)
+        .unwrap_or_default();
+    let filter = cosmos_filter(cfg);
+    let tags = meta.tags.iter().chain(log.entries.iter().flat_map(|e| &e.tags));
+    if let Some(tag) = filter.blocked_by(tags) {
+        return Some(Err(tag.to_string()));
+    }
+    let redacted = log.entries.iter().filter(|e| e.tags.iter().any(|t| t == REDACTED_TAG)).count();
+
+    let entries = log
+        .entries
+        .into_iter()
+        .map(|e| CommandEntry {
+            id: e.id,
+            cmd: e.cmd,
+            exit_code: e.exit_code,
+            output: e.output_preview,
+            stderr: String::new(),
+            cwd: e.cwd,
+            timestamp: e.timestamp,
+            seq: e.seq,
+            duration_ms: e.duration_ms.unwrap_or(0),
+            signal: e.signal,
+            network: e.network,
+            processes: e.processes,
+            note: e.note,
+        })
+        .collect();
+    let mut doc = SessionDoc {
+        id: meta.session_id.clone(),
+        session_id: meta.session_id,
+        workspace: None,
+        // sessions recorded before the metadata named them were recorded here
+        host: meta.host.unwrap_or_else(current_host),
+        user: meta.user.unwrap_or_else(current_user),
+        started_at: meta.started_at,
+        ended_at: meta.ended_at.unwrap_or_else(now_stamp),
+        environment: meta.environment,
+        entries,
+        content_hash: String::new(),
+        encryption: None,
+        compression: None,
+        partition: String::new(),
+    };
+    doc.assign_workspace(cfg.workspace.clone(), partition_by_workspace(cfg));
+    doc.restrict(filter.content);
+    if let Some(anonymizer) = Anonymizer::from_config(&cfg.anonymize) {
+        doc.anonymize(&anonymizer);
+    }
+    Some(Ok((doc, redacted)))
+}
+
+/// Ask the running pty session to stop and give it a moment to flush its logs.
+// nth-last command of the active session and the directory it was logged in
+fn last_entry(nth: usize) -> io::Result<Option<(PathBuf, recli::command_log::CommandEntry)>> {
+    let Some(log_dir) = SessionManager::new().active_log_dir() else {
+        println!("no active session");
+        return Ok(None);
+    };
+    let mut log = recli::command_log::CommandLog::load_from_file(&log_dir).map_err(io::Error::from)?;
+    let Some(index) = log.entries.len().checked_sub(nth.max(1)) else {
+        println!("the session has {} recorded commands", log.entries.len());
+        return Ok(None);
+    };
+    Ok(Some((log_dir, log.entries.swap_remove(index))))
+}
+
+// full output of a recorded command; the previews in commands.json are capped
+fn entry_output(log_dir: &Path, entry: &recli::command_log::CommandEntry, stderr: bool) -> Vec<u8> {
+    let (path, preview) = if stderr {
+        (entry.stderr_path.as_ref(), entry.stderr.as_deref().unwrap_or_default())
+    } else {
+        (entry.output_path.as_ref(), entry.output_preview.as_str())
+    };
+    path.and_then(|p| recli::command_log::read_sidecar(&log_dir.join(p)).ok())
+        .unwrap_or_else(|| preview.as_bytes().to_vec())
+}
+
+/// print the captured output of the nth-last command of the active session
+fn show_last_output(nth: usize, stderr: bool, timestamps: bool) -> io::Result<()> {
+    let Some((log_dir, entry)) = last_entry(nth)? else { return Ok(()) };
+    let bytes = entry_output(&log_dir, &entry, stderr);
+    if stderr && bytes.is_empty() {
+        eprintln!("no stderr captured for `{}` (is [recording] split_stderr on?)", entry.cmd);
+        return Ok(());
+    }
+    // on stderr, so the output can still be piped on as it was
+    if let Some(signal) = &entry.signal {
+        eprintln!("`{}` was {}", entry.cmd, signal.describe());
+    }
+    if let Some(network) = entry.network.as_ref().filter(|n| n.endpoint_count > 0) {
+        eprintln!("`{}` talked to {}", entry.cmd, network.describe());
+    }
+    if let Some(processes) = entry.processes.as_ref().filter(|p| p.count > 0) {
+        eprintln!("`{}` ran {}", entry.cmd, processes.describe());
+    }
+    if let Some(note) = &entry.note {
+        eprintln!("note: {}", note);
+    }
+    let mut out = io::stdout().lock();
+    if !timestamps {
+        out.write_all(&bytes)?;
+        return out.flush();
+    }
+    let Some(chunks_path) = &entry.chunks_path else {
+        eprintln!("no output timing recorded for `{}`", entry.cmd);
+        out.write_all(&bytes)?;
+        return out.flush();
+    };
+    use recli::command_log::{load_chunks, timed_lines, PAUSE_MIN_MS};
+    let chunks = load_chunks(&log_dir.join(chunks_path));
+    let mut previous = 0;
+    for (offset_ms, line) in timed_lines(&bytes, &chunks) {
+        let silent_ms = offset_ms.saturating_sub(previous);
+        if silent_ms >= PAUSE_MIN_MS {
+            writeln!(out, "{:>9}  ... {} without output", "", format_duration_ms(silent_ms))?;
+        }
+        previous = offset_ms;
+        write!(out, "{:>9}  ", format!("+{}", format_duration_ms(offset_ms)))?;
+        out.write_all(line)?;
+    }
+    if !bytes.ends_with(b"\n") && !bytes.is_empty() {
+        writeln!(out)?;
+    }
+    // silence after the last output, before the command finished
+    let last_ms = chunks.last().map_or(0, |c| c.offset_ms);
+    if let Some(duration) = entry.duration_ms.filter(|ms| ms.saturating_sub(last_ms) >= PAUSE_MIN_MS) {
+        writeln!(out, "{:>9}  ... {} without output", "", format_duration_ms(duration - last_ms))?;
+    }
+    if let Some(pause) = &entry.longest_pause {
+        writeln!(
+            out,
+            "longest pause: {} after +{} ({} bytes in)",
+            format_duration_ms(pause.ms),
+            format_duration_ms(pause.at_ms),
+            pause.at_byte
+        )?;
+    }
+    out.flush()
+}
+
+/// render a recorded session in `format` to a file or stdout
+fn export_session(cfg: &Config, session: Option<&str>, format: &str, all: bool, output: Option<&Path>) -> io::Result<()> {
+    let base = cfg.session_log_dir();
+    if all {
+        if format != "parquet" {
+            println!("--all only works with --format parquet");
+            return Ok(());
+        }
+        let dirs = history::session_dirs(&base);
+        let data = recli::export::commands_parquet(&dirs).map_err(io::Error::other)?;
+        return write_export(&format!("{} sessions", dirs.len()), &data, output);
+    }
+    let Some(log_dir) = session_dir(&base, session)? else { return Ok(()) };
+
+    let rendered = match format {
+        "html" => recli::export::session_html(&log_dir).map(String::into_bytes),
+        "svg" => recli::export::session_svg(&log_dir).map(String::into_bytes),
+        "gif" => recli::export::session_gif(&log_dir),
+        // ffmpeg writes the file itself
+        "webm" => recli::export::session_webm(&log_dir, output).map(|_| Vec::new()),
+        "parquet" => recli::export::commands_parquet(std::slice::from_ref(&log_dir)),
+        other => Err(format!("unknown export format: {}", other)),
+    }
+    .map_err(io::Error::other)?;
+    if format == "webm" {
+        if let Some(path) = output {
+            println!("exported {} to {}", log_dir.display(), path.display());
+        }
+        return Ok(());
+    }
+    write_export(&log_dir.display().to_string(), &rendered, output)
+}
+
+// the named session's directory, or the most recent one; says so when there is none. A name
+// that isn't a session id is an error
+fn session_dir(base: &Path, session: Option<&str>) -> io::Result<Option<PathBuf>> {
+    let log_dir = match session {
+        Some(id) => {
+            check_session_id(id)?;
+            base.join(id)
+        }
+        None => match history::latest_session(base) {
+            Some(dir) => dir,
+            None => {
+                println!("no recorded sessions in {}", base.display());
+                return Ok(None);
+            }
+        },
+    };
+    if !log_dir.is_dir() {
+        println!("no recorded session at {}", log_dir.display());
+        return Ok(None);
+    }
+    Ok(Some(log_dir))
+}
+
+/// print the log directory, a session's directory in it, or with `latest` the newest session's;
+/// only the path goes to stdout, for scripts
+fn print_session_path(cfg: &Config, session: Option<&str>, latest: bool) -> io::Result<()> {
+    let base = cfg.session_log_dir();
+    let path = match (session, latest) {
+        // the link can be missing or stale, e.g. after sessions were moved in
+        (_, true) => history::linked_latest(&base)
+            .or_else(|| history::latest_session(&base))
+            .ok_or_else(|| RecliError::Session(format!("no recorded sessions in {}", base.display())))?,
+        (Some(id), false) => {
+            check_session_id(id)?;
+            Some(base.join(id))
+                .filter(|dir| dir.is_dir())
+                .ok_or_else(|| RecliError::Session(format!("no recorded session at {}", base.join(id).display())))?
+        }
+        (None, false) => base,
+    };
+    println!("{}", path.display());
+    Ok(())
+}
+
+/// upload a session's html page and asciicast and print where it can be viewed
+async fn share_session(cfg: &Config, session: Option<&str>, target: Option<&str>, expires: Option<&str>) -> io::Result<()> {
+    let Some(log_dir) = session_dir(&cfg.session_log_dir(), session)? else { return Ok(()) };
+    let target = share::pick_target(&cfg.share, target).map_err(RecliError::Config)?;
+    if let Some(expires) = expires {
+        parse_age(expires).map_err(RecliError::Validation)?;
+    }
+    let session_id = log_dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let mut files = share::bundle(&log_dir, &session_id).map_err(io::Error::other)?;
+    // gists hold text, so only blobs are compressed
+    if cfg.upload.compress && target != share::Target::Gist {
+        for file in &mut files {
+            file.compress().map_err(io::Error::other)?;
+        }
+    }
+    // reqwest's blocking client can't run on the runtime's own threads
+    let (share_cfg, retry, expires) = (cfg.share.clone(), cfg.retry.clone(), expires.map(str::to_string));
+    let shared = tokio::task::spawn_blocking(move || share::upload(&share_cfg, target, &files, expires.as_deref(), &retry))
+        .await
+        .map_err(io::Error::other)?
+        .map_err(|e| RecliError::Upload { target: target.name().to_string(), source: e.into() })?;
+    println!("{}", shared.url);
+    if let Some(cast) = &shared.cast_url {
+        println!("asciicast: {}", cast);
+    }
+    if let Some(expires) = shared.expires {
+        println!("expires {}", share::describe_expiry(expires));
+    }
+    Ok(())
+}
+
+fn write_export(what: &str, data: &[u8], output: Option<&Path>) -> io::Result<()> {
+    match output {
+        Some(path) => {
+            fs::write(path, data)?;
+            println!("exported {} to {}", what, path.display());
+        }
+        None => io::stdout().write_all(data)?,
+    }
+    Ok(())
+}
+
+/// a recorded command as `recli open` needs it: its id, its command line, and where its output
+/// is, a sidecar file or inline
+struct RecordedOutput {
+    id: String,
+    cmd: String,
+    sidecar: Option<PathBuf>,
+    inline: Vec<u8>,
+}
+
+// the commands of the session in `dir`, recorded with a pty or by the simple logger
+fn recorded_outputs(dir: &Path) -> Vec<RecordedOutput> {
+    if let Ok(log) = recli::command_log::CommandLog::load_from_file(dir) {
+        return log
+            .entries
+            .into_iter()
+            .map(|e| RecordedOutput {
+                sidecar: e.output_path.map(|p| dir.join(p)),
+                inline: e.output_preview.into_bytes(),
+                id: e.id,
+                cmd: e.cmd,
+            })
+            .collect();
+    }
+    let session_id = dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let Some(log) = recli::lock::read_file(dir, "commands.json").ok().and_then(|text| serde_json::from_str::<CommandLog>(&text).ok())
+    else {
+        return Vec::new();
+    };
+    log.entries
+        .into_iter()
+        .map(|e| RecordedOutput {
+            id: match e.id.is_empty() {
+                true => recli::command_log::legacy_id(&session_id, &e.timestamp, &e.cmd),
+                false => e.id,
+            },
+            cmd: e.cmd,
+            sidecar: None,
+            inline: [e.output, e.stderr].concat().into_bytes(),
+        })
+        .collect()
+}
+
+/// open the output of the command with `id`, or with `last` the last one recorded, in the pager
+/// or with `edit` the editor; the session's directory goes to stdout first
+fn open_command(cfg: &Config, id: Option<&str>, last: bool, edit: bool) -> io::Result<()> {
+    let base = cfg.session_log_dir();
+    let found = match (id, last) {
+        (_, true) => SessionManager::new()
+            .active_log_dir()
+            .or_else(|| history::latest_session(&base))
+            .and_then(|dir| Some((recorded_outputs(&dir).pop()?, dir))),
+        // newest sessions first, where a command is most likely looked for
+        (Some(id), false) => history::session_dirs(&base)
+            .into_iter()
+            .rev()
+            .find_map(|dir| Some((recorded_outputs(&dir).into_iter().find(|c| c.id == id)?, dir))),
+        (None, false) => None,
+    };
+    let Some((command, log_dir)) = found else {
+        let what = id.map_or_else(|| "no recorded command".to_string(), |id| format!("no recorded command with id {}", id));
+        return Err(RecliError::Session(format!("{} in {}", what, base.display())).into());
+    };
+    println!("{}", log_dir.display());
+
+    // the sidecar itself when it is plain text, else the output written out where a pager can read it
+    let plain = command.sidecar.as_ref().filter(|p| {
+        let mut magic = [0u8; 4];
+        let read = fs::File::open(p).and_then(|mut f| io::Read::read(&mut f, &mut magic));
+        read.is_ok_and(|n| n > 0 && !magic.starts_with(recli::command_log::ZSTD_MAGIC))
+    });
+    let (path, temporary) = match plain {
+        Some(path) => (path.clone(), false),
+        None => {
+            let output = command
+                .sidecar
+                .as_ref()
+                .and_then(|p| recli::command_log::read_sidecar(p).ok())
+                .unwrap_or(command.inline);
+            if output.is_empty() {
+                eprintln!("`{}` printed nothing", command.cmd);
+                return Ok(());
+            }
+            (private_temp_file(&format!("recli-{}", command.id), &output)?, true)
+        }
+    };
+    let program = match edit {
+        true => env::var("VISUAL").or_else(|_| env::var("EDITOR")).unwrap_or_else(|_| "vi".to_string()),
+        false => env::var("PAGER").unwrap_or_else(|_| "less -R".to_string()),
+    };
+    // through the shell, as $PAGER and $EDITOR may carry arguments of their own
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", program))
+        .arg("sh")
+        .arg(&path)
+        .status()?;
+    if temporary {
+        let _ = fs::remove_file(&path);
+    }
+    if !status.success() {
+        eprintln!("! {} exited with {}", program, status);
+    }
+    Ok(())
+}
+
+// `data` in a new file in the temp directory that only this user can read; the name is never
+// one that exists already, so nothing planted there in advance is written through
+fn private_temp_file(prefix: &str, data: &[u8]) -> io::Result<PathBuf> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let path = env::temp_dir().join(format!("{}-{}.out", prefix, uuid::Uuid::new_v4().simple()));
+    let written = options.open(&path).and_then(|mut file| file.write_all(data));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&path);
+        return Err(e);
+    }
+    Ok(path)
+}
+
+/// put the nth-last command line, or its output, on the system clipboard
+fn copy_last(nth: usize, output: bool, plain: bool) -> io::Result<()> {
+    let Some((log_dir, entry)) = last_entry(nth)? else { return Ok(()) };
+    let text = if !output {
+        entry.cmd.clone()
+    } else if plain {
+        recli::io::screen_text(&entry_output(&log_dir, &entry, false))
+    } else {
+        String::from_utf8_lossy(&entry_output(&log_dir, &entry, false)).to_string()
+    };
+
+    // arboard hands the text to the clipboard manager when it is dropped, so it outlives us
+    let copied = arboard::Clipboard::new().and_then(|mut c| c.set_text(text.clone()));
+    if let Err(e) = copied {
+        eprintln!("! could not copy to the clipboard: {}", e);
+        return Ok(());
+    }
+    if output {
+        println!("copied output of `{}` ({} bytes)", entry.cmd, text.len());
+    } else {
+        println!("copied: {}", entry.cmd);
+    }
+    Ok(())
+}
+
+/// put a one-line note on the nth-last command; a running session owns its commands.json, so the
+/// note goes through its control socket then
+async fn annotate_last(cfg: &Config, nth: usize, failed: bool, text: Option<String>) -> io::Result<()> {
+    let session_manager = SessionManager::new();
+    let active = session_manager.active_log_dir();
+    let log_dir = match &active {
+        Some(dir) => dir.clone(),
+        // in a recorded shell, its session rather than the latest one
+        None => match session_dir(&cfg.session_log_dir(), env::var("RECLI_SESSION_ID").ok().as_deref())? {
+            Some(dir) => dir,
+            None => return Ok(()),
+        },
+    };
+    let mut log = recli::command_log::CommandLog::load_from_file(&log_dir).map_err(io::Error::from)?;
+    let text = match text {
+        Some(text) => text,
+        None => {
+            let Some(entry) = log.nth_last(nth, failed) else {
+                println!("no such {}command in {}", if failed { "failed " } else { "" }, log_dir.display());
+                return Ok(());
+            };
+            if !io::stdin().is_terminal() {
+                return Err(RecliError::Validation("give the note as an argument, there is no terminal to ask on".to_string()).into());
+            }
+            eprint!("note for `{}` (exit {}): ", entry.cmd, entry.exit_code);
+            io::stderr().flush()?;
+            let mut line = String::new();
+            io::stdin().read_line(&mut line)?;
+            line
+        }
+    };
+
+    let (cmd, noted) = match active {
+        Some(_) => {
+            let request = recli::control::NoteRequest { nth, failed, text: text.clone() };
+            let noted = recli::control::note(&session_manager.control_socket(), &request)
+                .await
+                .map_err(RecliError::Control)?
+                .map_err(RecliError::Validation)?;
+            (noted.cmd, noted.exit_code)
+        }
+        None => {
+            let entry = log.note(nth, failed, &text).map_err(RecliError::Validation)?;
+            let noted = (entry.cmd.clone(), entry.exit_code);
+            log.save_to_file(&log_dir).map_err(io::Error::from)?;
+            noted
+        }
+    };
+    match text.trim().is_empty() {
+        true => println!("removed the note on `{}`", cmd),
+        false => println!("noted `{}` (exit {})", cmd, noted),
+    }
+    Ok(())
+}
+
+// `recli star` itself, which shows up in the history it searches
+fn is_star_command(cmd: &str) -> bool {
+    let mut words = cmd.split_whitespace();
+    let program = words.next().map(|w| w.rsplit('/').next().unwrap_or(w));
+    program == Some("recli") && words.next() == Some("star")
+}
+
+/// add the nth-last command of the active or latest session, or the newest one matching `search`,
+/// to the snippets library
+fn star_command(
+    cfg: &Config,
+    search: Option<&str>,
+    nth: usize,
+    name: Option<String>,
+    description: Option<String>,
+    placeholders: &[String],
+) -> io::Result<()> {
+    let base = cfg.session_log_dir();
+    let found = match search {
+        Some(text) => history::load_sessions(&base).into_iter().rev().find_map(|(id, entries)| {
+            let entry = entries.into_iter().rev().find(|e| e.cmd.contains(text) && !is_star_command(&e.cmd))?;
+            Some((id, entry))
+        }),
+        None => {
+            let dir = SessionManager::new().active_log_dir().or_else(|| history::latest_session(&base));
+            dir.and_then(|dir| {
+                let id = dir.file_name()?.to_string_lossy().to_string();
+                let mut entries = history::load_session(&dir)?;
+                let index = entries.len().checked_sub(nth.max(1))?;
+                Some((id, entries.swap_remove(index)))
+            })
+        }
+    };
+    let Some((session_id, entry)) = found else {
+        match search {
+            Some(text) => println!("no recorded command contains {:?}", text),
+            None => println!("no such recorded command"),
+        }
+        return Ok(());
+    };
+
+    let mut cmd = entry.cmd.trim().to_string();
+    for spec in placeholders {
+        // the name comes last, so the text may hold '=' itself, e.g. --env=staging=env
+        let Some((text, placeholder)) = spec.rsplit_once('=').filter(|(t, _)| !t.is_empty()) else {
+            return Err(RecliError::Validation(format!("expected TEXT=NAME, got {:?}", spec)).into());
+        };
+        if !snippets::valid_name(placeholder) {
+            return Err(RecliError::Validation(format!("placeholder names are letters, digits, - and _, got {:?}", placeholder)).into());
+        }
+        if !cmd.contains(text) {
+            return Err(RecliError::Validation(format!("{:?} is not in `{}`", text, cmd)).into());
+        }
+        cmd = snippets::parametrize(&cmd, text, placeholder);
+    }
+
+    let path = snippets::path();
+    let mut library = Snippets::load(&path)?;
+    if name.is_none() {
+        if let Some(existing) = library.find_cmd(&cmd).map(|s| s.name.clone()) {
+            if let (Some(description), Some(snippet)) = (description, library.get_mut(&existing)) {
+                snippet.description = Some(description);
+                library.save(&path)?;
+            }
+            println!("already starred as {}", existing);
+            return Ok(());
+        }
+    }
+    let name = match name {
+        Some(name) if !snippets::valid_name(&name) => {
+            return Err(RecliError::Validation(format!("snippet names are letters, digits, - and _, got {:?}", name)).into());
+        }
+        Some(name) => name,
+        None => library.name_for(&cmd),
+    };
+    let replaced = library.remove(&name);
+    library.snippets.push(Snippet {
+        name: name.clone(),
+        cmd: cmd.clone(),
+        description,
+        cwd: (!entry.cwd.is_empty()).then_some(entry.cwd),
+        session_id: Some(session_id),
+        starred_at: Utc::now(),
+    });
+    library.save(&path)?;
+    match replaced {
+        true => println!("replaced snippet {}: {}", name, cmd),
+        false => println!("starred as {}: {}", name, cmd),
+    }
+    Ok(())
+}
+
+fn list_snippets(json: bool) -> io::Result<()> {
+    let library = Snippets::load(&snippets::path())?;
+    if library.snippets.is_empty() && !json {
+        println!("no snippets yet; `recli star` adds the last command");
+    }
+    let width = library.snippets.iter().map(|s| s.name.chars().count()).max().unwrap_or(0);
+    for snippet in &library.snippets {
+        if json {
+            println!("{}", serde_json::to_string(snippet)?);
+            continue;
+        }
+        println!("{:<width$}  {}", snippet.name, snippet.cmd, width = width);
+        if let Some(description) = &snippet.description {
+            println!("{:<width$}  # {}", "", description, width = width);
+        }
+    }
+    Ok(())
+}
+
+/// run a snippet through $SHELL with its placeholders filled in, returning its exit code; on a
+/// terminal every placeholder not given is asked for, otherwise defaults are used as they are
+fn run_snippet(name: &str, values: &[String], print: bool) -> io::Result<i32> {
+    let library = Snippets::load(&snippets::path())?;
+    let Some(snippet) = library.get(name) else {
+        return Err(RecliError::Validation(format!("no snippet called {} (see recli snippets list)", name)).into());
+    };
+    let placeholders = snippets::placeholders(&snippet.cmd);
+    let mut given: HashMap<String, String> = HashMap::new();
+    for value in values {
+        let Some((key, value)) = value.split_once('=') else {
+            return Err(RecliError::Validation(format!("expected NAME=VALUE, got {:?}", value)).into());
+        };
+        if !placeholders.iter().any(|p| p.name == key) {
+            return Err(RecliError::Validation(format!("{} has no placeholder called {}", name, key)).into());
+        }
+        given.insert(key.to_string(), value.to_string());
+    }
+    if io::stdin().is_terminal() {
+        for placeholder in &placeholders {
+            if given.contains_key(&placeholder.name) {
+                continue;
+            }
+            match &placeholder.default {
+                Some(default) => eprint!("{} [{}]: ", placeholder.name, default),
+                None => eprint!("{}: ", placeholder.name),
+            }
+            io::stderr().flush()?;
+            let mut line = String::new();
+            io::stdin().read_line(&mut line)?;
+            let line = line.trim_end_matches(['\r', '\n']);
+            // enter keeps the default
+            if !line.is_empty() || placeholder.default.is_none() {
+                given.insert(placeholder.name.clone(), line.to_string());
+            }
+        }
+    }
+    let cmd = snippets::fill(&snippet.cmd, &given)
+        .map_err(|missing| RecliError::Validation(format!("{} needs a value for {}", name, missing.join(", "))))?;
+    if print {
+        println!("{}", cmd);
+        return Ok(0);
+    }
+
+    eprintln!("+ {}", cmd);
+    let shell = env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
+    let status = Command::new(&shell).arg("-c").arg(&cmd).status()?;
+    use std::os::unix::process::ExitStatusExt;
+    Ok(status.code().unwrap_or_else(|| 128 + status.signal().unwrap_or(0)))
+}
+
+fn end_pty_session() {
+    let session_manager = SessionManager::new();
+    if !session_manager.is_session_active() {
+        println!("no active session");
+        return;
+    }
+
+    let Some(pid) = session_manager.active_pid() else {
+        println!("no active session pid found");
+        return;
+    };
+
+    #[cfg(unix)]
+    unsafe {
+        // send sigterm to allow graceful shutdown
+        let _ = libc::kill(pid as i32, libc::SIGTERM);
+    }
+
+    // wait briefly for the process to shut down and flush logs
+    let start = Instant::now();
+    while session_manager.process_exists_public(pid) && start.elapsed() < Duration::from_secs(5) {
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    println!("session end signal sent to pid {}", pid);
+}
+
+/// Pipe the output of tmux panes into `recli tmux record`, one session per pane.
+fn tmux_attach(cli: &Cli, target: Option<&str>, all: bool) -> io::Result<()> {
+    let panes = tmux::panes(target, all).map_err(RecliError::Validation)?;
+    let exe = env::current_exe()?;
+    // the recorder is started by the tmux server, so it gets the settings this run was given
+    let mut globals = String::new();
+    if let Some(config) = &cli.config {
+        globals.push_str(&format!(" --config {}", shell_quote(config)));
+    }
+    if let Some(log_dir) = &cli.log_dir {
+        globals.push_str(&format!(" --log-dir {}", shell_quote(log_dir)));
+    }
+    for pane in panes {
+        if pane.piped {
+            let ours = SessionManager::new().with_pid_file(tmux::pid_file(&pane.id)).is_session_active();
+            if ours {
+                println!("{}: already recorded", pane.id);
+            } else {
+                println!("{}: output is already piped elsewhere, skipped", pane.id);
+            }
+            continue;
+        }
+        // without a hook reporting directories, commands are recorded where the pane was at attach
+        let command = format!(
+            "cd {} && exec {}{} tmux record --pane {} --shell {} --cols {} --rows {}",
+            shell_quote(&pane.cwd),
+            shell_quote(&exe.to_string_lossy()),
+            globals,
+            shell_quote(&pane.id),
+            shell_quote(&pane.command),
+            pane.cols,
+            pane.rows
+        );
+        tmux::pipe_pane(&pane.id, Some(&command)).map_err(RecliError::Shell)?;
+        println!("{}: recording ({})", pane.id, pane.command);
+    }
+    Ok(())
+}
+
+/// Close the pipe of recorded tmux panes, which ends their sessions.
+fn tmux_detach(target: Option<&str>, all: bool) -> io::Result<()> {
+    for pane in tmux::panes(target, all).map_err(RecliError::Validation)? {
+        if !SessionManager::new().with_pid_file(tmux::pid_file(&pane.id)).is_session_active() {
+            if !all {
+                println!("{}: not recorded", pane.id);
+            }
+            continue;
+        }
+        tmux::pipe_pane(&pane.id, None).map_err(RecliError::Shell)?;
+        println!("{}: stopped", pane.id);
+    }
+    Ok(())
+}
+
+/// Record one tmux pane from its pipe and upload the session like `recli start` does.
+async fn tmux_record(cfg: &Config, pane: &str, shell: &str, cols: u16, rows: u16) -> io::Result<()> {
+    let log_dir = tmux::record(cfg, pane, shell, cols, rows).await.map_err(io::Error::from)?;
+    upload_finished_session(cfg, &log_dir, None).await;
+    Ok(())
+}
+
+// quote `s` for sh, which tmux runs pipe-pane commands with
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Draw the active session live from its control socket.
+async fn show_dashboard(cfg: &Config) -> io::Result<()> {
+    let session_manager = SessionManager::new();
+    if !session_manager.is_session_active() {
+        println!("no active session");
+        return Ok(());
+    }
+    let upload = match CosmosSetup::from_env() {
+        CosmosSetup::Unconfigured => vec!["cosmos db: not configured, the session stays local".to_string()],
+        CosmosSetup::Incomplete(missing) => vec![format!("cosmos db: not uploading, missing {}", missing.join(", "))],
+        CosmosSetup::Complete => {
+            let when = match upload_policy(cfg) {
+                UploadPolicy::Always => "uploaded when the session ends",
+                UploadPolicy::Ask => "uploaded when the session ends, after asking",
+                UploadPolicy::Never => "not uploaded (upload_policy = never)",
+            };
+            vec![format!("cosmos db: {}", when)]
+        }
+    };
+    dashboard::run(&session_manager.control_socket(), upload).await.map_err(io::Error::from)
+}
+
+/// Set or show the diagnostics filter of the active session through its control socket.
+async fn session_log_level(cfg: &Config, filter: Option<&str>) -> io::Result<()> {
+    let session_manager = SessionManager::new();
+    if !session_manager.is_session_active() {
+        return Err(RecliError::Session("no active session".to_string()).into());
+    }
+    let filter = recli::control::log_level(&session_manager.control_socket(), filter)
+        .await
+        .map_err(RecliError::Control)?
+        .map_err(RecliError::Validation)?;
+    println!("session diagnostics at {}, written to {}", filter, telemetry::log_dir(&cfg.logging).display());
+    Ok(())
+}
+
+/// Remove what recli left on this machine, as far as `scope` goes, after listing it; recorded data
+/// is only removed once confirmed or with `yes`.
+fn uninstall_recli(cfg: &Config, scope: uninstall::Scope, dry_run: bool, yes: bool) -> io::Result<()> {
+    let running = uninstall::running_recorders();
+    if !running.is_empty() {
+        let which: Vec<String> = running.iter().map(|p| p.display().to_string()).collect();
+        return Err(RecliError::Session(format!(
+            "recli is still recording ({}); end it with `recli end` or `recli tmux detach --all` first",
+            which.join(", ")
+        ))
+        .into());
+    }
+    let size = |bytes: u64| match bytes {
+        b if b >= 1 << 20 => format!("{:.1} MB", b as f64 / (1u64 << 20) as f64),
+        b if b >= 1 << 10 => format!("{:.1} KB", b as f64 / (1u64 << 10) as f64),
+        b => format!("{} B", b),
+    };
+    let items = uninstall::plan(cfg, scope);
+    let sessions = history::session_dirs(&cfg.session_log_dir()).len();
+    if items.is_empty() {
+        println!("nothing to remove");
+    }
+    for item in &items {
+        println!("{:>9}  {}  ({})", size(item.bytes), item.path.display(), item.what);
+    }
+
+    if !dry_run && !items.is_empty() {
+        if scope >= uninstall::Scope::Data && !yes {
+            if !io::stdin().is_terminal() {
+                return Err(RecliError::Validation("pass --yes to remove recorded data, there is no terminal to ask on".to_string()).into());
+            }
+            eprint!("remove all of the above, {} recorded sessions included? this can't be undone [y/N] ", sessions);
+            io::stderr().flush()?;
+            let mut answer = String::new();
+            io::stdin().read_line(&mut answer)?;
+            if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+                println!("nothing removed");
+                return Ok(());
+            }
+        }
+        let failed = uninstall::remove(&items);
+        for (path, e) in &failed {
+            eprintln!("! {}: {}", path.display(), e);
+        }
+        if !failed.is_empty() {
+            return Err(io::Error::other(format!("{} of {} paths could not be removed", failed.len(), items.len())));
+        }
+        match items.len() {
+            1 => println!("removed 1 path"),
+            n => println!("removed {} paths", n),
+        }
+    } else if dry_run && !items.is_empty() {
+        println!("dry run, nothing removed");
+    }
+
+    if scope == uninstall::Scope::Hooks && sessions > 0 {
+        println!("{} recorded sessions stay in {}; `recli uninstall --data` removes them too", sessions, cfg.session_log_dir().display());
+    }
+    if scope >= uninstall::Scope::Data {
+        println!("copies uploaded to cosmos db, the team server or share targets are not touched");
+    }
+    println!("hosts prepared with `recli ssh --install-hook` keep the hook in their ~/.recli");
+    if let Ok(exe) = env::current_exe() {
+        println!("the recli binary itself is {}", exe.display());
+    }
+    Ok(())
+}
+
+/// Print the tail of recli's own diagnostics log.
+fn show_internal_logs(cfg: &Config, count: usize, path_only: bool) -> io::Result<()> {
+    let dir = telemetry::log_dir(&cfg.logging);
+    if path_only {
+        println!("{}", dir.display());
+        return Ok(());
+    }
+
+    // daily files are named recli.YYYY-MM-DD.log so name order is date order
+    let mut files: Vec<PathBuf> = fs::read_dir(&dir)
+        .map(|rd| {
+            rd.filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| {
+                    let name = p.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                    name.starts_with("recli.") && name.ends_with(".log")
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+
+    let Some(latest) = files.last() else {
+        println!("no internal logs in {}", dir.display());
+        return Ok(());
+    };
+    let text = fs::read_to_string(latest)?;
+    let lines: Vec<&str> = text.lines().collect();
+    for line in &lines[lines.len().saturating_sub(count)..] {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let result = run().await;
+    telemetry::shutdown();
+    datadog::shutdown();
+    sentry::shutdown();
+    hooks::shutdown();
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("recli: {}", e);
+            // causes the message doesn't already spell out, e.g. the tls or dns error under a failed upload
+            let mut shown = e.to_string();
+            let mut source = e.source();
+            while let Some(cause) = source {
+                let text = cause.to_string();
+                if !shown.contains(&text) {
+                    eprintln!("  caused by: {}", text);
+                    shown = text;
+                }
+                source = cause.source();
+            }
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+// see RecliError::exit_code; errors recli didn't raise itself exit with 1
+fn exit_code(e: &io::Error) -> u8 {
+    e.get_ref()
+        .and_then(|inner| inner.downcast_ref::<RecliError>())
+        .map(RecliError::exit_code)
+        .unwrap_or(1)
+}
+
+/// Options given on the command line over what the config file says.
+fn apply_cli_overrides(cfg: &mut Config, log_dir: Option<&str>, verbose: bool, verify_upload: bool) {
+    if let Some(dir) = log_dir {
+        cfg.log_dir = Some(dir.to_string());
+    }
+    // -v turns on debug diagnostics unless [logging] already asks for more
+    if verbose && !matches!(cfg.logging.level.to_lowercase().as_str(), "debug" | "trace") {
+        cfg.logging.level = "debug".to_string();
+    }
+    if verify_upload {
+        cfg.azure.get_or_insert_default().cosmos.get_or_insert_default().verify_upload = true;
+    }
+}
+
+async fn run() -> io::Result<()> {
+    let cli = Cli::parse_args();
+
+    // config and telemetry come first so every command can be exported
+    dotenv::dotenv().ok();
+    // before loading, so strict mode can't keep the check itself from running
+    if let Some(RecliCommands::Config { command: ConfigCommands::Validate { json } }) = &cli.command {
+        return validate_config(cli.config.as_deref(), *json);
+    }
+    // the session reads the file, a problem with it is that session's to report
+    if let Some(RecliCommands::Config { command: ConfigCommands::Reload }) = &cli.command {
+        return reload_session_config().await;
+    }
+    let mut cfg = Config::load(cli.config.as_deref())?;
+    apply_cli_overrides(&mut cfg, cli.log_dir.as_deref(), cli.verbose, cli.verify_upload);
+    telemetry::init(&cfg.logging, &cfg.telemetry);
+    datadog::init(&cfg.datadog, cfg.workspace.as_deref(), &cfg.sinks.datadog, Anonymizer::from_config(&cfg.anonymize));
+    sentry::init(&cfg.sentry, cfg.workspace.as_deref(), &cfg.sinks.sentry, Anonymizer::from_config(&cfg.anonymize));
+    hooks::init(&cfg.hooks, &cfg.retry, cfg.workspace.as_deref());
+
+    match cli.command {
+        None => {
+            // interactive mode
+            let mut logger = CommandLogger::new(&cfg).await?.with_events(open_event_stream(&cli, &cfg)?);
+            logger.interactive_shell().await?;
+        }
+        Some(RecliCommands::Start { ref resume, ref filter_profile, ref watch, no_pty, ref tag, force_nested }) => {
+            let outer = outer_session(force_nested)?;
+            start_pty_session(&cli, &cfg, resume.as_deref(), filter_profile.as_deref(), watch, tag, no_pty, None, outer).await?;
+        }
+        Some(RecliCommands::ContainerExec { ref docker, ref podman, ref user, ref command, force_nested }) => {
+            let outer = outer_session(force_nested)?;
+            let (runtime, name) = match (docker, podman) {
+                (Some(name), _) => (Runtime::Docker, name),
+                (None, Some(name)) => (Runtime::Podman, name),
+                (None, None) => unreachable!("clap requires --docker or --podman"),
+            };
+            let info = container::inspect(runtime, name).map_err(RecliError::Validation)?;
+            // no command is the shell mode
+            let command = if command.is_empty() {
+                vec![cli.shell.clone().unwrap_or_else(|| "sh".to_string())]
+            } else {
+                command.clone()
+            };
+            let wrapped = WrappedSession::Container { info, user: user.clone(), command };
+            start_pty_session(&cli, &cfg, None, None, &[], &[], false, Some(wrapped), outer).await?;
+        }
+        Some(RecliCommands::Ssh { ref destination, install_hook, ref options, force_nested }) => {
+            let outer = outer_session(force_nested)?;
+            if install_hook {
+                ssh::install_hook(destination, options).map_err(RecliError::Shell)?;
+            }
+            let wrapped = WrappedSession::Ssh { destination: destination.clone(), options: options.clone(), hooked: install_hook };
+            start_pty_session(&cli, &cfg, None, None, &[], &[], false, Some(wrapped), outer).await?;
+        }
+        Some(RecliCommands::Path { ref session, latest }) => {
+            print_session_path(&cfg, session.as_deref(), latest)?;
+        }
+        Some(RecliCommands::Dashboard) => {
+            show_dashboard(&cfg).await?;
+        }
+        Some(RecliCommands::End) => {
+            end_pty_session();
+        }
+        Some(RecliCommands::Status) => {
+            println!("{}", SessionManager::new().get_status());
+        }
+        Some(RecliCommands::Last { nth, stderr, timestamps }) => {
+            show_last_output(nth, stderr, timestamps)?;
+        }
+        Some(RecliCommands::Open { ref id, last, edit }) => {
+            open_command(&cfg, id.as_deref(), last, edit)?;
+        }
+        Some(RecliCommands::Copy { output, nth, plain }) => {
+            copy_last(nth, output, plain)?;
+        }
+        Some(RecliCommands::AnnotateLast { text, nth, failed }) => {
+            annotate_last(&cfg, nth, failed, text).await?;
+        }
+        Some(RecliCommands::Star { ref search, nth, ref name, ref description, ref placeholder }) => {
+            star_command(&cfg, search.as_deref(), nth, name.clone(), description.clone(), placeholder)?;
+        }
+        Some(RecliCommands::Snippets { command: SnippetsCommands::List { json } }) => {
+            list_snippets(json)?;
+        }
+        Some(RecliCommands::Snippets { command: SnippetsCommands::Run { ref name, ref values, print } }) => {
+            let code = run_snippet(name, values, print)?;
+            if code != 0 {
+                telemetry::shutdown();
+                datadog::shutdown();
+                sentry::shutdown();
+                hooks::shutdown();
+                std::process::exit(code);
+            }
+        }
+        Some(RecliCommands::Snippets { command: SnippetsCommands::Remove { ref name } }) => {
+            let path = snippets::path();
+            let mut library = Snippets::load(&path)?;
+            if !library.remove(name) {
+                return Err(RecliError::Validation(format!("no snippet called {}", name)).into());
+            }
+            library.save(&path)?;
+            println!("removed snippet {}", name);
+        }
+        Some(RecliCommands::Doctor) => {
+            doctor(&cfg).await?;
+        }
+        Some(RecliCommands::History { count, here, json }) => {
+            let project = if here { Some(project_key(&env::current_dir()?)) } else { None };
+            search_index(&cfg, None, project.as_deref(), count, json)?;
+        }
+        Some(RecliCommands::Sessions { ref project, limit, json, local }) => {
+            list_sessions(&cfg, project.as_deref(), limit, json, local)?;
+        }
+        Some(RecliCommands::Search { ref text, limit, json }) => {
+            search_index(&cfg, Some(text), None, limit, json)?;
+        }
+        Some(RecliCommands::GrepOutput { ref pattern, ignore_case, fixed_strings, context, ref session, here, limit, json }) => {
+            if !grep_output(&cfg, pattern, ignore_case, fixed_strings, context, session.as_deref(), here, limit, json)? {
+                // nothing matched, as grep says it
+                std::process::exit(1);
+            }
+        }
+        Some(RecliCommands::Query { ref sql, json }) => {
+            query_index(&cfg, sql, json)?;
+        }
+        Some(RecliCommands::Report { ref since, email }) => {
+            session_report(&cfg, since, email)?;
+        }
+        Some(RecliCommands::Timeline { ref since, ref until, by_host, json }) => {
+            timeline(&cfg, since, until.as_deref(), by_host, json)?;
+        }
+        Some(RecliCommands::Bench { ref pattern, regex, ref since, here, include_failed, json }) => {
+            bench(&cfg, pattern, regex, since.as_deref(), here, include_failed, json)?;
+        }
+        Some(RecliCommands::Changed { ref path, json }) => {
+            file_history(&cfg, path, json)?;
+        }
+        Some(RecliCommands::Scrub { ref session, ref pattern, ref replace, dry_run, upload }) => {
+            scrub_session(&cfg, session, pattern, replace, dry_run, upload).await?;
+        }
+        Some(RecliCommands::Sync { dry_run: true, show, ref session, .. }) => {
+            sync_dry_run(&cfg, show, session.as_deref())?;
+        }
+        Some(RecliCommands::Sync { pull, .. }) => {
+            sync_index(&cfg, pull).await?;
+        }
+        Some(RecliCommands::Cloud { command: CloudCommands::Search(args) }) => {
+            cloud_search(&cfg, &args).await?;
+        }
+        Some(RecliCommands::Cloud { command: CloudCommands::Pull { session_id, force } }) => {
+            cloud_pull(&cfg, &session_id, force).await?;
+        }
+        Some(RecliCommands::Cloud { command: CloudCommands::Decrypt { ref session_id, ref output } }) => {
+            cloud_decrypt(&cfg, session_id, output.as_deref()).await?;
+        }
+        Some(RecliCommands::Cloud { command: CloudCommands::PrunePings { dry_run } }) => {
+            cloud_prune_pings(&cfg, dry_run).await?;
+        }
+        Some(RecliCommands::Tmux { command: TmuxCommands::Attach { ref target, all } }) => {
+            tmux_attach(&cli, target.as_deref(), all)?;
+        }
+        Some(RecliCommands::Tmux { command: TmuxCommands::Detach { ref target, all } }) => {
+            tmux_detach(target.as_deref(), all)?;
+        }
+        Some(RecliCommands::Tmux { command: TmuxCommands::Record { ref pane, ref shell, cols, rows } }) => {
+            tmux_record(&cfg, pane, shell, cols, rows).await?;
+        }
+        Some(RecliCommands::LogLevel { ref filter }) => {
+            session_log_level(&cfg, filter.as_deref()).await?;
+        }
+        Some(RecliCommands::Logs { count, path }) => {
+            show_internal_logs(&cfg, count, path)?;
+        }
+        Some(RecliCommands::Uninstall { data, all, dry_run, yes }) => {
+            let scope = match (data, all) {
+                (_, true) => uninstall::Scope::All,
+                (true, _) => uninstall::Scope::Data,
+                _ => uninstall::Scope::Hooks,
+            };
+            uninstall_recli(&cfg, scope, dry_run, yes)?;
+        }
+        Some(RecliCommands::Completions { shell }) => {
+            Cli::print_completions(shell);
+        }
+        Some(RecliCommands::CompleteLine { prefix, cwd, count, init }) => {
+            if let Some(shell) = init {
+                print!("{}", history::shell_snippet(&shell).unwrap_or_default());
+            } else {
+                let cwd = cwd.unwrap_or_else(|| {
+                    env::current_dir().map(|p| p.to_string_lossy().to_string()).unwrap_or_default()
+                });
+                let entries = history::load_all(&cfg.session_log_dir());
+                for line in history::complete(&entries, &prefix, &cwd, count) {
+                    println!("{}", line);
+                }
+            }
+        }
+        Some(RecliCommands::Pick { ref query, here, ref init }) => {
+            if let Some(shell) = init {
+                print!("{}", pick::shell_snippet(shell).unwrap_or_default());
+            } else if !pick_command(&cfg, query, here)? {
+                // nothing picked, as fzf says it
+                std::process::exit(130);
+            }
+        }
+        Some(RecliCommands::Replay { ref session, speed, idle_limit, raw }) => {
+            replay_session(&cfg, session.as_deref(), speed, idle_limit, raw)?;
+        }
+        Some(RecliCommands::Show { ref session, screen_at, local }) => {
+            show_session(&cfg, session.as_deref(), screen_at, local)?;
+        }
+        Some(RecliCommands::Suggest { ref prefix, after, cwd, count, json, ref init }) => {
+            match init {
+                Some(shell) => print!("{}", suggest::shell_snippet(shell).unwrap_or_default()),
+                None => suggest_next(&cfg, prefix, after, cwd, count, json)?,
+            }
+        }
+        Some(RecliCommands::Export { ref session, ref format, all, ref output }) => {
+            export_session(&cfg, session.as_deref(), format, all, output.as_deref())?;
+        }
+        Some(RecliCommands::Share { ref session, ref target, ref expires }) => {
+            share_session(&cfg, session.as_deref(), target.as_deref(), expires.as_deref()).await?;
+        }
+        // checked before the config was loaded
+        Some(RecliCommands::Config { .. }) => {}
+        Some(RecliCommands::Sinks { command: SinksCommands::Test { ref sink } }) => {
+            sinks_test(&cfg, sink.as_deref()).await?;
+        }
+        Some(RecliCommands::Plugins) => {
+            list_plugins(&cfg);
+        }
+        Some(RecliCommands::Exec(ref args)) => {
+            if let Some(plugin) = args.first().and_then(|name| plugins::find(&cfg.plugins, name)) {
+                let env = plugins::context_env(&cfg, cli.config.as_deref());
+                let code = plugins::run(&plugin, &args[1..], &env)
+                    .map_err(|e| io::Error::new(e.kind(), format!("failed to run {}: {}", plugin.path.display(), e)))?;
+                telemetry::shutdown();
+                datadog::shutdown();
+                sentry::shutdown();
+                hooks::shutdown();
+                std::process::exit(code);
+            }
+            // run as single command
+            let mut logger = CommandLogger::new(&cfg).await?.with_events(open_event_stream(&cli, &cfg)?);
+            let cmd = args.join(" ");
+            let exit_code = logger.run_command(&cmd).await;
+            if let (Some(provider), Some(entry)) = (ci::detect(), logger.entries.last()) {
+                ci_report(provider, entry);
+            }
+            logger.save_async().await?;
+            // exit skips destructors, and the event sinks still have to be handed what is queued
+            drop(logger);
+            telemetry::shutdown();
+            datadog::shutdown();
+            sentry::shutdown();
+            hooks::shutdown();
+            std::process::exit(exit_code);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use recli::config::EncryptionConfig;
+    use recli::util::encryption::ENCRYPTED_PREFIX;
+
+    fn user_key(fill: char) -> UserKey {
+        let cfg = EncryptionConfig {
+            enabled: true,
+            key_file: None,
+            key_command: Some(format!("echo {}", fill.to_string().repeat(43) + "=")),
+        };
+        UserKey::load(&cfg, false).unwrap().unwrap()
+    }
+
+    fn session_doc() -> SessionDoc {
+        serde_json::from_value(json!({
+            "id": "s1",
+            "session_id": "s1",
+            "host": "h",
+            "user": "u",
+            "started_at": "2026-10-15T09:00:00.000000Z",
+            "ended_at": "2026-10-15T09:05:00.000000Z",
+            "entries": [{
+                "cmd": "deploy --prod",
+                "exit_code": 1,
+                "output": "out",
+                "stderr": "err",
+                "cwd": "/srv",
+                "timestamp": "2026-10-15T09:01:00.000000Z",
+                "duration_ms": 10,
+                "note": "rolled back, the db password was wrong",
+            }],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn encryption_round_trips_notes_with_the_other_fields() {
+        let key = user_key('A');
+        let mut doc = session_doc();
+        let plain = doc.entries[0].clone();
+
+        doc.encrypt(&key).unwrap();
+        let sealed = &doc.entries[0];
+        for field in [&sealed.cmd, &sealed.output, &sealed.stderr, sealed.note.as_ref().unwrap()] {
+            assert!(field.starts_with(ENCRYPTED_PREFIX), "{:?} left in the clear", field);
+        }
+
+        doc.decrypt(Some(&key)).unwrap();
+        let opened = &doc.entries[0];
+        assert_eq!((&opened.cmd, &opened.output, &opened.stderr), (&plain.cmd, &plain.output, &plain.stderr));
+        assert_eq!(opened.note, plain.note);
+    }
+    #[test]
+    fn content_hash_changes_with_how_the_copy_is_stored() {
+        let doc = session_doc();
+        let (a, b) = (user_key('A'), user_key('Q'));
+        let plain = doc.compute_hash(false, None);
+        let hashes = [
+            doc.compute_hash(true, None),
+            doc.compute_hash(false, Some(&a)),
+            doc.compute_hash(true, Some(&a)),
+            doc.compute_hash(false, Some(&b)),
+        ];
+        assert!(hashes.iter().all(|h| *h != plain));
+        assert_ne!(hashes[1], hashes[3], "a new key uploads again");
+        assert_eq!(doc.compute_hash(false, Some(&a)), hashes[1]);
+    }
+}
@@ -0,0 +1,186 @@
+use crate::util::procfs::{self, ForegroundGroups, ProcStat};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+// endpoints kept per command; the count still covers all of them
+const MAX_LISTED: usize = 100;
+// socket tables of the network namespace, with the protocol each one is
+const SOCKET_TABLES: &[(&str, &str)] = &[
+    ("/proc/net/tcp", "tcp"),
+    ("/proc/net/tcp6", "tcp"),
+    ("/proc/net/udp", "udp"),
+    ("/proc/net/udp6", "udp"),
+];
+
+/// the network use of one command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkSummary {
+    // every interface but loopback, so other traffic of the machine meanwhile counts too
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub endpoint_count: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub endpoints: Vec<Endpoint>,
+}
+
+/// a remote address one of the command's processes had a socket to
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Endpoint {
+    pub protocol: String, // tcp or udp
+    pub address: String,  // e.g. 140.82.112.3:443
+    pub process: String,  // the name of the process holding the socket
+}
+
+/// samples the sockets of the command running below the shell `root` until finished; a connection
+/// opened and closed between two samples is missed
+pub struct NetworkWatch {
+    stop: Arc<AtomicBool>,
+    sampler: Option<JoinHandle<BTreeSet<Endpoint>>>,
+    counters: Option<(u64, u64)>,
+}
+
+impl NetworkWatch {
+    /// start watching the descendants of `root` (not `root` itself), every `interval`
+    pub fn start(root: u32, interval: Duration) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let sampler = std::thread::Builder::new()
+            .name("recli-network".to_string())
+            .spawn(move || {
+                let mut seen = BTreeSet::new();
+                let mut groups = ForegroundGroups::default();
+                loop {
+                    sample(root, &mut groups, &mut seen);
+                    if stopped.load(Ordering::Relaxed) {
+                        // which groups were the command's is only known now; background jobs never were
+                        return seen.into_iter().filter(|(pgid, _)| groups.contains(*pgid)).map(|(_, e)| e).collect();
+                    }
+                    std::thread::sleep(interval);
+                }
+            })
+            .map_err(|e| tracing::warn!(error = %e, "could not start watching the network"))
+            .ok();
+        Self { stop, sampler, counters: interface_counters() }
+    }
+
+    pub fn finish(self) -> NetworkSummary {
+        self.stop.store(true, Ordering::Relaxed);
+        let seen = self.sampler.and_then(|s| s.join().ok()).unwrap_or_default();
+        let (sent, received) = match (self.counters, interface_counters()) {
+            (Some((sent0, received0)), Some((sent1, received1))) => {
+                (sent1.saturating_sub(sent0), received1.saturating_sub(received0))
+            }
+            _ => (0, 0),
+        };
+        NetworkSummary {
+            bytes_sent: sent,
+            bytes_received: received,
+            endpoint_count: seen.len(),
+            endpoints: seen.into_iter().take(MAX_LISTED).collect(),
+        }
+    }
+}
+
+impl NetworkSummary {
+    /// e.g. "3 endpoints, 1.2 MB sent, 48.0 KB received"
+    pub fn describe(&self) -> String {
+        let endpoints = match self.endpoint_count {
+            1 => "1 endpoint".to_string(),
+            n => format!("{} endpoints", n),
+        };
+        format!("{}, {} sent, {} received", endpoints, human_bytes(self.bytes_sent), human_bytes(self.bytes_received))
+    }
+}
+
+fn human_bytes(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1 << 30 => format!("{:.1} GB", b as f64 / (1u64 << 30) as f64),
+        b if b >= 1 << 20 => format!("{:.1} MB", b as f64 / (1u64 << 20) as f64),
+        b if b >= 1 << 10 => format!("{:.1} KB", b as f64 / (1u64 << 10) as f64),
+        b => format!("{} B", b),
+    }
+}
+
+// note the remote end of every connected socket the processes below `root` hold right now
+fn sample(root: u32, groups: &mut ForegroundGroups, seen: &mut BTreeSet<(u32, Endpoint)>) {
+    let mut sockets: HashMap<u64, &ProcStat> = HashMap::new();
+    let all = procfs::processes();
+    groups.observe(&all, root);
+    for process in procfs::descendants(&all, root) {
+        let Ok(fds) = fs::read_dir(format!("/proc/{}/fd", process.pid)) else { continue };
+        for fd in fds.flatten() {
+            let Ok(target) = fs::read_link(fd.path()) else { continue };
+            let target = target.to_string_lossy();
+            let inode = target.strip_prefix("socket:[").and_then(|t| t.strip_suffix(']'));
+            if let Some(inode) = inode.and_then(|i| i.parse().ok()) {
+                sockets.insert(inode, process);
+            }
+        }
+    }
+    if sockets.is_empty() {
+        return;
+    }
+    for (table, protocol) in SOCKET_TABLES {
+        let Ok(text) = fs::read_to_string(table) else { continue };
+        // sl local_address rem_address st tx_queue:rx_queue tr:tm->when retrnsmt uid timeout inode
+        for line in text.lines().skip(1) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (Some(remote), Some(inode)) = (fields.get(2), fields.get(9)) else { continue };
+            let Some(process) = inode.parse().ok().and_then(|i: u64| sockets.get(&i)) else { continue };
+            // listening and unconnected sockets have no remote end
+            let Some(address) = parse_address(remote).filter(|a| !a.ip().is_unspecified()) else { continue };
+            let endpoint = Endpoint { protocol: protocol.to_string(), address: address.to_string(), process: process.name.clone() };
+            seen.insert((process.pgid, endpoint));
+        }
+    }
+}
+
+// "0100007F:0050" or 32 hex digits for ipv6; the kernel prints each 32-bit word of the address
+// as a number, so its bytes come back in order when taken in native byte order
+fn parse_address(hex: &str) -> Option<SocketAddr> {
+    let (ip, port) = hex.split_once(':')?;
+    let port = u16::from_str_radix(port, 16).ok()?;
+    let words: Vec<u32> = (0..ip.len() / 8)
+        .map(|i| u32::from_str_radix(&ip[i * 8..i * 8 + 8], 16))
+        .collect::<Result<_, _>>()
+        .ok()?;
+    let ip = match words.as_slice() {
+        [w] => Ipv4Addr::from(w.to_ne_bytes()).into(),
+        [a, b, c, d] => {
+            let mut bytes = [0u8; 16];
+            for (chunk, word) in bytes.chunks_mut(4).zip([a, b, c, d]) {
+                chunk.copy_from_slice(&word.to_ne_bytes());
+            }
+            let ip = Ipv6Addr::from(bytes);
+            // ipv4 peers of a dual-stack socket show up as ::ffff:a.b.c.d
+            ip.to_ipv4_mapped().map(Into::into).unwrap_or(ip.into())
+        }
+        _ => return None,
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+// bytes sent and received by all interfaces but loopback, from /proc/net/dev
+fn interface_counters() -> Option<(u64, u64)> {
+    let text = fs::read_to_string("/proc/net/dev").ok()?;
+    let mut totals = (0u64, 0u64);
+    for line in text.lines().skip(2) {
+        let Some((name, counters)) = line.split_once(':') else { continue };
+        if name.trim() == "lo" {
+            continue;
+        }
+        let counters: Vec<u64> = counters.split_whitespace().filter_map(|c| c.parse().ok()).collect();
+        // receive bytes come first, transmit bytes are the ninth counter
+        if let (Some(received), Some(sent)) = (counters.first(), counters.get(8)) {
+            totals.0 += sent;
+            totals.1 += received;
+        }
+    }
+    Some(totals)
+}
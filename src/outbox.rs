@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// sessions waiting for `recli sync` to upload them: one file per session, named by its id
+/// and holding its log directory. Webhook posts that failed wait under `posts/`.
+pub fn dir() -> PathBuf {
+    let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+    PathBuf::from(home).join(".recli").join("outbox")
+}
+
+/// queue the session recorded in `log_dir`
+pub fn add(log_dir: &Path) -> io::Result<()> {
+    let Some(id) = log_dir.file_name() else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a session directory"));
+    };
+    fs::create_dir_all(dir())?;
+    fs::write(dir().join(id), log_dir.to_string_lossy().as_bytes())
+}
+
+/// log directories of the queued sessions, oldest first; entries whose session is gone are dropped
+pub fn pending() -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir()) else { return Vec::new() };
+    let mut queued: Vec<(std::time::SystemTime, PathBuf)> = Vec::new();
+    for entry in entries.flatten() {
+        if !entry.path().is_file() {
+            continue;
+        }
+        let Ok(text) = fs::read_to_string(entry.path()) else { continue };
+        let log_dir = PathBuf::from(text.trim());
+        if !log_dir.is_dir() {
+            let _ = fs::remove_file(entry.path());
+            continue;
+        }
+        let queued_at = entry.metadata().and_then(|m| m.modified()).unwrap_or(std::time::UNIX_EPOCH);
+        queued.push((queued_at, log_dir));
+    }
+    queued.sort();
+    queued.into_iter().map(|(_, dir)| dir).collect()
+}
+
+/// take a session off the queue once it is uploaded
+pub fn remove(log_dir: &Path) {
+    if let Some(id) = log_dir.file_name() {
+        let _ = fs::remove_file(dir().join(id));
+    }
+}
+
+/// a webhook post that was given up on, kept to be sent again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Post {
+    pub url: String,
+    pub body: String,
+}
+
+fn posts_dir() -> PathBuf {
+    dir().join("posts")
+}
+
+/// queue a post; names sort in the order posts were queued
+pub fn add_post(post: &Post) -> io::Result<()> {
+    fs::create_dir_all(posts_dir())?;
+    let name = format!("{}-{}.json", chrono::Utc::now().format("%Y%m%dT%H%M%S%.9f"), std::process::id());
+    fs::write(posts_dir().join(name), serde_json::to_vec(post)?)
+}
+
+/// queued posts, oldest first, with the file each is kept in
+pub fn pending_posts() -> Vec<(PathBuf, Post)> {
+    let Ok(entries) = fs::read_dir(posts_dir()) else { return Vec::new() };
+    let mut posts: Vec<(PathBuf, Post)> = entries
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|entry| {
+            let post = serde_json::from_slice(&fs::read(entry.path()).ok()?).ok()?;
+            Some((entry.path(), post))
+        })
+        .collect();
+    posts.sort_by(|a, b| a.0.cmp(&b.0));
+    posts
+}
+
+/// put back a queued post whose body was changed, keeping its place in the queue
+pub fn rewrite_post(path: &Path, post: &Post) -> io::Result<()> {
+    // written aside and renamed, so a sync running meanwhile reads the old body or the new one
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_vec(post)?)?;
+    fs::rename(&tmp, path)
+}
+
+/// take a post off the queue once it was delivered
+pub fn remove_post(path: &Path) {
+    let _ = fs::remove_file(path);
+}
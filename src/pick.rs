@@ -0,0 +1,403 @@
+use crate::error::{RecliError, Result};
+use crate::history;
+use crate::util::time::format_duration_ms;
+use chrono::{DateTime, Local};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::{Frame, Terminal};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+// output bytes read for the preview, from the end of the command's output
+const PREVIEW_BYTES: u64 = 64 * 1024;
+
+/// a recorded command line the picker offers, as of its newest run
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub cmd: String,
+    pub cwd: String,
+    pub timestamp: String,
+    pub exit_code: i32,
+    pub duration_ms: Option<u64>,
+    pub runs: usize,
+    project: Option<String>,
+    log_dir: PathBuf,
+    output_preview: String,
+    output_path: Option<String>,
+}
+
+// the fields of a commands.json entry the picker shows
+#[derive(Deserialize)]
+struct Entry {
+    cmd: String,
+    #[serde(default)]
+    cwd: String,
+    #[serde(default)]
+    timestamp: String,
+    #[serde(default)]
+    exit_code: i32,
+    #[serde(default)]
+    duration_ms: Option<u64>,
+    #[serde(default)]
+    project: Option<String>,
+    #[serde(default)]
+    output_preview: String,
+    #[serde(default)]
+    output_path: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct EntriesFile {
+    #[serde(default)]
+    entries: Vec<Entry>,
+}
+
+/// every distinct command line recorded below `base`, newest first; with `project`, only those run
+/// in it (a git toplevel, or a directory outside any repository)
+pub fn candidates(base: &Path, project: Option<&str>) -> Vec<Candidate> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut candidates: Vec<Candidate> = Vec::new();
+    for dir in history::session_dirs(base).into_iter().rev() {
+        let Ok(text) = fs::read_to_string(dir.join("commands.json")) else { continue };
+        let Ok(file) = serde_json::from_str::<EntriesFile>(&text) else { continue };
+        for entry in file.entries.into_iter().rev() {
+            let cmd = entry.cmd.trim_end().to_string();
+            if cmd.is_empty() {
+                continue;
+            }
+            if let Some(&i) = seen.get(&cmd) {
+                candidates[i].runs += 1;
+                continue;
+            }
+            seen.insert(cmd.clone(), candidates.len());
+            candidates.push(Candidate {
+                cmd,
+                cwd: entry.cwd,
+                timestamp: entry.timestamp,
+                exit_code: entry.exit_code,
+                duration_ms: entry.duration_ms,
+                runs: 1,
+                project: entry.project,
+                log_dir: dir.clone(),
+                output_preview: entry.output_preview,
+                output_path: entry.output_path,
+            });
+        }
+    }
+    if let Some(project) = project {
+        candidates.retain(|c| c.project.as_deref().unwrap_or(&c.cwd) == project);
+    }
+    candidates
+}
+
+/// how well `query` matches `text`, fzf-style: each space-separated term has to appear in `text`
+/// in order, not necessarily in one piece; higher is better. Terms in lowercase ignore case.
+/// Returns the score and the char positions that matched.
+pub fn fuzzy_match(query: &str, text: &str) -> Option<(i64, Vec<usize>)> {
+    let chars: Vec<char> = text.chars().collect();
+    let lower: Vec<char> = chars.iter().flat_map(|c| c.to_lowercase()).collect();
+    // lowercasing may change the length of some chars; then case is matched as it is
+    let folded = if lower.len() == chars.len() { &lower } else { &chars };
+    let mut score = 0;
+    let mut positions = Vec::new();
+    for term in query.split_whitespace() {
+        let term: Vec<char> = term.chars().collect();
+        let haystack = if term.iter().any(|c| c.is_uppercase()) { &chars } else { folded };
+        let (term_score, found) = match_term(&term, haystack, &chars)?;
+        score += term_score;
+        positions.extend(found);
+    }
+    positions.sort_unstable();
+    positions.dedup();
+    Some((score, positions))
+}
+
+// find the term left to right, then walk back from where it ended to the latest start, so the
+// match is as tight as it gets; consecutive chars and word starts score higher, gaps lower
+fn match_term(term: &[char], haystack: &[char], original: &[char]) -> Option<(i64, Vec<usize>)> {
+    let mut t = 0;
+    let mut end = None;
+    for (i, c) in haystack.iter().enumerate() {
+        if *c == term[t] {
+            t += 1;
+            if t == term.len() {
+                end = Some(i);
+                break;
+            }
+        }
+    }
+    let end = end?;
+    let mut positions = Vec::with_capacity(term.len());
+    let mut t = term.len();
+    for i in (0..=end).rev() {
+        if haystack[i] == term[t - 1] {
+            positions.push(i);
+            t -= 1;
+            if t == 0 {
+                break;
+            }
+        }
+    }
+    positions.reverse();
+
+    let mut score = 0;
+    for (n, &i) in positions.iter().enumerate() {
+        score += 16;
+        if i == 0 || !original[i - 1].is_alphanumeric() {
+            score += 8;
+        }
+        if n > 0 && positions[n - 1] + 1 == i {
+            score += 4;
+        }
+    }
+    let span = positions[positions.len() - 1] - positions[0] + 1;
+    score -= (span - term.len()) as i64;
+    // a match at the very start, as in typing the program name, beats one deep in the arguments
+    if positions[0] == 0 {
+        score += 8;
+    }
+    Some((score, positions))
+}
+
+struct Picker {
+    candidates: Vec<Candidate>,
+    query: String,
+    // candidate indices matching the query, best first, with matched char positions
+    matches: Vec<(usize, Vec<usize>)>,
+    list: ListState,
+    // the preview of the selected candidate, kept until the selection moves
+    preview: Option<(usize, Vec<String>)>,
+}
+
+/// let the user pick one of `candidates` on the terminal, starting with `query` typed; the command
+/// line chosen, or none when the picker was left with Esc or ^C. The picker draws on /dev/tty, so
+/// stdout stays free for the result.
+pub fn run(candidates: Vec<Candidate>, query: &str) -> Result<Option<String>> {
+    let tty = OpenOptions::new().read(true).write(true).open("/dev/tty").map_err(RecliError::Terminal)?;
+    enable_raw_mode().map_err(RecliError::Terminal)?;
+    let mut out = tty.try_clone().map_err(RecliError::Terminal)?;
+    if let Err(e) = execute!(out, EnterAlternateScreen) {
+        let _ = disable_raw_mode();
+        return Err(RecliError::Terminal(e));
+    }
+    let result = Terminal::new(CrosstermBackend::new(tty)).map_err(RecliError::Terminal).and_then(|mut terminal| {
+        let mut picker = Picker { candidates, query: query.to_string(), matches: Vec::new(), list: ListState::default(), preview: None };
+        picker.filter();
+        picker.event_loop(&mut terminal)
+    });
+    let _ = disable_raw_mode();
+    let _ = execute!(out, LeaveAlternateScreen);
+    result
+}
+
+impl Picker {
+    fn event_loop(&mut self, terminal: &mut Terminal<CrosstermBackend<File>>) -> Result<Option<String>> {
+        loop {
+            self.load_preview();
+            terminal.draw(|frame| self.draw(frame)).map_err(RecliError::Terminal)?;
+            let Event::Key(key) = event::read().map_err(RecliError::Terminal)? else { continue };
+            if key.kind == KeyEventKind::Release {
+                continue;
+            }
+            match self.key(key) {
+                Some(done) => return Ok(done),
+                None => continue,
+            }
+        }
+    }
+
+    // apply a key; Some once the picker is done, holding the pick if there was one
+    fn key(&mut self, key: KeyEvent) -> Option<Option<String>> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => return Some(None),
+            KeyCode::Char('c' | 'g') if ctrl => return Some(None),
+            KeyCode::Enter => return Some(self.selected().map(|c| c.cmd.clone())),
+            KeyCode::Up => self.move_by(-1),
+            KeyCode::Down => self.move_by(1),
+            KeyCode::Char('p' | 'k') if ctrl => self.move_by(-1),
+            KeyCode::Char('n' | 'j') if ctrl => self.move_by(1),
+            KeyCode::PageUp => self.move_by(-10),
+            KeyCode::PageDown => self.move_by(10),
+            KeyCode::Char('u') if ctrl => {
+                self.query.clear();
+                self.filter();
+            }
+            KeyCode::Char('w') if ctrl => {
+                let kept = self.query.trim_end().rfind(' ').map_or(0, |i| i + 1);
+                self.query.truncate(kept);
+                self.filter();
+            }
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.filter();
+            }
+            KeyCode::Char(c) if !ctrl => {
+                self.query.push(c);
+                self.filter();
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn filter(&mut self) {
+        let mut matches: Vec<(i64, usize, Vec<usize>)> = self
+            .candidates
+            .iter()
+            .enumerate()
+            .filter_map(|(i, c)| fuzzy_match(&self.query, &c.cmd).map(|(score, positions)| (score, i, positions)))
+            .collect();
+        // candidates are newest first, so equal scores keep the more recent command on top
+        matches.sort_by_key(|(score, i, _)| (std::cmp::Reverse(*score), *i));
+        self.matches = matches.into_iter().map(|(_, i, positions)| (i, positions)).collect();
+        self.list.select((!self.matches.is_empty()).then_some(0));
+    }
+
+    fn move_by(&mut self, by: isize) {
+        if self.matches.is_empty() {
+            return;
+        }
+        let current = self.list.selected().unwrap_or(0) as isize;
+        let moved = (current + by).clamp(0, self.matches.len() as isize - 1);
+        self.list.select(Some(moved as usize));
+    }
+
+    fn selected(&self) -> Option<&Candidate> {
+        let (i, _) = self.matches.get(self.list.selected()?)?;
+        self.candidates.get(*i)
+    }
+
+    fn load_preview(&mut self) {
+        let Some(&(i, _)) = self.list.selected().and_then(|s| self.matches.get(s)) else {
+            self.preview = None;
+            return;
+        };
+        if self.preview.as_ref().is_some_and(|(p, _)| *p == i) {
+            return;
+        }
+        let candidate = &self.candidates[i];
+        let bytes = candidate
+            .output_path
+            .as_ref()
+            .and_then(|p| tail(&candidate.log_dir.join(p)))
+            .unwrap_or_else(|| candidate.output_preview.as_bytes().to_vec());
+        let text = crate::io::screen_text(&bytes);
+        let lines = text.lines().map(|l| l.replace('\t', "    ")).collect();
+        self.preview = Some((i, lines));
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Percentage(55), Constraint::Min(3)])
+            .split(frame.size());
+        let dim = Style::default().add_modifier(Modifier::DIM);
+
+        let prompt = Line::from(vec![
+            Span::styled("> ", Style::default().fg(Color::Cyan)),
+            Span::raw(self.query.clone()),
+            Span::styled(format!("  {}/{}", self.matches.len(), self.candidates.len()), dim),
+        ]);
+        frame.render_widget(Paragraph::new(prompt), rows[0]);
+        frame.set_cursor(rows[0].x + 2 + self.query.chars().count() as u16, rows[0].y);
+
+        let bold = Style::default().add_modifier(Modifier::BOLD).fg(Color::Yellow);
+        let items: Vec<ListItem> = self
+            .matches
+            .iter()
+            .map(|(i, positions)| {
+                let candidate = &self.candidates[*i];
+                let mut spans: Vec<Span> = candidate
+                    .cmd
+                    .chars()
+                    .enumerate()
+                    .map(|(n, c)| {
+                        let c = if c == '\n' { "\u{21b5} ".to_string() } else { c.to_string() };
+                        match positions.binary_search(&n).is_ok() {
+                            true => Span::styled(c, bold),
+                            false => Span::raw(c),
+                        }
+                    })
+                    .collect();
+                if candidate.exit_code != 0 {
+                    spans.push(Span::styled(format!("  exit {}", candidate.exit_code), Style::default().fg(Color::Red)));
+                }
+                ListItem::new(Line::from(spans))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::BOTTOM))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, rows[1], &mut self.list);
+
+        let Some(candidate) = self.selected() else { return };
+        let mut about = vec![format!("exit {}", candidate.exit_code)];
+        about.extend(candidate.duration_ms.map(format_duration_ms));
+        if let Ok(at) = DateTime::parse_from_rfc3339(&candidate.timestamp) {
+            about.push(at.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string());
+        }
+        if candidate.runs > 1 {
+            about.push(format!("ran {} times", candidate.runs));
+        }
+        about.push(candidate.cwd.clone());
+        let mut preview = vec![Line::from(Span::styled(about.join(" \u{b7} "), dim))];
+        let height = rows[2].height.saturating_sub(1) as usize;
+        // the end of the output is where errors and results usually are
+        let lines = self.preview.as_ref().map(|(_, lines)| lines.as_slice()).unwrap_or_default();
+        preview.extend(lines[lines.len().saturating_sub(height)..].iter().map(|l| Line::from(l.clone())));
+        frame.render_widget(Paragraph::new(preview), rows[2]);
+    }
+}
+
+// the last PREVIEW_BYTES of a file
+fn tail(path: &Path) -> Option<Vec<u8>> {
+    use std::io::{Read, Seek, SeekFrom};
+    let mut file = File::open(path).ok()?;
+    let len = file.metadata().ok()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(PREVIEW_BYTES))).ok()?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).ok()?;
+    Some(bytes)
+}
+
+/// readline/zle glue that backs Ctrl-R with `recli pick`, starting from what was typed
+pub fn shell_snippet(shell: &str) -> Option<&'static str> {
+    match shell {
+        "bash" => Some(
+            r#"# --- recli history picker (bash) ---
+_recli_pick() {
+    local selected
+    selected=$(recli pick -- "$READLINE_LINE") || return
+    READLINE_LINE=$selected
+    READLINE_POINT=${#READLINE_LINE}
+}
+bind -x '"\C-r": _recli_pick'
+"#,
+        ),
+        "zsh" => Some(
+            r#"# --- recli history picker (zsh) ---
+_recli_pick() {
+    local selected
+    selected=$(recli pick -- "$BUFFER" </dev/tty)
+    if [[ $? -eq 0 ]]; then
+        BUFFER=$selected
+        CURSOR=${#BUFFER}
+    fi
+    zle reset-prompt
+}
+zle -N _recli_pick
+bindkey '^R' _recli_pick
+"#,
+        ),
+        _ => None,
+    }
+}
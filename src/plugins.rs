@@ -0,0 +1,121 @@
+use crate::config::{Config, PluginsConfig};
+use crate::session::SessionManager;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{ChildStdin, Command, Stdio};
+
+const PREFIX: &str = "recli-";
+
+/// an installed `recli-<name>` executable
+#[derive(Debug, Clone)]
+pub struct Plugin {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// plugins in the plugin directory, sorted by name
+pub fn discover(cfg: &PluginsConfig) -> Vec<Plugin> {
+    let Ok(dir) = fs::read_dir(cfg.plugin_dir()) else { return Vec::new() };
+    let mut plugins: Vec<Plugin> = dir
+        .flatten()
+        .filter(|e| is_executable(&e.path()))
+        .filter_map(|e| {
+            let name = e.file_name().to_str()?.strip_prefix(PREFIX)?.to_string();
+            Some(Plugin { name, path: e.path() })
+        })
+        .filter(|p| !p.name.is_empty())
+        .collect();
+    plugins.sort_by(|a, b| a.name.cmp(&b.name));
+    plugins
+}
+
+/// the plugin `recli <name>` should run, if one is installed
+pub fn find(cfg: &PluginsConfig, name: &str) -> Option<Plugin> {
+    // a name with a path separator would escape the plugin directory
+    if name.is_empty() || name.contains(std::path::MAIN_SEPARATOR) || name.starts_with('.') {
+        return None;
+    }
+    let path = cfg.plugin_dir().join(format!("{}{}", PREFIX, name));
+    is_executable(&path).then(|| Plugin {
+        name: name.to_string(),
+        path,
+    })
+}
+
+/// environment a plugin runs with: where recli keeps things and the active session, if any
+pub fn context_env(cfg: &Config, config_path: Option<&str>) -> Vec<(&'static str, String)> {
+    let mut env = vec![
+        ("RECLI_VERSION", env!("CARGO_PKG_VERSION").to_string()),
+        ("RECLI_LOG_DIR", cfg.session_log_dir().to_string_lossy().to_string()),
+        ("RECLI_PLUGIN_DIR", cfg.plugins.plugin_dir().to_string_lossy().to_string()),
+    ];
+    if let Ok(exe) = std::env::current_exe() {
+        env.push(("RECLI_BIN", exe.to_string_lossy().to_string()));
+    }
+    if let Some(path) = config_path {
+        env.push(("RECLI_CONFIG", path.to_string()));
+    }
+    if let Some(ws) = &cfg.workspace {
+        env.push(("RECLI_WORKSPACE", ws.clone()));
+    }
+    if let Some(session) = SessionManager::new().active_session() {
+        env.push(("RECLI_SESSION_ID", session.session_id));
+        env.push(("RECLI_SESSION_DIR", session.log_dir.to_string_lossy().to_string()));
+    }
+    env
+}
+
+/// run `plugin` with `args` in the foreground; returns its exit code
+pub fn run(plugin: &Plugin, args: &[String], env: &[(&'static str, String)]) -> io::Result<i32> {
+    let status = Command::new(&plugin.path)
+        .args(args)
+        .envs(env.iter().map(|(k, v)| (*k, v)))
+        .status()?;
+    // a plugin killed by a signal reports like a shell would
+    #[cfg(unix)]
+    if let Some(sig) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return Ok(128 + sig);
+    }
+    Ok(status.code().unwrap_or(1))
+}
+
+/// start the configured event sink plugins; each reads LogEventV1 lines on stdin
+pub fn start_sinks(cfg: &PluginsConfig, env: &[(&'static str, String)]) -> Vec<ChildStdin> {
+    let mut sinks = Vec::new();
+    for name in &cfg.event_sinks {
+        let Some(plugin) = find(cfg, name) else {
+            tracing::warn!(plugin = %name, dir = %cfg.plugin_dir().display(), "event sink plugin not found");
+            continue;
+        };
+        // a sink must not draw into the terminal being recorded
+        let child = Command::new(&plugin.path)
+            .arg("events")
+            .envs(env.iter().map(|(k, v)| (*k, v)))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        match child {
+            Ok(mut child) => {
+                if let Some(stdin) = child.stdin.take() {
+                    tracing::debug!(plugin = %name, pid = child.id(), "event sink started");
+                    sinks.push(stdin);
+                }
+            }
+            Err(e) => tracing::warn!(plugin = %name, error = %e, "event sink failed to start"),
+        }
+    }
+    sinks
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
@@ -0,0 +1,101 @@
+use crate::config::{PolicyConfig, PolicyRule, RecordMode};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// policy file a project ships in its repository, found from the session's directory upward
+pub const PROJECT_POLICY_FILE: &str = ".recli/policy.toml";
+
+/// tag on commands recorded without their output because of the policy
+pub const METADATA_ONLY_TAG: &str = "metadata-only";
+
+// words in front of the real program that don't change what runs
+const WRAPPERS: &[&str] = &["sudo", "env", "time", "nohup", "command", "exec", "nice"];
+
+/// the recording rules in effect for a session
+#[derive(Debug, Clone, Default)]
+pub struct RecordingPolicy {
+    rules: Vec<(Vec<String>, RecordMode)>,
+    default: RecordMode,
+}
+
+impl RecordingPolicy {
+    /// the project policy above `cwd`, then the active profile, then the user's own rules
+    pub fn load(cfg: &PolicyConfig, cwd: &Path) -> Result<Self, String> {
+        let mut policy = Self::default();
+        let mut default = None;
+        if let Some(path) = find_project_policy(cwd) {
+            let text = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            let project: PolicyConfig = toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+            policy.add(&project.rules);
+            default = Some(project.default);
+        }
+        if let Some(name) = &cfg.profile {
+            let profile = cfg
+                .profiles
+                .get(name)
+                .ok_or_else(|| format!("no recording policy profile named {}", name))?;
+            policy.add(&profile.rules);
+            default = default.or(profile.default);
+        }
+        policy.add(&cfg.rules);
+        policy.default = default.unwrap_or(cfg.default);
+        Ok(policy)
+    }
+
+    fn add(&mut self, rules: &[PolicyRule]) {
+        for rule in rules {
+            let words: Vec<String> = rule.command.split_whitespace().map(str::to_string).collect();
+            if !words.is_empty() {
+                self.rules.push((words, rule.mode));
+            }
+        }
+    }
+
+    /// how `cmd` is recorded; every part of a pipeline or command list is checked and the
+    /// strictest mode wins, so `cat key | gpg --import` counts as gpg
+    pub fn mode(&self, cmd: &str) -> RecordMode {
+        split_commands(cmd)
+            .iter()
+            .map(|words| self.mode_of(words))
+            .max()
+            .unwrap_or(self.default)
+    }
+
+    fn mode_of(&self, words: &[&str]) -> RecordMode {
+        let words = program_words(words);
+        self.rules
+            .iter()
+            .find(|(rule, _)| {
+                rule.len() <= words.len() && rule.iter().zip(&words).all(|(r, w)| r == w)
+            })
+            .map(|(_, mode)| *mode)
+            .unwrap_or(self.default)
+    }
+}
+
+// words of each simple command in `cmd`, split at pipes, `;`, `&&` and `||`
+fn split_commands(cmd: &str) -> Vec<Vec<&str>> {
+    cmd.split(['|', ';', '&', '\n'])
+        .map(|part| part.split_whitespace().collect::<Vec<_>>())
+        .filter(|words| !words.is_empty())
+        .collect()
+}
+
+// drop variable assignments and wrappers in front of the program, and its directory
+fn program_words<'a>(words: &[&'a str]) -> Vec<&'a str> {
+    let start = words
+        .iter()
+        .position(|w| !((w.contains('=') && !w.starts_with('=')) || WRAPPERS.contains(w) || w.starts_with('-')))
+        .unwrap_or(words.len());
+    let mut rest: Vec<&str> = words[start..].to_vec();
+    if let Some(first) = rest.first_mut() {
+        *first = first.rsplit('/').next().unwrap_or(first);
+    }
+    rest
+}
+
+fn find_project_policy(cwd: &Path) -> Option<PathBuf> {
+    cwd.ancestors()
+        .map(|dir| dir.join(PROJECT_POLICY_FILE))
+        .find(|path| path.is_file())
+}
@@ -0,0 +1,168 @@
+use crate::util::procfs::{self, ForegroundGroups};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+// processes listed per command, busiest first; count and peak still cover all of them
+const MAX_LISTED: usize = 50;
+
+/// the processes one command ran
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessSummary {
+    pub count: usize, // processes seen while the command ran
+    pub peak: usize,  // the most seen at once
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub processes: Vec<ProcessInfo>,
+}
+
+/// one process below the shell, as last sampled
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub ppid: u32,
+    pub name: String,
+    pub cpu_ms: u64,  // user and system time when last seen
+    pub seen_ms: u64, // from the first sample it was in to the last
+    // still there when the command finished, e.g. a daemon it left behind or a stuck child
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub left_running: bool,
+}
+
+/// samples the processes of the command running below the shell `root` until finished; one that
+/// starts and exits between two samples is missed
+pub struct ProcessWatch {
+    stop: Arc<AtomicBool>,
+    sampler: Option<JoinHandle<Samples>>,
+}
+
+#[derive(Default)]
+struct Samples {
+    // keyed by start time and pid, so a reused pid is another process
+    seen: BTreeMap<(u64, u32), Seen>,
+    // processes per group in each sample that differed from the one before
+    counts: Vec<HashMap<u32, usize>>,
+    groups: ForegroundGroups,
+}
+
+struct Seen {
+    ppid: u32,
+    pgid: u32,
+    name: String,
+    cpu_ticks: u64,
+    first: Instant,
+    last: Instant,
+    in_last_sample: bool,
+}
+
+impl ProcessWatch {
+    /// start watching the descendants of `root` (not `root` itself), every `interval`
+    pub fn start(root: u32, interval: Duration) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let sampler = std::thread::Builder::new()
+            .name("recli-processes".to_string())
+            .spawn(move || {
+                let mut samples = Samples::default();
+                loop {
+                    samples.take(root);
+                    if stopped.load(Ordering::Relaxed) {
+                        return samples;
+                    }
+                    std::thread::sleep(interval);
+                }
+            })
+            .map_err(|e| tracing::warn!(error = %e, "could not start watching processes"))
+            .ok();
+        Self { stop, sampler }
+    }
+
+    pub fn finish(self) -> ProcessSummary {
+        self.stop.store(true, Ordering::Relaxed);
+        let samples = self.sampler.and_then(|s| s.join().ok()).unwrap_or_default();
+        // which groups were the command's is only known now; background jobs never were
+        let groups = &samples.groups;
+        let peak = samples
+            .counts
+            .iter()
+            .map(|sample| sample.iter().filter(|(g, _)| groups.contains(**g)).map(|(_, n)| n).sum())
+            .max()
+            .unwrap_or(0);
+        let mut processes: Vec<ProcessInfo> = samples
+            .seen
+            .into_iter()
+            .filter(|(_, seen)| groups.contains(seen.pgid))
+            .map(|((_, pid), seen)| ProcessInfo {
+                pid,
+                ppid: seen.ppid,
+                name: seen.name,
+                cpu_ms: procfs::ticks_to_ms(seen.cpu_ticks),
+                seen_ms: seen.last.duration_since(seen.first).as_millis() as u64,
+                left_running: seen.in_last_sample,
+            })
+            .collect();
+        let count = processes.len();
+        // stable, so processes that used the same cpu stay in the order they started
+        processes.sort_by_key(|p| std::cmp::Reverse(p.cpu_ms));
+        processes.truncate(MAX_LISTED);
+        ProcessSummary { count, peak, processes }
+    }
+}
+
+impl Samples {
+    fn take(&mut self, root: u32) {
+        let now = Instant::now();
+        let all = procfs::processes();
+        self.groups.observe(&all, root);
+        let current = procfs::descendants(&all, root);
+        let mut counts: HashMap<u32, usize> = HashMap::new();
+        for process in &current {
+            *counts.entry(process.pgid).or_default() += 1;
+        }
+        if self.counts.last() != Some(&counts) {
+            self.counts.push(counts);
+        }
+        for seen in self.seen.values_mut() {
+            seen.in_last_sample = false;
+        }
+        for process in current {
+            let seen = self.seen.entry((process.start_ticks, process.pid)).or_insert_with(|| Seen {
+                ppid: process.ppid,
+                pgid: process.pgid,
+                name: process.name.clone(),
+                cpu_ticks: 0,
+                first: now,
+                last: now,
+                in_last_sample: true,
+            });
+            // a process that execs keeps its pid but changes its name, e.g. the shell's fork becoming `make`
+            seen.name.clone_from(&process.name);
+            seen.cpu_ticks = process.cpu_ticks;
+            seen.last = now;
+            seen.in_last_sample = true;
+        }
+    }
+}
+
+impl ProcessSummary {
+    /// e.g. "12 processes, at most 4 at once, busiest cc1 (3.2s cpu)"
+    pub fn describe(&self) -> String {
+        let mut text = match self.count {
+            1 => "1 process".to_string(),
+            n => format!("{} processes", n),
+        };
+        if self.peak > 1 {
+            text.push_str(&format!(", at most {} at once", self.peak));
+        }
+        if let Some(busiest) = self.processes.first().filter(|p| p.cpu_ms > 0) {
+            text.push_str(&format!(", busiest {} ({:.1}s cpu)", busiest.name, busiest.cpu_ms as f64 / 1000.0));
+        }
+        let left = self.processes.iter().filter(|p| p.left_running).count();
+        if left > 0 {
+            text.push_str(&format!(", {} left running", left));
+        }
+        text
+    }
+}
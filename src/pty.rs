@@ -1,16 +1,240 @@
+use crate::config::{HotkeyConfig, InputRecording};
 use crate::error::{RecliError, Result};
-use crate::io::OutputHandler;
-use crate::session::SessionManager;
-use crate::command_detector::CommandDetector;
-use crossterm::{
-    event::{self, Event},
-    terminal::{disable_raw_mode, enable_raw_mode},
+use crate::io::{
+    self as recli_io, paste_framing, HotkeyAction, HotkeyMatcher, InputChunk, MouseEvent, MouseMode,
+    MouseModeTracker, OutputHandler, OutputWatch, PasteModeTracker, PasteSplitter,
 };
+use crate::session::{LogEvent, SessionManager};
+use crate::cast::{CastWriter, InputRecorder};
+use crate::command_detector::{CommandDetector, Detection};
+use crate::control::ConfigReloaded;
+use crate::filters::FilterChain;
+use crate::policy::RecordingPolicy;
+use crate::reload::ConfigReload;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
 use portable_pty::{CommandBuilder, PtySize};
+use std::fs;
 use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+/// version of the zsh hook, in its first line and announced by it when loaded
+pub(crate) const ZSH_HOOK_VERSION: u32 = 10;
+
+/// recli's zsh hook, written to ~/.recli/recli.zsh; markers go to stderr or $RECLI_MARKER_FILE
+pub(crate) const ZSH_HOOK: &str = r#"# --- recli hook (v10) ---
+                # state tracking
+                typeset -g RECLI_INITIALIZED=0
+                typeset -g RECLI_IN_COMMAND=0
+                typeset -g RECLI_LAST_CMD=""
+
+                # emit marker to stderr, or to a file when recording without a pty; a nested
+                # session's markers carry its namespace
+                function _recli_emit() {
+                    local marker="${RECLI_MARKER_NS:+@$RECLI_MARKER_NS }$1"
+                    if [[ -n "${RECLI_MARKER_FILE:-}" ]]; then
+                        printf '\x1e%s\n' "$marker" >> "$RECLI_MARKER_FILE"
+                    else
+                        printf '\x1e%s\n' "$marker" >&2
+                    fi
+                }
+
+                # preexec: start of command
+                function _recli_preexec() {
+                    if (( RECLI_INITIALIZED == 1 )); then
+                        RECLI_IN_COMMAND=1
+                        RECLI_LAST_CMD="$1"
+                        # a marker ends at a newline, so the lines of a multi-line command go first
+                        if [[ "$1" == *$'\n'* ]]; then
+                            local line
+                            for line in "${(@f)1}"; do
+                                _recli_emit "RECLI_LINE:${line//$'\r'/}"
+                            done
+                        fi
+                        _recli_emit "RECLI_START:${1%%$'\n'*}"
+                        # tee the command's stderr to a file recli picks up; the terminal still sees it
+                        if [[ -n "${RECLI_STDERR_FILE:-}" ]]; then
+                            : >| "$RECLI_STDERR_FILE"
+                            exec {RECLI_STDERR_FD}>&2
+                            exec 2> >(tee -a -- "$RECLI_STDERR_FILE" >&2)
+                        fi
+                    fi
+                }
+
+                # precmd: before prompt shows
+                function _recli_precmd() {
+                    local exit_code=$?
+                    local -a ps=("${pipestatus[@]}")
+                    if (( RECLI_INITIALIZED == 0 )); then
+                        RECLI_INITIALIZED=1
+                        return 0
+                    fi
+                    if (( RECLI_IN_COMMAND == 1 )); then
+                        RECLI_IN_COMMAND=0
+                        # restore stderr before any marker is written to it
+                        if [[ -n "${RECLI_STDERR_FD:-}" ]]; then
+                            exec 2>&$RECLI_STDERR_FD {RECLI_STDERR_FD}>&-
+                            unset RECLI_STDERR_FD
+                        fi
+                        _recli_emit "RECLI_END:$exit_code"
+                        _recli_emit "RECLI_PIPE:[${(j:,:)ps}]"
+                        _recli_emit "RECLI_PWD:$PWD"
+                    fi
+                }
+
+                # register hooks safely: remove old and add new
+                typeset -ag precmd_functions
+                typeset -ag preexec_functions
+                precmd_functions=("${(@)precmd_functions:#_recli_precmd}")
+                preexec_functions=("${(@)preexec_functions:#_recli_preexec}")
+                precmd_functions=(_recli_precmd ${precmd_functions})
+                preexec_functions+=(_recli_preexec)
+
+                # lets recli tell a hook left from an older version, which lacks markers it expects
+                _recli_emit "RECLI_HOOK_VERSION:zsh:10"
+
+                # optional debug marker
+                if [[ -n "${RECLI_DEBUG_MARKERS:-}" ]]; then
+                    _recli_emit "RECLI_DEBUG:hook_loaded_v10"
+                fi
+                "#;
+
+/// ~/.recli/.zshenv for ZDOTDIR=~/.recli: the user's .zshenv, read from the ZDOTDIR they had,
+/// which it may move; ZDOTDIR then points back here for the next file
+pub(crate) const ZSH_BOOTSTRAP_ENV: &str = r#"# --- recli bootstrap .zshenv (v1) ---
+        typeset -g _RECLI_ZDOTDIR="$ZDOTDIR"
+        # the user's own ZDOTDIR, or none, while their file is read
+        if [[ -n "${RECLI_USER_ZDOTDIR+x}" ]]; then
+            export ZDOTDIR="$RECLI_USER_ZDOTDIR"
+        else
+            unset ZDOTDIR
+        fi
+        [[ -r "${ZDOTDIR:-$HOME}/.zshenv" ]] && source "${ZDOTDIR:-$HOME}/.zshenv"
+
+        # a .zshenv may set ZDOTDIR, e.g. to $HOME/.config/zsh; the rest of the user's files are there
+        if [[ -n "${ZDOTDIR+x}" ]]; then
+            typeset -g RECLI_USER_ZDOTDIR="$ZDOTDIR"
+            [[ ${(t)ZDOTDIR} == *export* ]] && typeset -g _RECLI_ZDOTDIR_EXPORTED=1
+        else
+            unset RECLI_USER_ZDOTDIR
+        fi
+        ZDOTDIR="$_RECLI_ZDOTDIR"
+        "#;
+
+/// ~/.recli/.zprofile, for login shells: the user's .zprofile, read from their ZDOTDIR
+pub(crate) const ZSH_BOOTSTRAP_PROFILE: &str = r#"# --- recli bootstrap .zprofile (v1) ---
+        if [[ -n "${RECLI_USER_ZDOTDIR+x}" ]]; then
+            ZDOTDIR="$RECLI_USER_ZDOTDIR"
+        else
+            unset ZDOTDIR
+        fi
+        [[ -r "${ZDOTDIR:-$HOME}/.zprofile" ]] && source "${ZDOTDIR:-$HOME}/.zprofile"
+        ZDOTDIR="$_RECLI_ZDOTDIR"
+        "#;
+
+/// ~/.recli/.zshrc: the user's .zshrc first, read from their ZDOTDIR, then the hook. ZDOTDIR stays
+/// the user's from here on, so a login shell reads their .zlogin and child shells their files.
+pub(crate) const ZSH_BOOTSTRAP: &str = r#"# --- recli bootstrap .zshrc (v4) ---
+        # ZDOTDIR as the user had it, exported or not
+        if [[ -n "${RECLI_USER_ZDOTDIR+x}" ]]; then
+            ZDOTDIR="$RECLI_USER_ZDOTDIR"
+            if [[ -n "${_RECLI_ZDOTDIR_EXPORTED:-}" ]]; then
+                export ZDOTDIR
+            else
+                typeset +x ZDOTDIR
+            fi
+        else
+            unset ZDOTDIR
+        fi
+
+        # source user config first (including p10k instant prompt)
+        [[ -r "${ZDOTDIR:-$HOME}/.zshrc" ]] && source "${ZDOTDIR:-$HOME}/.zshrc"
+
+        # give p10k a moment to finish initialization if present
+        if typeset -f p10k &>/dev/null; then
+            sleep 0.1
+        fi
+
+        # load recli hooks last
+        [[ -r "$_RECLI_ZDOTDIR/recli.zsh" ]] && source "$_RECLI_ZDOTDIR/recli.zsh"
+        unset _RECLI_ZDOTDIR _RECLI_ZDOTDIR_EXPORTED RECLI_USER_ZDOTDIR
+
+        # debug: print arrays to stderr if requested
+        if [[ -n "${RECLI_DEBUG_MARKERS:-}" ]]; then
+            typeset -p precmd_functions preexec_functions >&2
+        fi
+        "#;
+
+/// the files zsh reads from ZDOTDIR=~/.recli, in the order it reads them
+pub(crate) const ZSH_BOOTSTRAP_FILES: [(&str, &str); 3] = [
+    (".zshenv", ZSH_BOOTSTRAP_ENV),
+    (".zprofile", ZSH_BOOTSTRAP_PROFILE),
+    (".zshrc", ZSH_BOOTSTRAP),
+];
+
+/// write the hook and the bootstrap files into `dir`; always the latest version
+pub(crate) fn write_zsh_bootstrap(dir: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    // hook file that emits markers to stderr
+    refresh_hook_file(&dir.join("recli.zsh"), ZSH_HOOK)?;
+    for (name, content) in ZSH_BOOTSTRAP_FILES {
+        refresh_hook_file(&dir.join(name), content)?;
+        // remove compiled zsh caches that could shadow fresh text
+        let _ = fs::remove_file(dir.join(format!("{}.zwc", name)));
+    }
+    let _ = fs::remove_file(dir.join("recli.zsh.zwc"));
+    Ok(())
+}
+
+/// write one of recli's generated shell files unless `path` already holds exactly `content`. A
+/// file from another version is replaced quietly; one edited by hand is replaced too, with a
+/// notice, since the edits would be lost on every start anyway.
+pub(crate) fn refresh_hook_file(path: &Path, content: &str) -> std::io::Result<()> {
+    if let Ok(existing) = fs::read_to_string(path) {
+        if existing == content {
+            return Ok(());
+        }
+        match (hook_file_version(&existing), hook_file_version(content)) {
+            (Some(old), Some(new)) if old != new => {
+                tracing::info!(path = %path.display(), old, new, "shell hook file regenerated")
+            }
+            _ => {
+                tracing::warn!(path = %path.display(), "shell hook file was modified, regenerated");
+                eprintln!(
+                    "[recli] {} was changed since recli wrote it and has been rewritten; shell settings belong in your own startup files",
+                    path.display()
+                );
+            }
+        }
+    }
+    fs::write(path, content)
+}
+
+/// the version in the first line of a generated shell file, as in `# --- recli hook (v10) ---`
+pub(crate) fn hook_file_version(text: &str) -> Option<u32> {
+    let line = text.lines().next()?.strip_prefix("# --- recli ")?;
+    let start = line.find("(v")? + 2;
+    let len = line[start..].find(')')?;
+    line[start..start + len].parse().ok()
+}
+
+// set, or remove, the variables of `env` on a command for the pty
+fn set_env(cmd: &mut CommandBuilder, env: &[(&str, Option<String>)]) {
+    for (key, value) in env {
+        match value {
+            Some(value) => cmd.env(key, value),
+            None => cmd.env_remove(key),
+        }
+    }
+}
+
+/// the ZDOTDIR zsh would have used without recli, for the bootstrap files to read the user's own
+/// from; recli's directory doesn't count, as when recli is started from a recorded shell
+pub(crate) fn user_zdotdir(current: Option<&str>, ours: &Path) -> Option<String> {
+    current.filter(|dir| !dir.is_empty() && Path::new(dir) != ours).map(str::to_string)
+}
 
 /// PTY session with a shell
 pub struct PtySession {
@@ -19,6 +243,47 @@ pub struct PtySession {
     session_manager: Option<Arc<Mutex<SessionManager>>>,
     // set when we receive a termination signal to end the loop
     terminated: Arc<AtomicBool>,
+    // set with terminated when the controlling terminal went away
+    hung_up: Arc<AtomicBool>,
+    // intercepted key sequences; disabled means full pass-through
+    hotkeys: HotkeyConfig,
+    // toggled by the pause hotkey; output is shown but not logged while set
+    paused: Arc<AtomicBool>,
+    // whether the child currently has bracketed paste mode enabled
+    child_paste_mode: Arc<AtomicBool>,
+    // mouse reporting the child currently asks for
+    child_mouse_mode: Arc<Mutex<MouseMode>>,
+    // set on terminal focus-in to force a size check; only seen when the child enabled focus
+    // reporting, the periodic resync is what catches missed resizes otherwise
+    resync_size: Arc<AtomicBool>,
+    // have the shell hook tee each command's stderr to a file
+    split_stderr: bool,
+    // wasm filters applied to output before it is shown and logged
+    output_filters: Option<FilterChain>,
+    // mark error lines of command output on screen
+    highlight_errors: bool,
+    // exit code and duration line after each command
+    command_summary: bool,
+    // how to note why a command failed, shown after it
+    note_prompt: Option<String>,
+    // alerts on [watch] patterns in command output
+    output_watch: Option<OutputWatch>,
+    // timed recording of the screen, for replay and animated export
+    cast: Arc<Mutex<Option<CastWriter>>>,
+    // which commands are recorded with output, without it, or not at all
+    recording_policy: RecordingPolicy,
+    // keystrokes kept next to the cast
+    input_recording: InputRecording,
+    // how command boundaries are found
+    detection: Detection,
+    // record through shell hooks alone, without a pty
+    hook_only: bool,
+    // program and arguments run instead of an interactive shell, e.g. docker exec
+    command: Option<Vec<String>>,
+    // the config file, followed for settings that can change mid-session
+    config_reload: Option<ConfigReload>,
+    // started with --force-nested in a shell another session records
+    nested: bool,
 }
 
 impl PtySession {
@@ -28,6 +293,26 @@ impl PtySession {
             verbose,
             session_manager: None,
             terminated: Arc::new(AtomicBool::new(false)),
+            hung_up: Arc::new(AtomicBool::new(false)),
+            hotkeys: HotkeyConfig::default(),
+            paused: Arc::new(AtomicBool::new(false)),
+            child_paste_mode: Arc::new(AtomicBool::new(false)),
+            child_mouse_mode: Arc::new(Mutex::new(MouseMode::default())),
+            resync_size: Arc::new(AtomicBool::new(false)),
+            split_stderr: false,
+            output_filters: None,
+            highlight_errors: false,
+            command_summary: false,
+            note_prompt: None,
+            output_watch: None,
+            cast: Arc::new(Mutex::new(None)),
+            recording_policy: RecordingPolicy::default(),
+            input_recording: InputRecording::Off,
+            detection: Detection::HookMarkers,
+            hook_only: false,
+            command: None,
+            config_reload: None,
+            nested: false,
         }
     }
 
@@ -39,35 +324,227 @@ impl PtySession {
             verbose,
             session_manager: Some(session_manager),
             terminated: Arc::new(AtomicBool::new(false)),
+            hung_up: Arc::new(AtomicBool::new(false)),
+            hotkeys: HotkeyConfig::default(),
+            paused: Arc::new(AtomicBool::new(false)),
+            child_paste_mode: Arc::new(AtomicBool::new(false)),
+            child_mouse_mode: Arc::new(Mutex::new(MouseMode::default())),
+            resync_size: Arc::new(AtomicBool::new(false)),
+            split_stderr: false,
+            output_filters: None,
+            highlight_errors: false,
+            command_summary: false,
+            note_prompt: None,
+            output_watch: None,
+            cast: Arc::new(Mutex::new(None)),
+            recording_policy: RecordingPolicy::default(),
+            input_recording: InputRecording::Off,
+            detection: Detection::HookMarkers,
+            hook_only: false,
+            command: None,
+            config_reload: None,
+            nested: false,
         }
     }
 
+    /// use the given hotkey bindings instead of the (disabled) defaults
+    pub fn with_hotkeys(mut self, hotkeys: HotkeyConfig) -> Self {
+        self.hotkeys = hotkeys;
+        self
+    }
+
+    /// filter output through the given wasm modules (needs command logging)
+    pub fn with_output_filters(mut self, filters: Option<FilterChain>) -> Self {
+        self.output_filters = filters;
+        self
+    }
+
+    /// put a colored bar before error lines of command output (needs command logging)
+    pub fn with_error_highlighting(mut self, enabled: bool) -> Self {
+        self.highlight_errors = enabled;
+        self
+    }
+
+    /// print exit code and duration after each command (needs command logging)
+    pub fn with_command_summary(mut self, enabled: bool) -> Self {
+        self.command_summary = enabled;
+        self
+    }
+
+    /// after a failed command, show how to add a note to it (needs command logging)
+    pub fn with_note_prompt(mut self, hint: Option<String>) -> Self {
+        self.note_prompt = hint;
+        self
+    }
+
+    /// alert on output lines matching watched patterns (needs command logging)
+    pub fn with_output_watch(mut self, watch: Option<OutputWatch>) -> Self {
+        self.output_watch = watch;
+        self
+    }
+
+    /// record commands only as far as the policy allows (needs command logging)
+    pub fn with_recording_policy(mut self, policy: RecordingPolicy) -> Self {
+        self.recording_policy = policy;
+        self
+    }
+
+    /// record typed keys, or only their timing, into the session's input stream (needs command logging)
+    pub fn with_input_recording(mut self, mode: InputRecording) -> Self {
+        self.input_recording = mode;
+        self
+    }
+
+    /// find commands with osc 133 marks or prompt patterns instead of recli's zsh hook
+    pub fn with_detection(mut self, detection: Detection) -> Self {
+        self.detection = detection;
+        self
+    }
+
+    /// skip the pty and record commands through bash or zsh hooks alone; their output isn't kept
+    pub fn with_hook_only(mut self, enabled: bool) -> Self {
+        self.hook_only = enabled;
+        self
+    }
+
+    /// record each command's stderr separately as well (needs the zsh hook)
+    pub fn with_split_stderr(mut self, enabled: bool) -> Self {
+        self.split_stderr = enabled;
+        self
+    }
+
+    // where the hook should tee stderr, when capture is on and a session is recording
+    fn stderr_capture_file(&self) -> Option<PathBuf> {
+        if !self.split_stderr {
+            return None;
+        }
+        let sm = self.session_manager.as_ref()?.lock().ok()?;
+        sm.log_dir().map(|dir| dir.join(crate::command_log::STDERR_FILE))
+    }
+
+    /// run `argv` in the pty instead of the shell; there is no hook, so commands inside it are
+    /// only found with osc 133 or prompt-regex detection
+    pub fn with_command(mut self, argv: Vec<String>) -> Self {
+        self.command = Some(argv).filter(|a| !a.is_empty());
+        self
+    }
+
+    /// read the config again when it changes or `recli config reload` asks (needs command logging)
+    pub fn with_config_reload(mut self, reload: ConfigReload) -> Self {
+        self.config_reload = Some(reload);
+        self
+    }
+
+    /// a session inside another's shell: its shell gets its own pid file, so recli commands run
+    /// there address it, and a marker namespace
+    pub fn with_nested(mut self, nested: bool) -> Self {
+        self.nested = nested;
+        self
+    }
+
+    // what the shell is told of the session recording it. Variables an outer session's shell
+    // passed down are dropped (None) rather than left to point at that session's files.
+    fn session_env(&self) -> Vec<(&'static str, Option<String>)> {
+        let (pid_file, log_base) = self
+            .session_manager
+            .as_ref()
+            .and_then(|sm| sm.lock().ok())
+            .map(|sm| {
+                let base = sm.log_dir().and_then(Path::parent).map(|d| d.to_string_lossy().to_string());
+                (Some(sm.pid_file().to_string_lossy().to_string()), base)
+            })
+            .unwrap_or_default();
+        let mut env = vec![
+            ("RECLI_SESSION_ID", self.session_id()),
+            ("RECLI_PID_FILE", pid_file.filter(|_| self.nested)),
+            ("RECLI_MARKER_NS", self.marker_namespace()),
+            ("RECLI_MARKER_FILE", None),
+            ("RECLI_STDERR_FILE", None),
+            ("RECLI_DEBUG_MARKERS", None),
+        ];
+        // where sessions go, as --log-dir would say; the session's own directory is in it under its id
+        if let Some(base) = log_base {
+            env.push(("RECLI_LOG_DIR", Some(base)));
+        }
+        env
+    }
+
+    fn session_id(&self) -> Option<String> {
+        let sm = self.session_manager.as_ref()?.lock().ok()?;
+        sm.current_config().map(|c| c.session_id)
+    }
+
+    // a nested session's hook marks its markers with the session id
+    fn marker_namespace(&self) -> Option<String> {
+        self.session_id().filter(|_| self.nested)
+    }
+
     /// start and run the PTY session
     pub async fn run(&mut self, shell: &str) -> Result<()> {
-    self.verbose_print(&format!("starting pty session with shell: {}", shell));
+    tracing::debug!(shell, "starting pty session");
+
+        // reject bad bindings before the terminal is switched to raw mode
+        let hotkeys = HotkeyMatcher::from_config(&self.hotkeys)
+            .map_err(|e| RecliError::Session(format!("invalid hotkey config: {}", e)))?;
+
+        if self.hook_only && self.command.is_none() {
+            return self.run_hook_only(shell, None).await;
+        }
 
         // create PTY system and get terminal size
         let pty_system = portable_pty::native_pty_system();
         let pty_size = self.get_terminal_size()?;
 
-        // create PTY pair and spawn shell
-        let pty_pair = pty_system
-            .openpty(pty_size)
-            .map_err(|e| RecliError::Pty(e.into()))?;
+        // create PTY pair and spawn shell; without one, shell hooks still record the commands
+        let pty_pair = match pty_system.openpty(pty_size) {
+            Ok(pair) => pair,
+            Err(e) if self.session_manager.is_some() && self.command.is_none() => return self.run_hook_only(shell, Some(e.to_string())).await,
+            Err(e) => return Err(RecliError::Pty(e.into())),
+        };
+        self.send_log_event(LogEvent::Resize {
+            cols: pty_size.cols,
+            rows: pty_size.rows,
+        });
+        let log_dir = self
+            .session_manager
+            .as_ref()
+            .and_then(|sm| sm.lock().ok().and_then(|sm| sm.log_dir().map(|d| d.to_path_buf())));
+        let mut input_recorder = None;
+        if let Some(log_dir) = log_dir {
+            match CastWriter::open(&log_dir, pty_size.cols, pty_size.rows, shell) {
+                Ok(cast) => self.cast = Arc::new(Mutex::new(Some(cast))),
+                Err(e) => tracing::warn!(error = %e, "not recording a cast of this session"),
+            }
+            match InputRecorder::open(&log_dir, self.input_recording, pty_size.cols, pty_size.rows) {
+                Ok(recorder) => input_recorder = recorder,
+                Err(e) => tracing::warn!(error = %e, "not recording input of this session"),
+            }
+        }
 
+        let session_env = self.session_env();
         let mut cmd = CommandBuilder::new(shell);
+        set_env(&mut cmd, &session_env);
         // ensure child shell is interactive so it displays a prompt and processes commands
         cmd.cwd(std::env::current_dir()?);
         if let Ok(term) = std::env::var("TERM") {
             cmd.env("TERM", term);
         }
 
+        // a wrapped program such as docker exec runs as given, without any hook
+        if let Some(argv) = &self.command {
+            cmd = CommandBuilder::from_argv(argv.iter().map(Into::into).collect());
+            set_env(&mut cmd, &session_env);
+            cmd.cwd(std::env::current_dir()?);
+            if let Ok(term) = std::env::var("TERM") {
+                cmd.env("TERM", term);
+            }
         // launch zsh with a controlled zdotdir so our hook always loads without touching user config
-        if shell.contains("zsh") {
+        } else if shell.contains("zsh") && self.detection.uses_hook() {
             match Self::ensure_zsh_bootstrap_files() {
                 Ok(zdotdir) => {
                     // rebuild command to force zsh to read $ZDOTDIR/.zshrc
                     let mut z = CommandBuilder::new("zsh");
+                    set_env(&mut z, &session_env);
                     // preserve environment like TERM
                     if let Ok(term) = std::env::var("TERM") {
                         z.env("TERM", term);
@@ -75,23 +552,33 @@ impl PtySession {
                     if let Ok(cwd) = std::env::current_dir() {
                         z.cwd(cwd);
                     }
+                    match user_zdotdir(std::env::var("ZDOTDIR").ok().as_deref(), &zdotdir) {
+                        Some(dir) => z.env("RECLI_USER_ZDOTDIR", dir),
+                        None => z.env_remove("RECLI_USER_ZDOTDIR"),
+                    }
                     z.env("ZDOTDIR", zdotdir.to_string_lossy().to_string());
+                    if let Some(file) = self.stderr_capture_file() {
+                        z.env("RECLI_STDERR_FILE", file.to_string_lossy().to_string());
+                    }
                     // enable debug marker emission from hook when verbose
                     if self.verbose {
                         z.env("RECLI_DEBUG_MARKERS", "1");
                     }
                     // show which zdotdir we are using in verbose mode for easy verification
-                    self.verbose_print(&format!("Using ZDOTDIR: {}", zdotdir.display()));
+                    tracing::debug!(zdotdir = %zdotdir.display(), "using zsh bootstrap");
                     z.arg("-i");
                     cmd = z;
                 }
                 Err(e) => {
-                    self.verbose_print(&format!("failed to prepare zsh bootstrap files: {}", e));
+                    tracing::warn!("failed to prepare zsh bootstrap files: {}", e);
                     // fallback to interactive shell
                     cmd.arg("-i");
                 }
             }
         } else {
+            if self.split_stderr {
+                tracing::warn!("split_stderr needs the zsh hook; stderr stays merged");
+            }
             // fallback to user's shell interactively
             cmd.arg("-i");
             // enable debug marker emission from hook when verbose
@@ -105,39 +592,24 @@ impl PtySession {
             .spawn_command(cmd)
             .map_err(|e| RecliError::Pty(e.into()))?;
 
-        self.verbose_print(&format!(
-            "pty session started with pid: {:?}",
-            child.process_id()
-        ));
+        tracing::debug!(pid = ?child.process_id(), "pty session started");
+        if let Some(pid) = child.process_id() {
+            self.send_log_event(LogEvent::ShellStarted { pid });
+        }
 
         // set up terminal for raw input
-        enable_raw_mode().map_err(|e| RecliError::Terminal(format!("{:?}", e.kind())))?;
+        enable_raw_mode().map_err(RecliError::Terminal)?;
 
         // get PTY handles
         let mut pty_reader = pty_pair
             .master
             .try_clone_reader()
             .map_err(|e| RecliError::Pty(e.into()))?;
-        let mut pty_writer = pty_pair
+        let pty_writer = pty_pair
             .master
             .take_writer()
             .map_err(|e| RecliError::Pty(e.into()))?;
 
-        // forward raw stdin bytes to the pty to preserve all control/meta sequences and ime input
-        // this avoids lossy translation of key events
-        let mut stdin = std::io::stdin();
-        std::thread::spawn(move || {
-            let mut buf = [0u8; 8192];
-            loop {
-                match stdin.read(&mut buf) {
-                    Ok(0) => break, // eof
-                    Ok(n) => {
-                        let _ = pty_writer.write_all(&buf[..n]);
-                    }
-                    Err(_) => break,
-                }
-            }
-        });
 
         // spawn background task for pty output and run it through the command detector
         // this allows us to infer command boundaries from prompts and capture full output reliably
@@ -145,19 +617,64 @@ impl PtySession {
         // create a shared detector instance with optional debug
         let detector_shared: Option<Arc<Mutex<CommandDetector>>> = sm_for_output
             .as_ref()
-            .map(|sm| Arc::new(Mutex::new(CommandDetector::new_with_debug(sm.clone(), self.verbose))));
+            .map(|sm| {
+                let detector = CommandDetector::new(sm.clone())
+                    .with_filters(self.output_filters.take())
+                    .with_error_highlighting(self.highlight_errors)
+                    .with_command_summary(self.command_summary)
+                    .with_note_prompt(self.note_prompt.clone())
+                    .with_watch(self.output_watch.take())
+                    .with_policy(std::mem::take(&mut self.recording_policy))
+                    .with_detection(std::mem::take(&mut self.detection))
+                    .with_marker_namespace(self.marker_namespace());
+                Arc::new(Mutex::new(detector))
+            });
+
+        // forward raw stdin bytes to the pty to preserve all control/meta sequences and ime input
+        // this avoids lossy translation of key events
+        // configured hotkeys are matched on typed bytes; pastes and everything else pass through
+        let forwarder = InputForwarder {
+            writer: pty_writer,
+            hotkeys,
+            pastes: PasteSplitter::default(),
+            paste_buf: Vec::new(),
+            paste_len: 0,
+            paste_framed: false,
+            child_paste_mode: self.child_paste_mode.clone(),
+            child_mouse_mode: self.child_mouse_mode.clone(),
+            resync_size: self.resync_size.clone(),
+            terminated: self.terminated.clone(),
+            hung_up: self.hung_up.clone(),
+            paused: self.paused.clone(),
+            session_manager: self.session_manager.clone(),
+            input_recorder,
+            pty_fd: pty_pair.master.as_raw_fd(),
+            note: None,
+            detector: detector_shared.clone(),
+        };
+        let sequence_timeout_ms = self.hotkeys.sequence_timeout_ms;
+        let input_thread = std::thread::spawn(move || forwarder.run(sequence_timeout_ms));
 
         let detector_for_output = detector_shared.clone();
-        let verbose_flag = self.verbose;
-        let output_task = tokio::spawn(async move {
+        let paused_for_output = self.paused.clone();
+        let paste_mode_for_output = self.child_paste_mode.clone();
+        let mouse_mode_for_output = self.child_mouse_mode.clone();
+        let cast_for_output = self.cast.clone();
+        let dropped_for_output = self
+            .session_manager
+            .as_ref()
+            .and_then(|sm| sm.lock().ok().map(|sm| sm.output_dropped()));
+        // blocking reads get their own thread so they never starve the async log writer
+        let output_task = tokio::task::spawn_blocking(move || {
             let mut buffer = [0u8; 8192];
+            let mut paste_mode = PasteModeTracker::default();
+            let mut mouse_mode = MouseModeTracker::default();
+            let mut was_dropped = false;
             loop {
                 match pty_reader.read(&mut buffer) {
                     Ok(0) => {
                         // eof shell exited
-                        if verbose_flag {
-                            eprintln!("[pty] eof detected, shell exited");
-                        }
+                        tracing::debug!("eof on pty, shell exited");
                         // flush any active command so it gets recorded
                         if let Some(det) = &detector_for_output {
                             if let Ok(mut det) = det.lock() {
@@ -168,52 +685,101 @@ impl PtySession {
                     }
                     Ok(n) => {
                         // feed through detector to emit structured events while preserving original output
-                        if verbose_flag {
-                            eprintln!("[pty] read {} bytes from pty", n);
+                        tracing::trace!(bytes = n, "read from pty");
+                        paste_mode.observe(&buffer[..n]);
+                        paste_mode_for_output.store(paste_mode.enabled(), Ordering::Relaxed);
+                        mouse_mode.observe(&buffer[..n]);
+                        if let Ok(mut mode) = mouse_mode_for_output.lock() {
+                            *mode = mouse_mode.mode();
                         }
+                        let mut withheld = false;
+                        let mut ended = Vec::new();
                         let processed = if let Some(det) = &detector_for_output {
                             if let Ok(mut det) = det.lock() {
-                                det.process_output(&buffer[..n])
+                                det.set_paused(paused_for_output.load(Ordering::Relaxed));
+                                let processed = det.process_output(&buffer[..n]);
+                                withheld = det.output_withheld();
+                                ended = det.take_ended();
+                                processed
                             } else {
                                 OutputHandler::process_output(&buffer[..n])
                             }
                         } else {
                             OutputHandler::process_output(&buffer[..n])
                         };
+                        let dropped = dropped_for_output.as_ref().is_some_and(|d| d.load(Ordering::Relaxed));
+                        if dropped != was_dropped {
+                            was_dropped = dropped;
+                            let notice: &[u8] = if dropped {
+                                b"\r\n\x1b[33m[recli] log space limit reached: recording commands without their output\x1b[0m\r\n"
+                            } else {
+                                b"\r\n\x1b[33m[recli] recording command output again\x1b[0m\r\n"
+                            };
+                            let _ = OutputHandler::forward_to_stdout(notice);
+                        }
+                        if let Some(cast) = cast_for_output.lock().ok().as_mut().and_then(|c| c.as_mut()) {
+                            if !paused_for_output.load(Ordering::Relaxed) && !dropped && !withheld {
+                                cast.output(&processed);
+                            }
+                            // marked even when the output isn't recorded, to keep markers and commands in step
+                            for cmd in &ended {
+                                cast.marker(cmd);
+                            }
+                        }
                         if OutputHandler::forward_to_stdout(&processed).is_err() {
                             break;
                         }
                     }
                     Err(e) => {
-                        if verbose_flag {
-                            eprintln!("[pty] error reading from pty: {}", e);
-                        }
+                        tracing::warn!("error reading from pty: {}", e);
                         break;
                     }
                 }
             }
         });
 
-        // listen for sigterm to end gracefully
+        // listen for sigterm to end gracefully, and for sighup when the terminal is closed
         #[cfg(unix)]
         {
+            use tokio::signal::unix::{signal, SignalKind};
             let term_flag = self.terminated.clone();
-            tokio::spawn(async move {
-                if let Ok(mut sig) = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
-                    let _ = sig.recv().await;
+            let hup_flag = self.hung_up.clone();
+            if let (Ok(mut term), Ok(mut hup)) = (signal(SignalKind::terminate()), signal(SignalKind::hangup())) {
+                tokio::spawn(async move {
+                    tokio::select! {
+                        _ = term.recv() => {
+                            tracing::info!("received termination signal, ending session");
+                        }
+                        _ = hup.recv() => {
+                            // nothing can be printed to a closed terminal
+                            hup_flag.store(true, Ordering::Relaxed);
+                        }
+                    }
                     term_flag.store(true, Ordering::Relaxed);
-                    eprintln!("[recli] received termination signal, ending session");
-                }
-            });
+                });
+            }
         }
 
         // input handling loop
         let result = self
-            .input_loop(&mut child, &pty_pair)
+            .input_loop(&mut child, &pty_pair, detector_shared.as_ref())
             .await;
 
         // cleanup
-        disable_raw_mode().map_err(|e| RecliError::Terminal(format!("{:?}", e.kind())))?;
+        if self.hung_up.load(Ordering::Relaxed) {
+            tracing::info!("terminal hung up, finalizing session");
+            detach_from_terminal();
+            self.hang_up_child(&mut child).await;
+        } else {
+            disable_raw_mode().map_err(RecliError::Terminal)?;
+            // a child killed mid-run can leave the real terminal reporting mouse events
+            if self.child_mouse_mode.lock().map(|m| m.reporting).unwrap_or(false) {
+                let _ = OutputHandler::forward_to_stdout(recli_io::mouse_modes_off());
+            }
+        }
+        // stop forwarding keys so nothing typed from here on goes to the dead shell
+        self.terminated.store(true, Ordering::Relaxed);
+        let _ = input_thread.join();
         // flush the detector to end any open command before aborting output task
         if let Some(det) = &detector_shared {
             if let Ok(mut det) = det.lock() {
@@ -222,16 +788,59 @@ impl PtySession {
         }
         output_task.abort();
 
+        self.stop_session().await;
+
+    tracing::debug!("pty session ended");
+        result
+    }
+
     // persist logs by stopping the session when we own it
-            if let Some(sm) = &self.session_manager {
-                if let Ok(mut sm) = sm.lock() {
-                    if let Ok(Some(log_dir)) = sm.stop_session_async().await {
-                        println!("\rsession ended, logs saved to: {}", log_dir.display());
-                    }
-                }
+    async fn stop_session(&mut self) {
+        // the manager is taken out of the mutex so no lock is held while the log task drains
+        let owned = self
+            .session_manager
+            .as_ref()
+            .and_then(|sm| sm.lock().ok().map(|mut sm| std::mem::take(&mut *sm)));
+        if let Some(mut sm) = owned {
+            if let Ok(Some(log_dir)) = sm.stop_session_async().await {
+                println!("\rsession ended, logs saved to: {}", log_dir.display());
+            }
+        }
+    }
+
+    // record with shell hooks writing to a file: commands, exit codes and directories, no output
+    async fn run_hook_only(&mut self, shell: &str, reason: Option<String>) -> Result<()> {
+        let sm = self
+            .session_manager
+            .clone()
+            .ok_or_else(|| RecliError::Session("hook-only recording needs a session".to_string()))?;
+        let log_dir = sm
+            .lock()
+            .ok()
+            .and_then(|sm| sm.log_dir().map(|d| d.to_path_buf()))
+            .ok_or_else(|| RecliError::Session("no active session directory".to_string()))?;
+        match reason {
+            Some(reason) => {
+                tracing::warn!(reason, "no pty, recording with shell hooks only");
+                eprintln!("[recli] can't open a pseudo-terminal ({}); recording with shell hooks only", reason);
             }
+            None => eprintln!("[recli] recording with shell hooks only"),
+        }
+        eprintln!("[recli] commands, exit codes and directories are kept; their output, the cast and keystrokes are not");
 
-    self.verbose_print("pty session ended");
+        let zdotdir = if shell.contains("zsh") {
+            Self::ensure_zsh_bootstrap_files()
+                .map_err(|e| tracing::warn!("failed to prepare zsh bootstrap files: {}", e))
+                .ok()
+        } else {
+            None
+        };
+        let session_env = self.session_env();
+        let mut detector = CommandDetector::new(sm)
+            .with_policy(std::mem::take(&mut self.recording_policy))
+            .with_marker_namespace(self.marker_namespace());
+        let result = crate::hook_only::run(shell, &log_dir, zdotdir, &session_env, &mut detector).await;
+        self.stop_session().await;
         result
     }
 
@@ -240,47 +849,106 @@ impl PtySession {
         &mut self,
         child: &mut Box<dyn portable_pty::Child + Send + Sync>,
         pty_pair: &portable_pty::PtyPair,
+        detector: Option<&Arc<Mutex<CommandDetector>>>,
     ) -> Result<()> {
+        // the terminal may have been resized between openpty and spawning the child
+        let mut last_size = self.resync_pty_size(pty_pair)?;
+        let mut last_resync = Instant::now();
+        let mut last_config_check = Instant::now();
+        let reload_requested = self
+            .session_manager
+            .as_ref()
+            .and_then(|sm| sm.lock().ok().map(|sm| sm.reload_requested()));
+        #[cfg(unix)]
+        let parent = unsafe { libc::getppid() };
         loop {
             // honour termination flag set by sigterm handler
             if self.terminated.load(Ordering::Relaxed) {
                 break;
             }
+            // reparented: the terminal emulator or shell that started us is gone
+            #[cfg(unix)]
+            if unsafe { libc::getppid() } != parent {
+                self.hung_up.store(true, Ordering::Relaxed);
+                break;
+            }
             // if shell process is still alive
             if let Ok(Some(exit_status)) = child.try_wait() {
-                self.verbose_print(&format!(
-                    "shell process exited with status: {:?}",
-                    exit_status
-                ));
+                tracing::debug!(status = ?exit_status, "shell process exited");
                 break;
             }
 
-            // poll for input events (nonblocking with timeout)
-            if event::poll(Duration::from_millis(50))
-                .map_err(|e| RecliError::Terminal(format!("{:?}", e.kind())))?
+            // stdin belongs to the raw forwarder thread, so crossterm must not read events from it;
+            // resizes are picked up by comparing the terminal size instead
+            if self.resync_size.swap(false, Ordering::Relaxed)
+                || last_resync.elapsed() >= SIZE_RESYNC_INTERVAL
             {
-                match event::read().map_err(|e| RecliError::Terminal(format!("{:?}", e.kind())))? {
-                    Event::Key(_key_event) => {
-                        // ignore key events here, raw stdin forwarder handles input
-                    }
-                    Event::Resize(cols, rows) => {
-                        self.handle_resize(cols, rows, pty_pair)?;
-                    }
-                    Event::Mouse(_) => {
-                        // ignore mouse events for now
-                    }
-                    Event::FocusGained | Event::FocusLost => {
-                        // ignore focus events
-                    }
-                    Event::Paste(_text) => {
-                        // ignore explicit paste events; raw stdin forwarder already sends bytes
-                    }
+                last_size = self.resync_pty_size(pty_pair)?;
+                last_resync = Instant::now();
+            } else if let Ok(size) = crossterm::terminal::size() {
+                if size != last_size {
+                    self.handle_resize(size.0, size.1, pty_pair)?;
+                    last_size = size;
                 }
             }
+            let asked = reload_requested.as_ref().is_some_and(|r| r.swap(false, Ordering::Relaxed));
+            if asked || last_config_check.elapsed() >= CONFIG_CHECK_INTERVAL {
+                last_config_check = Instant::now();
+                self.reload_config(asked, detector);
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
         }
         Ok(())
     }
 
+    // read the config again when asked or when it changed, and swap what changed of the output
+    // settings into the detector
+    fn reload_config(&mut self, asked: bool, detector: Option<&Arc<Mutex<CommandDetector>>>) {
+        let Some(reload) = self.config_reload.as_mut() else { return };
+        if !reload.changed() && !asked {
+            return;
+        }
+        let reloaded = match reload.reload() {
+            Ok((reloaded, output)) => {
+                if let Some((filters, watch)) = output {
+                    if let Some(mut det) = detector.and_then(|d| d.lock().ok()) {
+                        det.reconfigure(filters, watch);
+                    }
+                }
+                tracing::info!(applied = ?reloaded.applied, restart = ?reloaded.restart, "config reloaded");
+                reloaded
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "config not reloaded");
+                // nothing else tells someone who just saved the file that it didn't take
+                if !asked {
+                    let notice = format!("\r\n\x1b[33m[recli] config not reloaded: {}\x1b[0m\r\n", e);
+                    let _ = OutputHandler::forward_to_stdout(notice.as_bytes());
+                }
+                ConfigReloaded { error: Some(e), ..ConfigReloaded::default() }
+            }
+        };
+        self.send_log_event(LogEvent::ConfigReloaded(reloaded));
+    }
+
+    // pass the hangup on to the shell, as a terminal would, and give it a moment to exit
+    async fn hang_up_child(&self, child: &mut Box<dyn portable_pty::Child + Send + Sync>) {
+        #[cfg(unix)]
+        if let Some(pid) = child.process_id() {
+            unsafe {
+                libc::kill(pid as i32, libc::SIGHUP);
+            }
+        }
+        let deadline = Instant::now() + CHILD_HANGUP_GRACE;
+        while Instant::now() < deadline {
+            if !matches!(child.try_wait(), Ok(None)) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        let _ = child.kill();
+    }
+
     /// handle terminal resize events
     fn handle_resize(&self, cols: u16, rows: u16, pty_pair: &portable_pty::PtyPair) -> Result<()> {
         let new_size = PtySize {
@@ -295,14 +963,38 @@ impl PtySession {
             .resize(new_size)
             .map_err(|e| RecliError::Pty(e.into()))?;
 
-    self.verbose_print(&format!("terminal resized to {}x{}", cols, rows));
+    tracing::debug!(cols, rows, "terminal resized");
+        self.send_log_event(LogEvent::Resize { cols, rows });
+        if let Some(cast) = self.cast.lock().ok().as_mut().and_then(|c| c.as_mut()) {
+            cast.resize(cols, rows);
+        }
         Ok(())
     }
 
+    /// resize the pty if it disagrees with the real terminal; returns the terminal size
+    fn resync_pty_size(&self, pty_pair: &portable_pty::PtyPair) -> Result<(u16, u16)> {
+        let Ok(size) = crossterm::terminal::size() else {
+            return Ok((0, 0));
+        };
+        let current = pty_pair.master.get_size().map(|s| (s.cols, s.rows)).ok();
+        if current != Some(size) {
+            self.handle_resize(size.0, size.1, pty_pair)?;
+        }
+        Ok(size)
+    }
+
+    fn send_log_event(&self, event: LogEvent) {
+        if let Some(sm) = &self.session_manager {
+            if let Ok(sm) = sm.lock() {
+                sm.send_log_event(event);
+            }
+        }
+    }
+
     /// get current terminal size
     fn get_terminal_size(&self) -> Result<PtySize> {
         let (cols, rows) = crossterm::terminal::size()
-            .map_err(|e| RecliError::Terminal(format!("{:?}", e.kind())))?;
+            .map_err(RecliError::Terminal)?;
 
         Ok(PtySize {
             rows,
@@ -312,104 +1004,449 @@ impl PtySession {
         })
     }
 
-    /// print verbose message if verbose mode is enabled
-    fn verbose_print(&self, message: &str) {
-        if self.verbose {
-            eprintln!("[recli] {}", message);
+    // command lifecycle is managed by command_detector from pty output
+}
+
+// point stdio at /dev/null once the terminal is gone, so finishing up can't fail on writes
+fn detach_from_terminal() {
+    #[cfg(unix)]
+    if let Ok(null) = fs::OpenOptions::new().read(true).write(true).open("/dev/null") {
+        use std::os::unix::io::AsRawFd;
+        for fd in 0..=2 {
+            unsafe {
+                libc::dup2(null.as_raw_fd(), fd);
+            }
         }
     }
-
-    // command lifecycle is managed by command_detector from pty output
 }
 
-impl PtySession {
-        fn ensure_zsh_bootstrap_files() -> std::io::Result<std::path::PathBuf> {
-            use std::fs;
-                use std::path::PathBuf;
+// how long the shell gets to exit after being sent sighup
+const CHILD_HANGUP_GRACE: Duration = Duration::from_secs(2);
+// full size check against the pty, catching changes the cheap poll missed (e.g. multiplexer reattach).
+// recli doesn't turn on focus reporting (`?1004`) itself: a shell that never asked for it would be
+// sent the focus sequences as input, so this is the one check that always runs
+const SIZE_RESYNC_INTERVAL: Duration = Duration::from_secs(2);
+// focus-in, reported only while the child has focus reporting on; it checks the size early
+const FOCUS_IN: &[u8] = b"\x1b[I";
+// how often the config file is checked for changes
+const CONFIG_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+// how often the idle input thread looks up from stdin to see whether the session ended
+const INPUT_POLL_MS: u64 = 100;
 
-                let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
-                let dir = PathBuf::from(&home).join(".recli");
-                fs::create_dir_all(&dir)?;
+// how long a possible paste marker cut across reads is held before it is sent as typed input
+const PASTE_HOLD_MS: u64 = 50;
+// pasted text kept in the log per paste, like the output preview cap
+const PASTE_LOG_CAP: usize = 8 * 1024;
 
-                                // hook file that emits markers to stderr; always write latest version
-                                let hook = dir.join("recli.zsh");
-                                let hook_content = r#"# --- recli hook (v5) ---
-                # state tracking
-                typeset -g RECLI_INITIALIZED=0
-                typeset -g RECLI_IN_COMMAND=0
-                typeset -g RECLI_LAST_CMD=""
+// state owned by the stdin forwarding thread
+struct InputForwarder {
+    writer: Box<dyn Write + Send>,
+    hotkeys: HotkeyMatcher,
+    pastes: PasteSplitter,
+    paste_buf: Vec<u8>,
+    paste_len: usize,
+    // the current paste was sent with framing because the child asked for it
+    paste_framed: bool,
+    child_paste_mode: Arc<AtomicBool>,
+    child_mouse_mode: Arc<Mutex<MouseMode>>,
+    resync_size: Arc<AtomicBool>,
+    terminated: Arc<AtomicBool>,
+    hung_up: Arc<AtomicBool>,
+    paused: Arc<AtomicBool>,
+    session_manager: Option<Arc<Mutex<SessionManager>>>,
+    input_recorder: Option<InputRecorder>,
+    // the pty, to tell whether the child has echo off
+    pty_fd: Option<i32>,
+    // a note being typed after the note hotkey; keys go here instead of to the shell
+    note: Option<Vec<u8>>,
+    // applies the recording policy and output filters to pasted text
+    detector: Option<Arc<Mutex<CommandDetector>>>,
+}
 
-                # emit marker to stderr
-                function _recli_emit() {
-                    local marker="$1"
-                    printf '\x1e%s\n' "$marker" >&2
+impl InputForwarder {
+    fn run(mut self, sequence_timeout_ms: u64) {
+        let mut stdin = std::io::stdin();
+        let mut buf = [0u8; 8192];
+        loop {
+            // the session is over; stdin goes back to whoever asks next (e.g. the upload prompt)
+            if self.terminated.load(Ordering::Relaxed) {
+                break;
+            }
+            // held bytes go out as-is when their continuation doesn't arrive in time
+            if self.hotkeys.has_pending() || self.pastes.has_held() {
+                let timeout = if self.hotkeys.has_pending() {
+                    sequence_timeout_ms
+                } else {
+                    PASTE_HOLD_MS
+                };
+                if !recli_io::stdin_ready(timeout) {
+                    for chunk in self.pastes.flush() {
+                        self.handle_chunk(chunk);
+                    }
+                    let pending = self.hotkeys.flush();
+                    self.type_keys(&pending);
+                    continue;
+                }
+            } else if !recli_io::stdin_ready(INPUT_POLL_MS) {
+                continue;
+            }
+            match stdin.read(&mut buf) {
+                Ok(n) if n > 0 => {
+                    for chunk in self.pastes.feed(&buf[..n]) {
+                        self.handle_chunk(chunk);
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                // in raw mode ctrl-d is a byte, so eof or eio means the terminal was closed
+                _ => {
+                    self.hung_up.store(true, Ordering::Relaxed);
+                    self.terminated.store(true, Ordering::Relaxed);
+                    break;
                 }
+            }
+        }
+    }
 
-                # preexec: start of command
-                function _recli_preexec() {
-                    if (( RECLI_INITIALIZED == 1 )); then
-                        RECLI_IN_COMMAND=1
-                        RECLI_LAST_CMD="$1"
-                        _recli_emit "RECLI_START:$1"
-                    fi
+    fn handle_chunk(&mut self, chunk: InputChunk) {
+        match chunk {
+            InputChunk::Keys(bytes) => {
+                for chunk in recli_io::split_mouse(&bytes) {
+                    match chunk {
+                        InputChunk::Mouse(event) => self.forward_mouse(event),
+                        InputChunk::Keys(keys) => {
+                            // focus-in (only sent when the child enabled focus reporting),
+                            // a head start on the periodic resync
+                            if keys.windows(FOCUS_IN.len()).any(|w| w == FOCUS_IN) {
+                                self.resync_size.store(true, Ordering::Relaxed);
+                            }
+                            if self.note.is_some() {
+                                self.edit_note(&keys);
+                                continue;
+                            }
+                            let (forward, actions) = self.hotkeys.feed(&keys);
+                            self.type_keys(&forward);
+                            for action in actions {
+                                self.handle_action(action);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            InputChunk::Mouse(event) => self.forward_mouse(event),
+            InputChunk::PasteStart => {
+                // keys typed before the paste are not part of a hotkey
+                let pending = self.hotkeys.flush();
+                self.type_keys(&pending);
+                // keep framing only if the child enabled bracketed paste, otherwise it would see raw escapes
+                self.paste_framed = self.child_paste_mode.load(Ordering::Relaxed);
+                if self.paste_framed {
+                    self.write(paste_framing(true));
+                }
+                self.paste_buf.clear();
+                self.paste_len = 0;
+            }
+            InputChunk::PasteData(bytes) => {
+                self.write(&bytes);
+                self.paste_len += bytes.len();
+                let room = PASTE_LOG_CAP.saturating_sub(self.paste_buf.len());
+                self.paste_buf
+                    .extend_from_slice(&bytes[..bytes.len().min(room)]);
+            }
+            InputChunk::PasteEnd => {
+                if self.paste_framed {
+                    self.write(paste_framing(false));
                 }
+                let hidden = self.pty_fd.is_some_and(recli_io::input_hidden);
+                let paused = self.paused.load(Ordering::Relaxed);
+                if let Some(recorder) = self.input_recorder.as_mut().filter(|_| !paused) {
+                    recorder.paste(&self.paste_buf, hidden);
+                }
+                // a password, a paste while paused or one the policy keeps out is logged by its size only
+                let text = match &self.detector {
+                    _ if hidden || paused => None,
+                    Some(det) => det.lock().ok().and_then(|mut det| det.paste_text(self.paste_buf.clone())),
+                    None => Some(String::from_utf8_lossy(&self.paste_buf).to_string()),
+                };
+                self.send(LogEvent::Paste {
+                    text,
+                    bytes: self.paste_len,
+                });
+            }
+        }
+    }
 
-                # precmd: before prompt shows
-                function _recli_precmd() {
-                    local exit_code=$?
-                    local -a ps=("${pipestatus[@]}")
-                    if (( RECLI_INITIALIZED == 0 )); then
-                        RECLI_INITIALIZED=1
-                        return 0
-                    fi
-                    if (( RECLI_IN_COMMAND == 1 )); then
-                        RECLI_IN_COMMAND=0
-                        _recli_emit "RECLI_END:$exit_code"
-                        _recli_emit "RECLI_PIPE:[${(j:,:)ps}]"
-                        _recli_emit "RECLI_PWD:$PWD"
-                    fi
+    // re-encode a mouse report the way the child asked for, or drop it if it didn't ask
+    fn forward_mouse(&mut self, event: MouseEvent) {
+        let mode = self.child_mouse_mode.lock().map(|m| *m).unwrap_or_default();
+        if !mode.reporting {
+            return;
+        }
+        let pending = self.hotkeys.flush();
+        self.type_keys(&pending);
+        if let Some(bytes) = event.encode(mode.sgr) {
+            self.write(&bytes);
+        }
+    }
+
+    fn handle_action(&mut self, action: HotkeyAction) {
+        match action {
+            HotkeyAction::Terminate => {
+                eprint!("\r\n[recli] hotkey: ending session\r\n");
+                self.terminated.store(true, Ordering::Relaxed);
+            }
+            HotkeyAction::Pause => {
+                let paused = !self.paused.fetch_xor(true, Ordering::Relaxed);
+                let state = if paused { "paused" } else { "resumed" };
+                eprint!("\r\n[recli] recording {}\r\n", state);
+            }
+            HotkeyAction::Annotate => {
+                self.send(LogEvent::Annotate {
+                    tag: "bookmark".to_string(),
+                });
+                eprint!("\r\n[recli] command bookmarked\r\n");
+            }
+            HotkeyAction::Hung => {
+                self.send(LogEvent::Hung);
+                eprint!("\r\n[recli] command tagged as hung\r\n");
+            }
+            HotkeyAction::Interrupt => match self.pty_fd.map(|fd| unsafe { libc::tcgetpgrp(fd) }) {
+                // as ^C would, but also for programs that read the terminal raw and never see one
+                Some(pgid) if pgid > 0 => {
+                    unsafe {
+                        libc::kill(-pgid, libc::SIGINT);
+                    }
+                    eprint!("\r\n[recli] sent SIGINT to process group {}\r\n", pgid);
                 }
+                _ => eprint!("\r\n[recli] nothing to interrupt\r\n"),
+            },
+            HotkeyAction::Note => {
+                self.note = Some(Vec::new());
+                eprint!("\r\n[recli] note (enter saves, esc drops): ");
+            }
+        }
+    }
 
-                # register hooks safely: remove old and add new
-                typeset -ag precmd_functions
-                typeset -ag preexec_functions
-                precmd_functions=("${(@)precmd_functions:#_recli_precmd}")
-                preexec_functions=("${(@)preexec_functions:#_recli_preexec}")
-                precmd_functions=(_recli_precmd ${precmd_functions})
-                preexec_functions+=(_recli_preexec)
+    // keys typed into a note are echoed by recli, the shell never sees them
+    fn edit_note(&mut self, bytes: &[u8]) {
+        let Some(note) = self.note.as_mut() else { return };
+        let mut echo = Vec::new();
+        for &b in bytes {
+            match b {
+                b'\r' | b'\n' => {
+                    let text = String::from_utf8_lossy(note).to_string();
+                    self.note = None;
+                    let _ = std::io::stderr().write_all(&echo);
+                    eprint!("\r\n");
+                    self.send(LogEvent::Note { text });
+                    return;
+                }
+                // esc, also the start of arrows and other keys a one-line note has no use for
+                0x1b | 0x03 | 0x07 => {
+                    self.note = None;
+                    let _ = std::io::stderr().write_all(&echo);
+                    eprint!("\r\n[recli] note dropped\r\n");
+                    return;
+                }
+                0x7f | 0x08 => {
+                    // a whole character, continuation bytes first
+                    while note.last().is_some_and(|b| b & 0xc0 == 0x80) {
+                        note.pop();
+                    }
+                    if note.pop().is_some() {
+                        echo.extend_from_slice(b"\x08 \x08");
+                    }
+                }
+                b if b >= 0x20 => {
+                    note.push(b);
+                    echo.push(b);
+                }
+                _ => {}
+            }
+        }
+        let _ = std::io::stderr().write_all(&echo);
+    }
 
-                # optional debug marker
-                if [[ -n "${RECLI_DEBUG_MARKERS:-}" ]]; then
-                    _recli_emit "RECLI_DEBUG:hook_loaded_v5"
-                fi
-                "#;
-                                fs::write(&hook, hook_content)?;
+    // forward typed keys and note them in the input stream
+    fn type_keys(&mut self, bytes: &[u8]) {
+        if !bytes.is_empty() && !self.paused.load(Ordering::Relaxed) {
+            if let Some(recorder) = &mut self.input_recorder {
+                // looked at before the keys go out: an enter can start a password prompt right away
+                let hidden = self.pty_fd.is_some_and(recli_io::input_hidden);
+                recorder.keys(bytes, hidden);
+            }
+        }
+        self.write(bytes);
+    }
 
-            // bootstrap .zshrc: user's ~/.zshrc FIRST, then our hook LAST; always write latest version
-                let bootstrap = dir.join(".zshrc");
-            let bootstrap_content = r#"# --- recli bootstrap .zshrc (v3) ---
-        # source user config first (including p10k instant prompt)
-        [[ -r ~/.zshrc ]] && source ~/.zshrc
+    fn write(&mut self, bytes: &[u8]) {
+        if !bytes.is_empty() {
+            let _ = self.writer.write_all(bytes);
+        }
+    }
 
-        # give p10k a moment to finish initialization if present
-        if typeset -f p10k &>/dev/null; then
-            sleep 0.1
-        fi
+    fn send(&self, event: LogEvent) {
+        if let Some(sm) = &self.session_manager {
+            if let Ok(sm) = sm.lock() {
+                sm.send_log_event(event);
+            }
+        }
+    }
+}
 
-        # load recli hooks last
-        [[ -r ~/.recli/recli.zsh ]] && source ~/.recli/recli.zsh
+impl PtySession {
+    fn ensure_zsh_bootstrap_files() -> std::io::Result<PathBuf> {
+        let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+        let dir = PathBuf::from(&home).join(".recli");
+        write_zsh_bootstrap(&dir)?;
+        Ok(dir)
+    }
+}
 
-        # debug: print arrays to stderr if requested
-        if [[ -n "${RECLI_DEBUG_MARKERS:-}" ]]; then
-            typeset -p precmd_functions preexec_functions >&2
-        fi
-        "#;
-            fs::write(&bootstrap, bootstrap_content)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    // where `needle` first appears in `text`
+    fn at(text: &str, needle: &str) -> usize {
+        text.find(needle).unwrap_or_else(|| panic!("{:?} not in:\n{}", needle, text))
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("recli-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn bootstrap_files_are_in_zsh_startup_order() {
+        let names: Vec<&str> = ZSH_BOOTSTRAP_FILES.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, [".zshenv", ".zprofile", ".zshrc"]);
+    }
+
+    #[test]
+    fn each_bootstrap_file_reads_the_users_own_from_their_zdotdir() {
+        for (name, content) in ZSH_BOOTSTRAP_FILES {
+            let switched = at(content, "ZDOTDIR=\"$RECLI_USER_ZDOTDIR\"");
+            let sourced = at(content, &format!("source \"${{ZDOTDIR:-$HOME}}/{}\"", name));
+            assert!(switched < sourced, "{} is read before ZDOTDIR is the user's", name);
+        }
+    }
+
+    #[test]
+    fn bootstrap_files_dont_hardcode_home_paths() {
+        for (name, content) in ZSH_BOOTSTRAP_FILES {
+            assert!(!content.contains("~/"), "{} reads a file from ~ whatever ZDOTDIR is", name);
+        }
+    }
+
+    #[test]
+    fn zshenv_picks_up_a_zdotdir_it_sets_and_points_zsh_back_at_the_bootstrap() {
+        let sourced = at(ZSH_BOOTSTRAP_ENV, "/.zshenv\"");
+        let kept = at(ZSH_BOOTSTRAP_ENV, "RECLI_USER_ZDOTDIR=\"$ZDOTDIR\"");
+        let back = ZSH_BOOTSTRAP_ENV.rfind("ZDOTDIR=\"$_RECLI_ZDOTDIR\"").unwrap();
+        assert!(sourced < kept && kept < back);
+    }
 
-                // remove compiled zsh caches that could shadow fresh text
-                let _ = fs::remove_file(dir.join(".zshrc.zwc"));
-                let _ = fs::remove_file(dir.join("recli.zsh.zwc"));
+    #[test]
+    fn zprofile_points_zsh_back_at_the_bootstrap() {
+        let sourced = at(ZSH_BOOTSTRAP_PROFILE, "/.zprofile\"");
+        let back = ZSH_BOOTSTRAP_PROFILE.rfind("ZDOTDIR=\"$_RECLI_ZDOTDIR\"").unwrap();
+        assert!(sourced < back);
+    }
+
+    #[test]
+    fn zshrc_loads_the_hook_last_and_leaves_the_users_zdotdir() {
+        let hook = at(ZSH_BOOTSTRAP, "/recli.zsh\"");
+        assert!(at(ZSH_BOOTSTRAP, "/.zshrc\"") < hook);
+        assert!(hook < at(ZSH_BOOTSTRAP, "unset _RECLI_ZDOTDIR"));
+        assert!(!ZSH_BOOTSTRAP.contains("ZDOTDIR=\"$_RECLI_ZDOTDIR\""));
+    }
 
-                Ok(dir)
+    #[test]
+    fn user_zdotdir_leaves_out_recli_own_and_empty_values() {
+        let ours = Path::new("/home/u/.recli");
+        assert_eq!(user_zdotdir(Some("/home/u/.config/zsh"), ours).as_deref(), Some("/home/u/.config/zsh"));
+        assert_eq!(user_zdotdir(Some("/home/u/.recli"), ours), None);
+        assert_eq!(user_zdotdir(Some(""), ours), None);
+        assert_eq!(user_zdotdir(None, ours), None);
+    }
+
+    #[test]
+    fn write_zsh_bootstrap_writes_every_file_and_drops_stale_caches() {
+        let dir = scratch_dir("bootstrap");
+        fs::write(dir.join(".zshrc.zwc"), "stale").unwrap();
+        write_zsh_bootstrap(&dir).unwrap();
+        for (name, content) in ZSH_BOOTSTRAP_FILES {
+            assert_eq!(fs::read_to_string(dir.join(name)).unwrap(), content);
         }
+        assert_eq!(fs::read_to_string(dir.join("recli.zsh")).unwrap(), ZSH_HOOK);
+        assert!(!dir.join(".zshrc.zwc").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn hooks_announce_the_version_in_their_first_line() {
+        assert_eq!(hook_file_version(ZSH_HOOK), Some(ZSH_HOOK_VERSION));
+        assert_eq!(hook_file_version(crate::hook_only::BASH_HOOK), Some(crate::hook_only::BASH_HOOK_VERSION));
+        at(ZSH_HOOK, &format!("RECLI_HOOK_VERSION:zsh:{}", ZSH_HOOK_VERSION));
+        at(crate::hook_only::BASH_HOOK, &format!("RECLI_HOOK_VERSION:bash:{}", crate::hook_only::BASH_HOOK_VERSION));
+        for (_, content) in ZSH_BOOTSTRAP_FILES {
+            assert!(hook_file_version(content).is_some(), "{}", content);
+        }
+        assert_eq!(hook_file_version("# my own .zshrc (v2)\n"), None);
+    }
+
+    #[test]
+    fn refresh_hook_file_replaces_stale_and_edited_copies_only() {
+        let dir = scratch_dir("refresh");
+        let path = dir.join("recli.zsh");
+        let old = format!("(v{})", ZSH_HOOK_VERSION - 1);
+        fs::write(&path, ZSH_HOOK.replacen(&format!("(v{})", ZSH_HOOK_VERSION), &old, 1)).unwrap();
+        refresh_hook_file(&path, ZSH_HOOK).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), ZSH_HOOK);
+
+        fs::write(&path, format!("{}alias ll='ls -l'\n", ZSH_HOOK)).unwrap();
+        refresh_hook_file(&path, ZSH_HOOK).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), ZSH_HOOK);
+
+        // a current copy isn't written again
+        let written = fs::metadata(&path).unwrap().modified().unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        refresh_hook_file(&path, ZSH_HOOK).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), written);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // runs a real zsh when there is one: a .zshenv that moves ZDOTDIR must have the .zshrc read
+    // from there, and the shell must end up with the user's ZDOTDIR
+    #[test]
+    fn zsh_reads_the_users_files_through_the_bootstrap() {
+        if Command::new("zsh").arg("--version").output().is_err() {
+            return;
+        }
+        let home = scratch_dir("zdotdir");
+        let bootstrap = home.join(".recli");
+        write_zsh_bootstrap(&bootstrap).unwrap();
+        let config = home.join("config");
+        fs::create_dir_all(&config).unwrap();
+        fs::write(home.join(".zshenv"), "ZDOTDIR=\"$HOME/config\"\nprint -r -- env >> \"$HOME/trace\"\n").unwrap();
+        fs::write(config.join(".zshrc"), "print -r -- \"rc $ZDOTDIR\" >> \"$HOME/trace\"\n").unwrap();
+        fs::write(home.join(".zshrc"), "print -r -- wrong >> \"$HOME/trace\"\n").unwrap();
+
+        let out = Command::new("zsh")
+            .args(["-i", "-c", "print -r -- \"cmd $ZDOTDIR\" >> \"$HOME/trace\""])
+            .env("HOME", &home)
+            .env("ZDOTDIR", &bootstrap)
+            .env_remove("RECLI_USER_ZDOTDIR")
+            .output()
+            .unwrap();
+        assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+        let trace = fs::read_to_string(home.join("trace")).unwrap();
+        let config = config.display();
+        assert_eq!(trace, format!("env\nrc {}\ncmd {}\n", config, config));
+        fs::remove_dir_all(&home).unwrap();
+    }
 }
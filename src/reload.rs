@@ -0,0 +1,145 @@
+use crate::config::{self, Config};
+use crate::config_check;
+use crate::control::ConfigReloaded;
+use crate::filters::FilterChain;
+use crate::hooks;
+use crate::io::OutputWatch;
+use crate::util::telemetry;
+use serde_json::Value;
+use std::fs;
+use std::time::SystemTime;
+
+// the sections a running session takes up
+const LIVE: [&str; 5] = ["filters", "watch", "hooks", "retry", "logging"];
+
+/// new filters and watched patterns for the command detector, when either changed
+pub type OutputSettings = (Option<FilterChain>, Option<OutputWatch>);
+
+type Overrides = Box<dyn Fn(&mut Config) + Send>;
+
+/// the config file of a running session, read again when it changes or a client asks, so output
+/// filters, watched patterns, hooks and the diagnostics level follow it without a restart. Other
+/// sections are reported as waiting for the next session.
+pub struct ConfigReload {
+    path: String,
+    modified: Option<SystemTime>,
+    // what the session runs with now
+    current: Config,
+    filter_profile: Option<String>,
+    // --watch patterns, kept on top of [watch]
+    watch: Vec<String>,
+    // command-line options that take precedence over the file, applied after each read
+    overrides: Option<Overrides>,
+}
+
+impl ConfigReload {
+    /// `path` from --config, else the default; `cfg` as the session was started with
+    pub fn new(path: Option<&str>, cfg: &Config, filter_profile: Option<&str>, watch: &[String]) -> Self {
+        let path = path.map(str::to_string).unwrap_or_else(Config::default_path);
+        Self {
+            modified: modified(&path),
+            path,
+            current: cfg.clone(),
+            filter_profile: filter_profile.map(str::to_string),
+            watch: watch.to_vec(),
+            overrides: None,
+        }
+    }
+
+    /// settings such as --log-dir or -v, applied over every config read again
+    pub fn with_overrides(mut self, overrides: impl Fn(&mut Config) + Send + 'static) -> Self {
+        self.overrides = Some(Box::new(overrides));
+        self
+    }
+
+    /// whether the file was written since it was last read; a file that is gone (an editor may be
+    /// replacing it) doesn't count
+    pub fn changed(&mut self) -> bool {
+        let now = modified(&self.path);
+        if now.is_none() || now == self.modified {
+            return false;
+        }
+        self.modified = now;
+        true
+    }
+
+    /// read the file and put what changed of the live sections into effect. A file that can't be
+    /// used, or output filters that fail to load, change nothing: the filters in place may be the
+    /// ones redacting secrets.
+    pub fn reload(&mut self) -> Result<(ConfigReloaded, Option<OutputSettings>), String> {
+        self.modified = modified(&self.path);
+        let text = match fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("{}: {}", self.path, e)),
+        };
+        let (cfg, problems) = config_check::check(&text);
+        // problems elsewhere wait for the next session, as do those sections
+        let strict = cfg.as_ref().is_none_or(|c| c.strict) || config::strict_from_env();
+        let blocking: Vec<String> = problems
+            .iter()
+            .filter(|p| strict || p.key.as_deref().is_some_and(|k| LIVE.contains(&k.split(['.', '[']).next().unwrap_or(k))))
+            .map(|p| p.to_string())
+            .collect();
+        let Some(mut cfg) = cfg.filter(|_| blocking.is_empty()) else {
+            return Err(format!("{}: {}", self.path, blocking.join("; ")));
+        };
+        for problem in &problems {
+            tracing::warn!(path = %self.path, %problem, "config read again with problems");
+        }
+        cfg.apply_env();
+        if let Some(overrides) = &self.overrides {
+            overrides(&mut cfg);
+        }
+
+        let before = sections(&self.current);
+        let after = sections(&cfg);
+        let changed = |key: &str| before.get(key) != after.get(key);
+        let mut reloaded = ConfigReloaded::default();
+        let output = match changed("filters") || changed("watch") {
+            true => {
+                let filters = FilterChain::load(&cfg.filters, self.filter_profile.as_deref())?;
+                let mut watch_cfg = cfg.watch.clone();
+                watch_cfg.patterns.extend_from_slice(&self.watch);
+                let watch = OutputWatch::new(&watch_cfg)?;
+                Some((filters, watch))
+            }
+            false => None,
+        };
+        for key in ["filters", "watch"].into_iter().filter(|k| changed(k)) {
+            reloaded.applied.push(key.to_string());
+        }
+        if changed("hooks") || changed("retry") {
+            hooks::reload(&cfg.hooks, &cfg.retry, self.current.workspace.as_deref());
+            reloaded.applied.extend(["hooks", "retry"].into_iter().filter(|k| changed(k)).map(str::to_string));
+        }
+        if cfg.logging.level != self.current.logging.level {
+            match telemetry::reload_level(&cfg.logging) {
+                Ok(()) => reloaded.applied.push("logging.level".to_string()),
+                Err(e) => tracing::warn!(error = %e, "diagnostics level not changed"),
+            }
+        }
+
+        self.current.filters = cfg.filters.clone();
+        self.current.watch = cfg.watch.clone();
+        self.current.hooks = cfg.hooks.clone();
+        self.current.retry = cfg.retry.clone();
+        self.current.logging.level = cfg.logging.level.clone();
+        // compared with what the session runs with, so a section stays listed until a restart
+        let running = sections(&self.current);
+        reloaded.restart = after.keys().filter(|k| running.get(*k) != after.get(*k)).cloned().collect();
+        Ok((reloaded, output))
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// the top-level sections and settings of a config
+fn sections(cfg: &Config) -> serde_json::Map<String, Value> {
+    match serde_json::to_value(cfg) {
+        Ok(Value::Object(map)) => map,
+        _ => serde_json::Map::new(),
+    }
+}
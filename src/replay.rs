@@ -0,0 +1,284 @@
+use crate::cast::{Cast, CastEvent};
+use crate::error::{RecliError, Result};
+use crate::history::HistoryEntry;
+use chrono::DateTime;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{self, disable_raw_mode, enable_raw_mode};
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+// events due within this long of each other are drawn as one frame
+const FRAME: Duration = Duration::from_millis(16);
+// how far a command's logged start can be from where its output begins in the cast; the header's
+// timestamp is whole seconds
+const SLACK: f64 = 1.0;
+
+/// how a cast is played back
+#[derive(Debug, Clone, Copy)]
+pub struct ReplayOptions {
+    pub speed: f64,
+    // pauses longer than this many seconds are cut down to it
+    pub idle_limit: Option<f64>,
+    // write the recorded bytes straight to the terminal, as asciinema does
+    pub raw: bool,
+}
+
+/// the size `cast` started at and every resize after, as (seconds in, cols, rows)
+pub fn recorded_sizes(cast: &Cast) -> Vec<(f64, u16, u16)> {
+    let mut sizes = vec![(0.0, cast.width, cast.height)];
+    for event in &cast.events {
+        if let CastEvent::Resize { time, cols, rows } = event {
+            if *cols > 0 && *rows > 0 && sizes.last().map(|&(_, c, r)| (c, r)) != Some((*cols, *rows)) {
+                sizes.push((*time, *cols, *rows));
+            }
+        }
+    }
+    sizes
+}
+
+/// the widest and tallest the terminal was while `cast` was recorded
+pub fn largest_size(cast: &Cast) -> (u16, u16) {
+    let sizes = recorded_sizes(cast);
+    let cols = sizes.iter().map(|&(_, c, _)| c).max().unwrap_or(cast.width);
+    let rows = sizes.iter().map(|&(_, _, r)| r).max().unwrap_or(cast.height);
+    (cols, rows)
+}
+
+/// when the `n`th command (from 1) of `entries` ended on the cast's clock: at its marker, or for
+/// casts recorded without markers, by its timestamp against the start of the cast
+pub fn command_end(cast: &Cast, entries: &[HistoryEntry], n: usize) -> Option<f64> {
+    let entry = entries.get(n.checked_sub(1)?)?;
+    // commands recorded before markers were, or ended by the session ending, have none
+    let first = cast.markers.get(n - 1);
+    let last = (n - 1 + cast.markers.len()).checked_sub(entries.len()).and_then(|k| cast.markers.get(k));
+    if let Some((time, _)) = [first, last].into_iter().flatten().find(|(_, label)| *label == entry.cmd) {
+        return Some(*time);
+    }
+    let ended = DateTime::parse_from_rfc3339(&entry.timestamp).ok()?;
+    Some((ended.timestamp_millis() as f64 / 1000.0 - cast.timestamp? as f64).max(0.0))
+}
+
+/// the screen when a command that ran for `duration` seconds ended at `end`; for one that ran full
+/// screen, like vim or less, the last screen it drew before leaving
+pub fn screen_at(cast: &Cast, end: f64, duration: f64) -> vt100::Screen {
+    let mut parser = vt100::Parser::new(cast.height, cast.width, 0);
+    let mut full_screen = None;
+    for event in cast.events.iter().take_while(|e| e.time() <= end) {
+        match event {
+            CastEvent::Output { data, .. } => parser.process(data.as_bytes()),
+            CastEvent::Resize { cols, rows, .. } if *cols > 0 && *rows > 0 => parser.screen_mut().set_size(*rows, *cols),
+            CastEvent::Resize { .. } => {}
+        }
+        if event.time() >= end - duration - SLACK && parser.screen().alternate_screen() {
+            full_screen = Some(parser.screen().clone());
+        }
+    }
+    match full_screen {
+        Some(screen) if !parser.screen().alternate_screen() => screen,
+        _ => parser.screen().clone(),
+    }
+}
+
+/// play `cast` on the terminal until it ends or q, Esc or ^C is pressed; space pauses, + and -
+/// change the speed and n jumps to the end of the next command. Unless `raw`, output goes through a
+/// terminal emulator kept at the recorded size, so full-width output and full-screen programs look as
+/// they did even when this terminal is bigger; a smaller one shows the part around the cursor.
+pub fn play(cast: &Cast, options: ReplayOptions) -> Result<()> {
+    let mut out = io::BufWriter::new(io::stdout());
+    let mut screen = vt100::Parser::new(cast.height, cast.width, 0);
+    let mut view = View::default();
+    enable_raw_mode().map_err(RecliError::Terminal)?;
+    if !options.raw {
+        let _ = write!(out, "\x1b[H\x1b[2J");
+    }
+
+    let times = playback_times(cast, options.idle_limit);
+    let markers: Vec<f64> = cast.markers.iter().map(|(time, _)| playback_time(cast, &times, *time, options.idle_limit)).collect();
+    let mut player = Player {
+        speed: options.speed,
+        paused: false,
+        clock: 0.0,
+    };
+    let result = (|| {
+        let mut next = 0;
+        let mut tick = Instant::now();
+        loop {
+            let due = next;
+            while next < cast.events.len() && times[next] <= player.clock {
+                match &cast.events[next] {
+                    CastEvent::Output { data, .. } if options.raw => out.write_all(data.as_bytes()).map_err(RecliError::Terminal)?,
+                    CastEvent::Output { data, .. } => screen.process(data.as_bytes()),
+                    // a recorder without a terminal notes 0x0
+                    CastEvent::Resize { cols, rows, .. } if *cols > 0 && *rows > 0 => screen.screen_mut().set_size(*rows, *cols),
+                    CastEvent::Resize { .. } => {}
+                }
+                next += 1;
+            }
+            let status = player.status();
+            if !options.raw && (next > due || view.status != status) {
+                view.draw(&mut out, screen.screen(), &status).map_err(RecliError::Terminal)?;
+            }
+            out.flush().map_err(RecliError::Terminal)?;
+            if next == cast.events.len() {
+                return Ok(());
+            }
+
+            // events closer together than a frame are drawn as one
+            let wait = match player.paused {
+                true => Duration::from_secs(1),
+                false => Duration::from_secs_f64(((times[next] - player.clock) / player.speed).max(0.0)).max(FRAME),
+            };
+            let key = read_key(wait)?;
+            if !player.paused {
+                player.clock += tick.elapsed().as_secs_f64() * player.speed;
+            }
+            tick = Instant::now();
+            match key {
+                Some(Key::Quit) => return Ok(()),
+                Some(Key::Pause) => player.paused = !player.paused,
+                Some(Key::Faster) => player.speed = (player.speed * 2.0).min(MAX_SPEED),
+                Some(Key::Slower) => player.speed = (player.speed / 2.0).max(1.0 / MAX_SPEED),
+                // to the end of the next command, or of the cast when no command is left
+                Some(Key::NextCommand) => {
+                    let end = times.last().copied().unwrap_or(0.0);
+                    player.clock = markers.iter().copied().find(|&m| m > player.clock).unwrap_or(end).max(player.clock);
+                }
+                None => {}
+            }
+        }
+    })();
+
+    if !options.raw {
+        let _ = view.draw(&mut out, screen.screen(), "");
+        let _ = write!(out, "\x1b[m\x1b[?25h\x1b[{};1H\r\n", view.shown_rows.max(1));
+    } else {
+        let _ = write!(out, "\x1b[m\x1b[?25h\r\n");
+    }
+    let _ = out.flush();
+    let _ = disable_raw_mode();
+    result
+}
+
+// speeds + and - stop at, this many times faster or slower
+const MAX_SPEED: f64 = 16.0;
+
+// where playback is, on the cast's clock with long pauses cut
+struct Player {
+    speed: f64,
+    paused: bool,
+    clock: f64,
+}
+
+impl Player {
+    fn status(&self) -> String {
+        let speed = match self.speed {
+            s if s >= 1.0 => format!("{}x", s),
+            s => format!("1/{}x", (1.0 / s).round()),
+        };
+        let state = if self.paused { "paused" } else { "playing" };
+        format!("{} at {} · space pause · +/- speed · n next command · q quit", state, speed)
+    }
+}
+
+// when each event of `cast` plays, with pauses longer than `idle_limit` cut down to it
+fn playback_times(cast: &Cast, idle_limit: Option<f64>) -> Vec<f64> {
+    let mut times = Vec::with_capacity(cast.events.len());
+    let (mut clock, mut previous) = (0.0, 0.0);
+    for event in &cast.events {
+        let gap = (event.time() - previous).max(0.0);
+        clock += idle_limit.map_or(gap, |limit| gap.min(limit));
+        previous = event.time();
+        times.push(clock);
+    }
+    times
+}
+
+// when something at `time` in the cast plays, given the events' playback `times`
+fn playback_time(cast: &Cast, times: &[f64], time: f64, idle_limit: Option<f64>) -> f64 {
+    let before = cast.events.iter().rposition(|e| e.time() <= time);
+    let (at, played) = before.map_or((0.0, 0.0), |i| (cast.events[i].time(), times[i]));
+    let gap = time - at;
+    played + idle_limit.map_or(gap, |limit| gap.min(limit))
+}
+
+enum Key {
+    Quit,
+    Pause,
+    Faster,
+    Slower,
+    NextCommand,
+}
+
+// wait up to `timeout` for a key that controls playback
+fn read_key(timeout: Duration) -> Result<Option<Key>> {
+    let until = Instant::now() + timeout;
+    loop {
+        let left = until.saturating_duration_since(Instant::now());
+        if !event::poll(left).map_err(RecliError::Terminal)? {
+            return Ok(None);
+        }
+        let Event::Key(key) = event::read().map_err(RecliError::Terminal)? else { continue };
+        if key.kind == KeyEventKind::Release {
+            continue;
+        }
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        return Ok(Some(match key.code {
+            KeyCode::Char('c') if ctrl => Key::Quit,
+            KeyCode::Char('q') | KeyCode::Esc => Key::Quit,
+            KeyCode::Char(' ') => Key::Pause,
+            KeyCode::Char('+' | '=') => Key::Faster,
+            KeyCode::Char('-' | '_') => Key::Slower,
+            KeyCode::Char('n') => Key::NextCommand,
+            _ => continue,
+        }));
+    }
+}
+
+// the part of the emulated screen that fits this terminal, with a status line below when there's room
+#[derive(Default)]
+struct View {
+    // first emulated row shown, moved only to keep the cursor in view
+    top: u16,
+    shown_rows: u16,
+    status: String,
+}
+
+impl View {
+    fn draw(&mut self, out: &mut impl Write, screen: &vt100::Screen, status: &str) -> io::Result<()> {
+        let (rows, cols) = screen.size();
+        let (term_cols, term_rows) = terminal::size().unwrap_or((cols, rows));
+        let shown = rows.min(term_rows.max(1));
+        let (cursor_row, cursor_col) = screen.cursor_position();
+        if cursor_row < self.top {
+            self.top = cursor_row;
+        } else if cursor_row >= self.top + shown {
+            self.top = cursor_row + 1 - shown;
+        }
+        self.top = self.top.min(rows - shown);
+        // a screen that got smaller leaves rows behind, and the status line below them
+        for row in shown..(self.shown_rows + 1).min(term_rows) {
+            write!(out, "\x1b[{};1H\x1b[m\x1b[2K", row + 1)?;
+        }
+        self.shown_rows = shown;
+
+        write!(out, "\x1b[?25l")?;
+        let width = cols.min(term_cols);
+        for (i, contents) in screen.rows_formatted(0, width).skip(self.top as usize).take(shown as usize).enumerate() {
+            write!(out, "\x1b[{};1H\x1b[m\x1b[2K", i + 1)?;
+            out.write_all(&contents)?;
+        }
+        if shown < term_rows {
+            let status: String = status.chars().take(term_cols as usize).collect();
+            write!(out, "\x1b[{};1H\x1b[m\x1b[2K\x1b[2m{}", shown + 1, status)?;
+        }
+        self.status = status.to_string();
+        write!(out, "\x1b[m")?;
+        if cursor_col < width {
+            write!(out, "\x1b[{};{}H", cursor_row - self.top + 1, cursor_col + 1)?;
+            if !screen.hide_cursor() {
+                write!(out, "\x1b[?25h")?;
+            }
+        }
+        Ok(())
+    }
+}
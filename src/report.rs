@@ -0,0 +1,187 @@
+use crate::cast::TypingStats;
+use crate::config::ReportConfig;
+use crate::history::HistoryEntry;
+use crate::util::time::format_duration_ms;
+use chrono::{DateTime, Utc};
+use lettre::message::{header::ContentType, Mailbox};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use std::fmt::Write as _;
+use std::path::Path;
+
+// rows listed under failures and slowest commands
+const TOP: usize = 10;
+
+/// summary of the sessions recorded since a point in time
+#[derive(Debug)]
+pub struct Report {
+    pub since: DateTime<Utc>,
+    pub sessions: Vec<SessionSummary>,
+    pub failures: Vec<HistoryEntry>, // newest first
+    pub slowest: Vec<HistoryEntry>,  // longest first
+    pub typing: Option<TypingStats>, // over the sessions that recorded input
+}
+
+#[derive(Debug)]
+pub struct SessionSummary {
+    pub session_id: String,
+    pub commands: usize,
+    pub failed: usize,
+    pub duration_ms: u64, // time spent inside commands
+}
+
+impl Report {
+    /// summarize commands started at or after `since`; sessions without any are left out
+    pub fn build(sessions: Vec<(String, Vec<HistoryEntry>)>, since: DateTime<Utc>) -> Self {
+        let mut report = Report {
+            since,
+            sessions: Vec::new(),
+            failures: Vec::new(),
+            slowest: Vec::new(),
+            typing: None,
+        };
+
+        for (session_id, entries) in sessions {
+            let entries: Vec<HistoryEntry> = entries
+                .into_iter()
+                .filter(|e| {
+                    DateTime::parse_from_rfc3339(&e.timestamp)
+                        .map(|t| t.with_timezone(&Utc) >= since)
+                        .unwrap_or(false)
+                })
+                .collect();
+            if entries.is_empty() {
+                continue;
+            }
+            report.sessions.push(SessionSummary {
+                session_id,
+                commands: entries.len(),
+                failed: entries.iter().filter(|e| e.exit_code != 0).count(),
+                duration_ms: entries.iter().filter_map(|e| e.duration_ms).sum(),
+            });
+            report.failures.extend(entries.iter().filter(|e| e.exit_code != 0).cloned());
+            report.slowest.extend(entries.into_iter().filter(|e| e.duration_ms.is_some()));
+        }
+
+        report.failures.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        report.failures.truncate(TOP);
+        report.slowest.sort_by_key(|e| std::cmp::Reverse(e.duration_ms));
+        report.slowest.truncate(TOP);
+        report
+    }
+
+    /// add typing speed from the input streams of the reported sessions below `base`
+    pub fn with_typing(mut self, base: &Path) -> Self {
+        for s in &self.sessions {
+            if let Some(stats) = TypingStats::load(&base.join(&s.session_id)) {
+                self.typing.get_or_insert_with(TypingStats::default).add(&stats);
+            }
+        }
+        self
+    }
+
+    pub fn commands(&self) -> usize {
+        self.sessions.iter().map(|s| s.commands).sum()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.sessions.iter().map(|s| s.failed).sum()
+    }
+
+    /// one-line summary, also used as the email subject
+    pub fn subject(&self) -> String {
+        format!(
+            "recli report: {} sessions, {} commands, {} failed",
+            self.sessions.len(),
+            self.commands(),
+            self.failed()
+        )
+    }
+
+    /// plain text body
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let total: u64 = self.sessions.iter().map(|s| s.duration_ms).sum();
+        let _ = writeln!(out, "{}", self.subject());
+        let _ = writeln!(out, "since {}", self.since.format("%Y-%m-%d %H:%M UTC"));
+        let _ = writeln!(out, "time in commands: {}", format_duration_ms(total));
+        if let Some((typing, wpm)) = self.typing.as_ref().and_then(|t| Some((t, t.wpm()?))) {
+            let _ = writeln!(
+                out,
+                "typing: {:.0} wpm, {:.0}% corrections over {} keys",
+                wpm,
+                typing.correction_rate() * 100.0,
+                typing.keys
+            );
+        }
+
+        if self.sessions.is_empty() {
+            let _ = writeln!(out, "\nno commands recorded in this period");
+            return out;
+        }
+
+        let _ = writeln!(out, "\nsessions");
+        for s in &self.sessions {
+            let _ = writeln!(
+                out,
+                "  {}  {} commands, {} failed, {}",
+                s.session_id,
+                s.commands,
+                s.failed,
+                format_duration_ms(s.duration_ms)
+            );
+        }
+
+        if !self.failures.is_empty() {
+            let _ = writeln!(out, "\nfailures");
+            for e in &self.failures {
+                let _ = writeln!(out, "  {}  [{}]  {}  ({})", e.timestamp, e.exit_code, e.cmd, e.cwd);
+            }
+        }
+
+        if !self.slowest.is_empty() {
+            let _ = writeln!(out, "\nslowest");
+            for e in &self.slowest {
+                let ms = e.duration_ms.unwrap_or(0);
+                let _ = writeln!(out, "  {:>9}  {}  ({})", format_duration_ms(ms), e.cmd, e.cwd);
+            }
+        }
+        out
+    }
+}
+
+/// mail `body` to every configured recipient
+pub fn send_email(cfg: &ReportConfig, subject: &str, body: &str) -> Result<(), String> {
+    let host = cfg.smtp_host.as_deref().ok_or("no smtp_host in [report]")?;
+    let from: Mailbox = cfg
+        .from
+        .as_deref()
+        .ok_or("no from address in [report]")?
+        .parse()
+        .map_err(|e| format!("invalid from address: {}", e))?;
+    if cfg.to.is_empty() {
+        return Err("no recipients in [report] to".to_string());
+    }
+
+    let mut builder = Message::builder().from(from).subject(subject).header(ContentType::TEXT_PLAIN);
+    for to in &cfg.to {
+        let to: Mailbox = to.parse().map_err(|e| format!("invalid recipient {}: {}", to, e))?;
+        builder = builder.to(to);
+    }
+    let message = builder.body(body.to_string()).map_err(|e| e.to_string())?;
+
+    let transport = match cfg.tls.as_str() {
+        "starttls" => SmtpTransport::starttls_relay(host).map_err(|e| e.to_string())?,
+        "tls" => SmtpTransport::relay(host).map_err(|e| e.to_string())?,
+        "none" => SmtpTransport::builder_dangerous(host),
+        other => return Err(format!("invalid tls mode in [report]: {}", other)),
+    }
+    .port(cfg.smtp_port);
+    let transport = match (&cfg.username, &cfg.password) {
+        (Some(user), Some(pass)) => transport.credentials(Credentials::new(user.clone(), pass.clone())),
+        _ => transport,
+    };
+
+    transport.build().send(&message).map_err(|e| e.to_string())?;
+    Ok(())
+}
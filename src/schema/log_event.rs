@@ -0,0 +1,105 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// canonical log event v1 used for validation and future ingestion
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogEventV1 {
+    pub id: String,
+    pub schema_version: u8,
+    pub timestamp: String, // rfc3339 utc
+    pub host: String,
+    pub app: String, // "recli"
+    pub session_id: String,
+    // place of the command in its session, persisted with it; session notices have none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    // team namespace; absent for personal uploads
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+    pub level: String, // "INFO" | "WARN" | "ERROR"
+    pub command: String,
+    pub exit_code: Option<i32>,
+    pub error_type: Option<String>,
+    pub message: String,
+    pub tags: Vec<String>,
+    pub raw: Option<serde_json::Value>,
+}
+
+impl LogEventV1 {
+    /// event for one finished command, `seq` its place in the session; non-zero exits are errors.
+    /// The id depends only on what was recorded, so the same command always gets the same one.
+    #[allow(clippy::too_many_arguments)]
+    pub fn for_command(
+        host: &str,
+        session_id: &str,
+        workspace: Option<&str>,
+        seq: u64,
+        timestamp: &str,
+        command: &str,
+        exit_code: i32,
+        message: &str,
+        raw: Option<serde_json::Value>,
+    ) -> Self {
+        let failed = exit_code != 0;
+        Self {
+            id: Self::make_id(host, session_id, timestamp, command, &seq.to_string()),
+            schema_version: 1,
+            timestamp: timestamp.to_string(),
+            host: host.to_string(),
+            app: "recli".to_string(),
+            session_id: session_id.to_string(),
+            seq: Some(seq),
+            workspace: workspace.map(str::to_string),
+            level: if failed { "ERROR" } else { "INFO" }.to_string(),
+            command: command.to_string(),
+            exit_code: Some(exit_code),
+            error_type: failed.then(|| "nonzero_exit".to_string()),
+            message: message.to_string(),
+            tags: Vec::new(),
+            raw,
+        }
+    }
+
+    /// session-level notice such as start or end. `after` is the seq of the last command recorded
+    /// in the session before it and `nth` its place among the notices since, so the id doesn't
+    /// depend on the clock or on which process emitted it
+    pub fn for_message(host: &str, session_id: &str, workspace: Option<&str>, after: u64, nth: u64, message: &str) -> Self {
+        let timestamp = crate::util::time::now_stamp();
+        Self {
+            id: Self::make_id(host, session_id, "", message, &format!("{}.{}", after, nth)),
+            schema_version: 1,
+            timestamp,
+            host: host.to_string(),
+            app: "recli".to_string(),
+            session_id: session_id.to_string(),
+            seq: None,
+            workspace: workspace.map(str::to_string),
+            level: "INFO".to_string(),
+            command: String::new(),
+            exit_code: None,
+            error_type: None,
+            message: message.to_string(),
+            tags: Vec::new(),
+            raw: None,
+        }
+    }
+
+    /// build a deterministic id from fields
+    pub fn make_id(
+        host: &str,
+        session_id: &str,
+        timestamp: &str,
+        command: &str,
+        offset: &str,
+    ) -> String {
+        // offset can be file offset or sequence number to ensure uniqueness
+        let input = format!(
+            "{}|{}|{}|{}|{}",
+            host, session_id, timestamp, command, offset
+        );
+        let mut hasher = Sha256::new();
+        hasher.update(input.as_bytes());
+        let hash = hasher.finalize();
+        hex::encode(hash)
+    }
+}
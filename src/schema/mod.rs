@@ -0,0 +1,5 @@
+// schema module root
+// exposes canonical log event types and validation utilities
+
+pub mod log_event;
+pub mod validation;
@@ -0,0 +1,212 @@
+use crate::command_log::CommandLog;
+use crate::error::{RecliError, Result};
+use crate::outbox;
+use crate::util::compress;
+use regex::bytes;
+use regex::Regex;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+/// what a scrub replaced, per file of the session
+#[derive(Debug, Default)]
+pub struct ScrubReport {
+    pub files: Vec<(String, usize)>,
+}
+
+impl ScrubReport {
+    pub fn total(&self) -> usize {
+        self.files.iter().map(|(_, n)| n).sum()
+    }
+}
+
+/// replace every match of `pattern` with `replace` (which may refer to groups, as in `$1`) across the
+/// session recorded in `log_dir`: command lines, previews and notes in commands.json and the other
+/// json files, the .out and .err sidecars, compressed or not, and the casts. Cast output is scrubbed
+/// as one stream, so a secret split across two writes is still found. Queued hook posts of the
+/// session are scrubbed too. Nothing is written when `dry_run`.
+pub fn scrub_session(log_dir: &Path, pattern: &str, replace: &str, dry_run: bool) -> Result<ScrubReport> {
+    let text = Regex::new(pattern).map_err(|e| RecliError::Validation(format!("bad pattern: {}", e)))?;
+    let raw = bytes::Regex::new(pattern).map_err(|e| RecliError::Validation(format!("bad pattern: {}", e)))?;
+    let mut names: Vec<String> = fs::read_dir(log_dir)?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_file()))
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .collect();
+    names.sort();
+
+    let mut report = ScrubReport::default();
+    for name in names {
+        // the lock, and output timings, which hold no text
+        if name.starts_with('.') || name.ends_with(".chunks") {
+            continue;
+        }
+        let data = fs::read(log_dir.join(&name))?;
+        let (inner, compressed) = match name.strip_suffix(".zst") {
+            Some(inner) => (inner, true),
+            None => (name.as_str(), false),
+        };
+        let plain = match compressed {
+            true => compress::decompress(&data).map_err(std::io::Error::other)?,
+            false => data,
+        };
+        let (scrubbed, count) = if inner == "commands.json" {
+            scrub_commands(&plain, &text, replace)
+        } else if inner.ends_with(".json") {
+            scrub_json(&plain, &text, replace)
+        } else if inner.ends_with(".cast") {
+            scrub_cast(&plain, &text, replace)
+        } else {
+            let count = raw.find_iter(&plain).count();
+            (raw.replace_all(&plain, replace.as_bytes()).into_owned(), count)
+        };
+        if count == 0 {
+            continue;
+        }
+        if !dry_run {
+            let out = match compressed {
+                true => compress::compress(&scrubbed).map_err(std::io::Error::other)?,
+                false => scrubbed,
+            };
+            crate::lock::write_file(log_dir, &name, &out)?;
+        }
+        report.files.push((name, count));
+    }
+
+    // hook posts that failed carry the command and its preview and go out again on the next sync
+    let session = log_dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    for (path, mut post) in outbox::pending_posts() {
+        let Ok(mut body) = serde_json::from_str::<Value>(&post.body) else { continue };
+        if body["session_id"].as_str() != Some(session.as_str()) {
+            continue;
+        }
+        let count = scrub_value(&mut body, &text, replace);
+        if count == 0 {
+            continue;
+        }
+        if !dry_run {
+            post.body = body.to_string();
+            outbox::rewrite_post(&path, &post)?;
+        }
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        report.files.push((format!("outbox/posts/{}", name), count));
+    }
+    Ok(report)
+}
+
+// every string in the document, keys left alone; files that aren't json are treated as text
+fn scrub_json(data: &[u8], pattern: &Regex, replace: &str) -> (Vec<u8>, usize) {
+    let Ok(mut value) = serde_json::from_slice::<Value>(data) else {
+        let text = String::from_utf8_lossy(data);
+        let count = pattern.find_iter(&text).count();
+        return (pattern.replace_all(&text, replace).into_owned().into_bytes(), count);
+    };
+    let count = scrub_value(&mut value, pattern, replace);
+    (serde_json::to_vec_pretty(&value).unwrap_or_else(|_| data.to_vec()), count)
+}
+
+// commands.json goes back through CommandLog, so it keeps the layout recli writes it in
+fn scrub_commands(data: &[u8], pattern: &Regex, replace: &str) -> (Vec<u8>, usize) {
+    let Ok(mut value) = serde_json::from_slice::<Value>(data) else { return scrub_json(data, pattern, replace) };
+    let count = scrub_value(&mut value, pattern, replace);
+    let written = match serde_json::from_value::<CommandLog>(value.clone()) {
+        Ok(log) => serde_json::to_vec_pretty(&log),
+        Err(_) => serde_json::to_vec_pretty(&value),
+    };
+    (written.unwrap_or_else(|_| data.to_vec()), count)
+}
+
+fn scrub_value(value: &mut Value, pattern: &Regex, replace: &str) -> usize {
+    match value {
+        Value::String(s) => {
+            let count = pattern.find_iter(s).count();
+            if count > 0 {
+                *s = pattern.replace_all(s, replace).into_owned();
+            }
+            count
+        }
+        Value::Array(items) => items.iter_mut().map(|v| scrub_value(v, pattern, replace)).sum(),
+        Value::Object(map) => map.values_mut().map(|v| scrub_value(v, pattern, replace)).sum(),
+        _ => 0,
+    }
+}
+
+// an asciicast: the header and markers one by one, output and input events each as one stream
+fn scrub_cast(data: &[u8], pattern: &Regex, replace: &str) -> (Vec<u8>, usize) {
+    let text = String::from_utf8_lossy(data);
+    let mut lines: Vec<Option<Value>> = text.lines().map(|l| serde_json::from_str(l).ok()).collect();
+    let mut count = 0;
+    for kind in ["o", "i"] {
+        let mut parts: Vec<String> = Vec::new();
+        let mut at: Vec<usize> = Vec::new();
+        for (i, line) in lines.iter().enumerate() {
+            if let Some(Value::Array(event)) = line {
+                if event.get(1).and_then(Value::as_str) == Some(kind) {
+                    parts.push(event.get(2).and_then(Value::as_str).unwrap_or_default().to_string());
+                    at.push(i);
+                }
+            }
+        }
+        count += scrub_stream(&mut parts, pattern, replace);
+        for (part, i) in parts.into_iter().zip(at) {
+            if let Some(Value::Array(event)) = lines[i].as_mut() {
+                event[2] = Value::String(part);
+            }
+        }
+    }
+    for (i, line) in lines.iter_mut().enumerate() {
+        match line {
+            Some(Value::Array(event)) if event.get(1).and_then(Value::as_str) == Some("m") => {
+                if let Some(label) = event.get_mut(2) {
+                    count += scrub_value(label, pattern, replace);
+                }
+            }
+            Some(header @ Value::Object(_)) if i == 0 => count += scrub_value(header, pattern, replace),
+            _ => {}
+        }
+    }
+    let mut out = String::new();
+    for (line, original) in lines.iter().zip(text.lines()) {
+        match line {
+            Some(value) => out.push_str(&value.to_string()),
+            None => out.push_str(original),
+        }
+        out.push('\n');
+    }
+    (out.into_bytes(), count)
+}
+
+// scrub `parts` as if they were one string: a match spanning several of them is replaced in the
+// one it starts in and cut from the rest
+fn scrub_stream(parts: &mut [String], pattern: &Regex, replace: &str) -> usize {
+    let full = parts.concat();
+    let mut matches: Vec<(usize, usize, String)> = Vec::new();
+    for caps in pattern.captures_iter(&full) {
+        let Some(whole) = caps.get(0).filter(|m| !m.is_empty()) else { continue };
+        let mut replacement = String::new();
+        caps.expand(replace, &mut replacement);
+        matches.push((whole.start(), whole.end(), replacement));
+    }
+    let mut next = 0;
+    let mut start = 0;
+    for part in parts.iter_mut() {
+        let end = start + part.len();
+        let mut scrubbed = String::with_capacity(part.len());
+        let mut pos = start;
+        while let Some((m_start, m_end, replacement)) = matches.get(next).filter(|(m_start, _, _)| *m_start < end) {
+            scrubbed.push_str(&full[pos..(*m_start).max(pos)]);
+            if *m_start >= start {
+                scrubbed.push_str(replacement);
+            }
+            pos = (*m_end).min(end);
+            if *m_end > end {
+                break;
+            }
+            next += 1;
+        }
+        scrubbed.push_str(&full[pos..end]);
+        *part = scrubbed;
+        start = end;
+    }
+    matches.len()
+}
@@ -0,0 +1,1171 @@
+use crate::command_log::{CommandLog, TerminalSize};
+use crate::config::{HotkeyConfig, HungConfig};
+use crate::container::ContainerInfo;
+use crate::control::{ConfigReloaded, ControlServer};
+use crate::error::{RecliError, Result};
+use crate::events::EventStream;
+use crate::file_changes::{FileSnapshot, TrackLimits};
+use crate::history;
+use crate::hooks::{self, HookEvent};
+use crate::io::AltScreenTracker;
+use crate::network::NetworkWatch;
+use crate::process_tree::ProcessWatch;
+use crate::util::disk::StorageGuard;
+use crate::util::{datadog, sentry, time};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionConfig {
+    pub session_id: String,
+    pub log_dir: PathBuf,
+    #[serde(deserialize_with = "time::de_stamp")]
+    pub started_at: String,
+    pub shell: String,
+    // latest terminal title reported by the child (osc 0/2)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_title: Option<String>,
+    // initial terminal size and every resize, for faithful replay
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub size_history: Vec<TerminalSize>,
+    #[serde(default, deserialize_with = "time::de_opt_stamp", skip_serializing_if = "Option::is_none")]
+    pub ended_at: Option<String>,
+    // each time the session was reopened with `recli start --resume`
+    #[serde(default, deserialize_with = "time::de_stamps", skip_serializing_if = "Vec::is_empty")]
+    pub resumed_at: Vec<String>,
+    // sessions split at an idle gap point at each other
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub continues: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub continued_in: Option<String>,
+    // recorded through `recli exec` inside a docker or podman container
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<ContainerInfo>,
+    // ssh destination of a session recorded through `recli ssh`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+    // labels from `recli start --tag`, e.g. private to keep the session from uploaders
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    // machine and account recording it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(flatten)]
+    pub environment: SessionEnvironment,
+}
+
+/// the system, terminal and programs a session was recorded with, so an old session can still be
+/// made sense of. Sessions recorded before these were kept have none of them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SessionEnvironment {
+    // distribution and release from /etc/os-release, else the os family, e.g. "macos"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+    // kernel name and release as uname reports them, e.g. "Linux 6.8.0-45-generic"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kernel: Option<String>,
+    // $TERM_PROGRAM and $TERM_PROGRAM_VERSION, e.g. "iTerm.app 3.5.4"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub terminal: Option<String>,
+    // first line of `<shell> --version`; none for a container or ssh session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recli_version: Option<String>,
+    // directory the session was started in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+}
+
+impl SessionEnvironment {
+    /// what this process runs on; `shell` is asked for its version when it runs locally
+    pub fn detect(shell: Option<&str>) -> Self {
+        let non_empty = |v: String| Some(v.trim().to_string()).filter(|v| !v.is_empty());
+        let terminal = std::env::var("TERM_PROGRAM").ok().and_then(non_empty).map(|program| {
+            match std::env::var("TERM_PROGRAM_VERSION").ok().and_then(non_empty) {
+                Some(version) => format!("{} {}", program, version),
+                None => program,
+            }
+        });
+        let shell_version = shell.and_then(|shell| {
+            let out = std::process::Command::new(shell)
+                .arg("--version")
+                .stdin(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .output()
+                .ok()?;
+            out.status.success().then(|| String::from_utf8_lossy(&out.stdout).lines().next().map(str::to_string))?
+        });
+        Self {
+            os: os_release().or_else(|| Some(std::env::consts::OS.to_string())),
+            arch: Some(std::env::consts::ARCH.to_string()),
+            kernel: kernel(),
+            terminal,
+            shell_version: shell_version.and_then(non_empty),
+            recli_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            cwd: std::env::current_dir().ok().map(|d| d.to_string_lossy().to_string()),
+        }
+    }
+}
+
+// PRETTY_NAME of /etc/os-release, e.g. "Ubuntu 24.04.1 LTS"
+fn os_release() -> Option<String> {
+    let text = fs::read_to_string("/etc/os-release").ok()?;
+    let value = text.lines().find_map(|l| l.strip_prefix("PRETTY_NAME="))?;
+    Some(value.trim().trim_matches('"').to_string()).filter(|v| !v.is_empty())
+}
+
+#[cfg(unix)]
+fn kernel() -> Option<String> {
+    let mut name: libc::utsname = unsafe { std::mem::zeroed() };
+    if unsafe { libc::uname(&mut name) } != 0 {
+        return None;
+    }
+    let field = |f: &[libc::c_char]| unsafe { std::ffi::CStr::from_ptr(f.as_ptr()) }.to_string_lossy().to_string();
+    Some(format!("{} {}", field(&name.sysname), field(&name.release)))
+}
+
+#[cfg(not(unix))]
+fn kernel() -> Option<String> {
+    None
+}
+
+/// name of this machine, "unknown" when it can't be read
+pub fn current_host() -> String {
+    hostname::get()
+        .ok()
+        .and_then(|h| h.into_string().ok())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// the user recli runs as, from $USER
+pub fn current_user() -> String {
+    std::env::var("USER").unwrap_or_else(|_| "unknown".to_string())
+}
+
+// contents of session.pid: the pid plus enough to tell it apart from a recycled one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PidRecord {
+    pid: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    start_time: Option<u64>, // clock ticks after boot, from /proc/<pid>/stat
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    boot_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    session: Option<SessionConfig>,
+}
+
+#[derive(Debug)]
+pub struct SessionManager {
+    config: Option<SessionConfig>,
+    command_log: Arc<Mutex<CommandLog>>,
+    pid_file: PathBuf,
+    log_sender: Option<mpsc::UnboundedSender<LogEvent>>,
+    log_task: Option<tokio::task::JoinHandle<SessionConfig>>,
+    // directory holding one folder per session
+    log_base: PathBuf,
+    // live LogEventV1 stream for --json-events
+    events: Option<Arc<EventStream>>,
+    // gap between commands that starts a new segment
+    idle_segment: Option<Duration>,
+    // split into a new session at such a gap instead
+    idle_restart: bool,
+    // log quota and free space to keep, in MB; output stops being stored past either
+    quota_mb: Option<u64>,
+    min_free_mb: u64,
+    // set while output isn't stored for lack of space
+    output_dropped: Arc<AtomicBool>,
+    // set by `recli config reload` through the control socket until the recorder reads the config
+    reload_requested: Arc<AtomicBool>,
+    // snapshot the cwd at each prompt to see which files a command changed
+    track_files: Option<TrackLimits>,
+    // sample the sockets of each command's processes this often
+    track_network: Option<Duration>,
+    // sample the processes each command runs this often
+    track_processes: Option<Duration>,
+    // nudge when a running command prints nothing for this long
+    hung: Option<HungNudge>,
+    hung_tag: String,
+    // stamped on sessions recorded inside a container or over ssh
+    container: Option<ContainerInfo>,
+    remote: Option<String>,
+    tags: Vec<String>,
+}
+
+// how often a running command is checked for having gone quiet
+const STALL_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// what to do about a command that went quiet
+#[derive(Debug, Clone)]
+struct HungNudge {
+    after: Duration,
+    bell: bool,
+    // the hotkeys that help, e.g. "; ctrl-x h tags it hung, ctrl-x i interrupts it"
+    hint: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum LogEvent {
+    CommandStart { cmd: String, cwd: String },
+    Output { data: Vec<u8> },
+    CommandEnd { exit_code: i32, pipestatus: Option<Vec<i32>>, cwd: String },
+    Annotate { tag: String },
+    // live output matched a [watch] pattern
+    Watch { tag: String, pattern: String, line: String },
+    Paste { text: Option<String>, bytes: usize },
+    Title { title: String },
+    Resize { cols: u16, rows: u16 },
+    // the pty child, whose descendants a command's processes are
+    ShellStarted { pid: u32 },
+    // the user said the running command is hung
+    Hung,
+    // a note on the running command, or on the last one between commands
+    Note { text: String },
+    // write commands.json now, e.g. after a note was set through the control socket
+    Save,
+    // the config file was read again
+    ConfigReloaded(ConfigReloaded),
+}
+
+impl Default for SessionManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SessionManager {
+    pub fn new() -> Self {
+        let home_dir = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+        // the shell of a nested session names its pid file, so commands run there address that session
+        let pid_file = std::env::var_os("RECLI_PID_FILE")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| Path::new(&home_dir).join(".recli").join("session.pid"));
+
+        Self {
+            config: None,
+            command_log: Arc::new(Mutex::new(CommandLog::new())),
+            pid_file,
+            log_sender: None,
+            log_task: None,
+            log_base: Path::new(&home_dir).join(".recli").join("logs"),
+            events: None,
+            idle_segment: None,
+            idle_restart: false,
+            quota_mb: None,
+            min_free_mb: 0,
+            output_dropped: Arc::new(AtomicBool::new(false)),
+            reload_requested: Arc::new(AtomicBool::new(false)),
+            track_files: None,
+            track_network: None,
+            track_processes: None,
+            hung: None,
+            hung_tag: HungConfig::default().tag,
+            container: None,
+            remote: None,
+            tags: Vec::new(),
+        }
+    }
+
+    /// record sessions below `base` instead of ~/.recli/logs
+    pub fn with_log_dir(mut self, base: PathBuf) -> Self {
+        self.log_base = base;
+        self
+    }
+
+    /// claim `path` instead of ~/.recli/session.pid, for recorders running next to the main session
+    pub fn with_pid_file(mut self, path: PathBuf) -> Self {
+        self.pid_file = path;
+        self
+    }
+
+    /// emit every finished command as a LogEventV1 line on `events`
+    pub fn with_event_stream(mut self, events: EventStream) -> Self {
+        self.events = Some(Arc::new(events));
+        self
+    }
+
+    /// the stream events go to, for reporting things that happen after the pty is gone
+    pub fn event_stream(&self) -> Option<Arc<EventStream>> {
+        self.events.clone()
+    }
+
+    /// mark a segment boundary when the shell was idle for `threshold` between commands;
+    /// with `restart` the session is finished there and recording continues in a new one
+    pub fn with_idle_segmentation(mut self, threshold: Option<Duration>, restart: bool) -> Self {
+        self.idle_segment = threshold.filter(|t| !t.is_zero());
+        self.idle_restart = restart;
+        self
+    }
+
+    /// note which files below the cwd each command modified, created or deleted
+    pub fn with_file_tracking(mut self, limits: Option<TrackLimits>) -> Self {
+        self.track_files = limits;
+        self
+    }
+
+    /// note the bytes moved and the remote endpoints contacted while each command runs,
+    /// looking at the sockets of the shell's processes every `interval`
+    pub fn with_network_tracking(mut self, interval: Option<Duration>) -> Self {
+        self.track_network = interval;
+        self
+    }
+
+    /// note the processes each command ran, how many at once at most and the cpu each used,
+    /// looking below the shell every `interval`
+    pub fn with_process_tracking(mut self, interval: Option<Duration>) -> Self {
+        self.track_processes = interval;
+        self
+    }
+
+    /// mark a command that printed nothing for [hung] after_seconds, say so on the terminal with the
+    /// hotkeys that can tag or interrupt it, emit a warning event and run the command_hung hooks
+    pub fn with_hung_detection(mut self, cfg: &HungConfig, hotkeys: &HotkeyConfig) -> Self {
+        let mut keys = Vec::new();
+        if hotkeys.enabled {
+            keys.extend(hotkeys.hung.as_ref().map(|k| format!("{} tags it {}", k, cfg.tag)));
+            keys.extend(hotkeys.interrupt.as_ref().map(|k| format!("{} interrupts it", k)));
+        }
+        self.hung_tag = cfg.tag.clone();
+        self.hung = cfg.after_seconds.filter(|s| *s > 0).map(|s| HungNudge {
+            after: Duration::from_secs(s),
+            bell: cfg.bell,
+            hint: if keys.is_empty() { String::new() } else { format!("; {}", keys.join(", ")) },
+        });
+        self
+    }
+
+    /// record commands without their output while the logs directory is past `quota_mb`
+    /// or its disk has less than `min_free_mb` free
+    pub fn with_storage_limits(mut self, quota_mb: Option<u64>, min_free_mb: u64) -> Self {
+        self.quota_mb = quota_mb;
+        self.min_free_mb = min_free_mb;
+        self
+    }
+
+    /// mark new sessions as recorded inside `container`
+    pub fn with_container(mut self, container: ContainerInfo) -> Self {
+        self.container = Some(container);
+        self
+    }
+
+    /// mark new sessions as recorded on the ssh `destination`
+    pub fn with_remote(mut self, destination: &str) -> Self {
+        self.remote = Some(destination.to_string());
+        self
+    }
+
+    /// label new and resumed sessions with `tags`
+    pub fn with_tags(mut self, tags: &[String]) -> Self {
+        self.tags = tags.to_vec();
+        self
+    }
+
+    /// the file claimed while recording
+    pub fn pid_file(&self) -> &Path {
+        &self.pid_file
+    }
+
+    /// unix socket `recli dashboard` connects to, next to the pid file
+    pub fn control_socket(&self) -> PathBuf {
+        self.pid_file.with_extension("sock")
+    }
+
+    /// set while command output is not being stored for lack of space
+    pub fn output_dropped(&self) -> Arc<AtomicBool> {
+        self.output_dropped.clone()
+    }
+
+    /// set when a client asked for the config to be read again
+    pub fn reload_requested(&self) -> Arc<AtomicBool> {
+        self.reload_requested.clone()
+    }
+
+    pub fn is_session_active(&self) -> bool {
+        self.active_pid().is_some()
+    }
+
+    /// return the active session pid if present and alive
+    pub fn active_pid(&self) -> Option<u32> {
+        let record = self.read_pid_record()?;
+        self.is_recorder(&record).then_some(record.pid)
+    }
+
+    /// config of the session being recorded by another recli process, if one is running
+    pub fn active_session(&self) -> Option<SessionConfig> {
+        let record = self.read_pid_record()?;
+        if !self.is_recorder(&record) {
+            return None;
+        }
+        record.session
+    }
+
+    /// directory the active session is recording into, following any idle splits
+    pub fn active_log_dir(&self) -> Option<PathBuf> {
+        let mut dir = self.active_session()?.log_dir;
+        while let Some(next) = read_metadata(&dir).and_then(|m| m.continued_in) {
+            dir = dir.parent()?.join(next);
+        }
+        Some(dir)
+    }
+
+    /// remove a pid file left behind by a recorder that died, and close out its session;
+    /// returns the id of the session that was finalized, if any
+    pub fn cleanup_stale(&self) -> Option<String> {
+        if !self.pid_file.exists() {
+            return None;
+        }
+        let record = self.read_pid_record();
+        if record.as_ref().is_some_and(|r| self.is_recorder(r)) {
+            return None;
+        }
+        tracing::info!(pid_file = %self.pid_file.display(), "removing stale session pid file");
+        let _ = fs::remove_file(&self.pid_file);
+        finalize_orphan(record?.session?)
+    }
+
+    fn read_pid_record(&self) -> Option<PidRecord> {
+        let text = fs::read_to_string(&self.pid_file).ok()?;
+        // older versions wrote the bare pid
+        match text.trim().parse::<u32>() {
+            Ok(pid) => Some(PidRecord {
+                pid,
+                start_time: None,
+                boot_id: None,
+                session: None,
+            }),
+            Err(_) => serde_json::from_str(&text).ok(),
+        }
+    }
+
+    // the process in `record` is still running and is the one that wrote it
+    fn is_recorder(&self, record: &PidRecord) -> bool {
+        if !self.process_exists(record.pid) {
+            return false;
+        }
+        if record.boot_id.is_some() && record.boot_id != boot_id() {
+            return false;
+        }
+        match (record.start_time, process_start_time(record.pid)) {
+            (Some(recorded), Some(actual)) => recorded == actual,
+            _ => true,
+        }
+    }
+
+    pub fn start_session(&mut self, shell: &str, verbose: bool) -> Result<SessionConfig> {
+        if self.is_session_active() {
+            return Err(RecliError::Session("session already active".to_string()));
+        }
+
+        // create session directory
+        let (session_id, log_dir) = self.create_log_directory()?;
+
+        let config = SessionConfig {
+            session_id: session_id.clone(),
+            log_dir: log_dir.clone(),
+            // use rfc3339 utc for schema stability
+            started_at: time::now_stamp(),
+            shell: shell.to_string(),
+            last_title: None,
+            size_history: Vec::new(),
+            ended_at: None,
+            resumed_at: Vec::new(),
+            continues: None,
+            continued_in: None,
+            container: self.container.clone(),
+            remote: self.remote.clone(),
+            tags: self.tags.clone(),
+            host: Some(current_host()),
+            user: Some(current_user()),
+            // what runs in a container or over ssh isn't the local shell
+            environment: SessionEnvironment::detect(Some(shell).filter(|_| self.container.is_none() && self.remote.is_none())),
+        };
+
+        self.activate(config, verbose)
+    }
+
+    /// reopen an existing session directory; new commands are appended to its log
+    pub fn resume_session(&mut self, session_id: &str, shell: &str, verbose: bool) -> Result<SessionConfig> {
+        if self.is_session_active() {
+            return Err(RecliError::Session("session already active".to_string()));
+        }
+
+        check_session_id(session_id)?;
+        let log_dir = self.log_base.join(session_id);
+        let metadata_file = log_dir.join("session_metadata.json");
+        if !log_dir.join("commands.json").exists() && !metadata_file.exists() {
+            return Err(RecliError::Session(format!("no recorded session named {}", session_id)));
+        }
+
+        let previous = read_metadata(&log_dir);
+        let mut config = previous.unwrap_or_else(|| SessionConfig {
+            session_id: session_id.to_string(),
+            log_dir: log_dir.clone(),
+            started_at: time::now_stamp(),
+            shell: shell.to_string(),
+            last_title: None,
+            size_history: Vec::new(),
+            ended_at: None,
+            resumed_at: Vec::new(),
+            continues: None,
+            continued_in: None,
+            container: None,
+            remote: None,
+            tags: Vec::new(),
+            host: None,
+            user: None,
+            environment: SessionEnvironment::default(),
+        });
+        config.log_dir = log_dir.clone();
+        for tag in &self.tags {
+            if !config.tags.contains(tag) {
+                config.tags.push(tag.clone());
+            }
+        }
+        config.shell = shell.to_string();
+        config.ended_at = None;
+        config.resumed_at.push(time::now_stamp());
+
+        let mut log = if log_dir.join("commands.json").exists() {
+            CommandLog::load_from_file(&log_dir)?
+        } else {
+            CommandLog::new()
+        };
+        log.current_title = config.last_title.clone();
+        log.size_history = std::mem::take(&mut config.size_history);
+        self.command_log = Arc::new(Mutex::new(log));
+
+        self.activate(config, verbose)
+    }
+
+    // claim the pid file and start the log writer for `config`
+    fn activate(&mut self, config: SessionConfig, verbose: bool) -> Result<SessionConfig> {
+        let session_id = config.session_id.clone();
+        let log_dir = config.log_dir.clone();
+
+        // create pid file directory if it doesn't exist
+        if let Some(parent) = self.pid_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // write current process pid to file, with what identifies this process beyond it
+        let pid = std::process::id();
+        let record = PidRecord {
+            pid,
+            start_time: process_start_time(pid),
+            boot_id: boot_id(),
+            session: Some(config.clone()),
+        };
+        fs::write(&self.pid_file, serde_json::to_string_pretty(&record)?)?;
+        point_latest(&log_dir);
+
+        // set up logging channel
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let weak_tx = tx.downgrade();
+        self.log_sender = Some(tx);
+
+        // `recli dashboard` and other clients find the session through the socket next to its pid file
+        let control = ControlServer::start(
+            &self.control_socket(),
+            &config,
+            self.command_log.clone(),
+            weak_tx,
+            self.reload_requested.clone(),
+        )
+            .map_err(|e| tracing::warn!(error = %e, "no control socket for this session"))
+            .ok();
+
+    let command_log = Arc::clone(&self.command_log);
+        let mut config_clone = config.clone();
+        let events = self.events.clone();
+        let idle_segment = self.idle_segment;
+        let idle_restart = self.idle_restart;
+        let mut storage = (self.quota_mb.is_some() || self.min_free_mb > 0)
+            .then(|| StorageGuard::new(&self.log_base, self.quota_mb, self.min_free_mb));
+        let output_dropped = self.output_dropped.clone();
+        let track_files = self.track_files;
+        // taken while the shell waits at its prompt, so a command's changes are all after it
+        let mut prompt_files = track_files
+            .and_then(|limits| Some(FileSnapshot::take(&std::env::current_dir().ok()?, limits)));
+        let track_network = self.track_network;
+        let mut network: Option<NetworkWatch> = None;
+        let track_processes = self.track_processes;
+        let mut processes: Option<ProcessWatch> = None;
+        // until the pty says otherwise: the shell is recli's child, so a command is below recli too
+        let mut shell_pid = std::process::id();
+        let hung = self.hung.clone();
+        let hung_tag = self.hung_tag.clone();
+        let mut stall_check = tokio::time::interval(STALL_CHECK_INTERVAL);
+        stall_check.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        // editors and pagers sit quietly waiting for keys, they are never hung
+        let mut full_screen = AltScreenTracker::default();
+        if let Some(events) = &events {
+            // a resumed session's notices go on from its last recorded command
+            events.resume(&session_id, command_log.lock().unwrap().last_seq);
+            events.message(&session_id, "session started");
+        }
+        hooks::fire(HookEvent::SessionStart, &session_id, serde_json::json!({ "log_dir": log_dir }));
+
+    // spawn logging task
+    let handle = tokio::spawn(async move {
+            loop {
+                let event = tokio::select! {
+                    event = rx.recv() => match event {
+                        Some(event) => event,
+                        None => break,
+                    },
+                    _ = stall_check.tick(), if hung.is_some() => {
+                        if let Some(hung) = hung.as_ref().filter(|_| !full_screen.active()) {
+                            let mut log = command_log.lock().unwrap();
+                            check_stall(&mut log, hung, events.as_deref(), &config_clone.session_id);
+                        }
+                        continue;
+                    }
+                };
+                let mut log = command_log.lock().unwrap();
+                match event {
+                    LogEvent::CommandStart { cmd, cwd } => {
+                        let idle = log.idle_time().filter(|idle| idle_segment.is_some_and(|t| *idle >= t));
+                        if let Some(idle) = idle.filter(|_| log.current_cmd.is_empty()) {
+                            if !idle_restart {
+                                log.mark_segment(idle);
+                            } else if let Err(e) = split_session(&mut log, &mut config_clone, events.as_deref()) {
+                                tracing::warn!(error = %e, "could not split idle session, marking a segment instead");
+                                log.mark_segment(idle);
+                            }
+                        }
+                        hooks::fire(
+                            HookEvent::BeforeCommand,
+                            &config_clone.session_id,
+                            serde_json::json!({ "cmd": cmd, "cwd": cwd }),
+                        );
+                        let dropped = log.output_dropped;
+                        if let Some(storage) = storage.as_mut() {
+                            guard_storage(storage, 0, true, &mut log, &output_dropped, events.as_deref(), &config_clone.session_id);
+                        }
+                        if let Some(control) = &control {
+                            if log.output_dropped != dropped {
+                                control.output_dropped(log.output_dropped);
+                            }
+                            control.command_started(&cmd, &cwd);
+                        }
+                        log.start_command(cmd, cwd, &config_clone.log_dir);
+                        network = track_network.map(|interval| NetworkWatch::start(shell_pid, interval));
+                        processes = track_processes.map(|interval| ProcessWatch::start(shell_pid, interval));
+                    }
+                    LogEvent::Output { data } => {
+                        // once dropped, output stays off until the next command starts
+                        if let Some(storage) = storage.as_mut().filter(|_| !log.output_dropped) {
+                            guard_storage(storage, data.len(), false, &mut log, &output_dropped, events.as_deref(), &config_clone.session_id);
+                            if let Some(control) = control.as_ref().filter(|_| log.output_dropped) {
+                                control.output_dropped(true);
+                            }
+                        }
+                        if let Some(control) = &control {
+                            control.output(data.len());
+                        }
+                        full_screen.observe(&data);
+                        log.append_output_bytes(&data);
+                    }
+                    LogEvent::CommandEnd { exit_code, pipestatus, cwd } => {
+                        let before = log.entries.len();
+                        let cwd_after = PathBuf::from(&cwd);
+                        let files = prompt_files.take().zip(track_files).map(|(snapshot, limits)| {
+                            let now = FileSnapshot::take(&snapshot.dir, limits);
+                            (snapshot.diff(&now), now, limits)
+                        });
+                        let traffic = network.take().map(NetworkWatch::finish);
+                        let tree = processes.take().map(ProcessWatch::finish);
+                        log.finish_command(exit_code, pipestatus, cwd, &config_clone.log_dir);
+                        if let Some((changes, now, limits)) = files {
+                            if let Some(entry) = log.entries.get_mut(before) {
+                                entry.files = changes;
+                            }
+                            prompt_files = Some(if now.dir == cwd_after { now } else { FileSnapshot::take(&cwd_after, limits) });
+                        }
+                        if let Some((entry, traffic)) = log.entries.get_mut(before).zip(traffic) {
+                            entry.network = Some(traffic);
+                        }
+                        if let Some((entry, tree)) = log.entries.get_mut(before).zip(tree) {
+                            entry.processes = Some(tree);
+                        }
+                        if let Some(entry) = log.entries.get(before) {
+                            if let Some(control) = &control {
+                                control.command_finished(entry);
+                            }
+                            let stderr = entry.stderr.as_deref().unwrap_or("");
+                            if let Some(events) = &events {
+                                let raw = serde_json::to_value(entry).ok();
+                                let message = stderr.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+                                events.command(&config_clone.session_id, entry.seq, &entry.timestamp, &entry.cmd, entry.exit_code, message, raw);
+                            }
+                            datadog::record_command(
+                                &config_clone.session_id,
+                                &entry.cmd,
+                                entry.exit_code,
+                                &entry.cwd,
+                                &entry.timestamp,
+                                entry.duration_ms,
+                                stderr,
+                                config_clone.tags.iter().chain(log.entries.iter().flat_map(|e| &e.tags)),
+                            );
+                            sentry::report_command(
+                                &config_clone.session_id,
+                                &entry.cmd,
+                                entry.exit_code,
+                                &entry.cwd,
+                                &entry.timestamp,
+                                entry.duration_ms,
+                                entry.stderr.as_deref().unwrap_or(&entry.output_preview),
+                                config_clone.tags.iter().chain(log.entries.iter().flat_map(|e| &e.tags)),
+                            );
+                            hooks::fire(
+                                HookEvent::AfterCommand,
+                                &config_clone.session_id,
+                                serde_json::json!({ "command": entry }),
+                            );
+                        }
+                        // optional: keep the file warm in long sessions
+                        let _ = log.save_to_file(&config_clone.log_dir);
+                    }
+                    LogEvent::Annotate { tag } => {
+                        log.tag_current(tag);
+                    }
+                    LogEvent::Watch { tag, pattern, line } => {
+                        log.tag_current(tag);
+                        if let Some(events) = &events {
+                            events.message(&config_clone.session_id, &format!("watch /{}/ matched: {}", pattern, line));
+                        }
+                        hooks::fire(
+                            HookEvent::OutputMatch,
+                            &config_clone.session_id,
+                            serde_json::json!({ "cmd": log.current_cmd, "pattern": pattern, "line": line }),
+                        );
+                    }
+                    LogEvent::Paste { text, bytes } => {
+                        log.record_paste(text, bytes);
+                    }
+                    LogEvent::Title { title } => {
+                        log.set_title(title);
+                    }
+                    LogEvent::Resize { cols, rows } => {
+                        log.record_size(cols, rows);
+                    }
+                    LogEvent::ShellStarted { pid } => {
+                        shell_pid = pid;
+                    }
+                    LogEvent::Note { text } => {
+                        if !log.current_cmd.is_empty() {
+                            log.current_note = Some(text.trim().to_string());
+                        } else if log.note(1, false, &text).is_ok() {
+                            let _ = log.save_to_file(&config_clone.log_dir);
+                        }
+                    }
+                    LogEvent::Save => {
+                        let _ = log.save_to_file(&config_clone.log_dir);
+                    }
+                    LogEvent::ConfigReloaded(reloaded) => {
+                        if let Some(control) = &control {
+                            control.config_reloaded(reloaded);
+                        }
+                    }
+                    LogEvent::Hung => {
+                        log.tag_current(hung_tag.clone());
+                        // between commands the tag went to the last one
+                        let cmd = match log.current_cmd.is_empty() {
+                            true => log.entries.last().map(|e| e.cmd.clone()).unwrap_or_default(),
+                            false => log.current_cmd.clone(),
+                        };
+                        if let Some(events) = &events {
+                            events.warning(&config_clone.session_id, &format!("`{}` tagged as {}", cmd, hung_tag));
+                        }
+                    }
+                }
+            }
+            config_clone
+    });
+    self.log_task = Some(handle);
+
+        self.config = Some(config.clone());
+
+        if verbose {
+            println!("session started with id: {}", session_id);
+            println!("logs will be saved to: {}", log_dir.display());
+        }
+
+        Ok(config)
+    }
+
+    // sync wrapper for callers expecting a blocking stop
+    pub fn stop_session(&mut self) -> Result<Option<PathBuf>> {
+        let handle = tokio::runtime::Handle::current();
+        handle.block_on(self.stop_session_async())
+    }
+
+    pub async fn stop_session_async(&mut self) -> Result<Option<PathBuf>> {
+        if !self.is_session_active() {
+            return Ok(None);
+        }
+
+        // close the sender so the receiver can drain and exit
+        self.log_sender = None;
+
+        // wait for the logging task to finish processing all buffered events;
+        // it hands back the config of the last segment if the session was split
+        if let Some(task) = self.log_task.take() {
+            if let Ok(config) = task.await {
+                self.config = Some(config);
+            }
+        }
+        let log_dir = self.config.as_ref().map(|c| c.log_dir.clone());
+
+        // save final log
+        if let Some(config) = &self.config {
+            let mut log = self.command_log.lock().unwrap();
+            // defensively finalize any in-flight command to prevent empty logs
+            if !log.current_cmd.is_empty() {
+                let cwd = std::env::current_dir()
+                    .map(|p| p.to_string_lossy().to_string())
+                    .unwrap_or_else(|_| "/unknown".to_string());
+                // assume success exit code if unknown without shell integration
+                let log_dir = &config.log_dir;
+                log.finish_command(0, None, cwd, log_dir);
+            }
+            log.save_to_file(&config.log_dir)?;
+            let stderr_file = log.stderr_file.clone();
+            let _ = fs::remove_file(stderr_file.unwrap_or_else(|| config.log_dir.join(crate::command_log::STDERR_FILE)));
+            write_metadata(config, &log, time::now_stamp())?;
+            // another recorder may have started a session since this one did
+            point_latest(&config.log_dir);
+
+            if let Some(events) = &self.events {
+                events.message(&config.session_id, "session ended");
+            }
+            hooks::fire(HookEvent::SessionEnd, &config.session_id, serde_json::json!({ "log_dir": config.log_dir }));
+        }
+
+        // cleanup
+        if self.pid_file.exists() {
+            fs::remove_file(&self.pid_file)?;
+        }
+
+        self.config = None;
+        self.log_sender = None;
+
+        Ok(log_dir)
+    }
+
+    /// directory of the running session, if any
+    pub fn log_dir(&self) -> Option<&Path> {
+        self.config.as_ref().map(|c| c.log_dir.as_path())
+    }
+
+    pub fn get_status(&self) -> String {
+        if let Some(config) = &self.config {
+            format!(
+                "active session: {}\nstarted: {}\nlog directory: {}",
+                config.session_id,
+                config.started_at,
+                config.log_dir.display()
+            )
+        } else if self.is_session_active() {
+            "session is active but config not loaded".to_string()
+        } else {
+            "no active session".to_string()
+        }
+    }
+
+    /// return a copy of the current session config if available
+    pub fn current_config(&self) -> Option<SessionConfig> {
+        self.config.clone()
+    }
+
+    pub fn send_log_event(&self, event: LogEvent) {
+        if let Some(sender) = &self.log_sender {
+            let _ = sender.send(event);
+        }
+    }
+
+    // a directory of its own for a new session; recorders of tmux panes can start in the same second
+    fn create_log_directory(&self) -> Result<(String, PathBuf)> {
+        fs::create_dir_all(&self.log_base)?;
+        let base_id = generate_session_id();
+        let mut session_id = base_id.clone();
+        let mut n = 1;
+        loop {
+            let log_dir = self.log_base.join(&session_id);
+            match fs::create_dir(&log_dir) {
+                Ok(()) => return Ok((session_id, log_dir)),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    session_id = format!("{}_{}", base_id, n);
+                    n += 1;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn process_exists(&self, pid: u32) -> bool {
+        // on unix systems check if process exists by sending signal 0
+        #[cfg(unix)]
+        {
+            unsafe { libc::kill(pid as i32, 0) == 0 }
+        }
+
+        #[cfg(not(unix))]
+        {
+            // fallback for non-unix systems
+            false
+        }
+    }
+
+    /// public wrapper used by cli to poll termination
+    pub fn process_exists_public(&self, pid: u32) -> bool {
+        self.process_exists(pid)
+    }
+}
+
+// start time of `pid` in clock ticks after boot; linux only
+fn process_start_time(pid: u32) -> Option<u64> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // the command name is parenthesized and may hold spaces; starttime is the 22nd field
+    let after_comm = &stat[stat.rfind(')')? + 1..];
+    after_comm.split_whitespace().nth(19)?.parse().ok()
+}
+
+fn boot_id() -> Option<String> {
+    fs::read_to_string("/proc/sys/kernel/random/boot_id")
+        .ok()
+        .map(|id| id.trim().to_string())
+}
+
+// write an end to a session whose recorder died; returns its id
+fn finalize_orphan(mut config: SessionConfig) -> Option<String> {
+    // idle splits finish the earlier parts, so follow them to the part that was recording
+    while let Some(meta) = read_metadata(&config.log_dir) {
+        // a resumed session still carries the end of its previous run
+        if meta.ended_at.is_none() || meta.resumed_at != config.resumed_at {
+            break;
+        }
+        let next = meta.continued_in.clone()?;
+        config = SessionConfig {
+            session_id: next.clone(),
+            log_dir: config.log_dir.parent()?.join(&next),
+            started_at: meta.ended_at.clone().unwrap_or_default(),
+            resumed_at: Vec::new(),
+            ended_at: None,
+            continues: Some(meta.session_id.clone()),
+            continued_in: None,
+            ..meta
+        };
+    }
+    if !config.log_dir.exists() {
+        return None;
+    }
+
+    let log = CommandLog::load_from_file(&config.log_dir).unwrap_or_default();
+    let ended_at = log
+        .entries
+        .last()
+        .map(|e| e.timestamp.clone())
+        .unwrap_or_else(|| config.started_at.clone());
+    write_metadata(&config, &log, ended_at).ok()?;
+    // output of the command that was running is kept under a final name
+    let _ = fs::rename(config.log_dir.join("current.out"), config.log_dir.join("interrupted.out"));
+    let _ = fs::remove_file(config.log_dir.join(crate::command_log::STDERR_FILE));
+    Some(config.session_id)
+}
+
+// point the `latest` link next to `log_dir` at it; a `latest` that isn't a symlink is the user's
+fn point_latest(log_dir: &Path) {
+    let (Some(base), Some(name)) = (log_dir.parent(), log_dir.file_name()) else { return };
+    let link = base.join(history::LATEST_LINK);
+    if link.symlink_metadata().is_ok_and(|m| !m.file_type().is_symlink()) {
+        return;
+    }
+    // made aside and renamed over the old link, so a reader never finds it missing; relative,
+    // so it survives the log directory being moved
+    let tmp = base.join(format!(".{}.{}", history::LATEST_LINK, std::process::id()));
+    let _ = fs::remove_file(&tmp);
+    if let Err(e) = symlink(Path::new(name), &tmp).and_then(|_| fs::rename(&tmp, &link)) {
+        let _ = fs::remove_file(&tmp);
+        tracing::debug!(error = %e, "latest session link not updated");
+    }
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+// no link then; `recli path --latest` falls back to the newest session directory
+#[cfg(not(unix))]
+fn symlink(_target: &Path, _link: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "the latest link needs a unix platform"))
+}
+
+/// `id` as given for a session on the command line, which is joined onto the log directory, so
+/// it has to be a single name: no path separators and no `..`
+pub fn check_session_id(id: &str) -> Result<()> {
+    if id.is_empty() || id == "." || id.contains(['/', '\\']) || id.contains("..") {
+        return Err(RecliError::Validation(format!("{:?} is not a session id", id)));
+    }
+    Ok(())
+}
+
+/// pid file of a session started with --force-nested in a shell `outer` records
+pub fn nested_pid_file(outer: &str) -> PathBuf {
+    let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+    PathBuf::from(home).join(".recli").join("nested").join(format!("{}.pid", outer))
+}
+
+/// session_metadata.json of the session in `log_dir`, if it has readable metadata
+pub fn read_metadata(log_dir: &Path) -> Option<SessionConfig> {
+    let text = crate::lock::read_file(log_dir, "session_metadata.json").ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+fn generate_session_id() -> String {
+    let now = chrono::Local::now();
+    format!("recli_session_{}", now.format("%Y%m%d_%H%M%S"))
+}
+
+// session_metadata.json for a session that ended at `ended_at`
+fn write_metadata(config: &SessionConfig, log: &CommandLog, ended_at: String) -> Result<()> {
+    let metadata = SessionConfig {
+        last_title: log.current_title.clone().or_else(|| config.last_title.clone()),
+        // a log read back from disk has no size history of its own
+        size_history: if log.size_history.is_empty() {
+            config.size_history.clone()
+        } else {
+            log.size_history.clone()
+        },
+        ended_at: Some(ended_at),
+        ..config.clone()
+    };
+    let metadata = serde_json::to_string_pretty(&metadata)?;
+    crate::lock::write_file(&config.log_dir, "session_metadata.json", metadata.as_bytes())
+}
+
+// finish the session in `config` after an idle gap and keep recording into a new one next to it
+// switch output off when space runs short, and back on at a command start once there is enough again
+fn guard_storage(
+    storage: &mut StorageGuard,
+    incoming: usize,
+    fresh: bool,
+    log: &mut CommandLog,
+    dropped: &AtomicBool,
+    events: Option<&EventStream>,
+    session_id: &str,
+) {
+    let message = match storage.check(incoming, fresh) {
+        Some(reason) if !log.output_dropped => format!("{}; recording commands without their output", reason),
+        None if log.output_dropped => "enough space again; recording command output".to_string(),
+        _ => return,
+    };
+    log.set_output_dropped(!log.output_dropped);
+    dropped.store(log.output_dropped, Ordering::Relaxed);
+    tracing::warn!(session_id, "{}", message);
+    if let Some(events) = events {
+        events.warning(session_id, &message);
+    }
+}
+
+// mark the running command once it has printed nothing for as long as [hung] says, and nudge
+fn check_stall(log: &mut CommandLog, hung: &HungNudge, events: Option<&EventStream>, session_id: &str) {
+    let Some(silence) = log.output_silence().filter(|s| *s >= hung.after) else { return };
+    if !log.mark_stall() {
+        return;
+    }
+    let message = format!(
+        "`{}` has printed nothing for {}",
+        log.current_cmd,
+        crate::util::time::format_duration_ms(silence.as_millis() as u64)
+    );
+    tracing::info!(session_id, "{}", message);
+    // the terminal may be gone; a failed write must not take the log task down
+    let bell = if hung.bell { "\x07" } else { "" };
+    let _ = write!(std::io::stderr(), "{}\r\n\x1b[33m[recli] {}{}\x1b[0m\r\n", bell, message, hung.hint);
+    if let Some(events) = events {
+        events.warning(session_id, &format!("{}, it may be hung", message));
+    }
+    hooks::fire(
+        HookEvent::CommandHung,
+        session_id,
+        serde_json::json!({ "cmd": log.current_cmd, "silent_ms": silence.as_millis() as u64 }),
+    );
+}
+
+fn split_session(log: &mut CommandLog, config: &mut SessionConfig, events: Option<&EventStream>) -> Result<()> {
+    let base = config.log_dir.parent().map(Path::to_path_buf).unwrap_or_default();
+    let mut session_id = generate_session_id();
+    // a resumed session can be named for the current second already
+    let mut n = 1;
+    while base.join(&session_id).exists() {
+        session_id = format!("{}_{}", generate_session_id(), n);
+        n += 1;
+    }
+    let log_dir = base.join(&session_id);
+    fs::create_dir_all(&log_dir)?;
+    point_latest(&log_dir);
+
+    log.save_to_file(&config.log_dir)?;
+    write_metadata(
+        &SessionConfig {
+            continued_in: Some(session_id.clone()),
+            ..config.clone()
+        },
+        log,
+        time::now_stamp(),
+    )?;
+    if let Some(events) = events {
+        events.message(&config.session_id, "session ended");
+    }
+    hooks::fire(HookEvent::SessionEnd, &config.session_id, serde_json::json!({ "log_dir": config.log_dir }));
+
+    // the hook keeps teeing stderr to the file it was started with
+    let stderr_file = log
+        .stderr_file
+        .clone()
+        .unwrap_or_else(|| config.log_dir.join(crate::command_log::STDERR_FILE));
+    let mut next = CommandLog::new();
+    next.current_title = log.current_title.clone();
+    next.size_history = log.size_history.last().cloned().into_iter().collect();
+    next.stderr_file = Some(stderr_file);
+    next.output_dropped = log.output_dropped;
+    *log = next;
+
+    *config = SessionConfig {
+        session_id,
+        log_dir,
+        started_at: time::now_stamp(),
+        shell: config.shell.clone(),
+        last_title: None,
+        size_history: Vec::new(),
+        ended_at: None,
+        resumed_at: Vec::new(),
+        continues: Some(config.session_id.clone()),
+        continued_in: None,
+        container: config.container.clone(),
+        remote: config.remote.clone(),
+        tags: config.tags.clone(),
+        host: config.host.clone(),
+        user: config.user.clone(),
+        environment: config.environment.clone(),
+    };
+    if let Some(events) = events {
+        events.message(&config.session_id, "session started");
+    }
+    hooks::fire(HookEvent::SessionStart, &config.session_id, serde_json::json!({ "log_dir": config.log_dir }));
+    tracing::info!(session_id = %config.session_id, "idle gap, recording into a new session");
+    Ok(())
+}
@@ -0,0 +1,384 @@
+use crate::config::{RetryConfig, ShareConfig};
+use crate::util::compress;
+use crate::util::retry::{retry_blocking_if, transient_http};
+use crate::util::time::parse_age;
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::blocking::{Client, RequestBuilder};
+use reqwest::header::{CONTENT_ENCODING, CONTENT_TYPE};
+use reqwest::Url;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+// s3 refuses presigned urls valid for longer
+const S3_MAX_EXPIRY: i64 = 7 * 24 * 3600;
+const UPLOAD_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(120);
+
+/// a service `recli share` can upload to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Azure,
+    S3,
+    Gist,
+}
+
+impl Target {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "azure" => Ok(Target::Azure),
+            "s3" => Ok(Target::S3),
+            "gist" => Ok(Target::Gist),
+            other => Err(format!("unknown share target: {} (azure, s3 or gist)", other)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Target::Azure => "azure",
+            Target::S3 => "s3",
+            Target::Gist => "gist",
+        }
+    }
+}
+
+/// the target named on the command line or in [share], else the only one set up
+pub fn pick_target(cfg: &ShareConfig, name: Option<&str>) -> Result<Target, String> {
+    if let Some(name) = name.or(cfg.target.as_deref()) {
+        return Target::parse(name);
+    }
+    let configured: Vec<Target> = [
+        (Target::Azure, cfg.azure_sas_url.is_some()),
+        (Target::S3, cfg.s3_bucket.is_some()),
+        (Target::Gist, cfg.github_token.is_some()),
+    ]
+    .into_iter()
+    .filter_map(|(t, set)| set.then_some(t))
+    .collect();
+    match configured[..] {
+        [target] => Ok(target),
+        [] => Err("nothing to share to: set azure_sas_url, s3_bucket or github_token under [share]".to_string()),
+        _ => Err("more than one share target is set up; pick one with --target or target under [share]".to_string()),
+    }
+}
+
+/// one file of the bundle uploaded for a session
+pub struct BundleFile {
+    pub name: String,
+    pub content_type: &'static str,
+    pub encoding: Option<&'static str>, // stored as the blob's Content-Encoding
+    pub data: Vec<u8>,
+}
+
+impl BundleFile {
+    /// zstd the data; browsers undo it when they fetch the blob
+    pub fn compress(&mut self) -> Result<(), String> {
+        self.data = compress::compress(&self.data)?;
+        self.encoding = Some(compress::ENCODING);
+        Ok(())
+    }
+}
+
+/// the session's html page and, when it was recorded in a pty, its asciicast
+pub fn bundle(log_dir: &Path, session_id: &str) -> Result<Vec<BundleFile>, String> {
+    let html = crate::export::session_html(log_dir)?;
+    let mut files = vec![BundleFile {
+        name: format!("{}.html", session_id),
+        content_type: "text/html; charset=utf-8",
+        encoding: None,
+        data: html.into_bytes(),
+    }];
+    if let Ok(cast) = std::fs::read(log_dir.join("session.cast")) {
+        files.push(BundleFile {
+            name: format!("{}.cast", session_id),
+            content_type: "application/x-asciicast",
+            encoding: None,
+            data: cast,
+        });
+    }
+    Ok(files)
+}
+
+/// where an uploaded bundle can be viewed
+pub struct Shared {
+    pub url: String,              // the html page
+    pub cast_url: Option<String>, // the asciicast, for `asciinema play`
+    pub expires: Option<DateTime<Utc>>,
+}
+
+/// upload `files` to `target`, each retried as `retry` says; `expires` overrides [share] expires
+/// and only applies to s3
+pub fn upload(
+    cfg: &ShareConfig,
+    target: Target,
+    files: &[BundleFile],
+    expires: Option<&str>,
+    retry: &RetryConfig,
+) -> Result<Shared, String> {
+    if expires.is_some() && target != Target::S3 {
+        return Err(match target {
+            Target::Azure => "azure links last as long as the sas in azure_sas_url; --expires only applies to s3",
+            _ => "gists don't expire; --expires only applies to s3",
+        }
+        .to_string());
+    }
+    let client = Client::builder().timeout(UPLOAD_TIMEOUT).build().map_err(|e| e.to_string())?;
+    let mut urls = Vec::new();
+    let mut expiry = None;
+    match target {
+        Target::Azure => {
+            let sas = cfg.azure_sas_url.as_deref().ok_or("azure_sas_url is not set under [share]")?;
+            let container = Url::parse(sas).map_err(|e| format!("azure_sas_url: {}", e))?;
+            let mut link = container.clone();
+            if let Some(read) = &cfg.azure_read_sas {
+                link.set_query(Some(read.trim_start_matches('?')));
+            }
+            expiry = link
+                .query_pairs()
+                .find(|(k, _)| k == "se")
+                .and_then(|(_, v)| DateTime::parse_from_rfc3339(&v).ok())
+                .map(|t| t.with_timezone(&Utc));
+            for file in files {
+                let mut url = container.clone();
+                url.path_segments_mut().map_err(|_| "azure_sas_url is not a container url")?.pop_if_empty().push(&file.name);
+                let request = || {
+                    let request = client
+                        .put(url.clone())
+                        .header("x-ms-blob-type", "BlockBlob")
+                        .header("x-ms-blob-content-type", file.content_type);
+                    match file.encoding {
+                        Some(encoding) => request.header("x-ms-blob-content-encoding", encoding),
+                        None => request,
+                    }
+                    .body(file.data.clone())
+                };
+                send(request, &format!("uploading {}", file.name), retry)?;
+                link.set_path(url.path());
+                urls.push(link.to_string());
+            }
+        }
+        Target::S3 => {
+            let s3 = S3::from_config(cfg)?;
+            let lifetime = parse_age(expires.unwrap_or(&cfg.expires))?;
+            if lifetime.num_seconds() <= 0 || lifetime.num_seconds() > S3_MAX_EXPIRY {
+                return Err("s3 links can last from 1s to 7d".to_string());
+            }
+            let now = Utc::now();
+            expiry = Some(now + lifetime);
+            for file in files {
+                let key = format!("{}{}", cfg.s3_prefix, file.name);
+                let put = s3.presign("PUT", &key, 15 * 60, now);
+                let request = || {
+                    let request = client.put(&put).header(CONTENT_TYPE, file.content_type);
+                    match file.encoding {
+                        Some(encoding) => request.header(CONTENT_ENCODING, encoding),
+                        None => request,
+                    }
+                    .body(file.data.clone())
+                };
+                send(request, &format!("uploading {}", file.name), retry)?;
+                urls.push(s3.presign("GET", &key, lifetime.num_seconds(), now));
+            }
+        }
+        Target::Gist => {
+            let token = cfg.github_token.as_deref().ok_or("github_token is not set under [share] (or GITHUB_TOKEN)")?;
+            let mut content = serde_json::Map::new();
+            for file in files {
+                content.insert(file.name.clone(), json!({ "content": String::from_utf8_lossy(&file.data) }));
+            }
+            let session = files.first().map(|f| f.name.trim_end_matches(".html")).unwrap_or_default();
+            let body = json!({ "description": format!("recli session {}", session), "public": false, "files": content }).to_string();
+            let request = || {
+                client
+                    .post("https://api.github.com/gists")
+                    .bearer_auth(token)
+                    .header("accept", "application/vnd.github+json")
+                    .header("user-agent", concat!("recli/", env!("CARGO_PKG_VERSION")))
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(body.clone())
+            };
+            let reply = send(request, "uploading the gist", retry)?.text().map_err(|e| format!("gist: {}", e))?;
+            let gist: Value = serde_json::from_str(&reply).map_err(|e| format!("gist: {}", e))?;
+            let page = gist["html_url"].as_str().ok_or("github returned no gist url")?;
+            urls.push(page.to_string());
+            // the page shows every file; the cast is also linked raw for players
+            if let Some(cast) = files.get(1) {
+                urls.push(gist["files"][&cast.name]["raw_url"].as_str().unwrap_or(page).to_string());
+            }
+        }
+    }
+    let mut urls = urls.into_iter();
+    Ok(Shared {
+        url: urls.next().unwrap_or_default(),
+        cast_url: urls.next(),
+        expires: expiry,
+    })
+}
+
+/// write a small blob under `prefix` to `target` and delete it again, timing both;
+/// nothing is retried, so failures and latency show as they are
+pub fn canary(cfg: &ShareConfig, target: Target, prefix: &str) -> Result<Vec<(&'static str, std::time::Duration)>, String> {
+    let client = Client::builder().timeout(UPLOAD_TIMEOUT).build().map_err(|e| e.to_string())?;
+    let name = format!("{}canary-{}.txt", prefix, uuid::Uuid::new_v4());
+    let body = "recli sinks test\n";
+    let once = RetryConfig { attempts: 0, ..Default::default() };
+    let timed = |request: &dyn Fn() -> RequestBuilder, what: &str| {
+        let start = std::time::Instant::now();
+        send(request, what, &once).map(|response| (response, start.elapsed()))
+    };
+    let (write, delete) = match target {
+        Target::Azure => {
+            let sas = cfg.azure_sas_url.as_deref().ok_or("azure_sas_url is not set under [share]")?;
+            let mut url = Url::parse(sas).map_err(|e| format!("azure_sas_url: {}", e))?;
+            url.path_segments_mut()
+                .map_err(|_| "azure_sas_url is not a container url")?
+                .pop_if_empty()
+                .extend(name.split('/'));
+            let (_, write) = timed(&|| client.put(url.clone()).header("x-ms-blob-type", "BlockBlob").body(body), "writing the canary")?;
+            let (_, delete) = timed(&|| client.delete(url.clone()), "deleting the canary (the sas needs d)")?;
+            (write, delete)
+        }
+        Target::S3 => {
+            let s3 = S3::from_config(cfg)?;
+            let (key, now) = (format!("{}{}", cfg.s3_prefix, name), Utc::now());
+            let (put, delete) = (s3.presign("PUT", &key, 15 * 60, now), s3.presign("DELETE", &key, 15 * 60, now));
+            let (_, write) = timed(&|| client.put(&put).body(body), "writing the canary")?;
+            let (_, delete) = timed(&|| client.delete(&delete), "deleting the canary")?;
+            (write, delete)
+        }
+        Target::Gist => {
+            let token = cfg.github_token.as_deref().ok_or("github_token is not set under [share] (or GITHUB_TOKEN)")?;
+            let github = |request: RequestBuilder| {
+                request
+                    .bearer_auth(token)
+                    .header("accept", "application/vnd.github+json")
+                    .header("user-agent", concat!("recli/", env!("CARGO_PKG_VERSION")))
+            };
+            let file = name.rsplit('/').next().unwrap_or(&name);
+            let gist = json!({ "description": "recli sinks test", "public": false, "files": { file: { "content": body } } });
+            let create = || {
+                github(client.post("https://api.github.com/gists"))
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(gist.to_string())
+            };
+            let (response, write) = timed(&create, "creating the canary gist")?;
+            let created: Value = serde_json::from_str(&response.text().map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+            let url = format!("https://api.github.com/gists/{}", created["id"].as_str().ok_or("github returned no gist id")?);
+            let (_, delete) = timed(&|| github(client.delete(&url)), "deleting the canary gist")?;
+            (write, delete)
+        }
+    };
+    Ok(vec![("write", write), ("delete", delete)])
+}
+
+// send what `request` builds, again while it fails in a way that may pass, and fail on
+// anything but 2xx; `what` names it in errors, urls stay out as they carry credentials
+fn send(request: impl Fn() -> RequestBuilder, what: &str, retry: &RetryConfig) -> Result<reqwest::blocking::Response, String> {
+    let attempt = || {
+        let response = request().send()?;
+        match response.error_for_status_ref().err().filter(transient_http) {
+            Some(e) => Err(e),
+            None => Ok(response),
+        }
+    };
+    let response = retry_blocking_if(attempt, retry, transient_http).map_err(|e| format!("{}: {}", what, e.without_url()))?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().unwrap_or_default();
+    let body: String = body.trim().chars().take(300).collect();
+    Err(format!("{}: {} {}", what, status, body))
+}
+
+// presigned urls signed with aws signature v4
+struct S3 {
+    bucket: String,
+    region: String,
+    endpoint: Option<String>,
+    key_id: String,
+    secret: String,
+    token: Option<String>,
+}
+
+impl S3 {
+    fn from_config(cfg: &ShareConfig) -> Result<Self, String> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let (Some(key_id), Some(secret)) = (var("AWS_ACCESS_KEY_ID"), var("AWS_SECRET_ACCESS_KEY")) else {
+            return Err("s3 sharing needs AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY".to_string());
+        };
+        Ok(Self {
+            bucket: cfg.s3_bucket.clone().ok_or("s3_bucket is not set under [share]")?,
+            region: cfg.s3_region.clone(),
+            endpoint: cfg.s3_endpoint.as_ref().map(|e| e.trim_end_matches('/').to_string()),
+            key_id,
+            secret,
+            token: var("AWS_SESSION_TOKEN"),
+        })
+    }
+
+    fn presign(&self, method: &str, key: &str, expires_secs: i64, now: DateTime<Utc>) -> String {
+        let (base, path) = match &self.endpoint {
+            Some(endpoint) => (endpoint.clone(), format!("/{}/{}", self.bucket, key)),
+            None => (format!("https://{}.s3.{}.amazonaws.com", self.bucket, self.region), format!("/{}", key)),
+        };
+        let host = base.split_once("://").map(|(_, h)| h).unwrap_or(&base);
+        let path = uri_encode(&path, false);
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let scope = format!("{}/{}/s3/aws4_request", now.format("%Y%m%d"), self.region);
+
+        let mut params = vec![
+            ("X-Amz-Algorithm", "AWS4-HMAC-SHA256".to_string()),
+            ("X-Amz-Credential", format!("{}/{}", self.key_id, scope)),
+            ("X-Amz-Date", amz_date.clone()),
+            ("X-Amz-Expires", expires_secs.to_string()),
+            ("X-Amz-SignedHeaders", "host".to_string()),
+        ];
+        if let Some(token) = &self.token {
+            params.push(("X-Amz-Security-Token", token.clone()));
+        }
+        params.sort();
+        let query: Vec<String> = params.iter().map(|(k, v)| format!("{}={}", k, uri_encode(v, true))).collect();
+        let query = query.join("&");
+
+        let request = format!("{}\n{}\n{}\nhost:{}\n\nhost\nUNSIGNED-PAYLOAD", method, path, query, host);
+        let to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", amz_date, scope, hex::encode(Sha256::digest(request)));
+        let mut key = hmac(format!("AWS4{}", self.secret).as_bytes(), now.format("%Y%m%d").to_string().as_bytes());
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            key = hmac(&key, part.as_bytes());
+        }
+        let signature = hex::encode(hmac(&key, to_sign.as_bytes()));
+        format!("{}{}?{}&X-Amz-Signature={}", base, path, query, signature)
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    // hmac takes keys of any length
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac key");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+// percent-encoding as sigv4 wants it; `/` is kept in paths
+fn uri_encode(s: &str, encode_slash: bool) -> String {
+    let mut out = String::new();
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(b as char),
+            b'/' if !encode_slash => out.push('/'),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+/// human form of when a link stops working
+pub fn describe_expiry(expires: DateTime<Utc>) -> String {
+    let left = expires - Utc::now();
+    let left = if left >= Duration::days(2) {
+        format!("{} days", left.num_days())
+    } else {
+        format!("{} hours", left.num_hours().max(0))
+    };
+    format!("{} (in {})", expires.format("%Y-%m-%d %H:%M UTC"), left)
+}
@@ -0,0 +1,168 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// commands starred with `recli star`, kept to be run again with `recli snippets run`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Snippets {
+    #[serde(default)]
+    pub snippets: Vec<Snippet>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snippet {
+    pub name: String,
+    pub cmd: String, // may hold placeholders, {{name}} or {{name:default}}
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    // where it was recorded, for context
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    pub starred_at: DateTime<Utc>,
+}
+
+/// a value a snippet asks for when run
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placeholder {
+    pub name: String,
+    pub default: Option<String>,
+}
+
+/// ~/.recli/snippets.json
+pub fn path() -> PathBuf {
+    let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+    PathBuf::from(home).join(".recli").join("snippets.json")
+}
+
+impl Snippets {
+    /// the library at `path`, empty when there is none yet
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => Ok(serde_json::from_str(&text)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// write through a temporary file, so a crash never leaves half a library
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, path)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Snippet> {
+        self.snippets.iter().find(|s| s.name == name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Snippet> {
+        self.snippets.iter_mut().find(|s| s.name == name)
+    }
+
+    /// the snippet already holding exactly `cmd`
+    pub fn find_cmd(&self, cmd: &str) -> Option<&Snippet> {
+        self.snippets.iter().find(|s| s.cmd == cmd)
+    }
+
+    /// remove the snippet called `name`, saying whether there was one
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.snippets.len();
+        self.snippets.retain(|s| s.name != name);
+        self.snippets.len() != before
+    }
+
+    /// a free name for `cmd` from its first words, e.g. "cargo-build" or "cargo-build-2"
+    pub fn name_for(&self, cmd: &str) -> String {
+        let words: Vec<String> = cmd
+            .split_whitespace()
+            .map(|w| w.rsplit('/').next().unwrap_or(w))
+            .map(|w| w.chars().filter(|c| c.is_alphanumeric() || *c == '-' || *c == '_').collect::<String>())
+            .filter(|w| !w.is_empty() && !w.starts_with('-'))
+            .take(2)
+            .collect();
+        let base = match words.is_empty() {
+            true => "snippet".to_string(),
+            false => words.join("-").to_lowercase(),
+        };
+        let mut name = base.clone();
+        let mut n = 1;
+        while self.get(&name).is_some() {
+            n += 1;
+            name = format!("{}-{}", base, n);
+        }
+        name
+    }
+}
+
+/// the placeholders of `cmd` in the order they first appear, each once
+pub fn placeholders(cmd: &str) -> Vec<Placeholder> {
+    let mut found: Vec<Placeholder> = Vec::new();
+    for (_, placeholder) in scan(cmd) {
+        if !found.iter().any(|p| p.name == placeholder.name) {
+            found.push(placeholder);
+        }
+    }
+    found
+}
+
+/// `cmd` with every placeholder replaced by its value in `values`, or else its default;
+/// the names of any left without one are the error
+pub fn fill(cmd: &str, values: &HashMap<String, String>) -> Result<String, Vec<String>> {
+    let mut out = String::new();
+    let mut missing: Vec<String> = Vec::new();
+    let mut rest = 0;
+    for (range, placeholder) in scan(cmd) {
+        out.push_str(&cmd[rest..range.start]);
+        rest = range.end;
+        match values.get(&placeholder.name).or(placeholder.default.as_ref()) {
+            Some(value) => out.push_str(value),
+            None if !missing.contains(&placeholder.name) => missing.push(placeholder.name),
+            None => {}
+        }
+    }
+    out.push_str(&cmd[rest..]);
+    match missing.is_empty() {
+        true => Ok(out),
+        false => Err(missing),
+    }
+}
+
+/// `cmd` with each `text` in it turned into a placeholder called `name`, defaulting to `text`
+pub fn parametrize(cmd: &str, text: &str, name: &str) -> String {
+    cmd.replace(text, &format!("{{{{{}:{}}}}}", name, text))
+}
+
+/// whether `name` can name a placeholder
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+// placeholders with where they are; anything else in braces, like docker's {{.State}} or {{ json . }},
+// is left as it is
+fn scan(cmd: &str) -> Vec<(std::ops::Range<usize>, Placeholder)> {
+    let mut found = Vec::new();
+    let mut from = 0;
+    while let Some(start) = cmd[from..].find("{{").map(|i| from + i) {
+        let Some(end) = cmd[start + 2..].find("}}").map(|i| start + 2 + i) else { break };
+        let inner = &cmd[start + 2..end];
+        let (name, default) = match inner.split_once(':') {
+            Some((name, default)) => (name, Some(default.to_string())),
+            None => (inner, None),
+        };
+        if valid_name(name) {
+            found.push((start..end + 2, Placeholder { name: name.to_string(), default }));
+            from = end + 2;
+        } else {
+            from = start + 2;
+        }
+    }
+    found
+}
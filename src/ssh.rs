@@ -0,0 +1,52 @@
+use crate::hook_only::BASH_HOOK;
+use crate::pty::{ZSH_BOOTSTRAP_FILES, ZSH_HOOK};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// the remote login shell, with recli's hook loaded after the user's own config
+const HOOKED_SHELL: &str = r#"case "$SHELL" in *zsh) if [ -n "${ZDOTDIR+x}" ]; then export RECLI_USER_ZDOTDIR="$ZDOTDIR"; fi; ZDOTDIR="$HOME/.recli" exec zsh -i ;; *) exec bash --rcfile "$HOME/.recli/recli.bash" -i ;; esac"#;
+
+/// `ssh -t` to `destination` with the user's extra `options`; with `hooked`, the remote
+/// shell is started with the hook `install_hook` put there
+pub fn argv(destination: &str, options: &[String], hooked: bool) -> Vec<String> {
+    let mut argv = vec!["ssh".to_string(), "-t".to_string()];
+    argv.extend(options.iter().cloned());
+    argv.push(destination.to_string());
+    if hooked {
+        argv.push(HOOKED_SHELL.to_string());
+    }
+    argv
+}
+
+/// copy recli's bash and zsh hooks to ~/.recli on `destination` over one ssh connection
+pub fn install_hook(destination: &str, options: &[String]) -> Result<(), String> {
+    let mut child = Command::new("ssh")
+        .args(options)
+        .arg(destination)
+        .arg("sh")
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("ssh: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(install_script().as_bytes())
+            .map_err(|e| format!("ssh {}: {}", destination, e))?;
+    }
+    let status = child.wait().map_err(|e| format!("ssh: {}", e))?;
+    if !status.success() {
+        return Err(format!("installing the hook on {} failed ({})", destination, status));
+    }
+    Ok(())
+}
+
+// sh script writing the hook files, as recli writes them locally
+fn install_script() -> String {
+    let mut script = String::from("set -e\nmkdir -p \"$HOME/.recli\"\ncd \"$HOME/.recli\"\n");
+    let files = [("recli.bash", BASH_HOOK), ("recli.zsh", ZSH_HOOK)].into_iter().chain(ZSH_BOOTSTRAP_FILES);
+    for (name, content) in files {
+        script.push_str(&format!("cat > {} <<'RECLI_EOF'\n{}\nRECLI_EOF\n", name, content.trim_end()));
+    }
+    // compiled caches could shadow the fresh files
+    script.push_str("rm -f .zshenv.zwc .zprofile.zwc .zshrc.zwc recli.zsh.zwc\n");
+    script
+}
@@ -0,0 +1,154 @@
+use crate::index::IndexedCommand;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+
+// commands further apart than this in a session don't count as one following the other
+const MAX_GAP_MINUTES: i64 = 30;
+// how much each source counts: what followed here, what followed anywhere, what runs here most
+const HERE: f64 = 0.6;
+const ANYWHERE: f64 = 0.3;
+const FREQUENT: f64 = 0.1;
+
+/// a likely next command
+#[derive(Debug, Clone, Serialize)]
+pub struct Suggestion {
+    pub cmd: String,
+    pub score: f64, // 0 to 1
+    // how often it came right after the previous command in this project
+    pub followed: usize,
+}
+
+/// what tends to follow what, learned from recorded sessions: a first-order markov chain over
+/// command lines, per project and overall, falling back to how often commands run in the project
+#[derive(Debug, Default)]
+pub struct Model {
+    here: HashMap<String, HashMap<String, Weight>>,
+    anywhere: HashMap<String, HashMap<String, Weight>>,
+    frequent: HashMap<String, Weight>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Weight {
+    sum: f64,
+    count: usize,
+}
+
+impl Model {
+    /// learn from `commands`, a session at a time and oldest first within each (as `Index::in_order`
+    /// gives them); `project` is the git toplevel or directory suggestions are for
+    pub fn build(commands: &[IndexedCommand], project: &str, now: DateTime<Utc>) -> Self {
+        let mut model = Self::default();
+        let mut previous: Option<(&IndexedCommand, Option<DateTime<Utc>>)> = None;
+        for command in commands {
+            let cmd = command.cmd.trim();
+            let at = DateTime::parse_from_rfc3339(&command.timestamp).ok().map(|t| t.with_timezone(&Utc));
+            if cmd.is_empty() {
+                continue;
+            }
+            // recent habits win over old ones, and a command that failed is a weaker hint
+            let age_days = at.map(|t| (now - t).num_hours().max(0) as f64 / 24.0).unwrap_or(30.0);
+            let mut weight = 1.0 / (1.0 + age_days / 7.0);
+            if command.exit_code != 0 {
+                weight *= 0.5;
+            }
+            let here = command.project.as_deref().unwrap_or(&command.cwd) == project;
+            if here {
+                add(model.frequent.entry(cmd.to_string()).or_default(), weight);
+            }
+            if let Some((prev, prev_at)) = previous.filter(|(p, _)| p.session_id == command.session_id) {
+                let close = match (prev_at, at) {
+                    (Some(a), Some(b)) => (b - a).num_minutes() <= MAX_GAP_MINUTES,
+                    _ => true,
+                };
+                if close {
+                    let prev = prev.cmd.trim().to_string();
+                    if here {
+                        add(model.here.entry(prev.clone()).or_default().entry(cmd.to_string()).or_default(), weight);
+                    }
+                    add(model.anywhere.entry(prev).or_default().entry(cmd.to_string()).or_default(), weight);
+                }
+            }
+            previous = Some((command, at));
+        }
+        model
+    }
+
+    /// up to `limit` commands likely to follow `after`, best first, only those starting with `prefix`
+    pub fn suggest(&self, after: Option<&str>, prefix: &str, limit: usize) -> Vec<Suggestion> {
+        let after = after.map(str::trim).filter(|a| !a.is_empty());
+        let here = after.and_then(|a| self.here.get(a));
+        let anywhere = after.and_then(|a| self.anywhere.get(a));
+        let mut scores: HashMap<&str, f64> = HashMap::new();
+        let sources = [(here, HERE), (anywhere, ANYWHERE), (Some(&self.frequent), FREQUENT)];
+        // with nothing known about what follows, frequency is all there is and counts in full
+        let known = here.is_some() || anywhere.is_some();
+        for (weights, share) in sources {
+            let Some(weights) = weights else { continue };
+            let share = if known { share } else { 1.0 };
+            let total: f64 = weights.values().map(|w| w.sum).sum();
+            if total <= 0.0 {
+                continue;
+            }
+            for (cmd, weight) in weights {
+                if cmd.starts_with(prefix) && cmd != prefix {
+                    *scores.entry(cmd).or_default() += share * weight.sum / total;
+                }
+            }
+        }
+        let mut ranked: Vec<Suggestion> = scores
+            .into_iter()
+            .map(|(cmd, score)| Suggestion {
+                cmd: cmd.to_string(),
+                score,
+                followed: here.and_then(|h| h.get(cmd)).map_or(0, |w| w.count),
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.cmd.cmp(&b.cmd)));
+        ranked.truncate(limit);
+        ranked
+    }
+}
+
+fn add(weight: &mut Weight, by: f64) {
+    weight.sum += by;
+    weight.count += 1;
+}
+
+/// readline/zle glue that fills an empty line with the likeliest next command, or completes what
+/// was typed with one; bound to Ctrl-X s, other candidates are listed below the prompt
+pub fn shell_snippet(shell: &str) -> Option<&'static str> {
+    match shell {
+        "bash" => Some(
+            r#"# --- recli next-command suggestions (bash) ---
+_recli_suggest() {
+    local IFS=$'\n'
+    local last=$(fc -ln -1 2>/dev/null)
+    local -a matches=($(recli suggest --cwd "$PWD" --after "$last" -- "$READLINE_LINE"))
+    (( ${#matches[@]} )) || return
+    if (( ${#matches[@]} > 1 )); then
+        printf '%s\n' "${matches[@]:1}"
+    fi
+    READLINE_LINE="${matches[0]}"
+    READLINE_POINT=${#READLINE_LINE}
+}
+bind -x '"\C-xs": _recli_suggest'
+"#,
+        ),
+        "zsh" => Some(
+            r#"# --- recli next-command suggestions (zsh) ---
+_recli_suggest() {
+    local -a matches
+    matches=("${(@f)$(recli suggest --cwd "$PWD" --after "$(fc -ln -1 2>/dev/null)" -- "$BUFFER")}")
+    [[ -n "${matches[1]}" ]] || return
+    BUFFER="${matches[1]}"
+    CURSOR=${#BUFFER}
+    (( ${#matches} > 1 )) && zle -M "${(F)matches[2,-1]}"
+}
+zle -N _recli_suggest
+bindkey '^Xs' _recli_suggest
+"#,
+        ),
+        _ => None,
+    }
+}
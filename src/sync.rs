@@ -0,0 +1,226 @@
+use crate::config::{RetryConfig, TeamConfig};
+use crate::history;
+use crate::index::IndexedCommand;
+use crate::util::anonymize::Anonymizer;
+use crate::util::compress;
+use crate::util::retry::{retry_if, transient_http};
+use crate::util::throttle::Throttle;
+use reqwest::{Client, RequestBuilder, Response, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::time::Duration;
+
+/// version prefix of every path on a team server
+pub const PROTOCOL: &str = "v1";
+// commands asked for per index page; the server may send fewer
+const PAGE_SIZE: usize = 1000;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// one recorded session as pushed to a team server; `PUT /v1/sessions/{host}/{session_id}`
+/// replaces what the server has under that key, so pushing again is always safe
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionPush {
+    pub session_id: String,
+    pub host: String,
+    pub user: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+    /// session_metadata.json as recorded; left out when anonymizing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Value>,
+    pub commands: Vec<IndexedCommand>,
+}
+
+impl SessionPush {
+    /// read the session recorded in `log_dir`; with an anonymizer, names and paths are pseudonyms
+    pub fn load(
+        log_dir: &Path,
+        host: &str,
+        user: &str,
+        workspace: Option<&str>,
+        anonymizer: Option<&Anonymizer>,
+    ) -> Option<Self> {
+        let session_id = log_dir.file_name()?.to_string_lossy().to_string();
+        let entries = history::load_session(log_dir)?;
+        let (host, user) = match anonymizer {
+            Some(a) => (a.name(host), a.name(user)),
+            None => (host.to_string(), user.to_string()),
+        };
+        let path = |p: String| anonymizer.map(|a| a.path(&p)).unwrap_or(p);
+        let commands = entries
+            .into_iter()
+            .map(|e| IndexedCommand {
+                id: e.id,
+                session_id: session_id.clone(),
+                host: host.clone(),
+                user: user.clone(),
+                workspace: workspace.map(str::to_string),
+                cmd: e.cmd,
+                exit_code: e.exit_code,
+                cwd: path(e.cwd),
+                timestamp: e.timestamp,
+                duration_ms: e.duration_ms.unwrap_or(0),
+                project: e.project.map(path),
+            })
+            .collect();
+        let metadata = match anonymizer {
+            Some(_) => None,
+            None => crate::lock::read_file(log_dir, "session_metadata.json")
+                .ok()
+                .and_then(|text| serde_json::from_str(&text).ok()),
+        };
+        Some(Self {
+            session_id,
+            host,
+            user,
+            workspace: workspace.map(str::to_string),
+            metadata,
+            commands,
+        })
+    }
+
+    /// digest of the pushed body, kept to skip sessions that haven't changed since
+    pub fn content_hash(&self) -> String {
+        let body = serde_json::to_vec(self).unwrap_or_default();
+        hex::encode(Sha256::digest(body))
+    }
+}
+
+/// one page of the team's index from `GET /v1/index`
+#[derive(Debug, Clone, Deserialize)]
+pub struct IndexPage {
+    #[serde(default)]
+    pub commands: Vec<IndexedCommand>,
+    /// opaque position to continue from on the next pull
+    #[serde(default)]
+    pub cursor: Option<String>,
+    /// more pages follow right away
+    #[serde(default)]
+    pub more: bool,
+}
+
+/// client of a recli team server, authenticated with a bearer token
+pub struct TeamClient {
+    base: Url,
+    token: String,
+    http: Client,
+    compress: bool,
+    retry: RetryConfig,
+}
+
+impl TeamClient {
+    /// none unless [team] names a server
+    pub fn from_config(cfg: &TeamConfig) -> Result<Option<Self>, String> {
+        let Some(server) = cfg.server.as_deref().filter(|s| !s.is_empty()) else { return Ok(None) };
+        let token = cfg
+            .token
+            .clone()
+            .filter(|t| !t.is_empty())
+            .ok_or("[team] server is set but there is no token (token or RECLI_TEAM__TOKEN)")?;
+        // a trailing slash keeps a path prefix when joining, e.g. https://host/recli/
+        let base = Url::parse(&format!("{}/", server.trim_end_matches('/'))).map_err(|e| format!("[team] server: {}", e))?;
+        let http = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .user_agent(concat!("recli/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(Some(Self { base, token, http, compress: false, retry: RetryConfig::default() }))
+    }
+
+    /// send pushed sessions zstd-compressed, with `Content-Encoding: zstd`
+    pub fn with_compression(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    /// how requests that fail on the way or with a server error are retried
+    pub fn with_retry(mut self, retry: &RetryConfig) -> Self {
+        self.retry = retry.clone();
+        self
+    }
+
+    /// the server's address, for messages
+    pub fn server(&self) -> &str {
+        self.base.as_str()
+    }
+
+    /// store `session` on the server under its host and id, once `throttle` lets it go
+    pub async fn push(&self, session: &SessionPush, throttle: &mut Throttle) -> Result<(), String> {
+        let mut url = self.base.clone();
+        url.path_segments_mut()
+            .map_err(|_| "the team server url can't take a path")?
+            .pop_if_empty()
+            .extend([PROTOCOL, "sessions", &session.host, &session.session_id]);
+        let mut body = serde_json::to_vec(session).map_err(|e| e.to_string())?;
+        if self.compress {
+            body = compress::compress(&body)?;
+        }
+        throttle.wait(body.len()).await;
+        let response = self
+            .send(|| {
+                let request = self.http.put(url.clone()).header(reqwest::header::CONTENT_TYPE, "application/json");
+                match self.compress {
+                    true => request.header(reqwest::header::CONTENT_ENCODING, compress::ENCODING),
+                    false => request,
+                }
+                .body(body.clone())
+            })
+            .await?;
+        check(response, &format!("pushing {}", session.session_id)).await.map(|_| ())
+    }
+
+    /// commands the server received after `cursor`, or from the start without one
+    pub async fn pull(&self, cursor: Option<&str>) -> Result<IndexPage, String> {
+        let mut url = self.base.clone();
+        url.path_segments_mut()
+            .map_err(|_| "the team server url can't take a path")?
+            .pop_if_empty()
+            .extend([PROTOCOL, "index"]);
+        url.query_pairs_mut().append_pair("limit", &PAGE_SIZE.to_string());
+        if let Some(cursor) = cursor {
+            url.query_pairs_mut().append_pair("cursor", cursor);
+        }
+        let response = self.send(|| self.http.get(url.clone())).await?;
+        let text = check(response, "pulling the index").await?;
+        serde_json::from_str(&text).map_err(|e| format!("unreadable index page: {}", e))
+    }
+
+    /// whether the server takes the token, asking for a single command; nothing is written,
+    /// as the protocol has no way to take a push back
+    pub async fn probe(&self) -> Result<(), String> {
+        let mut url = self.base.clone();
+        url.path_segments_mut()
+            .map_err(|_| "the team server url can't take a path")?
+            .pop_if_empty()
+            .extend([PROTOCOL, "index"]);
+        url.query_pairs_mut().append_pair("limit", "1");
+        let response = self.http.get(url).bearer_auth(&self.token).send().await.map_err(|e| e.without_url().to_string())?;
+        check(response, "reading the index").await.map(|_| ())
+    }
+
+    // send what `request` builds with the token, again while it fails in a way that may pass;
+    // other error statuses come back as responses for check to report
+    async fn send(&self, request: impl Fn() -> RequestBuilder) -> Result<Response, String> {
+        let attempt = || async {
+            let response = request().bearer_auth(&self.token).send().await?;
+            match response.error_for_status_ref().err().filter(transient_http) {
+                Some(e) => Err(e),
+                None => Ok(response),
+            }
+        };
+        retry_if(attempt, &self.retry, transient_http).await.map_err(|e| e.without_url().to_string())
+    }
+}
+
+// the body of a 2xx response, or an error naming what failed
+async fn check(response: reqwest::Response, what: &str) -> Result<String, String> {
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    match status {
+        s if s.is_success() => Ok(body),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(format!("{}: the server refused the token ({})", what, status)),
+        _ => Err(format!("{}: {} {}", what, status, body.trim().chars().take(300).collect::<String>())),
+    }
+}
@@ -0,0 +1,139 @@
+use crate::cast::CastWriter;
+use crate::command_detector::{CommandDetector, Detection};
+use crate::config::Config;
+use crate::error::{RecliError, Result};
+use crate::policy::RecordingPolicy;
+use crate::session::{LogEvent, SessionManager};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncReadExt;
+
+// fields of a pane, tab separated, as list-panes and display-message print them
+const PANE_FORMAT: &str = "#{pane_id}\t#{pane_pipe}\t#{pane_width}\t#{pane_height}\t#{pane_current_command}\t#{pane_current_path}";
+
+/// a tmux pane and whether something already reads its output
+#[derive(Debug, Clone)]
+pub struct Pane {
+    pub id: String, // e.g. %3
+    pub piped: bool,
+    pub cols: u16,
+    pub rows: u16,
+    pub command: String, // what runs in the pane right now
+    pub cwd: String,
+}
+
+/// the pane `target` names, the current pane, or with `all` every pane of the current tmux session
+pub fn panes(target: Option<&str>, all: bool) -> std::result::Result<Vec<Pane>, String> {
+    let current = std::env::var("TMUX_PANE").ok();
+    let target = match (target, current.as_deref()) {
+        (Some(t), _) => t,
+        (None, Some(t)) => t,
+        (None, None) => return Err("not inside tmux; name a pane with --target".to_string()),
+    };
+    let out = if all {
+        tmux(&["list-panes", "-s", "-t", target, "-F", PANE_FORMAT])?
+    } else {
+        tmux(&["display-message", "-p", "-t", target, PANE_FORMAT])?
+    };
+    Ok(out.lines().filter_map(parse_pane).collect())
+}
+
+fn parse_pane(line: &str) -> Option<Pane> {
+    let mut fields = line.split('\t');
+    Some(Pane {
+        id: fields.next()?.to_string(),
+        piped: fields.next()? == "1",
+        cols: fields.next()?.parse().ok()?,
+        rows: fields.next()?.parse().ok()?,
+        command: fields.next().unwrap_or_default().to_string(),
+        cwd: fields.next().unwrap_or_default().to_string(),
+    })
+}
+
+/// send the output of `pane` to `command` (run through sh), or stop sending it when none is given
+pub fn pipe_pane(pane: &str, command: Option<&str>) -> std::result::Result<(), String> {
+    match command {
+        Some(command) => tmux(&["pipe-pane", "-O", "-o", "-t", pane, command]),
+        None => tmux(&["pipe-pane", "-t", pane]),
+    }
+    .map(|_| ())
+}
+
+/// what `pane` shows left of its cursor, e.g. the prompt the shell is waiting at
+pub fn cursor_line(pane: &str) -> std::result::Result<String, String> {
+    let cursor = tmux(&["display-message", "-p", "-t", pane, "#{cursor_x} #{cursor_y}"])?;
+    let (x, y) = cursor.trim().split_once(' ').ok_or("tmux printed no cursor position")?;
+    let x: usize = x.parse().map_err(|_| format!("bad cursor column {}", x))?;
+    // -N keeps the space after the prompt
+    let line = tmux(&["capture-pane", "-p", "-N", "-t", pane, "-S", y, "-E", y])?;
+    Ok(line.trim_end_matches('\n').chars().take(x).collect())
+}
+
+fn tmux(args: &[&str]) -> std::result::Result<String, String> {
+    let out = Command::new("tmux").args(args).output().map_err(|e| format!("tmux: {}", e))?;
+    if !out.status.success() {
+        return Err(format!("tmux {}: {}", args[0], String::from_utf8_lossy(&out.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).to_string())
+}
+
+/// pid file of the recorder of `pane`, so each pane is its own session next to `recli start`
+pub fn pid_file(pane: &str) -> PathBuf {
+    let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+    let name = pane.trim_start_matches('%');
+    PathBuf::from(home).join(".recli").join("tmux").join(format!("pane-{}.pid", name))
+}
+
+/// record what tmux pipes in on stdin as a session of its own until the pipe is closed, returning
+/// the session directory;
+/// without recli's hook in the pane, commands are found by osc 133 marks or prompt patterns
+pub async fn record(cfg: &Config, pane: &str, shell: &str, cols: u16, rows: u16) -> Result<PathBuf> {
+    let (strategy, patterns) = cfg.detection.for_unhooked_shell(shell);
+    let detection = Detection::new(strategy, &patterns).map_err(RecliError::Config)?;
+    let policy = RecordingPolicy::load(&cfg.policy, &std::env::current_dir()?).map_err(RecliError::Config)?;
+
+    let mut manager = SessionManager::new()
+        .with_log_dir(cfg.session_log_dir())
+        .with_pid_file(pid_file(pane))
+        .with_storage_limits(cfg.recording.quota_mb, cfg.recording.min_free_mb);
+    manager.cleanup_stale();
+    let config = manager.start_session(shell, false)?;
+    manager.send_log_event(LogEvent::Resize { cols, rows });
+    let mut cast = CastWriter::open(&config.log_dir, cols, rows, &config.shell)
+        .map_err(|e| tracing::warn!(error = %e, "not recording a cast of this pane"))
+        .ok();
+
+    let manager = Arc::new(Mutex::new(manager));
+    let mut detector = CommandDetector::new(manager.clone())
+        .with_policy(policy)
+        .with_detection(detection);
+    // the prompt was drawn before the pipe opened; without it the first command would go unseen
+    match cursor_line(pane) {
+        Ok(line) => {
+            detector.process_output(line.as_bytes());
+        }
+        Err(e) => tracing::warn!(error = %e, "could not read the pane's current line"),
+    }
+    let mut stdin = tokio::io::stdin();
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = stdin.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        let shown = detector.process_output(&buf[..n]);
+        if let Some(cast) = cast.as_mut().filter(|_| !detector.output_withheld()) {
+            cast.output(&shown);
+        }
+    }
+    detector.finish();
+    drop(detector);
+
+    let mut manager = Arc::try_unwrap(manager)
+        .map_err(|_| RecliError::Session("pane recorder still in use".to_string()))?
+        .into_inner()
+        .map_err(|_| RecliError::Session("pane recorder lock poisoned".to_string()))?;
+    manager.stop_session_async().await?;
+    Ok(config.log_dir)
+}
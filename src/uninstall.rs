@@ -0,0 +1,157 @@
+use crate::config::Config;
+use crate::history;
+use crate::session::SessionManager;
+use crate::util::{disk, telemetry};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// how much `recli uninstall` takes away
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    /// the shell hooks, the zsh bootstrap, pid files and sockets; recordings and settings stay
+    Hooks,
+    /// also the recorded sessions, the search indexes, the upload outbox and recli's own logs
+    Data,
+    /// everything under ~/.recli, config and keys included
+    All,
+}
+
+/// one file or directory to remove
+#[derive(Debug, Clone)]
+pub struct Item {
+    pub path: PathBuf,
+    pub what: &'static str,
+    pub bytes: u64,
+    // a directory configured outside ~/.recli, of which only what recli wrote goes
+    partial: bool,
+}
+
+// written by recli when a session starts, replaced by the next one
+const HOOK_FILES: [(&str, &str); 11] = [
+    ("recli.zsh", "zsh hook"),
+    ("recli.zsh.zwc", "zsh hook cache"),
+    (".zshenv", "zsh bootstrap (ZDOTDIR)"),
+    (".zshenv.zwc", "zsh bootstrap cache"),
+    (".zprofile", "zsh bootstrap (ZDOTDIR)"),
+    (".zprofile.zwc", "zsh bootstrap cache"),
+    (".zshrc", "zsh bootstrap (ZDOTDIR)"),
+    (".zshrc.zwc", "zsh bootstrap cache"),
+    ("recli.bash", "bash hook"),
+    ("session.pid", "pid file"),
+    ("session.sock", "control socket"),
+];
+
+/// ~/.recli
+pub fn home_dir() -> PathBuf {
+    let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+    PathBuf::from(home).join(".recli")
+}
+
+/// what exists of what `scope` removes, per `cfg` for directories that can be moved elsewhere.
+/// With `Scope::All`, ~/.recli is one item, and only directories configured outside it are listed
+/// besides it.
+pub fn plan(cfg: &Config, scope: Scope) -> Vec<Item> {
+    let home = home_dir();
+    let mut items = Vec::new();
+    if scope < Scope::All {
+        for (name, what) in HOOK_FILES {
+            items.push((home.join(name), what));
+        }
+        items.push((home.join("tmux"), "tmux pane pid files"));
+        items.push((home.join("nested"), "pid files of nested sessions"));
+    }
+    if scope >= Scope::Data {
+        items.push((cfg.session_log_dir(), "recorded sessions"));
+        items.push((crate::index::default_path(), "search index"));
+        // left by a build with the fulltext feature, whichever this one is
+        items.push((home.join("fulltext"), "full-text index"));
+        items.push((crate::outbox::dir(), "upload outbox"));
+        items.push((telemetry::log_dir(&cfg.logging), "recli's own logs"));
+    }
+    if scope == Scope::All {
+        items.retain(|(path, _)| !path.starts_with(&home));
+        items.push((home.clone(), "config, keys, snippets, plugins and everything else in ~/.recli"));
+    }
+    items
+        .into_iter()
+        .filter(|(path, _)| path.symlink_metadata().is_ok())
+        .map(|(path, what)| {
+            let partial = path.is_dir() && !path.starts_with(&home);
+            let bytes = match partial {
+                true => ours(&path).iter().map(|p| size(p)).sum(),
+                false => size(&path),
+            };
+            Item { path, what, bytes, partial }
+        })
+        .collect()
+}
+
+/// remove the items, going on past failures; returns those that failed
+pub fn remove(items: &[Item]) -> Vec<(PathBuf, io::Error)> {
+    let mut failed = Vec::new();
+    for item in items {
+        let paths = match item.partial {
+            true => ours(&item.path),
+            false => vec![item.path.clone()],
+        };
+        for path in paths {
+            if let Err(e) = remove_path(&path) {
+                failed.push((path, e));
+            }
+        }
+        // the directory goes too once nothing else is in it
+        if item.partial {
+            let _ = fs::remove_dir(&item.path);
+        }
+    }
+    failed
+}
+
+/// pid files of recorders still running, `recli start`, nested sessions and tmux panes; nothing
+/// should be removed from under them
+pub fn running_recorders() -> Vec<PathBuf> {
+    let home = home_dir();
+    let mut pid_files = vec![home.join("session.pid")];
+    for dir in ["tmux", "nested"] {
+        if let Ok(entries) = fs::read_dir(home.join(dir)) {
+            pid_files.extend(entries.filter_map(|e| e.ok()).map(|e| e.path()).filter(|p| p.extension().is_some_and(|e| e == "pid")));
+        }
+    }
+    pid_files
+        .into_iter()
+        .filter(|p| SessionManager::new().with_pid_file(p.clone()).is_session_active())
+        .collect()
+}
+
+// what recli wrote into a directory it shares with other things: session folders, the latest
+// link, and its own rotated logs (recli.<date>.log)
+fn ours(dir: &Path) -> Vec<PathBuf> {
+    let mut paths = history::session_dirs(dir);
+    let latest = dir.join(history::LATEST_LINK);
+    if latest.symlink_metadata().is_ok_and(|m| m.file_type().is_symlink()) {
+        paths.push(latest);
+    }
+    if let Ok(entries) = fs::read_dir(dir) {
+        paths.extend(entries.filter_map(|e| e.ok()).map(|e| e.path()).filter(|p| {
+            let name = p.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            p.is_file() && name.starts_with("recli.") && name.ends_with(".log")
+        }));
+    }
+    paths
+}
+
+fn size(path: &Path) -> u64 {
+    match path.is_dir() {
+        true => disk::dir_size(path),
+        false => fs::metadata(path).map(|m| m.len()).unwrap_or(0),
+    }
+}
+
+fn remove_path(path: &Path) -> io::Result<()> {
+    match path.symlink_metadata() {
+        Ok(m) if m.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(_) => Ok(()),
+    }
+}
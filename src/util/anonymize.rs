@@ -0,0 +1,81 @@
+use crate::config::AnonymizeConfig;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+
+// pseudonyms keep this many bytes of the digest
+const HASH_BYTES: usize = 6;
+
+/// replaces host, user and path components with salted hashes; the same value and salt
+/// always give the same pseudonym, so sessions of one machine or directory still group together
+#[derive(Debug, Clone)]
+pub struct Anonymizer {
+    salt: String,
+}
+
+impl Anonymizer {
+    /// none unless [anonymize] is enabled
+    pub fn from_config(cfg: &AnonymizeConfig) -> Option<Self> {
+        if !cfg.enabled {
+            return None;
+        }
+        let salt = cfg.salt.clone().filter(|s| !s.is_empty()).unwrap_or_else(local_salt);
+        Some(Self { salt })
+    }
+
+    /// pseudonym for a host or user name
+    pub fn name(&self, value: &str) -> String {
+        format!("anon-{}", self.hash("name", value))
+    }
+
+    /// every component hashed on its own, so paths below one directory still share a prefix; in a
+    /// domain of their own, so `/home/<user>` doesn't give away which pseudonym is that user's
+    pub fn path(&self, path: &str) -> String {
+        path.split('/')
+            .map(|c| if c.is_empty() { String::new() } else { self.hash("path", c) })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    fn hash(&self, domain: &str, value: &str) -> String {
+        let digest = Sha256::new()
+            .chain_update(self.salt.as_bytes())
+            .chain_update([0])
+            .chain_update(domain.as_bytes())
+            .chain_update([0])
+            .chain_update(value.as_bytes())
+            .finalize();
+        hex::encode(&digest[..HASH_BYTES])
+    }
+}
+
+// without a configured salt one is made up on first use and kept in ~/.recli/anonymize.salt,
+// so pseudonyms stay stable for this user (teams set a shared salt to correlate across users)
+fn local_salt() -> String {
+    let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+    let path = PathBuf::from(home).join(".recli").join("anonymize.salt");
+    if let Some(salt) = fs::read_to_string(&path).ok().map(|s| s.trim().to_string()).filter(|s| !s.is_empty()) {
+        return salt;
+    }
+    let mut bytes = [0u8; 16];
+    let random = fs::File::open("/dev/urandom").and_then(|mut f| f.read_exact(&mut bytes));
+    if random.is_err() {
+        // still unguessable enough to keep names out of plain sight
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        bytes = Sha256::digest(format!("{}:{}", nanos, std::process::id()))[..16]
+            .try_into()
+            .unwrap_or_default();
+    }
+    let salt = hex::encode(bytes);
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    if let Err(e) = fs::write(&path, &salt) {
+        tracing::warn!("could not save the anonymization salt to {}: {}", path.display(), e);
+    }
+    salt
+}
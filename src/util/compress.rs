@@ -0,0 +1,38 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+/// prefix of a compressed field value: "zstd:" + base64(zstd frame)
+pub const COMPRESSED_PREFIX: &str = "zstd:";
+/// the `Content-Encoding` of compressed bodies and blobs
+pub const ENCODING: &str = "zstd";
+// zstd's default; higher levels cost more cpu than they save on logs and json
+const LEVEL: i32 = 3;
+// below this, base64 and the frame header eat what compression saves
+const MIN_FIELD_LEN: usize = 256;
+
+/// one zstd frame of `data`
+pub fn compress(data: &[u8]) -> Result<Vec<u8>, String> {
+    zstd::encode_all(data, LEVEL).map_err(|e| format!("compressing: {}", e))
+}
+
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    zstd::decode_all(data).map_err(|e| format!("decompressing: {}", e))
+}
+
+/// `text` compressed for a json field, or as it is when that wouldn't make it shorter
+pub fn compress_field(text: &str) -> Result<String, String> {
+    if text.len() < MIN_FIELD_LEN {
+        return Ok(text.to_string());
+    }
+    let packed = format!("{}{}", COMPRESSED_PREFIX, BASE64.encode(compress(text.as_bytes())?));
+    Ok(if packed.len() < text.len() { packed } else { text.to_string() })
+}
+
+/// undo compress_field; values without the prefix are returned as they are
+pub fn decompress_field(value: &str) -> Result<String, String> {
+    let Some(packed) = value.strip_prefix(COMPRESSED_PREFIX) else {
+        return Ok(value.to_string());
+    };
+    let bytes = BASE64.decode(packed).map_err(|e| format!("compressed field is not base64: {}", e))?;
+    String::from_utf8(decompress(&bytes)?).map_err(|e| e.to_string())
+}
@@ -0,0 +1,227 @@
+use crate::config::{DatadogConfig, SinkContent, SinkFilter};
+use crate::util::anonymize::Anonymizer;
+use reqwest::blocking::Client;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Mutex, OnceLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+// the intake rejects requests with more logs than this
+const MAX_BATCH: usize = 1000;
+const FIRST_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+enum Msg {
+    Log(Value),
+    Shutdown,
+}
+
+// batching worker installed by init when [datadog] has an api key
+struct Sink {
+    tx: Sender<Msg>,
+    worker: Mutex<Option<JoinHandle<()>>>,
+    host: String,
+    service: String,
+    source: String,
+    ddtags: String,
+    workspace: Option<String>,
+    filter: SinkFilter,
+    anonymizer: Option<Anonymizer>,
+}
+
+struct Intake {
+    client: Option<Client>,
+    url: String,
+    api_key: String,
+    max_retries: usize,
+}
+
+static SINK: OnceLock<Sink> = OnceLock::new();
+
+/// start the datadog logs sink, sending what `filter` allows with names and paths pseudonymized by
+/// `anonymizer`; no-op unless an api key is configured
+pub fn init(cfg: &DatadogConfig, workspace: Option<&str>, filter: &SinkFilter, anonymizer: Option<Anonymizer>) {
+    let Some(api_key) = cfg.api_key.clone().filter(|k| !k.is_empty()) else { return };
+    let intake = Intake {
+        client: None,
+        url: cfg
+            .intake_url
+            .clone()
+            .unwrap_or_else(|| format!("https://http-intake.logs.{}/api/v2/logs", cfg.site)),
+        api_key,
+        max_retries: cfg.max_retries,
+    };
+    let batch_size = cfg.batch_size.clamp(1, MAX_BATCH);
+    let flush_interval = Duration::from_millis(cfg.flush_interval_ms.max(100));
+
+    let (tx, rx) = mpsc::channel();
+    let worker = std::thread::Builder::new()
+        .name("recli-datadog".to_string())
+        .spawn(move || run(rx, intake, batch_size, flush_interval));
+    let worker = match worker {
+        Ok(w) => w,
+        Err(e) => {
+            tracing::warn!(error = %e, "datadog sink disabled");
+            return;
+        }
+    };
+
+    let mut tags = cfg.tags.clone();
+    if let Some(ws) = workspace {
+        tags.push(format!("workspace:{}", ws));
+    }
+    let host = hostname::get()
+        .ok()
+        .and_then(|h| h.into_string().ok())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let _ = SINK.set(Sink {
+        tx,
+        worker: Mutex::new(Some(worker)),
+        host: anonymizer.as_ref().map_or(host.clone(), |a| a.name(&host)),
+        service: cfg.service.clone(),
+        source: cfg.source.clone(),
+        ddtags: tags.join(","),
+        workspace: workspace.map(str::to_string),
+        filter: filter.clone(),
+        anonymizer,
+    });
+}
+
+/// queue one log per recorded command; no-op unless the sink is running. `tags` are those of the
+/// session and its commands so far, for [sinks.datadog] skip_tags
+#[allow(clippy::too_many_arguments)]
+pub fn record_command<'a>(
+    session_id: &str,
+    cmd: &str,
+    exit_code: i32,
+    cwd: &str,
+    timestamp: &str,
+    duration_ms: Option<u64>,
+    stderr: &str,
+    tags: impl IntoIterator<Item = &'a String>,
+) {
+    let Some(sink) = SINK.get() else { return };
+    // the sink gets single commands, so metadata only leaves nothing to send
+    if sink.filter.content == SinkContent::Metadata || sink.filter.blocked_by(tags).is_some() {
+        return;
+    }
+    let cwd = sink.anonymizer.as_ref().map_or(cwd.to_string(), |a| a.path(cwd));
+
+    let failed = exit_code != 0;
+    let mut log = json!({
+        "ddsource": sink.source,
+        "ddtags": sink.ddtags,
+        "hostname": sink.host,
+        "service": sink.service,
+        "status": if failed { "error" } else { "info" },
+        "message": cmd,
+        "date": timestamp,
+        "session_id": session_id,
+        "cwd": cwd,
+        "exit_code": exit_code,
+    });
+    if let Some(ms) = duration_ms {
+        log["duration_ms"] = ms.into();
+    }
+    if let Some(ws) = &sink.workspace {
+        log["workspace"] = ws.as_str().into();
+    }
+    let output = sink.filter.content == SinkContent::Full;
+    if let Some(line) = stderr.lines().find(|l| !l.trim().is_empty()).filter(|_| failed && output) {
+        log["error"] = json!({ "message": line });
+    }
+    let _ = sink.tx.send(Msg::Log(log));
+}
+
+/// send whatever is still queued; call before the process exits
+pub fn shutdown() {
+    let Some(sink) = SINK.get() else { return };
+    let _ = sink.tx.send(Msg::Shutdown);
+    let worker = sink.worker.lock().ok().and_then(|mut w| w.take());
+    if let Some(worker) = worker {
+        let _ = worker.join();
+    }
+}
+
+// collect logs until the batch is full or the oldest one has waited flush_interval
+fn run(rx: Receiver<Msg>, mut intake: Intake, batch_size: usize, flush_interval: Duration) {
+    // the blocking client runs its own runtime, so it has to be built off the async one
+    match Client::builder().timeout(Duration::from_secs(10)).build() {
+        Ok(client) => intake.client = Some(client),
+        Err(e) => tracing::warn!(error = %e, "datadog sink disabled"),
+    }
+    let mut batch: Vec<Value> = Vec::new();
+    let mut deadline: Option<Instant> = None;
+    loop {
+        let msg = match deadline {
+            Some(d) => rx.recv_timeout(d.saturating_duration_since(Instant::now())),
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match msg {
+            Ok(Msg::Log(log)) => {
+                if batch.is_empty() {
+                    deadline = Some(Instant::now() + flush_interval);
+                }
+                batch.push(log);
+                if batch.len() >= batch_size {
+                    intake.send(&batch, intake.max_retries);
+                    batch.clear();
+                    deadline = None;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                intake.send(&batch, intake.max_retries);
+                batch.clear();
+                deadline = None;
+            }
+            Ok(Msg::Shutdown) | Err(RecvTimeoutError::Disconnected) => {
+                // one retry at most so an unreachable intake can't hold up exit
+                if !batch.is_empty() {
+                    intake.send(&batch, intake.max_retries.min(1));
+                }
+                return;
+            }
+        }
+    }
+}
+
+impl Intake {
+    // post a batch, backing off exponentially on 408/429/5xx and network errors
+    fn send(&self, batch: &[Value], max_retries: usize) {
+        let Some(client) = &self.client else { return };
+        let Ok(body) = serde_json::to_vec(batch) else { return };
+        let mut delay = FIRST_BACKOFF;
+        for attempt in 0..=max_retries {
+            let result = client
+                .post(&self.url)
+                .header("DD-API-KEY", &self.api_key)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone())
+                .send();
+            match result {
+                Ok(resp) if resp.status().is_success() => {
+                    tracing::debug!(logs = batch.len(), "datadog batch sent");
+                    return;
+                }
+                Ok(resp) if !retryable(resp.status()) => {
+                    tracing::warn!(status = %resp.status(), logs = batch.len(), "datadog rejected batch, dropped");
+                    return;
+                }
+                Ok(resp) => tracing::debug!(status = %resp.status(), attempt, "datadog intake busy"),
+                Err(e) => tracing::debug!(error = %e, attempt, "datadog intake unreachable"),
+            }
+            if attempt < max_retries {
+                std::thread::sleep(delay);
+                delay = (delay * 2).min(MAX_BACKOFF);
+            }
+        }
+        tracing::warn!(logs = batch.len(), "datadog batch dropped after retries");
+    }
+}
+
+fn retryable(status: StatusCode) -> bool {
+    status == StatusCode::REQUEST_TIMEOUT || status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
@@ -0,0 +1,108 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const MB: u64 = 1024 * 1024;
+// free space is looked up again after this much output or time
+const RECHECK_BYTES: u64 = MB;
+const RECHECK_INTERVAL: Duration = Duration::from_secs(1);
+// the logs directory is walked again at a command start after this long
+const RECOUNT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// decides whether command output may still be written below the logs directory
+#[derive(Debug)]
+pub struct StorageGuard {
+    base: PathBuf,
+    quota: Option<u64>,
+    min_free: u64,
+    // bytes below base: counted now and then, kept up to date with what is written in between
+    used: u64,
+    counted_at: Option<Instant>,
+    // once over quota, only a recount (something was deleted) can tell it isn't anymore
+    over_quota: bool,
+    free: Option<u64>,
+    checked_at: Option<Instant>,
+    since_check: u64,
+}
+
+impl StorageGuard {
+    pub fn new(base: &Path, quota_mb: Option<u64>, min_free_mb: u64) -> Self {
+        Self {
+            base: base.to_path_buf(),
+            quota: quota_mb.map(|mb| mb * MB),
+            min_free: min_free_mb * MB,
+            used: 0,
+            counted_at: None,
+            over_quota: false,
+            free: None,
+            checked_at: None,
+            since_check: 0,
+        }
+    }
+
+    /// none when `incoming` more bytes may be written, else the reason they may not;
+    /// `fresh` re-reads the disk instead of trusting recent numbers (at a command start)
+    pub fn check(&mut self, incoming: usize, fresh: bool) -> Option<String> {
+        let incoming = incoming as u64;
+        if let Some(quota) = self.quota {
+            let recount = fresh && (self.over_quota || self.counted_at.is_some_and(|t| t.elapsed() >= RECOUNT_INTERVAL));
+            if recount || self.counted_at.is_none() {
+                self.used = dir_size(&self.base);
+                self.counted_at = Some(Instant::now());
+            }
+            self.over_quota = self.used + incoming > quota;
+            if self.over_quota {
+                return Some(format!("log quota of {} MB reached", quota / MB));
+            }
+        }
+        if self.min_free > 0 {
+            let stale = self.checked_at.is_none_or(|t| t.elapsed() >= RECHECK_INTERVAL);
+            if fresh || stale || self.since_check + incoming >= RECHECK_BYTES {
+                self.free = free_space(&self.base);
+                self.checked_at = Some(Instant::now());
+                self.since_check = 0;
+            }
+            // a filesystem recli can't stat is not treated as full
+            let free = self.free.map(|f| f.saturating_sub(self.since_check));
+            if free.is_some_and(|f| f < self.min_free + incoming) {
+                return Some(format!("less than {} MB free for logs", self.min_free / MB));
+            }
+        }
+        self.used += incoming;
+        self.since_check += incoming;
+        None
+    }
+}
+
+/// bytes available to unprivileged users on the filesystem holding `path` (or its closest existing parent)
+#[cfg(unix)]
+pub fn free_space(path: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    let existing = path.ancestors().find(|p| p.exists())?;
+    let c_path = CString::new(existing.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+// unknown without statvfs, so only the quota can stop output
+#[cfg(not(unix))]
+pub fn free_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// total size of the files below `path`
+pub fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else { return 0 };
+    entries
+        .filter_map(|e| e.ok())
+        .map(|e| match e.file_type() {
+            Ok(t) if t.is_dir() => dir_size(&e.path()),
+            Ok(_) => e.metadata().map(|m| m.len()).unwrap_or(0),
+            Err(_) => 0,
+        })
+        .sum()
+}
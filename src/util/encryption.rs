@@ -0,0 +1,197 @@
+use crate::config::EncryptionConfig;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// prefix of an encrypted field value: "enc:v1:" + base64(nonce || ciphertext)
+pub const ENCRYPTED_PREFIX: &str = "enc:v1:";
+const ALGORITHM: &str = "AES-256-GCM";
+const NONCE_LEN: usize = 12;
+
+/// the user's key encryption key; it never leaves the machine, only data keys wrapped by it do
+pub struct UserKey {
+    key: Key<Aes256Gcm>,
+    id: String,
+}
+
+/// how a session's data key is stored next to its encrypted fields
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    pub alg: String,
+    /// short fingerprint of the user key the data key is wrapped with
+    pub key_id: String,
+    pub wrapped_key: String,
+}
+
+impl UserKey {
+    /// the key from RECLI_ENCRYPTION__KEY, `key_command` or `key_file`, in that order;
+    /// none while [encryption] is off. a missing key file is created when `create` is set.
+    pub fn load(cfg: &EncryptionConfig, create: bool) -> Result<Option<Self>, String> {
+        if !cfg.enabled {
+            return Ok(None);
+        }
+        let encoded = if let Some(key) = std::env::var("RECLI_ENCRYPTION__KEY").ok().filter(|k| !k.trim().is_empty()) {
+            key
+        } else if let Some(command) = &cfg.key_command {
+            let out = Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .output()
+                .map_err(|e| format!("key_command: {}", e))?;
+            if !out.status.success() {
+                return Err(format!("key_command exited with {}", out.status));
+            }
+            String::from_utf8_lossy(&out.stdout).into_owned()
+        } else {
+            let path = key_file(cfg);
+            match fs::read_to_string(&path) {
+                Ok(key) => key,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound && create => generate_key_file(&path)?,
+                Err(e) => return Err(format!("{}: {}", path.display(), e)),
+            }
+        };
+        Self::from_base64(encoded.trim()).map(Some)
+    }
+
+    fn from_base64(encoded: &str) -> Result<Self, String> {
+        let bytes = BASE64.decode(encoded).map_err(|e| format!("encryption key is not base64: {}", e))?;
+        if bytes.len() != 32 {
+            return Err(format!("encryption key must be 32 bytes, got {}", bytes.len()));
+        }
+        let id = hex::encode(&Sha256::digest(&bytes)[..4]);
+        Ok(Self { key: *Key::<Aes256Gcm>::from_slice(&bytes), id })
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// hex hmac-sha256 of `data` under this key, for hashes stored next to encrypted data that must
+    /// not let whoever holds the data confirm a guess at the plain text
+    pub fn mac(&self, data: &[u8]) -> String {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(self.key.as_slice()).expect("hmac key");
+        mac.update(b"recli content hash\0");
+        mac.update(data);
+        hex::encode(mac.finalize().into_bytes())
+    }
+}
+
+/// encrypts and decrypts the fields of one session with its own data key;
+/// the session id is bound to every value so fields can't be moved between sessions
+pub struct SessionCipher {
+    cipher: Aes256Gcm,
+    session_id: String,
+}
+
+impl SessionCipher {
+    /// a fresh data key for `session_id` and its envelope, wrapped by `user`
+    pub fn generate(user: &UserKey, session_id: &str) -> Result<(Self, Envelope), String> {
+        let data_key = Aes256Gcm::generate_key(OsRng);
+        let wrapped = seal(&Aes256Gcm::new(&user.key), data_key.as_slice(), session_id)?;
+        let envelope = Envelope {
+            alg: ALGORITHM.to_string(),
+            key_id: user.id.clone(),
+            wrapped_key: wrapped,
+        };
+        Ok((Self { cipher: Aes256Gcm::new(&data_key), session_id: session_id.to_string() }, envelope))
+    }
+
+    /// unwrap the data key of `session_id` from its envelope
+    pub fn open(user: &UserKey, envelope: &Envelope, session_id: &str) -> Result<Self, String> {
+        if envelope.alg != ALGORITHM {
+            return Err(format!("unsupported encryption {}", envelope.alg));
+        }
+        if envelope.key_id != user.id {
+            return Err(format!(
+                "session {} was encrypted with key {}, this key is {}",
+                session_id, envelope.key_id, user.id
+            ));
+        }
+        let data_key = open(&Aes256Gcm::new(&user.key), &envelope.wrapped_key, session_id)?;
+        if data_key.len() != 32 {
+            return Err("wrapped data key has the wrong length".to_string());
+        }
+        Ok(Self {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&data_key)),
+            session_id: session_id.to_string(),
+        })
+    }
+
+    pub fn encrypt(&self, plain: &str) -> Result<String, String> {
+        seal(&self.cipher, plain.as_bytes(), &self.session_id).map(|sealed| format!("{}{}", ENCRYPTED_PREFIX, sealed))
+    }
+
+    /// the plain text of an encrypted value; values without the prefix are returned as they are
+    pub fn decrypt(&self, value: &str) -> Result<String, String> {
+        let Some(sealed) = value.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(value.to_string());
+        };
+        let plain = open(&self.cipher, sealed, &self.session_id)?;
+        String::from_utf8(plain).map_err(|e| e.to_string())
+    }
+}
+
+// base64(nonce || ciphertext) with `aad` authenticated alongside
+fn seal(cipher: &Aes256Gcm, plain: &[u8], aad: &str) -> Result<String, String> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let mut sealed = cipher
+        .encrypt(&nonce, Payload { msg: plain, aad: aad.as_bytes() })
+        .map_err(|_| "encryption failed".to_string())?;
+    sealed.splice(0..0, nonce.iter().copied());
+    Ok(BASE64.encode(sealed))
+}
+
+fn open(cipher: &Aes256Gcm, sealed: &str, aad: &str) -> Result<Vec<u8>, String> {
+    let bytes = BASE64.decode(sealed).map_err(|e| format!("corrupt encrypted value: {}", e))?;
+    if bytes.len() < NONCE_LEN {
+        return Err("corrupt encrypted value: too short".to_string());
+    }
+    let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: aad.as_bytes() })
+        .map_err(|_| "decryption failed: wrong key or tampered data".to_string())
+}
+
+fn key_file(cfg: &EncryptionConfig) -> PathBuf {
+    match &cfg.key_file {
+        Some(path) => PathBuf::from(shellexpand::tilde(path).as_ref()),
+        None => {
+            let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+            PathBuf::from(home).join(".recli").join("upload.key")
+        }
+    }
+}
+
+// a new random user key, readable only by its owner
+fn generate_key_file(path: &std::path::Path) -> Result<String, String> {
+    use std::io::Write;
+
+    let encoded = BASE64.encode(Aes256Gcm::generate_key(OsRng));
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    // elsewhere the file takes the acl of the profile directory it is created in
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    file.write_all(encoded.as_bytes()).map_err(|e| format!("{}: {}", path.display(), e))?;
+    eprintln!(
+        "created upload encryption key {}; back it up, uploaded sessions can't be read without it",
+        path.display()
+    );
+    Ok(encoded)
+}
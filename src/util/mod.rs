@@ -0,0 +1,11 @@
+pub mod anonymize;
+pub mod compress;
+pub mod datadog;
+pub mod disk;
+pub mod encryption;
+pub mod procfs;
+pub mod retry;
+pub mod sentry;
+pub mod telemetry;
+pub mod throttle;
+pub mod time;
@@ -0,0 +1,92 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+/// what /proc/<pid>/stat says about a process
+#[derive(Debug, Clone)]
+pub struct ProcStat {
+    pub pid: u32,
+    pub ppid: u32,
+    pub pgid: u32,
+    pub tpgid: i32, // foreground group of its terminal, -1 without one
+    pub name: String,
+    pub cpu_ticks: u64,   // user and system time, in clock ticks
+    pub start_ticks: u64, // since boot; tells a reused pid apart
+}
+
+/// every process that can be read right now
+pub fn processes() -> Vec<ProcStat> {
+    let Ok(entries) = fs::read_dir("/proc") else { return Vec::new() };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let pid = entry.file_name().to_str()?.parse().ok()?;
+            parse_stat(pid, &fs::read_to_string(entry.path().join("stat")).ok()?)
+        })
+        .collect()
+}
+
+/// the processes whose chain of parents leads to `root`, not `root` itself
+pub fn descendants(all: &[ProcStat], root: u32) -> Vec<&ProcStat> {
+    let mut children: HashMap<u32, Vec<&ProcStat>> = HashMap::new();
+    for process in all {
+        children.entry(process.ppid).or_default().push(process);
+    }
+    let mut found = Vec::new();
+    let mut queue = vec![root];
+    while let Some(pid) = queue.pop() {
+        for &child in children.get(&pid).into_iter().flatten() {
+            found.push(child);
+            queue.push(child.pid);
+        }
+    }
+    found
+}
+
+/// tells the command a shell runs apart from its background jobs: with job control each job has a
+/// process group of its own, and the command's is the one the terminal has in the foreground.
+/// Without job control everything below the shell shares its group, and all of it counts.
+#[derive(Debug, Default)]
+pub struct ForegroundGroups {
+    groups: HashSet<u32>,
+}
+
+impl ForegroundGroups {
+    /// note the group `shell` has in the foreground right now, unless that is the shell itself
+    pub fn observe(&mut self, all: &[ProcStat], shell: u32) {
+        let Some(shell) = all.iter().find(|p| p.pid == shell) else { return };
+        if shell.tpgid > 0 && shell.tpgid as u32 != shell.pgid {
+            self.groups.insert(shell.tpgid as u32);
+        }
+    }
+
+    /// whether `pgid` belongs to the command, as far as has been observed
+    pub fn contains(&self, pgid: u32) -> bool {
+        self.groups.is_empty() || self.groups.contains(&pgid)
+    }
+}
+
+/// clock ticks as milliseconds
+pub fn ticks_to_ms(ticks: u64) -> u64 {
+    let per_second = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    let per_second = if per_second > 0 { per_second as u64 } else { 100 };
+    ticks * 1000 / per_second
+}
+
+fn parse_stat(pid: u32, stat: &str) -> Option<ProcStat> {
+    // the name in parentheses may hold spaces and parentheses itself, so split at the last ')'
+    let (head, rest) = stat.rsplit_once(')')?;
+    let name = head.split_once('(')?.1.to_string();
+    // state ppid pgrp session tty_nr tpgid flags minflt cminflt majflt cmajflt utime stime
+    // cutime cstime priority nice num_threads itrealvalue starttime
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    let field = |i: usize| fields.get(i).and_then(|f| f.parse::<u64>().ok());
+    Some(ProcStat {
+        pid,
+        ppid: field(1)? as u32,
+        pgid: field(2)? as u32,
+        tpgid: fields.get(5)?.parse().ok()?,
+        name,
+        cpu_ticks: field(11)? + field(12)?,
+        start_ticks: field(19)?,
+    })
+}
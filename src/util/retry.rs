@@ -0,0 +1,74 @@
+use crate::config::RetryConfig;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::time::Duration;
+use tokio::time::sleep;
+
+/// retry an async operation with exponential backoff and jitter
+pub async fn retry<F, Fut, T, E>(op: F, policy: &RetryConfig) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, E>>,
+{
+    retry_if(op, policy, |_| true).await
+}
+
+/// like retry, but errors `transient` says no to are returned right away
+pub async fn retry_if<F, Fut, T, E>(mut op: F, policy: &RetryConfig, transient: impl Fn(&E) -> bool) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, E>>,
+{
+    let mut rng = StdRng::from_entropy();
+    let mut attempt = 0usize;
+    loop {
+        match op().await {
+            Ok(v) => return Ok(v),
+            Err(e) => {
+                if attempt >= policy.attempts || !transient(&e) {
+                    return Err(e);
+                }
+                let delay = backoff(&mut rng, policy.backoff_ms, attempt);
+                tracing::debug!(attempt = attempt + 1, delay_ms = delay.as_millis() as u64, "retrying");
+                sleep(delay).await;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// retry_if for blocking code, sleeping the thread between attempts
+pub fn retry_blocking_if<T, E>(
+    mut op: impl FnMut() -> Result<T, E>,
+    policy: &RetryConfig,
+    transient: impl Fn(&E) -> bool,
+) -> Result<T, E> {
+    let mut rng = StdRng::from_entropy();
+    let mut attempt = 0usize;
+    loop {
+        match op() {
+            Ok(v) => return Ok(v),
+            Err(e) => {
+                if attempt >= policy.attempts || !transient(&e) {
+                    return Err(e);
+                }
+                std::thread::sleep(backoff(&mut rng, policy.backoff_ms, attempt));
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// whether a failed http request may succeed when sent again: no answer, a timeout,
+/// throttling or a server error
+pub fn transient_http(e: &reqwest::Error) -> bool {
+    match e.status() {
+        Some(status) => status.is_server_error() || status.as_u16() == 408 || status.as_u16() == 429,
+        None => !e.is_builder() && !e.is_redirect() && !e.is_decode(),
+    }
+}
+
+fn backoff(rng: &mut StdRng, base_delay_ms: u64, attempt: usize) -> Duration {
+    let expo = base_delay_ms.saturating_mul(1u64 << attempt.min(10));
+    let jitter: u64 = rng.gen_range(0..(expo / 2 + 1));
+    Duration::from_millis(expo + jitter)
+}
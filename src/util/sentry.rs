@@ -0,0 +1,219 @@
+use crate::config::{SentryConfig, SinkContent, SinkFilter};
+use crate::util::anonymize::Anonymizer;
+use reqwest::blocking::Client;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Mutex, OnceLock};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+// tail of the output attached to each event
+const EXCERPT_MAX: usize = 4096;
+
+// reporter installed by init when [sentry] has a dsn
+struct Reporter {
+    tx: Sender<Value>,
+    worker: Mutex<Option<JoinHandle<()>>>,
+    dsn: String,
+    host: String,
+    workspace: Option<String>,
+    cfg: SentryConfig,
+    filter: SinkFilter,
+    anonymizer: Option<Anonymizer>,
+}
+
+/// where and how to post envelopes, taken from a dsn like https://KEY@HOST/PROJECT
+struct Dsn {
+    envelope_url: String,
+    auth: String,
+}
+
+static REPORTER: OnceLock<Reporter> = OnceLock::new();
+
+/// start reporting failed commands, with what `filter` allows and names and paths pseudonymized by
+/// `anonymizer`; no-op unless a dsn is configured
+pub fn init(cfg: &SentryConfig, workspace: Option<&str>, filter: &SinkFilter, anonymizer: Option<Anonymizer>) {
+    let Some(dsn_str) = cfg.dsn.clone().filter(|d| !d.is_empty()) else { return };
+    let Some(dsn) = parse_dsn(&dsn_str) else {
+        tracing::warn!("sentry disabled: invalid dsn");
+        return;
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let worker = match std::thread::Builder::new()
+        .name("recli-sentry".to_string())
+        .spawn(move || run(rx, dsn))
+    {
+        Ok(w) => w,
+        Err(e) => {
+            tracing::warn!(error = %e, "sentry disabled");
+            return;
+        }
+    };
+    let host = hostname::get()
+        .ok()
+        .and_then(|h| h.into_string().ok())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let _ = REPORTER.set(Reporter {
+        tx,
+        worker: Mutex::new(Some(worker)),
+        dsn: dsn_str,
+        host: anonymizer.as_ref().map_or(host.clone(), |a| a.name(&host)),
+        workspace: workspace.map(str::to_string),
+        cfg: cfg.clone(),
+        filter: filter.clone(),
+        anonymizer,
+    });
+}
+
+/// report a finished command when it failed or its output matches an error pattern. `tags` are
+/// those of the session and its commands so far, for [sinks.sentry] skip_tags
+#[allow(clippy::too_many_arguments)]
+pub fn report_command<'a>(
+    session_id: &str,
+    cmd: &str,
+    exit_code: i32,
+    cwd: &str,
+    timestamp: &str,
+    duration_ms: Option<u64>,
+    output: &str,
+    tags: impl IntoIterator<Item = &'a String>,
+) {
+    let Some(reporter) = REPORTER.get() else { return };
+    let cfg = &reporter.cfg;
+    // an event is about a single command, so metadata only leaves nothing to report
+    if reporter.filter.content == SinkContent::Metadata || reporter.filter.blocked_by(tags).is_some() {
+        return;
+    }
+    let cwd = reporter.anonymizer.as_ref().map_or(cwd.to_string(), |a| a.path(cwd));
+
+    let matched = cfg.error_patterns.iter().find(|p| !p.is_empty() && output.contains(p.as_str()));
+    let failed = exit_code != 0 && cfg.report_nonzero && !cfg.ignore_exit_codes.contains(&exit_code);
+    if !failed && matched.is_none() {
+        return;
+    }
+
+    // patterns are still matched with `commands`, only the output itself stays home
+    let excerpt = (reporter.filter.content == SinkContent::Full).then(|| tail(output, EXCERPT_MAX));
+    let summary = if exit_code != 0 {
+        format!("`{}` exited with {}", cmd, exit_code)
+    } else {
+        format!("`{}` printed an error", cmd)
+    };
+    let event_id = {
+        let digest = Sha256::digest(format!("{}\n{}\n{}", session_id, timestamp, cmd).as_bytes());
+        hex::encode(&digest[..16])
+    };
+
+    let mut tags = json!({
+        "exit_code": exit_code.to_string(),
+        "session_id": session_id,
+    });
+    if let Some(ws) = &reporter.workspace {
+        tags["workspace"] = ws.as_str().into();
+    }
+    if let Some(pattern) = matched {
+        tags["matched_pattern"] = pattern.as_str().into();
+    }
+
+    let mut event = json!({
+        "event_id": event_id,
+        "timestamp": timestamp,
+        "platform": "other",
+        "level": "error",
+        "logger": "recli",
+        "server_name": reporter.host,
+        "message": { "formatted": summary },
+        // one issue per command line and exit code rather than per message text
+        "fingerprint": ["recli", cmd, exit_code.to_string()],
+        "tags": tags,
+        "contexts": {
+            "command": {
+                "type": "default",
+                "cmd": cmd,
+                "cwd": cwd,
+                "exit_code": exit_code,
+                "duration_ms": duration_ms,
+                "session_id": session_id,
+            }
+        },
+    });
+    if let Some(excerpt) = excerpt {
+        event["extra"] = json!({ "output": excerpt });
+    }
+    if let Some(env) = &cfg.environment {
+        event["environment"] = env.as_str().into();
+    }
+
+    let header = json!({ "event_id": event["event_id"], "dsn": reporter.dsn, "sent_at": timestamp });
+    let _ = reporter.tx.send(json!([header, event]));
+}
+
+/// wait for queued events to be sent; call before the process exits
+pub fn shutdown() {
+    let Some(reporter) = REPORTER.get() else { return };
+    let worker = reporter.worker.lock().ok().and_then(|mut w| w.take());
+    if let Some(worker) = worker {
+        let _ = reporter.tx.send(Value::Null);
+        let _ = worker.join();
+    }
+}
+
+fn run(rx: Receiver<Value>, dsn: Dsn) {
+    // the blocking client runs its own runtime, so it has to be built off the async one
+    let client = match Client::builder().timeout(Duration::from_secs(10)).build() {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::warn!(error = %e, "sentry disabled");
+            return;
+        }
+    };
+    // a null value marks shutdown
+    while let Ok(Value::Array(parts)) = rx.recv() {
+        let [header, event] = parts.as_slice() else { continue };
+        let body = format!("{}\n{}\n{}\n", header, json!({ "type": "event" }), event);
+        match client
+            .post(&dsn.envelope_url)
+            .header("X-Sentry-Auth", &dsn.auth)
+            .header(reqwest::header::CONTENT_TYPE, "application/x-sentry-envelope")
+            .body(body)
+            .send()
+        {
+            Ok(resp) if resp.status().is_success() => tracing::debug!(event_id = %header["event_id"], "sentry event sent"),
+            Ok(resp) => tracing::warn!(status = %resp.status(), "sentry rejected event"),
+            Err(e) => tracing::warn!(error = %e, "sentry unreachable, event dropped"),
+        }
+    }
+}
+
+fn parse_dsn(dsn: &str) -> Option<Dsn> {
+    let (scheme, rest) = dsn.split_once("://")?;
+    let (key, rest) = rest.split_once('@')?;
+    let key = key.split(':').next().filter(|k| !k.is_empty())?;
+    let (host_and_prefix, project) = rest.trim_end_matches('/').rsplit_once('/')?;
+    if project.is_empty() || host_and_prefix.is_empty() {
+        return None;
+    }
+    Some(Dsn {
+        envelope_url: format!("{}://{}/api/{}/envelope/", scheme, host_and_prefix, project),
+        auth: format!(
+            "Sentry sentry_version=7, sentry_key={}, sentry_client=recli/{}",
+            key,
+            env!("CARGO_PKG_VERSION")
+        ),
+    })
+}
+
+// last `max` bytes of `text`, cut on a char boundary
+fn tail(text: &str, max: usize) -> &str {
+    if text.len() <= max {
+        return text;
+    }
+    let mut start = text.len() - max;
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    &text[start..]
+}
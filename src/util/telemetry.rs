@@ -0,0 +1,167 @@
+use crate::config::{LoggingConfig, TelemetryConfig};
+use opentelemetry::trace::{Span, Status, Tracer, TracerProvider as _};
+use opentelemetry::{Array, KeyValue, StringValue, Value};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, reload, EnvFilter, Registry};
+
+// otlp pipeline installed by init when [telemetry] names an endpoint
+struct Exporter {
+    provider: SdkTracerProvider,
+    tags: Vec<String>,
+}
+
+static EXPORTER: OnceLock<Exporter> = OnceLock::new();
+// swaps the level filter of the installed subscriber
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// initialize global tracing subscriber from config level or env
+/// diagnostics go to a daily rotated json file so they never land on the recorded terminal;
+/// when an otlp endpoint is configured, spans are also exported over otlp/http
+pub fn init(logging: &LoggingConfig, telemetry: &TelemetryConfig) {
+    let env_filter = std::env::var("RUST_LOG").unwrap_or_else(|_| map_level(&logging.level));
+
+    // fall back to stderr only when the log directory is unusable
+    let appender = build_appender(logging);
+    let stderr_layer = appender
+        .is_none()
+        .then(|| fmt::layer().with_target(false).with_writer(std::io::stderr));
+    let file_layer = appender.map(|a| fmt::layer().json().with_writer(a));
+
+    let provider = telemetry
+        .otlp_endpoint
+        .as_deref()
+        .and_then(|endpoint| build_provider(endpoint, telemetry));
+    let otel_layer = provider
+        .as_ref()
+        .map(|p| tracing_opentelemetry::layer().with_tracer(p.tracer("recli")));
+
+    let (filter, handle) = reload::Layer::new(EnvFilter::new(env_filter));
+    let installed = tracing_subscriber::registry()
+        .with(filter)
+        .with(file_layer)
+        .with(stderr_layer)
+        .with(otel_layer)
+        .try_init();
+    if installed.is_ok() {
+        let _ = FILTER.set(handle);
+    }
+
+    if let Some(provider) = provider {
+        let _ = EXPORTER.set(Exporter {
+            provider,
+            tags: telemetry.tags.clone(),
+        });
+    }
+}
+
+/// apply the level of a config read again while recli runs; RUST_LOG still wins, as it does at start
+pub fn reload_level(logging: &LoggingConfig) -> Result<(), String> {
+    let env_filter = std::env::var("RUST_LOG").unwrap_or_else(|_| map_level(&logging.level));
+    set_filter(&env_filter)
+}
+
+/// replace the filter deciding which diagnostics are kept, e.g. "debug" or "recli::pty=trace"
+pub fn set_filter(directives: &str) -> Result<(), String> {
+    let filter = EnvFilter::try_new(directives).map_err(|e| e.to_string())?;
+    let handle = FILTER.get().ok_or_else(|| "diagnostics are not set up".to_string())?;
+    handle.reload(filter).map_err(|e| e.to_string())
+}
+
+/// the filter in effect, as set at start or since
+pub fn current_filter() -> Option<String> {
+    FILTER.get()?.with_current(|f| f.to_string()).ok()
+}
+
+/// emit one span per recorded command; no-op unless an otlp exporter is configured
+pub fn record_command(cmd: &str, exit_code: i32, cwd: &str, started_at: SystemTime, duration_ms: u64) {
+    let Some(exporter) = EXPORTER.get() else { return };
+    let tracer = exporter.provider.tracer("recli");
+
+    let tags: Vec<StringValue> = exporter.tags.iter().cloned().map(StringValue::from).collect();
+    let mut span = tracer
+        .span_builder(cmd.to_string())
+        .with_start_time(started_at)
+        .with_attributes(vec![
+            KeyValue::new("recli.exit_code", exit_code as i64),
+            KeyValue::new("recli.cwd", cwd.to_string()),
+            KeyValue::new("recli.duration_ms", duration_ms as i64),
+            KeyValue::new("recli.tags", Value::Array(Array::String(tags))),
+        ])
+        .start(&tracer);
+
+    if exit_code != 0 {
+        span.set_status(Status::error(format!("exit code {}", exit_code)));
+    }
+    span.end_with_timestamp(started_at + Duration::from_millis(duration_ms));
+}
+
+/// flush pending spans; call before the process exits
+pub fn shutdown() {
+    if let Some(exporter) = EXPORTER.get() {
+        let _ = exporter.provider.shutdown();
+    }
+}
+
+/// directory holding recli's own rotated diagnostics
+pub fn log_dir(logging: &LoggingConfig) -> PathBuf {
+    match &logging.dir {
+        Some(dir) => PathBuf::from(shellexpand::tilde(dir).as_ref()),
+        None => {
+            let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+            PathBuf::from(home).join(".recli").join("internal-logs")
+        }
+    }
+}
+
+fn build_appender(logging: &LoggingConfig) -> Option<RollingFileAppender> {
+    // pruning old files scans the directory, so it must exist up front
+    let dir = log_dir(logging);
+    if let Err(e) = std::fs::create_dir_all(&dir) {
+        eprintln!("warning: internal logs disabled: {}", e);
+        return None;
+    }
+    RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("recli")
+        .filename_suffix("log")
+        .max_log_files(logging.max_files.max(1))
+        .build(dir)
+        .map_err(|e| eprintln!("warning: internal logs disabled: {}", e))
+        .ok()
+}
+
+fn build_provider(endpoint: &str, telemetry: &TelemetryConfig) -> Option<SdkTracerProvider> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()
+        .map_err(|e| eprintln!("warning: otlp exporter disabled: {}", e))
+        .ok()?;
+
+    let service_name = telemetry.service_name.clone().unwrap_or_else(|| "recli".to_string());
+    let resource = Resource::builder().with_service_name(service_name).build();
+
+    Some(
+        SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(resource)
+            .build(),
+    )
+}
+
+fn map_level(level: &str) -> String {
+    match level.to_lowercase().as_str() {
+        "error" => "error".into(),
+        "warn" => "warn".into(),
+        "debug" => "debug".into(),
+        "trace" => "trace".into(),
+        _ => "info".into(),
+    }
+}
@@ -0,0 +1,35 @@
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// spaces out uploads so their average rate stays under a cap; payloads are sent whole,
+/// the next one waits until the previous could have gone out at the capped rate
+#[derive(Debug)]
+pub struct Throttle {
+    bytes_per_sec: Option<u64>,
+    next: Option<Instant>,
+}
+
+impl Throttle {
+    /// no waiting at all without a cap
+    pub fn new(max_kbps: Option<u64>) -> Self {
+        Self {
+            bytes_per_sec: max_kbps.filter(|k| *k > 0).map(|k| k * 1024),
+            next: None,
+        }
+    }
+
+    /// wait until `bytes` may be sent, then count them
+    pub async fn wait(&mut self, bytes: usize) {
+        let Some(rate) = self.bytes_per_sec else { return };
+        let now = Instant::now();
+        let start = match self.next {
+            Some(next) if next > now => {
+                tracing::debug!(wait_ms = (next - now).as_millis() as u64, "upload rate limit");
+                tokio::time::sleep_until(next).await;
+                next
+            }
+            _ => now,
+        };
+        self.next = Some(start + Duration::from_secs_f64(bytes as f64 / rate as f64));
+    }
+}
@@ -0,0 +1,124 @@
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use serde::{Deserialize, Deserializer};
+
+/// how recli writes a moment to disk: rfc3339 in utc with microseconds, all the same width so
+/// they sort as text
+pub fn stamp(t: DateTime<Utc>) -> String {
+    t.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+/// the current moment as `stamp` writes it
+pub fn now_stamp() -> String {
+    stamp(Utc::now())
+}
+
+/// a timestamp written by any version of recli: rfc3339 with any offset, chrono's `... UTC`
+/// display form, or a local date and time without a zone, as older session metadata has
+pub fn parse_stamp(text: &str) -> Option<DateTime<Utc>> {
+    let text = text.trim();
+    if let Ok(t) = DateTime::parse_from_rfc3339(text) {
+        return Some(t.with_timezone(&Utc));
+    }
+    if let Some(utc) = text.strip_suffix(" UTC") {
+        return NaiveDateTime::parse_from_str(utc, "%Y-%m-%d %H:%M:%S%.f").ok().map(|t| t.and_utc());
+    }
+    let local = ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f", "%Y%m%d_%H%M%S"]
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(text, f).ok())?;
+    Local.from_local_datetime(&local).earliest().map(|t| t.with_timezone(&Utc))
+}
+
+/// `text` rewritten as `stamp` would have it; left alone when it can't be read
+pub fn normalize_stamp(text: &str) -> String {
+    parse_stamp(text).map(stamp).unwrap_or_else(|| text.to_string())
+}
+
+/// a stored timestamp for people: utc, or with `local` the local time and its offset
+pub fn display_stamp(text: &str, local: bool) -> String {
+    match parse_stamp(text) {
+        Some(t) if local => t.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S %:z").to_string(),
+        Some(t) => t.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+        None => text.to_string(),
+    }
+}
+
+/// serde `deserialize_with` for a timestamp field, migrating older forms as it is read
+pub fn de_stamp<'de, D: Deserializer<'de>>(d: D) -> Result<String, D::Error> {
+    Ok(normalize_stamp(&String::deserialize(d)?))
+}
+
+/// `de_stamp` for an optional timestamp
+pub fn de_opt_stamp<'de, D: Deserializer<'de>>(d: D) -> Result<Option<String>, D::Error> {
+    Ok(Option::<String>::deserialize(d)?.map(|t| normalize_stamp(&t)))
+}
+
+/// `de_stamp` for a list of timestamps
+pub fn de_stamps<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<String>, D::Error> {
+    Ok(Vec::<String>::deserialize(d)?.iter().map(|t| normalize_stamp(t)).collect())
+}
+
+/// parse a time bound given as rfc3339, a plain date (yyyy-mm-dd, midnight utc),
+/// a local date and time (yyyy-mm-dd hh:mm), today or yesterday (local midnight)
+/// or an age relative to now such as 30m, 2h, 3d or 1w
+pub fn parse_time_bound(spec: &str) -> Result<DateTime<Utc>, String> {
+    let spec = spec.trim();
+    if let Ok(t) = DateTime::parse_from_rfc3339(spec) {
+        return Ok(t.with_timezone(&Utc));
+    }
+    if let Ok(d) = NaiveDate::parse_from_str(spec, "%Y-%m-%d") {
+        return Ok(d.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc());
+    }
+    let local = ["%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S"]
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(spec, f).ok());
+    let days_back = match spec {
+        "today" => Some(0),
+        "yesterday" => Some(1),
+        _ => None,
+    };
+    let local = local.or_else(|| {
+        let day = Local::now().date_naive() - Duration::days(days_back?);
+        day.and_hms_opt(0, 0, 0)
+    });
+    if let Some(local) = local {
+        // the earlier reading when a clock change makes the time ambiguous
+        return Local
+            .from_local_datetime(&local)
+            .earliest()
+            .map(|t| t.with_timezone(&Utc))
+            .ok_or_else(|| format!("{} does not exist in the local time zone", spec));
+    }
+
+    let age = parse_age(spec).map_err(|_| format!("invalid time: {}", spec))?;
+    Utc::now()
+        .checked_sub_signed(age)
+        .ok_or_else(|| format!("{} reaches past the earliest time recli can represent", spec))
+}
+
+/// parse a length of time such as 30m, 2h, 3d or 1w
+pub fn parse_age(spec: &str) -> Result<Duration, String> {
+    let spec = spec.trim();
+    let split = spec.char_indices().last().map(|(i, _)| i).unwrap_or(0);
+    let (num, unit) = spec.split_at(split);
+    let n: i64 = num.parse().map_err(|_| format!("invalid duration: {}", spec))?;
+    let age = match unit {
+        "s" => Duration::try_seconds(n),
+        "m" => Duration::try_minutes(n),
+        "h" => Duration::try_hours(n),
+        "d" => Duration::try_days(n),
+        "w" => Duration::try_weeks(n),
+        _ => return Err(format!("invalid duration: {}", spec)),
+    };
+    age.ok_or_else(|| format!("duration too long: {}", spec))
+}
+
+/// short human form of a duration: 850ms, 4.2s, 3m 12s, 1h 05m
+pub fn format_duration_ms(ms: u64) -> String {
+    let secs = ms / 1000;
+    match secs {
+        0 => format!("{}ms", ms),
+        1..=59 => format!("{:.1}s", ms as f64 / 1000.0),
+        60..=3599 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}